@@ -0,0 +1,96 @@
+//! 对比顺序句柄、连接池和多路复用句柄的吞吐，需要先启动服务端：
+//!
+//!     cargo run --bin server
+//!     MINI_REDIS_ADDR=127.0.0.1:6379 cargo bench --bench client
+
+use bytes::Bytes;
+use mini_redis::handle::{Handle, Options};
+use mini_redis::pool::{Pool, PoolOptions};
+use std::future::Future;
+use std::time::Instant;
+
+const TASKS: usize = 50;
+const REQUESTS: usize = 2000;
+
+async fn bench<F, Fut>(name: &str, f: F)
+where
+    F: Fn(usize) -> Fut,
+    Fut: Future<Output = mini_redis::Result<()>> + Send + 'static,
+{
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..TASKS).map(|i| tokio::spawn(f(i))).collect();
+    for task in tasks {
+        if let Err(err) = task.await.unwrap() {
+            println!("{:<12} failed: {}", name, err);
+            return;
+        }
+    }
+    let elapsed = start.elapsed();
+    let total = TASKS * REQUESTS;
+    println!(
+        "{:<12} {:>8} requests in {:>8.2?}  {:>10.0} req/s",
+        name,
+        total,
+        elapsed,
+        total as f64 / elapsed.as_secs_f64()
+    );
+}
+
+#[tokio::main]
+async fn main() {
+    let addr = std::env::var("MINI_REDIS_ADDR").unwrap_or_else(|_| "127.0.0.1:6379".to_string());
+    let mut options = Options::new(addr.clone());
+    options.connect_retries = 0;
+    if Handle::new(options.clone()).ping(None).await.is_err() {
+        println!("no server at {}, skipping benchmarks", addr);
+        return;
+    }
+
+    let handle = Handle::new(options.clone());
+    bench("handle", |i| {
+        let handle = handle.clone();
+        async move {
+            for n in 0..REQUESTS {
+                handle.set(&format!("bench:{}", i), Bytes::from(n.to_string())).await?;
+            }
+            Ok(())
+        }
+    })
+    .await;
+
+    let pool = Pool::new(
+        options.clone(),
+        PoolOptions {
+            max_size: TASKS,
+            ..PoolOptions::default()
+        },
+    );
+    bench("pool", |i| {
+        let pool = pool.clone();
+        async move {
+            for n in 0..REQUESTS {
+                let mut conn = pool.get().await?;
+                let args = [
+                    Bytes::from_static(b"SET"),
+                    Bytes::from(format!("bench:{}", i)),
+                    Bytes::from(n.to_string()),
+                ];
+                conn.call(&args).await?;
+            }
+            Ok(())
+        }
+    })
+    .await;
+
+    let multiplexed = Handle::multiplexed(options);
+    bench("multiplexed", |i| {
+        let handle = multiplexed.clone();
+        async move {
+            for n in 0..REQUESTS {
+                handle.set(&format!("bench:{}", i), Bytes::from(n.to_string())).await?;
+            }
+            Ok(())
+        }
+    })
+    .await;
+}
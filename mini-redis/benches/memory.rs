@@ -0,0 +1,173 @@
+//! 对比小的有序集合、哈希、集合和列表在紧凑编码和完整编码下占用的内存：
+//!
+//!     cargo bench --bench memory
+//!
+//! 每一轮单独启动一个服务端进程，用启动参数指定这一轮的编码阈值，不会改动任何已经在运行的服务端。
+//! used_memory 是服务端按编码估算的大小，rss 是进程实际多占用的物理内存（只有 Linux 上能读到）
+
+use mini_redis::handle::{Handle, Options};
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const KEYS: usize = 10_000;
+const MEMBERS: usize = 32;
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Zset,
+    Hash,
+    Intset,
+    Set,
+    List,
+}
+
+const KINDS: &[Kind] = &[Kind::Zset, Kind::Hash, Kind::Intset, Kind::Set, Kind::List];
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Zset => "zset",
+            Kind::Hash => "hash",
+            Kind::Intset => "intset",
+            Kind::Set => "set",
+            Kind::List => "list",
+        }
+    }
+
+    /// 紧凑编码和完整编码两轮各自的启动参数
+    fn settings(self, compact: bool) -> Vec<(&'static str, String)> {
+        let members = MEMBERS.to_string();
+        match (self, compact) {
+            (Kind::Zset, true) => vec![("zset-max-listpack-entries", members)],
+            (Kind::Zset, false) => vec![("zset-max-listpack-entries", "0".into())],
+            (Kind::Hash, true) => vec![("hash-max-listpack-entries", members)],
+            (Kind::Hash, false) => vec![("hash-max-listpack-entries", "0".into())],
+            (Kind::Intset, true) => vec![("set-max-intset-entries", members)],
+            (Kind::Intset, false) => vec![("set-max-intset-entries", "0".into()), ("set-max-listpack-entries", "0".into())],
+            (Kind::Set, true) => vec![("set-max-listpack-entries", members)],
+            (Kind::Set, false) => vec![("set-max-listpack-entries", "0".into())],
+            // 每个 quicklist 节点只放一项，相当于一个元素一个节点的链表
+            (Kind::List, true) => vec![("list-max-listpack-size", "-2".into())],
+            (Kind::List, false) => vec![("list-max-listpack-size", "1".into())],
+        }
+    }
+
+    async fn write(self, handle: &Handle, key: &str, i: usize) -> mini_redis::Result<()> {
+        let names: Vec<String> = (0..MEMBERS).map(|j| format!("member:{}", j)).collect();
+        let values: Vec<&[u8]> = names.iter().map(|name| name.as_bytes()).collect();
+        match self {
+            Kind::Zset => {
+                let items: Vec<(f64, &[u8])> =
+                    values.iter().enumerate().map(|(j, name)| (((i * 7 + j * 13) % 1000) as f64, *name)).collect();
+                handle.zadd(key, &items).await?;
+            }
+            Kind::Hash => {
+                let pairs: Vec<(&str, &[u8])> = names.iter().map(|name| (name.as_str(), &b"value"[..])).collect();
+                handle.hset(key, &pairs).await?;
+            }
+            Kind::Intset => {
+                let numbers: Vec<String> = (0..MEMBERS).map(|j| (i * MEMBERS + j).to_string()).collect();
+                let numbers: Vec<&[u8]> = numbers.iter().map(|n| n.as_bytes()).collect();
+                handle.sadd(key, &numbers).await?;
+            }
+            Kind::Set => {
+                handle.sadd(key, &values).await?;
+            }
+            Kind::List => {
+                handle.rpush(key, &values).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 这一轮专用的服务端进程，drop 时杀掉，基准出错或 panic 也不会留下进程
+struct Server {
+    child: Child,
+    port: u16,
+}
+
+impl Server {
+    fn start(settings: &[(&str, String)]) -> mini_redis::Result<Server> {
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let mut command = Command::new(env!("CARGO_BIN_EXE_server"));
+        command.arg("--port").arg(port.to_string());
+        for (name, value) in settings {
+            command.arg(format!("--{}", name)).arg(value);
+        }
+        let child = command.current_dir(std::env::temp_dir()).stdout(Stdio::null()).stderr(Stdio::null()).spawn()?;
+        let server = Server { child, port };
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while std::net::TcpStream::connect(("127.0.0.1", port)).is_err() {
+            if Instant::now() > deadline {
+                return Err(format!("server on port {} didn't start", port).into());
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        Ok(server)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Memory {
+    used: i64,
+    rss: i64,
+}
+
+async fn memory(handle: &Handle) -> mini_redis::Result<Memory> {
+    let info = handle.info("memory").await?;
+    let field = |name: &str| {
+        info.lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':')?.trim().parse().ok())
+            .unwrap_or(0)
+    };
+    Ok(Memory { used: field("used_memory"), rss: field("used_memory_rss") })
+}
+
+/// 在按这一轮的阈值启动的服务端上写入 KEYS 个各有 MEMBERS 个成员的 key，返回平均每个 key 的 used_memory 和 rss 增量
+async fn fill(kind: Kind, compact: bool) -> mini_redis::Result<(i64, i64)> {
+    let server = Server::start(&kind.settings(compact))?;
+    let handle = Handle::new(Options::new(format!("127.0.0.1:{}", server.port)));
+    let before = memory(&handle).await?;
+    for i in 0..KEYS {
+        kind.write(&handle, &format!("key:{}", i), i).await?;
+    }
+    let after = memory(&handle).await?;
+
+    let encoding = handle.object_encoding("key:0").await?.unwrap_or_default();
+    let usage = handle.memory_usage("key:0").await?.unwrap_or(0);
+    let used = (after.used - before.used) / KEYS as i64;
+    let rss = (after.rss - before.rss) / KEYS as i64;
+    println!("{:<8} {:<10} {:>14} {:>18} {:>14}", kind.name(), encoding, usage, used, rss);
+    Ok((used, rss))
+}
+
+async fn run() -> mini_redis::Result<()> {
+    println!("{} keys x {} members", KEYS, MEMBERS);
+    println!("{:<8} {:<10} {:>14} {:>18} {:>14}", "type", "encoding", "memory usage", "used_memory/key", "rss/key");
+    let saving = |compact: i64, full: i64| if full > 0 { 100 - compact * 100 / full } else { 0 };
+    let mut savings = Vec::new();
+    for &kind in KINDS {
+        let (compact_used, compact_rss) = fill(kind, true).await?;
+        let (full_used, full_rss) = fill(kind, false).await?;
+        savings.push((kind, saving(compact_used, full_used), saving(compact_rss, full_rss)));
+    }
+    for (kind, used, rss) in savings {
+        println!("{:<8} compact encoding saves {}% of used_memory and {}% of rss", kind.name(), used, rss);
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(err) = run().await {
+        println!("memory benchmark failed: {}", err);
+    }
+}
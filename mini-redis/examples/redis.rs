@@ -1,55 +1,51 @@
-
 use bytes::Bytes;
-use mini_redis::{client,Result};
-use tokio::sync::mpsc;
-
-
-enum Command {
-    Get{
-        key : String,
-    },
-    Set {
-        key : String,
-        value : Bytes,
+use mini_redis::handle::{Handle, Options};
+use mini_redis::tls::ClientOptions;
+use mini_redis::Result;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// cargo run --example redis -- [--addr host:port] [--tls-ca ca.pem [--tls-cert cert.pem --tls-key key.pem] [--sni name]]
+fn parse_options() -> Result<Options> {
+    let mut options = Options::new("127.0.0.1:6379");
+    let mut ca_cert = None;
+    let mut cert = None;
+    let mut key = None;
+    let mut server_name = "localhost".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "--addr" => options.addr = value,
+            "--tls-ca" => ca_cert = Some(PathBuf::from(value)),
+            "--tls-cert" => cert = Some(PathBuf::from(value)),
+            "--tls-key" => key = Some(PathBuf::from(value)),
+            "--sni" => server_name = value,
+            "--password" => options.password = Some(value),
+            _ => return Err(format!("unknown argument {}", arg).into()),
+        }
     }
+    options.tls = ca_cert.map(|ca_cert| ClientOptions { ca_cert, server_name, cert, key });
+    options.timeout = Duration::from_secs(1);
+    Ok(options)
 }
 
 #[tokio::main]
 async fn main() -> Result<()>{
+    // manager 任务在 Handle 内部，每个请求带着 oneshot 回传结果
+    let handle = Handle::new(parse_options()?);
+    let handle2 = handle.clone();
 
-    let (tx,mut rx) = mpsc::channel(32);
-    let tx2 = tx.clone();
-
-    let t1 = tokio::spawn(async move { 
-        let cmd = Command::Get { key: "hello".to_string() };
-        tx.send(cmd).await.unwrap();
+    let t1 = tokio::spawn(async move {
+        handle.get("hello").await
     });
     let t2 = tokio::spawn(async move {
-        let cmd = Command::Set {
-             key: "foo".to_string(), 
-             value: "bar".into() };
-        tx2.send(cmd).await;
-    });
-    let manager = tokio::spawn(async move {
-        let mut client = client::connect("127.0.0.1:6379").await.unwrap();
-        while let Some(cmd) = rx.recv().await {
-            use Command::*;
-            match cmd {
-                Get{key} => {
-                    client.get(&key).await;
-                }
-                Set{key , value} => {
-                    client.set(&key, value).await;
-                }
-    
-            }
-        }
+        handle2.set("foo", Bytes::from("bar")).await?;
+        handle2.get("foo").await
     });
 
-
-    t1.await.unwrap();
-    t2.await.unwrap();
-    manager.await.unwrap();
+    println!("GET hello = {:?}", t1.await??);
+    println!("GET foo = {:?}", t2.await??);
     Ok(())
 
-}
\ No newline at end of file
+}
@@ -0,0 +1,36 @@
+use bytes::Bytes;
+use mini_redis::client;
+use mini_redis::handle::{Handle, Options, TrackingOptions};
+use mini_redis::{Frame, Result};
+
+// 用一个订阅连接接收另一个连接的缓存失效通知
+// cargo run --example subscribe -- [host:port]
+#[tokio::main]
+async fn main() -> Result<()> {
+    let addr = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:6379".to_string());
+
+    let mut listener = client::connect(addr.as_str()).await?;
+    let id = match listener.request(&[b"CLIENT", b"ID"]).await? {
+        Frame::Integer(id) => id,
+        frame => return Err(format!("unexpected reply {:?}", frame).into()),
+    };
+    let mut subscriber = listener.subscribe(&["__redis__:invalidate"]).await?;
+
+    let handle = Handle::new(Options::new(addr.clone()));
+    handle
+        .client_tracking(&TrackingOptions {
+            redirect: Some(id),
+            ..TrackingOptions::default()
+        })
+        .await?;
+    println!("GET foo = {:?}", handle.get("foo").await?);
+
+    // 另一个连接修改 foo，上面缓存的值随之失效
+    Handle::new(Options::new(addr))
+        .set("foo", Bytes::from("changed"))
+        .await?;
+    if let Some(message) = subscriber.next_message().await? {
+        println!("{} -> {:?}", String::from_utf8_lossy(&message.channel), message.content);
+    }
+    Ok(())
+}
@@ -0,0 +1,429 @@
+use bytes::Bytes;
+use mini_redis::client::{self, Client};
+use mini_redis::{Frame, Result};
+use rand::Rng;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// cargo run --release --bin benchmark -- [-h host] [-p port] [-a password] [-c clients] [-n requests]
+//     [-P pipeline] [-r keyspace] [-d size] [-t set,get,incr,lpush,zadd | --mix set:1,get:9]
+//     [--csv file] [--json file] [-q]
+#[derive(Clone)]
+struct Options {
+    addr: String,
+    password: Option<String>,
+    clients: usize,
+    requests: usize,
+    pipeline: usize,
+    // 0 表示所有请求都用同一个 key
+    keyspace: u64,
+    size: usize,
+    tests: Vec<Test>,
+    csv: Option<String>,
+    json: Option<String>,
+    quiet: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Get,
+    Set,
+    Incr,
+    Lpush,
+    Zadd,
+    Pfadd,
+    Setbit,
+    Geoadd,
+}
+
+/// 一轮测试：单个命令，或者按权重混合的多个命令
+#[derive(Clone)]
+struct Test {
+    name: String,
+    mix: Vec<(Kind, u32)>,
+}
+
+struct Report {
+    name: String,
+    requests: usize,
+    elapsed: Duration,
+    errors: usize,
+    // 第一个错误回复，全部出错时用来提示原因
+    first_error: Option<String>,
+    // 每个请求的延迟，单位微秒，已排序
+    latencies: Vec<u64>,
+}
+
+const PERCENTILES: [f64; 7] = [50.0, 75.0, 90.0, 95.0, 99.0, 99.9, 100.0];
+
+fn parse_kind(name: &str) -> Result<Kind> {
+    match name.to_ascii_lowercase().as_str() {
+        "get" => Ok(Kind::Get),
+        "set" => Ok(Kind::Set),
+        "incr" => Ok(Kind::Incr),
+        "lpush" => Ok(Kind::Lpush),
+        "zadd" => Ok(Kind::Zadd),
+        "pfadd" => Ok(Kind::Pfadd),
+        "setbit" => Ok(Kind::Setbit),
+        "geoadd" => Ok(Kind::Geoadd),
+        _ => Err(format!("unknown test '{}'", name).into()),
+    }
+}
+
+fn parse_options() -> Result<Options> {
+    let mut host = "127.0.0.1".to_string();
+    let mut port = 6379u16;
+    let mut options = Options {
+        addr: String::new(),
+        password: None,
+        clients: 50,
+        requests: 100_000,
+        pipeline: 1,
+        keyspace: 0,
+        size: 3,
+        tests: Vec::new(),
+        csv: None,
+        json: None,
+        quiet: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "-q" {
+            options.quiet = true;
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("missing value for {}", arg))?;
+        match arg.as_str() {
+            "-h" => host = value,
+            "-p" => port = value.parse()?,
+            "-a" => options.password = Some(value),
+            "-c" => options.clients = value.parse()?,
+            "-n" => options.requests = value.parse()?,
+            "-P" => options.pipeline = value.parse()?,
+            "-r" => options.keyspace = value.parse()?,
+            "-d" => options.size = value.parse()?,
+            "-t" => {
+                for name in value.split(',').filter(|name| !name.is_empty()) {
+                    options.tests.push(Test {
+                        name: name.to_ascii_uppercase(),
+                        mix: vec![(parse_kind(name)?, 1)],
+                    });
+                }
+            }
+            // 例如 --mix get:9,set:1
+            "--mix" => {
+                let mut mix = Vec::new();
+                for item in value.split(',') {
+                    let (name, weight) = item.split_once(':').unwrap_or((item, "1"));
+                    mix.push((parse_kind(name)?, weight.parse()?));
+                }
+                options.tests.push(Test {
+                    name: format!("MIX {}", value),
+                    mix,
+                });
+            }
+            "--csv" => options.csv = Some(value),
+            "--json" => options.json = Some(value),
+            _ => return Err(format!("unknown option {}", arg).into()),
+        }
+    }
+    if options.clients == 0 || options.pipeline == 0 {
+        return Err("-c and -P must be greater than 0".into());
+    }
+    if options.tests.is_empty() {
+        for kind in ["set", "get", "incr", "lpush", "zadd"] {
+            options.tests.push(Test {
+                name: kind.to_ascii_uppercase(),
+                mix: vec![(parse_kind(kind)?, 1)],
+            });
+        }
+    }
+    options.addr = format!("{}:{}", host, port);
+    Ok(options)
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let mut reports = Vec::new();
+    for test in &options.tests {
+        match run(&options, test).await {
+            Ok(report) => {
+                print_report(&options, &report);
+                // 每个回复都是错误时结果没有意义，比如服务器不支持这个命令或者需要认证
+                if report.requests > 0 && report.errors == report.requests {
+                    eprintln!("{}: all {} requests failed: {}", test.name, report.requests, report.first_error.as_deref().unwrap_or(""));
+                    std::process::exit(1);
+                }
+                reports.push(report);
+            }
+            Err(err) => {
+                eprintln!("{}: {}", test.name, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(path) = &options.csv {
+        if let Err(err) = std::fs::write(path, to_csv(&reports)) {
+            eprintln!("failed to write {}: {}", path, err);
+        }
+    }
+    if let Some(path) = &options.json {
+        if let Err(err) = std::fs::write(path, to_json(&options, &reports)) {
+            eprintln!("failed to write {}: {}", path, err);
+        }
+    }
+}
+
+async fn connect(options: &Options) -> Result<Client> {
+    let mut client = client::connect(options.addr.as_str()).await?;
+    if let Some(password) = &options.password {
+        client.request(&[b"AUTH", password.as_bytes()]).await?;
+    }
+    Ok(client)
+}
+
+async fn run(options: &Options, test: &Test) -> Result<Report> {
+    // 先把连接都建好，不把建连时间算进结果
+    let mut clients = Vec::with_capacity(options.clients);
+    for _ in 0..options.clients {
+        clients.push(connect(options).await?);
+    }
+
+    let issued = Arc::new(AtomicUsize::new(0));
+    let start = Instant::now();
+    let workers: Vec<_> = clients
+        .into_iter()
+        .map(|client| tokio::spawn(worker(client, options.clone(), test.clone(), issued.clone())))
+        .collect();
+
+    let mut errors = 0;
+    let mut first_error = None;
+    let mut latencies = Vec::with_capacity(options.requests);
+    for worker in workers {
+        let (worker_errors, worker_error, worker_latencies) = worker.await??;
+        errors += worker_errors;
+        first_error = first_error.or(worker_error);
+        latencies.extend(worker_latencies);
+    }
+    let elapsed = start.elapsed();
+    latencies.sort_unstable();
+
+    Ok(Report {
+        name: test.name.clone(),
+        requests: latencies.len(),
+        elapsed,
+        errors,
+        first_error,
+        latencies,
+    })
+}
+
+// 每个连接每次领取 pipeline 个请求，一起发送后依次读取回复
+async fn worker(mut client: Client, options: Options, test: Test, issued: Arc<AtomicUsize>) -> Result<(usize, Option<String>, Vec<u64>)> {
+    let value = Bytes::from(vec![b'x'; options.size]);
+    let total_weight: u32 = test.mix.iter().map(|(_, weight)| weight).sum();
+    let mut errors = 0;
+    let mut first_error = None;
+    let mut latencies = Vec::new();
+
+    loop {
+        let start = issued.fetch_add(options.pipeline, Ordering::Relaxed);
+        if start >= options.requests {
+            break;
+        }
+        let batch = options.pipeline.min(options.requests - start);
+        for _ in 0..batch {
+            let kind = pick(&test.mix, total_weight);
+            client.send(&command(kind, options.keyspace, &value)).await?;
+        }
+        let sent = Instant::now();
+        client.flush().await?;
+        for _ in 0..batch {
+            if let Frame::Error(message) = client.read_reply().await? {
+                errors += 1;
+                first_error.get_or_insert(message);
+            }
+            latencies.push(sent.elapsed().as_micros() as u64);
+        }
+    }
+    Ok((errors, first_error, latencies))
+}
+
+fn pick(mix: &[(Kind, u32)], total_weight: u32) -> Kind {
+    if mix.len() == 1 {
+        return mix[0].0;
+    }
+    let mut n = rand::thread_rng().gen_range(0..total_weight.max(1));
+    for &(kind, weight) in mix {
+        if n < weight {
+            return kind;
+        }
+        n -= weight;
+    }
+    mix[0].0
+}
+
+fn key(prefix: &str, keyspace: u64) -> Bytes {
+    if keyspace == 0 {
+        return Bytes::from(format!("{}:__rand_int__", prefix));
+    }
+    let n = rand::thread_rng().gen_range(0..keyspace);
+    Bytes::from(format!("{}:{:012}", prefix, n))
+}
+
+fn command(kind: Kind, keyspace: u64, value: &Bytes) -> Vec<Bytes> {
+    match kind {
+        Kind::Get => vec![Bytes::from_static(b"GET"), key("key", keyspace)],
+        Kind::Set => vec![Bytes::from_static(b"SET"), key("key", keyspace), value.clone()],
+        Kind::Incr => vec![Bytes::from_static(b"INCR"), key("counter", keyspace)],
+        Kind::Lpush => vec![Bytes::from_static(b"LPUSH"), key("mylist", keyspace), value.clone()],
+        Kind::Zadd => {
+            let score = rand::thread_rng().gen_range(0..1_000_000).to_string();
+            vec![Bytes::from_static(b"ZADD"), Bytes::from_static(b"myzset"), Bytes::from(score), key("element", keyspace)]
+        }
+        Kind::Pfadd => vec![Bytes::from_static(b"PFADD"), Bytes::from_static(b"myhll"), key("element", keyspace)],
+        Kind::Setbit => {
+            let offset = rand::thread_rng().gen_range(0..1_000_000).to_string();
+            vec![Bytes::from_static(b"SETBIT"), key("bitmap", keyspace), Bytes::from(offset), Bytes::from_static(b"1")]
+        }
+        Kind::Geoadd => {
+            let mut rng = rand::thread_rng();
+            let longitude = format!("{:.6}", rng.gen_range(-180.0..180.0));
+            let latitude = format!("{:.6}", rng.gen_range(-85.0..85.0));
+            vec![
+                Bytes::from_static(b"GEOADD"),
+                Bytes::from_static(b"mygeo"),
+                Bytes::from(longitude),
+                Bytes::from(latitude),
+                key("element", keyspace),
+            ]
+        }
+    }
+}
+
+fn percentile(latencies: &[u64], p: f64) -> f64 {
+    if latencies.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * latencies.len() as f64).ceil() as usize;
+    latencies[rank.clamp(1, latencies.len()) - 1] as f64 / 1000.0
+}
+
+impl Report {
+    fn rps(&self) -> f64 {
+        self.requests as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    fn avg(&self) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        self.latencies.iter().sum::<u64>() as f64 / self.latencies.len() as f64 / 1000.0
+    }
+
+    fn min(&self) -> f64 {
+        self.latencies.first().copied().unwrap_or(0) as f64 / 1000.0
+    }
+}
+
+fn print_report(options: &Options, report: &Report) {
+    if options.quiet {
+        println!("{}: {:.2} requests per second, p50={:.3} msec", report.name, report.rps(), percentile(&report.latencies, 50.0));
+        return;
+    }
+    println!("====== {} ======", report.name);
+    println!("  {} requests completed in {:.2} seconds", report.requests, report.elapsed.as_secs_f64());
+    println!("  {} parallel clients", options.clients);
+    println!("  {} bytes payload", options.size);
+    println!("  pipeline {}", options.pipeline);
+    if report.errors > 0 {
+        println!("  {} errors", report.errors);
+    }
+    println!();
+    println!("Latency by percentile distribution:");
+    for p in PERCENTILES {
+        println!("{:>7.3}% <= {:.3} milliseconds", p, percentile(&report.latencies, p));
+    }
+    println!();
+    println!("Summary:");
+    println!("  throughput summary: {:.2} requests per second", report.rps());
+    println!("  latency summary (msec):");
+    println!("          avg       min       p50       p95       p99       max");
+    println!(
+        "    {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}",
+        report.avg(),
+        report.min(),
+        percentile(&report.latencies, 50.0),
+        percentile(&report.latencies, 95.0),
+        percentile(&report.latencies, 99.0),
+        percentile(&report.latencies, 100.0),
+    );
+    println!();
+}
+
+fn to_csv(reports: &[Report]) -> String {
+    let mut out = String::from("test,requests,seconds,rps,errors,avg_ms,min_ms,p50_ms,p95_ms,p99_ms,max_ms\n");
+    for report in reports {
+        let _ = writeln!(
+            out,
+            "\"{}\",{},{:.3},{:.2},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3}",
+            report.name.replace('"', "\"\""),
+            report.requests,
+            report.elapsed.as_secs_f64(),
+            report.rps(),
+            report.errors,
+            report.avg(),
+            report.min(),
+            percentile(&report.latencies, 50.0),
+            percentile(&report.latencies, 95.0),
+            percentile(&report.latencies, 99.0),
+            percentile(&report.latencies, 100.0),
+        );
+    }
+    out
+}
+
+fn to_json(options: &Options, reports: &[Report]) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "{{\"clients\":{},\"pipeline\":{},\"keyspace\":{},\"payload\":{},\"tests\":[",
+        options.clients, options.pipeline, options.keyspace, options.size
+    );
+    for (i, report) in reports.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{{\"test\":\"{}\",\"requests\":{},\"seconds\":{:.3},\"rps\":{:.2},\"errors\":{},\"avg_ms\":{:.3},\"min_ms\":{:.3},\"percentiles_ms\":{{",
+            report.name.replace('\\', "\\\\").replace('"', "\\\""),
+            report.requests,
+            report.elapsed.as_secs_f64(),
+            report.rps(),
+            report.errors,
+            report.avg(),
+            report.min(),
+        );
+        for (j, p) in PERCENTILES.iter().enumerate() {
+            if j > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "\"{}\":{:.3}", p, percentile(&report.latencies, *p));
+        }
+        out.push_str("}}");
+    }
+    out.push_str("]}\n");
+    out
+}
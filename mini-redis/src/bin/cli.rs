@@ -0,0 +1,675 @@
+use bytes::Bytes;
+use mini_redis::client::{self, Client};
+use mini_redis::tls::ClientOptions;
+use mini_redis::{Frame, Result};
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+// cargo run --bin cli -- [-h host] [-p port] [-a password] [--user name] [-3] [--raw|--no-raw]
+//                        [--tls --cacert ca.pem [--cert cert.pem --key key.pem] [--sni name]]
+//                        [--pipe] [command args...]
+struct Options {
+    host: String,
+    port: u16,
+    user: Option<String>,
+    password: Option<String>,
+    tls: Option<ClientOptions>,
+    resp3: bool,
+    // None 表示根据 stdout 是不是终端自动选择
+    raw: Option<bool>,
+    pipe: bool,
+    command: Vec<String>,
+}
+
+fn parse_options() -> Result<Options> {
+    let mut options = Options {
+        host: "127.0.0.1".to_string(),
+        port: 6379,
+        user: None,
+        password: None,
+        tls: None,
+        resp3: false,
+        raw: None,
+        pipe: false,
+        command: Vec::new(),
+    };
+    let (mut tls, mut ca_cert, mut cert, mut key, mut sni) = (false, None, None, None, None);
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for {}", arg));
+        match arg.as_str() {
+            "-h" => options.host = value()?,
+            "-p" => options.port = value()?.parse()?,
+            "-a" => options.password = Some(value()?),
+            "--user" => options.user = Some(value()?),
+            "-3" => options.resp3 = true,
+            "--raw" => options.raw = Some(true),
+            "--no-raw" => options.raw = Some(false),
+            "--pipe" => options.pipe = true,
+            "--tls" => tls = true,
+            "--cacert" => ca_cert = Some(PathBuf::from(value()?)),
+            "--cert" => cert = Some(PathBuf::from(value()?)),
+            "--key" => key = Some(PathBuf::from(value()?)),
+            "--sni" => sni = Some(value()?),
+            _ if arg.starts_with('-') && options.command.is_empty() => {
+                return Err(format!("unknown option {}", arg).into())
+            }
+            _ => {
+                options.command.push(arg);
+                options.command.extend(args.by_ref());
+            }
+        }
+    }
+    if tls {
+        let ca_cert = ca_cert.ok_or("--tls requires --cacert")?;
+        let server_name = sni.unwrap_or_else(|| options.host.clone());
+        options.tls = Some(ClientOptions { ca_cert, server_name, cert, key });
+    }
+    Ok(options)
+}
+
+async fn connect(options: &Options) -> Result<Client> {
+    let addr = format!("{}:{}", options.host, options.port);
+    let mut client = client::connect_with(addr.as_str(), options.tls.as_ref()).await?;
+    if let Some(password) = &options.password {
+        match &options.user {
+            Some(user) => client.request(&[b"AUTH", user.as_bytes(), password.as_bytes()]).await?,
+            None => client.request(&[b"AUTH", password.as_bytes()]).await?,
+        };
+    }
+    if options.resp3 {
+        if let Err(err) = client.request(&[b"HELLO", b"3"]).await {
+            eprintln!("Warning: server does not support RESP3 ({}), using RESP2", err);
+        }
+    }
+    Ok(client)
+}
+
+#[tokio::main]
+async fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    let result = if options.pipe {
+        pipe(&options).await
+    } else if !options.command.is_empty() {
+        let args = options.command.iter().map(|arg| Bytes::from(arg.clone())).collect();
+        run_once(&options, vec![args]).await
+    } else if io::stdin().is_terminal() {
+        repl(&options).await
+    } else {
+        match read_commands() {
+            Ok(commands) => run_once(&options, commands).await,
+            Err(err) => Err(err),
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
+
+fn raw_output(options: &Options) -> bool {
+    options.raw.unwrap_or_else(|| !io::stdout().is_terminal())
+}
+
+// 非交互模式：从 stdin 每行读取一条命令
+fn read_commands() -> Result<Vec<Vec<Bytes>>> {
+    let mut commands = Vec::new();
+    for (lineno, line) in io::stdin().lock().lines().enumerate() {
+        let args = split_args(&line?).map_err(|err| format!("line {}: {}", lineno + 1, err))?;
+        if !args.is_empty() {
+            commands.push(args);
+        }
+    }
+    Ok(commands)
+}
+
+async fn run_once(options: &Options, commands: Vec<Vec<Bytes>>) -> Result<()> {
+    let mut client = connect(options).await?;
+    let raw = raw_output(options);
+    let mut out = io::stdout().lock();
+    for args in commands {
+        let frame = client.call(&args).await?;
+        write!(out, "{}", format_reply(&frame, raw))?;
+    }
+    Ok(())
+}
+
+async fn repl(options: &Options) -> Result<()> {
+    let addr = format!("{}:{}", options.host, options.port);
+    let raw = raw_output(options);
+    let mut client = match connect(options).await {
+        Ok(client) => Some(client),
+        Err(err) => {
+            println!("Could not connect to mini-redis at {}: {}", addr, err);
+            None
+        }
+    };
+    let mut editor = Editor::new(history_path());
+
+    loop {
+        let prompt = match client {
+            Some(_) => format!("{}> ", addr),
+            None => "not connected> ".to_string(),
+        };
+        let line = match tokio::task::block_in_place(|| editor.read_line(&prompt))? {
+            Some(line) => line,
+            None => break,
+        };
+        let args = match split_args(&line) {
+            Ok(args) if args.is_empty() => continue,
+            Ok(args) => args,
+            Err(err) => {
+                println!("{}", err);
+                continue;
+            }
+        };
+        editor.add_history(&line);
+
+        match &args[0].to_ascii_lowercase()[..] {
+            b"quit" | b"exit" => break,
+            b"clear" => {
+                print!("\x1b[H\x1b[2J");
+                io::stdout().flush()?;
+                continue;
+            }
+            _ => {}
+        }
+
+        if client.is_none() {
+            match connect(options).await {
+                Ok(connected) => client = Some(connected),
+                Err(err) => {
+                    println!("Could not connect to mini-redis at {}: {}", addr, err);
+                    continue;
+                }
+            }
+        }
+        match client.as_mut().unwrap().call(&args).await {
+            Ok(frame) => print!("{}", format_reply(&frame, raw)),
+            Err(err) => {
+                println!("Error: {}", err);
+                client = None;
+            }
+        }
+    }
+    editor.save_history();
+    Ok(())
+}
+
+// --pipe：stdin 是 RESP 格式的命令流，分批发送并统计回复
+async fn pipe(options: &Options) -> Result<()> {
+    const BATCH: usize = 1000;
+
+    let mut input = Vec::new();
+    io::stdin().lock().read_to_end(&mut input)?;
+    let mut cursor = io::Cursor::new(&input[..]);
+    let mut commands = Vec::new();
+    while (cursor.position() as usize) < input.len() {
+        let args = match Frame::parse(&mut cursor)? {
+            Frame::Array(items) => items
+                .into_iter()
+                .map(|item| match item {
+                    Frame::Bulk(arg) => Ok(arg),
+                    Frame::Simple(arg) => Ok(Bytes::from(arg)),
+                    Frame::Integer(arg) => Ok(Bytes::from(arg.to_string())),
+                    frame => Err(format!("unexpected argument in --pipe input: {:?}", frame)),
+                })
+                .collect::<std::result::Result<Vec<_>, _>>()?,
+            frame => return Err(format!("unexpected frame in --pipe input: {:?}", frame).into()),
+        };
+        commands.push(args);
+    }
+
+    let mut client = connect(options).await?;
+    let (mut replies, mut errors) = (0, 0);
+    for batch in commands.chunks(BATCH) {
+        for args in batch {
+            client.send(args).await?;
+        }
+        client.flush().await?;
+        for _ in batch {
+            if let Frame::Error(msg) = client.read_reply().await? {
+                eprintln!("{}", msg);
+                errors += 1;
+            }
+            replies += 1;
+        }
+    }
+    println!("All data transferred. errors: {}, replies: {}", errors, replies);
+    if errors > 0 {
+        return Err(format!("{} commands failed", errors).into());
+    }
+    Ok(())
+}
+
+/// 按 redis-cli 的规则拆分参数：空白分隔，支持双引号（带转义）和单引号
+fn split_args(line: &str) -> Result<Vec<Bytes>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(args);
+        }
+        let mut arg = Vec::new();
+        let mut buf = [0; 4];
+        loop {
+            match chars.next() {
+                None => break,
+                Some(c) if c.is_whitespace() => break,
+                Some('"') => {
+                    loop {
+                        match chars.next() {
+                            None => return Err("Invalid argument(s): unbalanced quotes".into()),
+                            Some('"') => break,
+                            Some('\\') => match chars.next() {
+                                Some('n') => arg.push(b'\n'),
+                                Some('r') => arg.push(b'\r'),
+                                Some('t') => arg.push(b'\t'),
+                                Some('b') => arg.push(0x08),
+                                Some('a') => arg.push(0x07),
+                                Some('x') => {
+                                    let hex: String = chars.by_ref().take(2).collect();
+                                    match u8::from_str_radix(&hex, 16) {
+                                        Ok(byte) if hex.len() == 2 => arg.push(byte),
+                                        _ => {
+                                            arg.extend_from_slice(b"x");
+                                            arg.extend_from_slice(hex.as_bytes());
+                                        }
+                                    }
+                                }
+                                Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                                None => return Err("Invalid argument(s): unbalanced quotes".into()),
+                            },
+                            Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                        }
+                    }
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return Err("Invalid argument(s): closing quote must be followed by a space".into());
+                    }
+                }
+                Some('\'') => {
+                    loop {
+                        match chars.next() {
+                            None => return Err("Invalid argument(s): unbalanced quotes".into()),
+                            Some('\'') => break,
+                            Some('\\') if chars.peek() == Some(&'\'') => {
+                                chars.next();
+                                arg.push(b'\'');
+                            }
+                            Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                        }
+                    }
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return Err("Invalid argument(s): closing quote must be followed by a space".into());
+                    }
+                }
+                Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+            }
+        }
+        args.push(Bytes::from(arg));
+    }
+}
+
+fn format_reply(frame: &Frame, raw: bool) -> String {
+    let mut out = String::new();
+    if raw {
+        format_raw(frame, &mut out);
+    } else {
+        format_pretty(frame, "", &mut out);
+    }
+    out
+}
+
+fn format_raw(frame: &Frame, out: &mut String) {
+    match frame {
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+            for item in items {
+                format_raw(item, out);
+            }
+        }
+        Frame::Map(pairs) => {
+            for (key, value) in pairs {
+                format_raw(key, out);
+                format_raw(value, out);
+            }
+        }
+        Frame::Null => out.push('\n'),
+        Frame::Bulk(value) | Frame::Verbatim(_, value) => {
+            out.push_str(&String::from_utf8_lossy(value));
+            out.push('\n');
+        }
+        frame => {
+            out.push_str(&frame.to_string());
+            out.push('\n');
+        }
+    }
+}
+
+// 和 redis-cli 一样：嵌套的数组按序号缩进，字符串带引号显示
+fn format_pretty(frame: &Frame, indent: &str, out: &mut String) {
+    match frame {
+        Frame::Simple(value) => out.push_str(value),
+        Frame::Error(msg) => {
+            out.push_str("(error) ");
+            out.push_str(msg);
+        }
+        Frame::Integer(num) => out.push_str(&format!("(integer) {}", num)),
+        Frame::Bulk(value) => out.push_str(&quote(value)),
+        Frame::Null => out.push_str("(nil)"),
+        Frame::Double(num) => out.push_str(&format!("(double) {}", num)),
+        Frame::Boolean(value) => out.push_str(&format!("({})", value)),
+        Frame::BigNumber(num) => out.push_str(&format!("(big number) {}", num)),
+        Frame::Verbatim(_, value) => {
+            out.push_str(&String::from_utf8_lossy(value));
+            return;
+        }
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+            if items.is_empty() {
+                out.push_str(match frame {
+                    Frame::Set(_) => "(empty set)\n",
+                    _ => "(empty array)\n",
+                });
+                return;
+            }
+            let marker = if let Frame::Set(_) = frame { '~' } else { ')' };
+            let width = items.len().to_string().len();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(indent);
+                }
+                let prefix = format!("{:>width$}{} ", i + 1, marker, width = width);
+                let nested = format!("{}{}", indent, " ".repeat(prefix.len()));
+                out.push_str(&prefix);
+                format_pretty(item, &nested, out);
+            }
+            return;
+        }
+        Frame::Map(pairs) => {
+            if pairs.is_empty() {
+                out.push_str("(empty hash)\n");
+                return;
+            }
+            let width = pairs.len().to_string().len();
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(indent);
+                }
+                let prefix = format!("{:>width$}# ", i + 1, width = width);
+                out.push_str(&prefix);
+                let mut key_out = String::new();
+                format_pretty(key, "", &mut key_out);
+                out.push_str(key_out.trim_end());
+                out.push_str(" => ");
+                let nested = format!("{}{}", indent, " ".repeat(prefix.len() + key_out.trim_end().len() + 4));
+                format_pretty(value, &nested, out);
+            }
+            return;
+        }
+    }
+    out.push('\n');
+}
+
+fn quote(value: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &byte in value {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn history_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("MINI_REDIS_CLI_HISTFILE") {
+        return Some(PathBuf::from(path)).filter(|path| !path.as_os_str().is_empty());
+    }
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".mini_redis_cli_history"))
+}
+
+const HISTORY_MAX: usize = 1000;
+
+/// 简单的行编辑器：左右移动、历史记录、常用的 emacs 快捷键
+struct Editor {
+    history: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl Editor {
+    fn new(path: Option<PathBuf>) -> Editor {
+        let history = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|content| content.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        Editor { history, path }
+    }
+
+    fn add_history(&mut self, line: &str) {
+        // 不要把密码写进历史文件
+        let lower = line.trim_start().to_ascii_lowercase();
+        if lower.starts_with("auth ") || lower.starts_with("acl setuser") {
+            return;
+        }
+        if self.history.last().map(String::as_str) != Some(line) {
+            self.history.push(line.to_string());
+        }
+        if self.history.len() > HISTORY_MAX {
+            self.history.remove(0);
+        }
+    }
+
+    fn save_history(&self) {
+        if let Some(path) = &self.path {
+            let mut content = self.history.join("\n");
+            content.push('\n');
+            let _ = std::fs::write(path, content);
+        }
+    }
+
+    /// 读取一行，None 表示 EOF（空行上按 Ctrl-D）
+    fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let _raw = match RawMode::enable() {
+            Ok(raw) => raw,
+            // 终端不支持 raw 模式时退化成普通的按行读取
+            Err(_) => return self.read_plain(prompt),
+        };
+        let mut stdin = io::stdin().lock();
+        let mut line: Vec<char> = Vec::new();
+        let mut pos = 0;
+        // history.len() 表示正在编辑的新行
+        let mut index = self.history.len();
+        let mut saved = String::new();
+        refresh(prompt, &line, pos)?;
+
+        loop {
+            let c = match read_char(&mut stdin)? {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+            match c {
+                '\r' | '\n' => {
+                    print!("\r\n");
+                    io::stdout().flush()?;
+                    return Ok(Some(line.into_iter().collect()));
+                }
+                // Ctrl-C 放弃当前行
+                '\x03' => {
+                    print!("^C\r\n");
+                    line.clear();
+                    pos = 0;
+                    index = self.history.len();
+                }
+                '\x04' => {
+                    if line.is_empty() {
+                        print!("\r\n");
+                        io::stdout().flush()?;
+                        return Ok(None);
+                    }
+                    if pos < line.len() {
+                        line.remove(pos);
+                    }
+                }
+                '\x7f' | '\x08' if pos > 0 => {
+                    pos -= 1;
+                    line.remove(pos);
+                }
+                '\x01' => pos = 0,
+                '\x05' => pos = line.len(),
+                '\x02' => pos = pos.saturating_sub(1),
+                '\x06' => pos = (pos + 1).min(line.len()),
+                '\x0b' => line.truncate(pos),
+                '\x15' => {
+                    line.drain(..pos);
+                    pos = 0;
+                }
+                '\x17' => {
+                    let mut start = pos;
+                    while start > 0 && line[start - 1] == ' ' {
+                        start -= 1;
+                    }
+                    while start > 0 && line[start - 1] != ' ' {
+                        start -= 1;
+                    }
+                    line.drain(start..pos);
+                    pos = start;
+                }
+                '\x0c' => print!("\x1b[H\x1b[2J"),
+                '\x10' => self.step_history(&mut index, -1, &mut line, &mut pos, &mut saved),
+                '\x0e' => self.step_history(&mut index, 1, &mut line, &mut pos, &mut saved),
+                '\x1b' => {
+                    let seq = (read_char(&mut stdin)?, read_char(&mut stdin)?);
+                    match seq {
+                        (Some('['), Some('A')) => self.step_history(&mut index, -1, &mut line, &mut pos, &mut saved),
+                        (Some('['), Some('B')) => self.step_history(&mut index, 1, &mut line, &mut pos, &mut saved),
+                        (Some('['), Some('C')) => pos = (pos + 1).min(line.len()),
+                        (Some('['), Some('D')) => pos = pos.saturating_sub(1),
+                        (Some('['), Some('H')) | (Some('O'), Some('H')) => pos = 0,
+                        (Some('['), Some('F')) | (Some('O'), Some('F')) => pos = line.len(),
+                        (Some('['), Some('3')) => match read_char(&mut stdin)? {
+                            Some('~') if pos < line.len() => {
+                                line.remove(pos);
+                            }
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+                }
+                c if !c.is_control() => {
+                    line.insert(pos, c);
+                    pos += 1;
+                }
+                _ => {}
+            }
+            refresh(prompt, &line, pos)?;
+        }
+    }
+
+    fn step_history(&self, index: &mut usize, step: isize, line: &mut Vec<char>, pos: &mut usize, saved: &mut String) {
+        let next = match index.checked_add_signed(step) {
+            Some(next) if next <= self.history.len() => next,
+            _ => return,
+        };
+        if *index == self.history.len() {
+            *saved = line.iter().collect();
+        }
+        *index = next;
+        let text = self.history.get(next).unwrap_or(saved);
+        *line = text.chars().collect();
+        *pos = line.len();
+    }
+
+    fn read_plain(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+}
+
+fn refresh(prompt: &str, line: &[char], pos: usize) -> io::Result<()> {
+    let text: String = line.iter().collect();
+    let column = prompt.chars().count() + pos;
+    let mut out = io::stdout().lock();
+    write!(out, "\r{}{}\x1b[0K\r", prompt, text)?;
+    if column > 0 {
+        write!(out, "\x1b[{}C", column)?;
+    }
+    out.flush()
+}
+
+// 按 UTF-8 读取一个字符
+fn read_char(stdin: &mut impl Read) -> io::Result<Option<char>> {
+    let mut buf = [0; 4];
+    if stdin.read(&mut buf[..1])? == 0 {
+        return Ok(None);
+    }
+    let len = match buf[0] {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        _ => 4,
+    };
+    stdin.read_exact(&mut buf[1..len])?;
+    Ok(std::str::from_utf8(&buf[..len]).ok().and_then(|s| s.chars().next()))
+}
+
+/// 进入 raw 模式，drop 时恢复原来的终端设置
+struct RawMode {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl RawMode {
+    #[cfg(unix)]
+    fn enable() -> io::Result<RawMode> {
+        unsafe {
+            let mut original: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+            raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+            raw.c_cflag |= libc::CS8;
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { original })
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn enable() -> io::Result<RawMode> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
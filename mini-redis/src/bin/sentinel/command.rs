@@ -0,0 +1,230 @@
+use bytes::Bytes;
+use mini_redis::Frame;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::monitor::{self, Addr, Master, HELLO_PERIOD};
+use crate::Sentinel;
+
+// 这么久没有收到 hello 的 sentinel 不计入 CKQUORUM 的可用数量
+const SENTINEL_VALIDITY: u32 = 5;
+
+pub fn execute(sentinel: &Arc<Sentinel>, args: &[Bytes]) -> Frame {
+    let Some(name) = args.first() else { return Frame::error("ERR empty command") };
+    match &name.to_ascii_lowercase()[..] {
+        b"ping" if args.len() == 1 => Frame::Simple("PONG".to_string()),
+        b"info" => info(sentinel),
+        b"role" if args.len() == 1 => role(sentinel),
+        b"sentinel" if args.len() >= 2 => sentinel_command(sentinel, args),
+        b"ping" | b"role" | b"sentinel" => Frame::error(format!(
+            "ERR wrong number of arguments for '{}' command",
+            String::from_utf8_lossy(name).to_ascii_lowercase()
+        )),
+        _ => Frame::error(format!("ERR unknown command '{}'", String::from_utf8_lossy(name))),
+    }
+}
+
+fn no_such_master() -> Frame {
+    Frame::error("ERR No such master with that name")
+}
+
+fn elapsed_ms(at: Instant) -> String {
+    at.elapsed().as_millis().to_string()
+}
+
+// 和 Redis 一样回复成 [field, value, field, value, ...]
+fn fields(pairs: Vec<(&str, String)>) -> Frame {
+    Frame::Array(pairs.into_iter().flat_map(|(name, value)| [Frame::bulk(name.to_string()), Frame::bulk(value)]).collect())
+}
+
+fn master_info(master: &Master) -> Frame {
+    let mut flags = "master".to_string();
+    if master.instance.sdown {
+        flags.push_str(",s_down");
+    }
+    if master.odown {
+        flags.push_str(",o_down");
+    }
+    if master.failover.is_some() {
+        flags.push_str(",failover_in_progress");
+    }
+    fields(vec![
+        ("name", master.config.name.clone()),
+        ("ip", master.addr.host.clone()),
+        ("port", master.addr.port.to_string()),
+        ("flags", flags),
+        ("last-ok-ping-reply", elapsed_ms(master.instance.last_ok)),
+        ("num-slaves", master.replicas.len().to_string()),
+        ("num-other-sentinels", master.sentinels.len().to_string()),
+        ("quorum", master.config.quorum.to_string()),
+        ("config-epoch", master.config_epoch.to_string()),
+        ("down-after-milliseconds", master.config.down_after.as_millis().to_string()),
+        ("failover-timeout", master.config.failover_timeout.as_millis().to_string()),
+    ])
+}
+
+fn replicas(master: &Master) -> Frame {
+    Frame::Array(
+        master
+            .replicas
+            .iter()
+            .map(|(addr, instance)| {
+                let mut flags = "slave".to_string();
+                if instance.sdown {
+                    flags.push_str(",s_down");
+                }
+                let info = instance.info.as_ref();
+                let primary = info.and_then(|info| info.master.clone());
+                let link = if info.is_some_and(|info| info.link_up) { "ok" } else { "err" };
+                fields(vec![
+                    ("name", addr.to_string()),
+                    ("ip", addr.host.clone()),
+                    ("port", addr.port.to_string()),
+                    ("flags", flags),
+                    ("last-ok-ping-reply", elapsed_ms(instance.last_ok)),
+                    ("master-host", primary.as_ref().map(|addr| addr.host.clone()).unwrap_or_default()),
+                    ("master-port", primary.as_ref().map(|addr| addr.port.to_string()).unwrap_or_default()),
+                    ("master-link-status", link.to_string()),
+                    ("slave-repl-offset", instance.offset().to_string()),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn sentinels(master: &Master) -> Frame {
+    Frame::Array(
+        master
+            .sentinels
+            .iter()
+            .map(|(runid, peer)| {
+                fields(vec![
+                    ("name", peer.addr.to_string()),
+                    ("ip", peer.addr.host.clone()),
+                    ("port", peer.addr.port.to_string()),
+                    ("runid", runid.clone()),
+                    ("flags", "sentinel".to_string()),
+                    ("last-hello-message", elapsed_ms(peer.last_hello)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+// SENTINEL MASTERS | MASTER name | REPLICAS name | SENTINELS name | GET-MASTER-ADDR-BY-NAME name
+//   | IS-MASTER-DOWN-BY-ADDR ip port epoch runid | FAILOVER name | CKQUORUM name | MYID
+fn sentinel_command(sentinel: &Arc<Sentinel>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    let name = args.get(2).map(|name| String::from_utf8_lossy(name).into_owned()).unwrap_or_default();
+    let mut state = sentinel.state.lock().unwrap();
+    match (&sub[..], args.len()) {
+        (b"masters", 2) => Frame::Array(state.masters.values().map(master_info).collect()),
+        (b"master", 3) => state.masters.get(&name).map_or_else(no_such_master, master_info),
+        (b"replicas" | b"slaves", 3) => state.masters.get(&name).map_or_else(no_such_master, replicas),
+        (b"sentinels", 3) => state.masters.get(&name).map_or_else(no_such_master, sentinels),
+        (b"get-master-addr-by-name", 3) => match state.masters.get(&name) {
+            Some(master) => Frame::Array(vec![Frame::bulk(master.addr.host.clone()), Frame::bulk(master.addr.port.to_string())]),
+            None => Frame::Null,
+        },
+        (b"is-master-down-by-addr", 6) => {
+            let host = String::from_utf8_lossy(&args[2]).into_owned();
+            let port = std::str::from_utf8(&args[3]).ok().and_then(|port| port.parse::<u16>().ok());
+            let epoch = std::str::from_utf8(&args[4]).ok().and_then(|epoch| epoch.parse::<u64>().ok());
+            let (Some(port), Some(epoch)) = (port, epoch) else {
+                return Frame::error("ERR value is not an integer or out of range");
+            };
+            // runid 是 * 时只询问下线状态，否则同时请求在这个纪元投票给它
+            let runid = String::from_utf8_lossy(&args[5]).into_owned();
+            if runid != "*" {
+                state.current_epoch = state.current_epoch.max(epoch);
+            }
+            let addr = Addr { host, port };
+            let Some(master) = state.masters.values_mut().find(|master| master.addr == addr) else {
+                return Frame::Array(vec![Frame::Integer(0), Frame::bulk("*"), Frame::Integer(0)]);
+            };
+            let down = master.instance.sdown as i64;
+            let (leader_epoch, leader) = match runid.as_str() {
+                "*" => (0, "*".to_string()),
+                runid => master.vote(epoch, runid, &sentinel.myid),
+            };
+            Frame::Array(vec![Frame::Integer(down), Frame::bulk(leader), Frame::Integer(leader_epoch as i64)])
+        }
+        // 不等其它 sentinel 同意，立即故障转移
+        (b"failover", 3) => {
+            let Some(master) = state.masters.get_mut(&name) else { return no_such_master() };
+            if master.failover.is_some() {
+                return Frame::error("INPROG Failover already in progress");
+            }
+            if monitor::select_replica(master).is_none() {
+                return Frame::error("NOGOODSLAVE No suitable replica to promote");
+            }
+            master.failover = Some(Instant::now());
+            tokio::spawn(monitor::run_failover(sentinel.clone(), name, Vec::new(), true));
+            Frame::ok()
+        }
+        (b"ckquorum", 3) => {
+            let Some(master) = state.masters.get(&name) else { return no_such_master() };
+            let usable = 1 + master
+                .sentinels
+                .values()
+                .filter(|peer| peer.last_hello.elapsed() < HELLO_PERIOD * SENTINEL_VALIDITY)
+                .count();
+            let (_, needed) = master.quorum_needed();
+            if usable < master.config.quorum {
+                Frame::error(format!(
+                    "NOQUORUM {} usable Sentinels. Not enough available Sentinels to reach the specified quorum for this master",
+                    usable
+                ))
+            } else if usable < needed {
+                Frame::error(format!(
+                    "NOQUORUM {} usable Sentinels. Not enough available Sentinels to reach the majority and authorize a failover",
+                    usable
+                ))
+            } else {
+                Frame::Simple(format!("OK {} usable Sentinels. Quorum and failover authorization can be reached", usable))
+            }
+        }
+        (b"myid", 2) => Frame::bulk(sentinel.myid.clone()),
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            String::from_utf8_lossy(&args[1])
+        )),
+    }
+}
+
+fn info(sentinel: &Sentinel) -> Frame {
+    let state = sentinel.state.lock().unwrap();
+    let mut out = String::new();
+    let _ = writeln!(out, "# Sentinel\r");
+    let _ = writeln!(out, "sentinel_masters:{}\r", state.masters.len());
+    let _ = writeln!(out, "sentinel_current_epoch:{}\r", state.current_epoch);
+    for (i, master) in state.masters.values().enumerate() {
+        let status = if master.odown {
+            "odown"
+        } else if master.instance.sdown {
+            "sdown"
+        } else {
+            "ok"
+        };
+        let _ = writeln!(
+            out,
+            "master{}:name={},status={},address={},slaves={},sentinels={}\r",
+            i,
+            master.config.name,
+            status,
+            master.addr,
+            master.replicas.len(),
+            master.sentinels.len() + 1
+        );
+    }
+    Frame::bulk(out)
+}
+
+fn role(sentinel: &Sentinel) -> Frame {
+    let state = sentinel.state.lock().unwrap();
+    Frame::Array(vec![
+        Frame::bulk("sentinel"),
+        Frame::Array(state.masters.keys().map(|name| Frame::bulk(name.clone())).collect()),
+    ])
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+/// 一个被监控的主节点，对应配置里的 `sentinel monitor <name> <host> <port> <quorum>` 和它后面的选项
+#[derive(Debug, Clone)]
+pub struct MasterConfig {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+    // 至少这么多个 sentinel 认为主节点下线才开始故障转移
+    pub quorum: usize,
+    // 多久没有收到有效回复就认为实例主观下线
+    pub down_after: Duration,
+    // 一次故障转移最多持续多久，两次尝试之间至少间隔两倍的时间
+    pub failover_timeout: Duration,
+    pub auth_user: Option<String>,
+    pub auth_pass: Option<String>,
+}
+
+/// 启动参数，配置文件每行 `name value`，也可以用 `--name value` 覆盖，例如
+/// `--sentinel "monitor mymaster 127.0.0.1 6379 2"`
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind: String,
+    pub port: u16,
+    // 告诉其它 sentinel 的地址，默认和 bind 相同
+    pub announce_ip: Option<String>,
+    // tracing 的过滤规则
+    pub loglevel: String,
+    pub masters: Vec<MasterConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "127.0.0.1".to_string(),
+            port: 26379,
+            announce_ip: None,
+            loglevel: "info".to_string(),
+            masters: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> crate::Result<Config> {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1).peekable();
+
+        if let Some(path) = args.next_if(|arg| !arg.starts_with("--")) {
+            let content = std::fs::read_to_string(&path)?;
+            for (lineno, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                config
+                    .set(name, value.trim())
+                    .map_err(|e| format!("{}:{}: {}", path, lineno + 1, e))?;
+            }
+        }
+
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => return Err(format!("unexpected argument '{}'", arg).into()),
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for '--{}'", name))?;
+            config.set(name, &value)?;
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        match name.to_ascii_lowercase().as_str() {
+            "bind" => self.bind = value.to_string(),
+            "port" => self.port = value.parse()?,
+            "loglevel" => self.loglevel = value.to_string(),
+            "sentinel" => self.set_sentinel(value)?,
+            _ => return Err(format!("unknown config option '{}'", name).into()),
+        }
+        Ok(())
+    }
+
+    // sentinel monitor <name> <host> <port> <quorum> | sentinel <option> <name> <value> | sentinel announce-ip <ip>
+    fn set_sentinel(&mut self, value: &str) -> crate::Result<()> {
+        let parts: Vec<&str> = value.split_whitespace().collect();
+        let option = parts.first().map(|option| option.to_ascii_lowercase()).unwrap_or_default();
+        match (option.as_str(), &parts[1..]) {
+            ("monitor", [name, host, port, quorum]) => {
+                if self.masters.iter().any(|master| master.name == *name) {
+                    return Err(format!("duplicated master name '{}'", name).into());
+                }
+                let quorum: usize = quorum.parse()?;
+                if quorum == 0 {
+                    return Err("quorum must be at least 1".into());
+                }
+                self.masters.push(MasterConfig {
+                    name: name.to_string(),
+                    host: host.to_string(),
+                    port: port.parse()?,
+                    quorum,
+                    down_after: Duration::from_secs(30),
+                    failover_timeout: Duration::from_secs(180),
+                    auth_user: None,
+                    auth_pass: None,
+                });
+            }
+            ("announce-ip", [ip]) => self.announce_ip = Some(ip.to_string()),
+            (option, [name, value]) => {
+                let master = self
+                    .masters
+                    .iter_mut()
+                    .find(|master| master.name == *name)
+                    .ok_or_else(|| format!("no such master '{}', add it with 'sentinel monitor' first", name))?;
+                match option {
+                    "down-after-milliseconds" => master.down_after = Duration::from_millis(value.parse()?),
+                    "failover-timeout" => master.failover_timeout = Duration::from_millis(value.parse()?),
+                    "auth-user" => master.auth_user = Some(value.to_string()),
+                    "auth-pass" => master.auth_pass = Some(value.to_string()),
+                    _ => return Err(format!("unknown sentinel option '{}'", option).into()),
+                }
+            }
+            _ => return Err(format!("invalid sentinel option '{}'", value).into()),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+mod command;
+mod config;
+mod monitor;
+
+use bytes::Bytes;
+use mini_redis::{Connection, Frame};
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+use tracing_subscriber::EnvFilter;
+
+use config::Config;
+use monitor::{Addr, Master};
+
+pub use mini_redis::{Error, Result};
+
+// cargo run --bin sentinel -- [sentinel.conf] [--port 26379] [--sentinel "monitor mymaster 127.0.0.1 6379 2"]
+//     [--sentinel "down-after-milliseconds mymaster 5000"] [--sentinel "failover-timeout mymaster 60000"]
+//
+// 本地试验：启动一个主节点和两个 `--replicaof` 它的副本，再用不同的端口启动三个 sentinel，quorum 设为 2。
+// 杀掉主节点后，过了 down-after-milliseconds 再加上选举的几秒，SENTINEL GET-MASTER-ADDR-BY-NAME
+// 就会返回被提升的副本，另一个副本也会改为复制它
+
+/// 所有任务共享的 sentinel 状态
+pub struct Sentinel {
+    pub config: Config,
+    // 其它 sentinel 用它区分彼此，每次启动都不同
+    pub myid: String,
+    // 在 hello 消息里告诉其它 sentinel 的地址
+    pub addr: Addr,
+    pub state: Mutex<State>,
+}
+
+pub struct State {
+    // 选举的纪元，取自己和其它 sentinel 见过的最大值
+    pub current_epoch: u64,
+    pub masters: BTreeMap<String, Master>,
+}
+
+impl Sentinel {
+    fn new(config: Config) -> Sentinel {
+        let mut rng = rand::thread_rng();
+        let myid = (0..40).map(|_| std::char::from_digit(rng.gen_range(0..16), 16).unwrap()).collect();
+        let host = config.announce_ip.clone().unwrap_or_else(|| config.bind.clone());
+        let addr = Addr { host, port: config.port };
+        let masters = config.masters.iter().map(|master| (master.name.clone(), Master::new(master.clone()))).collect();
+        Sentinel { config, myid, addr, state: Mutex::new(State { current_epoch: 0, masters }) }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::from_args()?;
+    tracing_subscriber::fmt().with_env_filter(EnvFilter::try_new(&config.loglevel)?).try_init()?;
+    if config.masters.is_empty() {
+        return Err("nothing to monitor, add 'sentinel monitor <name> <host> <port> <quorum>'".into());
+    }
+    let listener = TcpListener::bind((config.bind.as_str(), config.port)).await?;
+    let sentinel = Arc::new(Sentinel::new(config));
+    info!(port = sentinel.config.port, myid = %sentinel.myid, "sentinel ready");
+    for master in &sentinel.config.masters {
+        info!(master = %master.name, addr = %format!("{}:{}", master.host, master.port), quorum = master.quorum, "+monitor");
+        tokio::spawn(monitor::monitor(sentinel.clone(), master.name.clone()));
+    }
+
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        socket.set_nodelay(true)?;
+        let sentinel = sentinel.clone();
+        tokio::spawn(async move {
+            if let Err(err) = serve(socket, &sentinel).await {
+                debug!(peer = %addr, %err, "connection error");
+            }
+        });
+    }
+}
+
+async fn serve(socket: TcpStream, sentinel: &Arc<Sentinel>) -> Result<()> {
+    let mut connect = Connection::new(socket);
+    while let Some(frame) = connect.read_frame().await? {
+        let response = match parse_args(frame) {
+            Some(args) => command::execute(sentinel, &args),
+            None => Frame::error("ERR Protocol error: expected array of bulk strings"),
+        };
+        connect.write_frame(&response).await?;
+    }
+    Ok(())
+}
+
+fn parse_args(frame: Frame) -> Option<Vec<Bytes>> {
+    match frame {
+        Frame::Array(parts) => parts
+            .into_iter()
+            .map(|part| match part {
+                Frame::Bulk(data) => Some(data),
+                Frame::Simple(s) => Some(Bytes::from(s)),
+                _ => None,
+            })
+            .collect(),
+        _ => None,
+    }
+}
@@ -0,0 +1,656 @@
+use bytes::Bytes;
+use mini_redis::handle::Options;
+use mini_redis::{client, Frame, Handle};
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::config::MasterConfig;
+use crate::Sentinel;
+
+// 每秒 PING 一次所有实例和其它 sentinel，同时用 INFO 刷新角色和复制偏移量
+const PERIOD: Duration = Duration::from_secs(1);
+// 每隔这么久在实例的 hello 频道上广播一次自己和主节点的配置
+pub const HELLO_PERIOD: Duration = Duration::from_secs(2);
+pub const HELLO_CHANNEL: &str = "__sentinel__:hello";
+// 单个请求的超时，比 PERIOD 短，免得检查越积越多
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(800);
+// 其它 sentinel 报告的主节点下线状态在这段时间内有效
+const VOTE_VALIDITY: Duration = Duration::from_secs(5);
+// 这么久没有回复或者没有刷新 INFO 的副本不参与选主
+const REPLICA_VALIDITY: Duration = Duration::from_secs(5);
+// 纠正同一个实例的复制配置至少间隔这么久，给它留出完成同步的时间
+const RECONF_INTERVAL: Duration = Duration::from_secs(10);
+// 实例报告的角色至少稳定这么久才去纠正，给 hello 消息留出传播新配置的时间
+const ROLE_SETTLE: Duration = Duration::from_secs(8);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Addr {
+    pub host: String,
+    pub port: u16,
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.host, self.port)
+    }
+}
+
+/// INFO replication 里关心的内容
+#[derive(Debug, Clone)]
+pub struct Info {
+    pub is_master: bool,
+    // 副本复制的主节点和连接状态
+    pub master: Option<Addr>,
+    pub link_up: bool,
+    pub offset: u64,
+    // 主节点报告的副本
+    pub replicas: Vec<Addr>,
+}
+
+/// 一个被监控的 Redis 实例（主节点或副本）
+pub struct Instance {
+    // 最近一次收到有效 PING 回复的时间
+    pub last_ok: Instant,
+    // 主观下线：超过 down-after-milliseconds 没有有效回复
+    pub sdown: bool,
+    pub info: Option<Info>,
+    pub info_at: Option<Instant>,
+    // INFO 里的角色或者复制的主节点上次变化的时间
+    role_at: Instant,
+    // 上次纠正它的复制配置的时间
+    reconf_at: Option<Instant>,
+}
+
+impl Instance {
+    fn new() -> Instance {
+        Instance { last_ok: Instant::now(), sdown: false, info: None, info_at: None, role_at: Instant::now(), reconf_at: None }
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.info.as_ref().map_or(0, |info| info.offset)
+    }
+}
+
+/// 通过 hello 消息发现的其它 sentinel
+pub struct Peer {
+    pub addr: Addr,
+    pub last_hello: Instant,
+    // 它最近一次回复主节点是否下线，以及回复的时间
+    down: bool,
+    down_at: Option<Instant>,
+}
+
+/// 一个被监控的主节点以及它的副本和负责它的其它 sentinel
+pub struct Master {
+    pub config: MasterConfig,
+    // 当前的主节点地址，故障转移后会变
+    pub addr: Addr,
+    pub instance: Instance,
+    pub replicas: BTreeMap<Addr, Instance>,
+    // 按 runid 记录
+    pub sentinels: BTreeMap<String, Peer>,
+    // 客观下线：至少 quorum 个 sentinel 认为主节点下线
+    pub odown: bool,
+    // 当前配置是哪个纪元的故障转移产生的，hello 消息里纪元更大的配置会覆盖它
+    pub config_epoch: u64,
+    // 这个 sentinel 在哪个纪元投票给了谁，每个纪元只投一次
+    pub leader: Option<(u64, String)>,
+    // 正在进行的故障转移开始的时间
+    pub failover: Option<Instant>,
+    // 上次尝试故障转移（或者投票给别人）的时间，两倍 failover-timeout 之内不再尝试
+    failover_attempt: Option<Instant>,
+}
+
+impl Master {
+    pub fn new(config: MasterConfig) -> Master {
+        let addr = Addr { host: config.host.clone(), port: config.port };
+        Master {
+            config,
+            addr,
+            instance: Instance::new(),
+            replicas: BTreeMap::new(),
+            sentinels: BTreeMap::new(),
+            odown: false,
+            config_epoch: 0,
+            leader: None,
+            failover: None,
+            failover_attempt: None,
+        }
+    }
+
+    fn instance_mut(&mut self, addr: &Addr) -> Option<&mut Instance> {
+        if *addr == self.addr {
+            Some(&mut self.instance)
+        } else {
+            self.replicas.get_mut(addr)
+        }
+    }
+
+    /// 收到其它 sentinel 的投票请求时调用，返回这个纪元投给了谁
+    pub fn vote(&mut self, epoch: u64, runid: &str, myid: &str) -> (u64, String) {
+        if self.leader.as_ref().is_none_or(|(voted, _)| *voted < epoch) {
+            self.leader = Some((epoch, runid.to_string()));
+            info!(master = %self.config.name, epoch, leader = runid, "+vote-for-leader");
+            // 别人在做故障转移，自己先不要尝试
+            if runid != myid {
+                self.failover_attempt = Some(Instant::now());
+            }
+        }
+        self.leader.clone().unwrap_or_default()
+    }
+
+    /// 可以参与投票的 sentinel 数（包括自己）和达成授权需要的票数
+    pub fn quorum_needed(&self) -> (usize, usize) {
+        let voters = self.sentinels.len() + 1;
+        (voters, (voters / 2 + 1).max(self.config.quorum))
+    }
+
+    fn switch(&mut self, to: Addr, epoch: u64) {
+        let from = std::mem::replace(&mut self.addr, to.clone());
+        info!(master = %self.config.name, %from, %to, epoch, "+switch-master");
+        // 原来的主节点恢复后要降为新主节点的副本
+        self.replicas.remove(&to);
+        self.replicas.insert(from, Instance::new());
+        self.instance = Instance::new();
+        self.config_epoch = epoch;
+        self.odown = false;
+    }
+
+    fn options(&self, addr: &Addr) -> Options {
+        let mut options = Options::new(addr.to_string());
+        options.timeout = REQUEST_TIMEOUT;
+        options.connect_retries = 0;
+        options.username = self.config.auth_user.clone();
+        options.password = self.config.auth_pass.clone();
+        options
+    }
+}
+
+// 其它 sentinel 不需要认证
+fn peer_options(addr: &Addr) -> Options {
+    let mut options = Options::new(addr.to_string());
+    options.timeout = REQUEST_TIMEOUT;
+    options.connect_retries = 0;
+    options
+}
+
+fn args(parts: &[&str]) -> Vec<Bytes> {
+    parts.iter().map(|part| Bytes::copy_from_slice(part.as_bytes())).collect()
+}
+
+/// 每个主节点一个监控任务：维护每个实例的检查任务，判断下线状态并在需要时发起故障转移
+pub async fn monitor(sentinel: Arc<Sentinel>, name: String) {
+    let mut watchers: HashMap<Addr, JoinHandle<()>> = HashMap::new();
+    let mut peers: HashMap<String, (Addr, Handle)> = HashMap::new();
+    let mut interval = tokio::time::interval(PERIOD);
+    loop {
+        interval.tick().await;
+        let (instances, sentinels, master_addr, master_down) = {
+            let mut state = sentinel.state.lock().unwrap();
+            let Some(master) = state.masters.get_mut(&name) else { return };
+            let down_after = master.config.down_after;
+            let name = &master.config.name;
+            let master_addr = master.addr.clone();
+            for (addr, instance) in std::iter::once((&master_addr, &mut master.instance)).chain(master.replicas.iter_mut()) {
+                // 主节点长时间报告自己是副本也算下线，否则没有人会去修复它
+                let demoted = *addr == master_addr
+                    && instance.info.as_ref().is_some_and(|info| !info.is_master)
+                    && instance.role_at.elapsed() > down_after;
+                let sdown = instance.last_ok.elapsed() > down_after || demoted;
+                if sdown != instance.sdown {
+                    instance.sdown = sdown;
+                    if sdown {
+                        warn!(master = %name, instance = %addr, "+sdown");
+                    } else {
+                        info!(master = %name, instance = %addr, "-sdown");
+                    }
+                }
+            }
+            let instances: Vec<Addr> = std::iter::once(master_addr.clone()).chain(master.replicas.keys().cloned()).collect();
+            let sentinels: Vec<(String, Addr)> =
+                master.sentinels.iter().map(|(runid, peer)| (runid.clone(), peer.addr.clone())).collect();
+            (instances, sentinels, master_addr, master.instance.sdown)
+        };
+
+        // 实例集合变化时启停对应的检查任务
+        watchers.retain(|addr, task| {
+            let keep = instances.contains(addr);
+            if !keep {
+                task.abort();
+            }
+            keep
+        });
+        for addr in instances {
+            watchers
+                .entry(addr.clone())
+                .or_insert_with(|| tokio::spawn(watch(sentinel.clone(), name.clone(), addr)));
+        }
+        peers.retain(|runid, _| sentinels.iter().any(|(id, _)| id == runid));
+        for (runid, addr) in sentinels {
+            if peers.get(&runid).is_none_or(|(known, _)| *known != addr) {
+                peers.insert(runid, (addr.clone(), Handle::new(peer_options(&addr))));
+            }
+        }
+
+        if master_down {
+            // 询问其它 sentinel 是否也认为主节点下线
+            for (runid, (_, handle)) in &peers {
+                let request = args(&[
+                    "SENTINEL",
+                    "IS-MASTER-DOWN-BY-ADDR",
+                    &master_addr.host,
+                    &master_addr.port.to_string(),
+                    "0",
+                    "*",
+                ]);
+                tokio::spawn(ask_down(sentinel.clone(), name.clone(), runid.clone(), handle.clone(), request));
+            }
+        }
+
+        let start = {
+            let mut state = sentinel.state.lock().unwrap();
+            let Some(master) = state.masters.get_mut(&name) else { return };
+            let votes = 1 + master
+                .sentinels
+                .values()
+                .filter(|peer| peer.down && peer.down_at.is_some_and(|at| at.elapsed() < VOTE_VALIDITY))
+                .count();
+            let odown = master.instance.sdown && votes >= master.config.quorum;
+            if odown != master.odown {
+                master.odown = odown;
+                if odown {
+                    warn!(master = %name, votes, quorum = master.config.quorum, "+odown");
+                } else {
+                    info!(master = %name, "-odown");
+                }
+            }
+            let retry = master.config.failover_timeout * 2;
+            let can_start = master.failover.is_none()
+                && master.failover_attempt.is_none_or(|at| at.elapsed() > retry);
+            if odown && can_start {
+                master.failover = Some(Instant::now());
+                master.failover_attempt = Some(Instant::now());
+                true
+            } else {
+                false
+            }
+        };
+        if start {
+            let handles = peers.values().map(|(_, handle)| handle.clone()).collect();
+            tokio::spawn(run_failover(sentinel.clone(), name.clone(), handles, false));
+        }
+    }
+}
+
+async fn ask_down(sentinel: Arc<Sentinel>, name: String, runid: String, handle: Handle, request: Vec<Bytes>) {
+    let reply: Frame = match handle.call(request).await {
+        Ok(reply) => reply,
+        Err(err) => {
+            debug!(sentinel = %runid, %err, "is-master-down-by-addr failed");
+            return;
+        }
+    };
+    let down = matches!(&reply, Frame::Array(items) if matches!(items.first(), Some(Frame::Integer(1))));
+    let mut state = sentinel.state.lock().unwrap();
+    if let Some(peer) = state.masters.get_mut(&name).and_then(|master| master.sentinels.get_mut(&runid)) {
+        peer.down = down;
+        peer.down_at = Some(Instant::now());
+    }
+}
+
+/// 一个实例的检查任务：每秒 PING 和 INFO，定期广播 hello，同时订阅它的 hello 频道
+async fn watch(sentinel: Arc<Sentinel>, name: String, addr: Addr) {
+    let options = {
+        let state = sentinel.state.lock().unwrap();
+        match state.masters.get(&name) {
+            Some(master) => master.options(&addr),
+            None => return,
+        }
+    };
+    let handle = Handle::new(options.clone());
+    tokio::select! {
+        _ = check(&sentinel, &name, &addr, &handle) => {}
+        _ = listen_hello(&sentinel, &options) => {}
+    }
+}
+
+async fn check(sentinel: &Sentinel, name: &str, addr: &Addr, handle: &Handle) {
+    let mut interval = tokio::time::interval(PERIOD);
+    let mut last_hello: Option<Instant> = None;
+    loop {
+        interval.tick().await;
+        // 正在加载数据或者和主节点断开的副本也算活着
+        let alive = match handle.call::<Frame>(args(&["PING"])).await {
+            Ok(_) => true,
+            Err(err) => err.to_string().starts_with("LOADING") || err.to_string().starts_with("MASTERDOWN"),
+        };
+        if alive {
+            update(sentinel, name, |master| {
+                if let Some(instance) = master.instance_mut(addr) {
+                    instance.last_ok = Instant::now();
+                }
+            });
+        }
+        if let Ok(text) = handle.info("replication").await {
+            let info = parse_info(&text);
+            let reconf = update(sentinel, name, |master| refresh(master, addr, info)).flatten();
+            if let Some(to) = reconf {
+                match handle.replicaof(&to.host, to.port).await {
+                    Ok(()) => info!(master = %name, instance = %addr, primary = %to, "+fix-slave-config"),
+                    Err(err) => warn!(master = %name, instance = %addr, %err, "failed to reconfigure replica"),
+                }
+            }
+        }
+        if last_hello.is_none_or(|at| at.elapsed() >= HELLO_PERIOD) {
+            last_hello = Some(Instant::now());
+            let hello = hello(sentinel, name);
+            if let Some(hello) = hello {
+                let _ = handle.publish(HELLO_CHANNEL, Bytes::from(hello)).await;
+            }
+        }
+    }
+}
+
+fn update<R>(sentinel: &Sentinel, name: &str, f: impl FnOnce(&mut Master) -> R) -> Option<R> {
+    let mut state = sentinel.state.lock().unwrap();
+    state.masters.get_mut(name).map(f)
+}
+
+/// 记录 INFO 的结果，发现新的副本；实例的复制配置和当前主节点不一致时返回应该复制的主节点
+fn refresh(master: &mut Master, addr: &Addr, info: Info) -> Option<Addr> {
+    let now = Instant::now();
+    {
+        let instance = master.instance_mut(addr)?;
+        if instance.info.as_ref().is_none_or(|old| old.is_master != info.is_master || old.master != info.master) {
+            instance.role_at = now;
+        }
+    }
+    if *addr == master.addr {
+        for replica in &info.replicas {
+            if !master.replicas.contains_key(replica) && *replica != master.addr {
+                info!(master = %master.config.name, replica = %replica, "+slave");
+                master.replicas.insert(replica.clone(), Instance::new());
+            }
+        }
+        master.instance.info = Some(info);
+        master.instance.info_at = Some(now);
+        return None;
+    }
+    // 只有主节点正常、也没有在做故障转移时才去纠正副本，否则可能和正在进行的切换冲突
+    let primary_ok = !master.instance.sdown
+        && master.failover.is_none()
+        && master.instance.info.as_ref().is_some_and(|info| info.is_master);
+    let misconfigured = info.is_master || info.master.as_ref() != Some(&master.addr);
+    let to = master.addr.clone();
+    let instance = master.replicas.get_mut(addr)?;
+    let reconf = primary_ok
+        && misconfigured
+        && instance.role_at.elapsed() > ROLE_SETTLE
+        && instance.reconf_at.is_none_or(|at| at.elapsed() > RECONF_INTERVAL);
+    instance.info = Some(info);
+    instance.info_at = Some(now);
+    if reconf {
+        instance.reconf_at = Some(now);
+        return Some(to);
+    }
+    None
+}
+
+fn parse_info(text: &str) -> Info {
+    let mut info = Info { is_master: false, master: None, link_up: false, offset: 0, replicas: Vec::new() };
+    let (mut host, mut port) = (None, None);
+    for line in text.lines() {
+        let Some((key, value)) = line.trim_end().split_once(':') else { continue };
+        match key {
+            "role" => info.is_master = value == "master",
+            "master_host" => host = Some(value.to_string()),
+            "master_port" => port = value.parse().ok(),
+            "master_link_status" => info.link_up = value == "up",
+            "slave_repl_offset" => info.offset = value.parse().unwrap_or(0),
+            "master_repl_offset" if info.is_master => info.offset = value.parse().unwrap_or(0),
+            // slave0:ip=127.0.0.1,port=6380,state=online,offset=123,lag=0
+            key if key.starts_with("slave") => {
+                let field = |name: &str| {
+                    value.split(',').find_map(|pair| pair.strip_prefix(name).and_then(|v| v.strip_prefix('=')))
+                };
+                if let (Some(ip), Some(port)) = (field("ip"), field("port").and_then(|port| port.parse().ok())) {
+                    info.replicas.push(Addr { host: ip.to_string(), port });
+                }
+            }
+            _ => {}
+        }
+    }
+    if let (Some(host), Some(port)) = (host, port) {
+        info.master = Some(Addr { host, port });
+    }
+    info
+}
+
+// ip,port,runid,current_epoch,master_name,master_ip,master_port,master_config_epoch
+fn hello(sentinel: &Sentinel, name: &str) -> Option<String> {
+    let state = sentinel.state.lock().unwrap();
+    let master = state.masters.get(name)?;
+    Some(format!(
+        "{},{},{},{},{},{},{},{}",
+        sentinel.addr.host,
+        sentinel.addr.port,
+        sentinel.myid,
+        state.current_epoch,
+        name,
+        master.addr.host,
+        master.addr.port,
+        master.config_epoch
+    ))
+}
+
+/// 订阅实例的 hello 频道，断开后重连，直到检查任务被取消
+async fn listen_hello(sentinel: &Sentinel, options: &Options) {
+    loop {
+        if let Err(err) = subscribe_hello(sentinel, options).await {
+            debug!(addr = %options.addr, %err, "hello subscription failed");
+        }
+        tokio::time::sleep(PERIOD).await;
+    }
+}
+
+async fn subscribe_hello(sentinel: &Sentinel, options: &Options) -> crate::Result<()> {
+    let mut client = match tokio::time::timeout(REQUEST_TIMEOUT, client::connect(options.addr.as_str())).await {
+        Ok(client) => client?,
+        Err(_) => return Err("connect timed out".into()),
+    };
+    if let Some(password) = &options.password {
+        let mut auth = vec![Bytes::from_static(b"AUTH")];
+        auth.extend(options.username.clone().map(Bytes::from));
+        auth.push(Bytes::from(password.clone()));
+        if let Frame::Error(msg) = client.call(&auth).await? {
+            return Err(msg.into());
+        }
+    }
+    let mut subscriber = client.subscribe(&[HELLO_CHANNEL]).await?;
+    while let Some(message) = subscriber.next_message().await? {
+        if let Frame::Bulk(payload) = message.content {
+            process_hello(sentinel, &String::from_utf8_lossy(&payload));
+        }
+    }
+    Ok(())
+}
+
+fn process_hello(sentinel: &Sentinel, hello: &str) {
+    let parts: Vec<&str> = hello.split(',').collect();
+    let [ip, port, runid, epoch, name, master_ip, master_port, config_epoch] = parts[..] else { return };
+    let (Ok(port), Ok(epoch), Ok(master_port), Ok(config_epoch)) =
+        (port.parse::<u16>(), epoch.parse::<u64>(), master_port.parse::<u16>(), config_epoch.parse::<u64>())
+    else {
+        return;
+    };
+    if runid == sentinel.myid {
+        return;
+    }
+    let mut state = sentinel.state.lock().unwrap();
+    state.current_epoch = state.current_epoch.max(epoch);
+    let Some(master) = state.masters.get_mut(name) else { return };
+    let addr = Addr { host: ip.to_string(), port };
+    // 同一个地址换了 runid，说明那个 sentinel 重启过
+    master.sentinels.retain(|id, peer| id == runid || peer.addr != addr);
+    match master.sentinels.get_mut(runid) {
+        Some(peer) => {
+            peer.addr = addr;
+            peer.last_hello = Instant::now();
+        }
+        None => {
+            info!(master = %name, sentinel = %addr, runid, "+sentinel");
+            master.sentinels.insert(
+                runid.to_string(),
+                Peer { addr, last_hello: Instant::now(), down: false, down_at: None },
+            );
+        }
+    }
+    // 别的 sentinel 完成了故障转移，用纪元更新的配置覆盖自己的
+    let announced = Addr { host: master_ip.to_string(), port: master_port };
+    if config_epoch > master.config_epoch && announced != master.addr {
+        info!(master = %name, from = runid, config_epoch, "+config-update-from");
+        master.switch(announced, config_epoch);
+    } else if config_epoch > master.config_epoch {
+        master.config_epoch = config_epoch;
+    }
+}
+
+/// 选举（SENTINEL FAILOVER 强制执行时跳过）并执行一次故障转移
+pub async fn run_failover(sentinel: Arc<Sentinel>, name: String, peers: Vec<Handle>, forced: bool) {
+    let result = async {
+        let epoch = if forced {
+            let mut state = sentinel.state.lock().unwrap();
+            state.current_epoch += 1;
+            state.current_epoch
+        } else {
+            // 随机等一会儿，避免几个 sentinel 同时发起选举瓜分选票
+            let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..1000));
+            tokio::time::sleep(jitter).await;
+            let (epoch, request) = {
+                let mut state = sentinel.state.lock().unwrap();
+                let current = state.current_epoch;
+                let master = state.masters.get(&name).ok_or("master removed")?;
+                if matches!(&master.leader, Some((epoch, leader)) if *epoch >= current && *leader != sentinel.myid) {
+                    return Err("already voted for another sentinel".into());
+                }
+                let addr = master.addr.clone();
+                state.current_epoch += 1;
+                let epoch = state.current_epoch;
+                let master = state.masters.get_mut(&name).ok_or("master removed")?;
+                master.vote(epoch, &sentinel.myid, &sentinel.myid);
+                let request = args(&[
+                    "SENTINEL",
+                    "IS-MASTER-DOWN-BY-ADDR",
+                    &addr.host,
+                    &addr.port.to_string(),
+                    &epoch.to_string(),
+                    &sentinel.myid,
+                ]);
+                (epoch, request)
+            };
+            info!(master = %name, epoch, "+try-failover");
+            let votes = elect(&sentinel, peers, request, epoch).await;
+            let needed = {
+                let state = sentinel.state.lock().unwrap();
+                state.masters.get(&name).ok_or("master removed")?.quorum_needed().1
+            };
+            if votes < needed {
+                return Err(format!("not elected as leader, got {} of {} votes", votes, needed).into());
+            }
+            info!(master = %name, epoch, votes, "+elected-leader");
+            epoch
+        };
+        failover(&sentinel, &name, epoch).await
+    }
+    .await;
+
+    let mut state = sentinel.state.lock().unwrap();
+    if let Some(master) = state.masters.get_mut(&name) {
+        master.failover = None;
+    }
+    if let Err(err) = result {
+        warn!(master = %name, %err, "-failover-abort");
+    }
+}
+
+/// 请求其它 sentinel 投票，返回得到的票数（包括自己）
+async fn elect(sentinel: &Sentinel, peers: Vec<Handle>, request: Vec<Bytes>, epoch: u64) -> usize {
+    let requests: Vec<_> = peers
+        .into_iter()
+        .map(|handle| {
+            let request = request.clone();
+            tokio::spawn(async move { handle.call::<Frame>(request).await })
+        })
+        .collect();
+    let mut votes = 1;
+    for request in requests {
+        if let Ok(Ok(Frame::Array(items))) = request.await {
+            if let [_, Frame::Bulk(leader), Frame::Integer(voted)] = &items[..] {
+                if *leader == sentinel.myid.as_bytes() && *voted as u64 == epoch {
+                    votes += 1;
+                }
+            }
+        }
+    }
+    votes
+}
+
+/// 选出复制偏移量最大的可用副本，偏移量相同时按地址排序
+pub fn select_replica(master: &Master) -> Option<Addr> {
+    master
+        .replicas
+        .iter()
+        .filter(|(_, instance)| !instance.sdown && instance.last_ok.elapsed() < REPLICA_VALIDITY)
+        .filter(|(_, instance)| instance.info_at.is_some_and(|at| at.elapsed() < REPLICA_VALIDITY))
+        .filter(|(_, instance)| instance.info.as_ref().is_some_and(|info| !info.is_master))
+        .max_by(|(a, x), (b, y)| x.offset().cmp(&y.offset()).then_with(|| b.cmp(a)))
+        .map(|(addr, _)| addr.clone())
+}
+
+async fn failover(sentinel: &Sentinel, name: &str, epoch: u64) -> crate::Result<()> {
+    let (promoted, others, options, timeout) = {
+        let state = sentinel.state.lock().unwrap();
+        let master = state.masters.get(name).ok_or("master removed")?;
+        let promoted = select_replica(master).ok_or("no suitable replica to promote")?;
+        let others: Vec<Addr> = master.replicas.keys().filter(|addr| **addr != promoted).cloned().collect();
+        let options: Vec<Options> = std::iter::once(&promoted).chain(&others).map(|addr| master.options(addr)).collect();
+        (promoted, others, options, master.config.failover_timeout)
+    };
+    info!(master = %name, replica = %promoted, epoch, "+selected-slave");
+    let mut options = options.into_iter();
+    let handle = Handle::new(options.next().unwrap());
+    handle.replicaof_no_one().await?;
+
+    // 等副本确认自己已经是主节点
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Ok(text) = handle.info("replication").await {
+            if parse_info(&text).is_master {
+                break;
+            }
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("{} was not promoted before failover-timeout", promoted).into());
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    info!(master = %name, replica = %promoted, "+promoted-slave");
+    if let Some(master) = sentinel.state.lock().unwrap().masters.get_mut(name) {
+        master.switch(promoted.clone(), epoch);
+    }
+
+    // 其余副本改为复制新的主节点，失败的由检查任务之后纠正
+    for (addr, options) in others.iter().zip(options) {
+        match Handle::new(options).replicaof(&promoted.host, promoted.port).await {
+            Ok(()) => info!(master = %name, replica = %addr, "+slave-reconf-sent"),
+            Err(err) => debug!(master = %name, replica = %addr, %err, "replica reconfiguration failed"),
+        }
+    }
+    info!(master = %name, epoch, "+failover-end");
+    Ok(())
+}
@@ -0,0 +1,809 @@
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::command::{self, CommandSpec, Context, ADMIN, BLOCKING, FAST, READONLY, WRITE};
+use mini_redis::Frame;
+use crate::glob::glob_match;
+
+const ACL_LOG_MAX_LEN: usize = 128;
+
+#[derive(Clone)]
+pub struct User {
+    pub name: String,
+    pub enabled: bool,
+    nopass: bool,
+    // 只保存 sha256 之后的密码
+    passwords: BTreeSet<String>,
+    commands: HashSet<&'static str>,
+    command_rules: Vec<String>,
+    allkeys: bool,
+    key_patterns: Vec<KeyPattern>,
+}
+
+// ~pattern 可以读写，%R~pattern 只能读，%W~pattern 只能写
+#[derive(Clone)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+impl KeyPattern {
+    fn describe(&self) -> String {
+        match (self.read, self.write) {
+            (true, false) => format!("%R~{}", self.pattern),
+            (false, true) => format!("%W~{}", self.pattern),
+            _ => format!("~{}", self.pattern),
+        }
+    }
+}
+
+pub struct Denied {
+    pub reason: &'static str,
+    pub object: String,
+}
+
+struct LogEntry {
+    count: u64,
+    reason: &'static str,
+    object: String,
+    username: String,
+    client_info: String,
+    created: Instant,
+    updated: Instant,
+}
+
+pub struct Acl {
+    users: BTreeMap<String, User>,
+    log: VecDeque<LogEntry>,
+}
+
+// 除了由标志推出的 read、write、fast、slow 等之外，每个命令所属的分类，和 Redis 的命令表一致
+fn command_categories(name: &str) -> &'static [&'static str] {
+    match name {
+        "copy" | "dbsize" | "del" | "dump" | "exists" | "expire" | "expireat" | "expiretime" | "object" | "persist"
+        | "pexpire" | "pexpireat" | "pexpiretime" | "pttl" | "randomkey" | "rename" | "renamenx" | "touch" | "ttl"
+        | "type" | "unlink" => &["keyspace"],
+        "migrate" | "restore" => &["keyspace", "dangerous"],
+        "decr" | "decrby" | "get" | "incr" | "incrby" | "set" => &["string"],
+        "bitcount" | "bitfield" | "bitfield_ro" | "bitop" | "bitpos" | "getbit" | "setbit" => &["bitmap"],
+        "hdel" | "hexists" | "hget" | "hgetall" | "hincrby" | "hkeys" | "hlen" | "hmget" | "hmset" | "hset"
+        | "hsetnx" | "hstrlen" | "hvals" => &["hash"],
+        "lindex" | "linsert" | "llen" | "lpop" | "lpush" | "lpushx" | "lrange" | "lrem" | "lset" | "ltrim" | "rpop"
+        | "rpush" | "rpushx" => &["list"],
+        "sadd" | "scard" | "sdiff" | "sinter" | "sismember" | "smembers" | "smismember" | "srem" | "sunion" => &["set"],
+        "zadd" | "zcard" | "zincrby" | "zrange" | "zrem" | "zscore" => &["sortedset"],
+        "pfadd" | "pfcount" | "pfdebug" | "pfmerge" | "pfselftest" => &["hyperloglog"],
+        "geoadd" | "geodist" | "geohash" | "geopos" | "geosearch" | "geosearchstore" => &["geo"],
+        "psubscribe" | "publish" | "pubsub" | "punsubscribe" | "subscribe" | "unsubscribe" => &["pubsub"],
+        "asking" | "auth" | "client" | "command" | "hello" | "ping" | "wait" => &["connection"],
+        "eval" | "evalsha" | "script" => &["scripting"],
+        "info" => &["dangerous"],
+        "role" => &["admin", "dangerous"],
+        _ => &[],
+    }
+}
+
+pub fn categories(spec: &CommandSpec) -> Vec<&'static str> {
+    let mut out = vec!["all"];
+    out.extend_from_slice(command_categories(spec.name));
+    if spec.has_flag(READONLY) {
+        out.push("read");
+    }
+    if spec.has_flag(WRITE) {
+        out.push("write");
+    }
+    if spec.has_flag(FAST) {
+        out.push("fast");
+    } else {
+        out.push("slow");
+    }
+    if spec.has_flag(BLOCKING) {
+        out.push("blocking");
+    }
+    if spec.has_flag(ADMIN) {
+        out.push("admin");
+        out.push("dangerous");
+    }
+    out.sort_unstable();
+    out.dedup();
+    out
+}
+
+fn all_categories() -> BTreeSet<&'static str> {
+    command::COMMANDS.iter().flat_map(categories).collect()
+}
+
+fn hash_password(password: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(password))
+}
+
+// 比较完所有字节才返回，耗时和第一个不同的字节在哪里无关
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl User {
+    fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: HashSet::new(),
+            command_rules: Vec::new(),
+            allkeys: false,
+            key_patterns: Vec::new(),
+        }
+    }
+
+    pub fn set_rule(&mut self, rule: &str) -> Result<(), String> {
+        let lower = rule.to_ascii_lowercase();
+        match lower.as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.set_rule("~*")?,
+            "resetkeys" => {
+                self.allkeys = false;
+                self.key_patterns.clear();
+            }
+            "allcommands" => self.set_rule("+@all")?,
+            "nocommands" => self.set_rule("-@all")?,
+            "reset" => {
+                for rule in ["resetpass", "resetkeys", "nocommands", "off"] {
+                    self.set_rule(rule)?;
+                }
+            }
+            "" => return Err("Syntax error".to_string()),
+            _ => match rule.as_bytes()[0] {
+                b'>' => {
+                    self.passwords.insert(hash_password(&rule.as_bytes()[1..]));
+                    self.nopass = false;
+                }
+                b'<' => {
+                    if !self.passwords.remove(&hash_password(&rule.as_bytes()[1..])) {
+                        return Err("no such password".to_string());
+                    }
+                }
+                b'#' => {
+                    let hash = &lower[1..];
+                    if hash.len() != 64 || !hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+                        return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string());
+                    }
+                    self.passwords.insert(hash.to_string());
+                    self.nopass = false;
+                }
+                b'!' => {
+                    if !self.passwords.remove(&lower[1..]) {
+                        return Err("no such password".to_string());
+                    }
+                }
+                b'~' => {
+                    if rule == "~*" {
+                        self.allkeys = true;
+                        self.key_patterns = vec![KeyPattern { pattern: "*".to_string(), read: true, write: true }];
+                    } else if !self.allkeys {
+                        self.key_patterns.push(KeyPattern { pattern: rule[1..].to_string(), read: true, write: true });
+                    }
+                }
+                b'%' => {
+                    let (permissions, pattern) = rule[1..].split_once('~').ok_or("Syntax error")?;
+                    let permissions = permissions.to_ascii_uppercase();
+                    let (read, write) = (permissions.contains('R'), permissions.contains('W'));
+                    if permissions.is_empty() || permissions.chars().any(|c| c != 'R' && c != 'W') {
+                        return Err("Syntax error".to_string());
+                    }
+                    if !self.allkeys {
+                        self.key_patterns.push(KeyPattern { pattern: pattern.to_string(), read, write });
+                    }
+                }
+                b'+' | b'-' => self.set_command_rule(&lower)?,
+                _ => return Err("Syntax error".to_string()),
+            },
+        }
+        Ok(())
+    }
+
+    fn set_command_rule(&mut self, rule: &str) -> Result<(), String> {
+        let allow = rule.starts_with('+');
+        let name = &rule[1..];
+        let specs: Vec<&'static CommandSpec> = match name.strip_prefix('@') {
+            Some(category) => {
+                if !all_categories().contains(category) {
+                    return Err(format!("Unknown command category '{}'", category));
+                }
+                command::COMMANDS
+                    .iter()
+                    .filter(|spec| categories(spec).contains(&category))
+                    .collect()
+            }
+            None => match command::lookup(name.as_bytes()) {
+                Some(spec) => vec![spec],
+                None => return Err(format!("Unknown command '{}'", name)),
+            },
+        };
+        for spec in specs {
+            if allow {
+                self.commands.insert(spec.name);
+            } else {
+                self.commands.remove(spec.name);
+            }
+        }
+        if name == "@all" {
+            self.command_rules.clear();
+        }
+        self.command_rules.push(rule.to_string());
+        Ok(())
+    }
+
+    fn check_password(&self, password: &[u8]) -> bool {
+        let hash = hash_password(password);
+        // 每个保存的哈希都完整比较一遍，匹配到了也不提前返回
+        let matched = self
+            .passwords
+            .iter()
+            .fold(false, |matched, stored| constant_time_eq(stored.as_bytes(), hash.as_bytes()) | matched);
+        self.nopass || matched
+    }
+
+    /// 写命令要求 key 可写，其它命令要求可读
+    fn can_access_key(&self, key: &[u8], write: bool) -> bool {
+        self.allkeys
+            || self.key_patterns.iter().any(|pattern| {
+                let allowed = if write { pattern.write } else { pattern.read };
+                allowed && glob_match(pattern.pattern.as_bytes(), key)
+            })
+    }
+
+    fn commands_description(&self) -> String {
+        if self.command_rules.is_empty() {
+            "-@all".to_string()
+        } else {
+            self.command_rules.join(" ")
+        }
+    }
+
+    fn keys_description(&self) -> String {
+        self.key_patterns
+            .iter()
+            .map(KeyPattern::describe)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// 形如 `user default on nopass ~* +@all`，ACL LIST 和 ACL 文件共用这一格式
+    fn describe(&self) -> String {
+        let mut parts = vec![
+            "user".to_string(),
+            self.name.clone(),
+            if self.enabled { "on" } else { "off" }.to_string(),
+        ];
+        if self.nopass {
+            parts.push("nopass".to_string());
+        }
+        parts.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        let keys = self.keys_description();
+        if !keys.is_empty() {
+            parts.push(keys);
+        }
+        parts.push(self.commands_description());
+        parts.join(" ")
+    }
+}
+
+impl Acl {
+    pub fn new(requirepass: Option<&str>) -> Acl {
+        let mut default = User::new("default");
+        for rule in ["on", "~*", "+@all"] {
+            default.set_rule(rule).unwrap();
+        }
+        match requirepass {
+            Some(password) => default.set_rule(&format!(">{}", password)).unwrap(),
+            None => default.set_rule("nopass").unwrap(),
+        }
+        let mut users = BTreeMap::new();
+        users.insert(default.name.clone(), default);
+        Acl {
+            users,
+            log: VecDeque::new(),
+        }
+    }
+
+    /// 新连接是否可以不经过 AUTH 直接以 default 用户身份使用
+    pub fn default_user_nopass(&self) -> bool {
+        self.users
+            .get("default")
+            .is_some_and(|user| user.enabled && user.nopass)
+    }
+
+    pub fn authenticate(&self, username: &str, password: &[u8]) -> bool {
+        match self.users.get(username) {
+            Some(user) => user.enabled && user.check_password(password),
+            None => false,
+        }
+    }
+
+    pub fn check(&self, username: &str, spec: &CommandSpec, args: &[Bytes]) -> Result<(), Denied> {
+        let user = match self.users.get(username) {
+            Some(user) if user.enabled => user,
+            _ => {
+                return Err(Denied {
+                    reason: "auth",
+                    object: spec.name.to_string(),
+                })
+            }
+        };
+        if !user.commands.contains(spec.name) {
+            return Err(Denied {
+                reason: "command",
+                object: spec.name.to_string(),
+            });
+        }
+        for key in spec.keys(args) {
+            if !user.can_access_key(key, spec.has_flag(WRITE)) {
+                return Err(Denied {
+                    reason: "key",
+                    object: String::from_utf8_lossy(key).into_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn log_denied(&mut self, reason: &'static str, object: &str, username: &str, client_info: &str) {
+        tracing::warn!(reason, object, user = username, client = client_info, "acl denied");
+        let now = Instant::now();
+        if let Some(entry) = self.log.iter_mut().find(|entry| {
+            entry.reason == reason
+                && entry.object == object
+                && entry.username == username
+                && now.duration_since(entry.updated) < Duration::from_secs(60)
+        }) {
+            entry.count += 1;
+            entry.updated = now;
+            entry.client_info = client_info.to_string();
+            return;
+        }
+        self.log.push_front(LogEntry {
+            count: 1,
+            reason,
+            object: object.to_string(),
+            username: username.to_string(),
+            client_info: client_info.to_string(),
+            created: now,
+            updated: now,
+        });
+        self.log.truncate(ACL_LOG_MAX_LEN);
+    }
+
+    fn set_user(&mut self, name: &str, rules: &[Bytes]) -> Result<(), String> {
+        let mut user = match self.users.get(name) {
+            Some(user) => user.clone(),
+            None => User::new(name),
+        };
+        // 任何一条规则出错都不修改原有用户
+        for rule in rules {
+            let rule = String::from_utf8_lossy(rule);
+            user.set_rule(&rule).map_err(|e| {
+                format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, e)
+            })?;
+        }
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn load(&mut self, path: &Path) -> crate::Result<()> {
+        let content = std::fs::read_to_string(path)?;
+        let mut users = BTreeMap::new();
+        for (lineno, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let name = match (parts.next(), parts.next()) {
+                (Some("user"), Some(name)) => name,
+                _ => return Err(format!("{}:{}: expected 'user <name> ...'", path.display(), lineno + 1).into()),
+            };
+            let mut user = User::new(name);
+            for rule in parts {
+                user.set_rule(rule)
+                    .map_err(|e| format!("{}:{}: {}", path.display(), lineno + 1, e))?;
+            }
+            users.insert(name.to_string(), user);
+        }
+        if !users.contains_key("default") {
+            let default = self.users.remove("default").unwrap_or_else(|| User::new("default"));
+            users.insert("default".to_string(), default);
+        }
+        self.users = users;
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> crate::Result<()> {
+        let mut content = String::new();
+        for user in self.users.values() {
+            content.push_str(&user.describe());
+            content.push('\n');
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, content)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn get_user(&self, name: &str) -> Frame {
+        let user = match self.users.get(name) {
+            Some(user) => user,
+            None => return Frame::Null,
+        };
+        let mut flags = vec![Frame::bulk(if user.enabled { "on" } else { "off" })];
+        if user.nopass {
+            flags.push(Frame::bulk("nopass"));
+        }
+        if user.allkeys {
+            flags.push(Frame::bulk("allkeys"));
+        }
+        Frame::Array(vec![
+            Frame::bulk("flags"),
+            Frame::Array(flags),
+            Frame::bulk("passwords"),
+            Frame::Array(user.passwords.iter().map(|hash| Frame::bulk(hash.clone())).collect()),
+            Frame::bulk("commands"),
+            Frame::bulk(user.commands_description()),
+            Frame::bulk("keys"),
+            Frame::bulk(user.keys_description()),
+        ])
+    }
+
+    fn log_frame(&self, count: usize) -> Frame {
+        let now = Instant::now();
+        Frame::Array(
+            self.log
+                .iter()
+                .take(count)
+                .map(|entry| {
+                    Frame::Array(vec![
+                        Frame::bulk("count"),
+                        Frame::Integer(entry.count as i64),
+                        Frame::bulk("reason"),
+                        Frame::bulk(entry.reason),
+                        Frame::bulk("context"),
+                        Frame::bulk("toplevel"),
+                        Frame::bulk("object"),
+                        Frame::bulk(entry.object.clone()),
+                        Frame::bulk("username"),
+                        Frame::bulk(entry.username.clone()),
+                        Frame::bulk("age-seconds"),
+                        Frame::bulk(format!("{:.3}", now.duration_since(entry.created).as_secs_f64())),
+                        Frame::bulk("client-info"),
+                        Frame::bulk(entry.client_info.clone()),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+pub fn auth(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (username, password) = match args.len() {
+        2 => ("default".to_string(), &args[1]),
+        3 => (String::from_utf8_lossy(&args[1]).into_owned(), &args[2]),
+        _ => return command::syntax_error(),
+    };
+    if args.len() == 2 && ctx.shared.acl.lock().unwrap().default_user_nopass() {
+        return Frame::error("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?");
+    }
+    match login(ctx, username, password) {
+        Ok(()) => Frame::ok(),
+        Err(err) => err,
+    }
+}
+
+/// AUTH 和 HELLO ... AUTH 共用的登录逻辑
+pub fn login(ctx: &mut Context<'_>, username: String, password: &[u8]) -> Result<(), Frame> {
+    let mut acl = ctx.shared.acl.lock().unwrap();
+    if acl.authenticate(&username, password) {
+        ctx.client.user = username;
+        ctx.client.authenticated = true;
+        Ok(())
+    } else {
+        acl.log_denied("auth", "AUTH", &username, &ctx.client.addr);
+        Err(Frame::error("WRONGPASS invalid username-password pair or user is disabled."))
+    }
+}
+
+pub fn acl(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    let name = args.get(2).map(|name| String::from_utf8_lossy(name).into_owned());
+    let mut acl = ctx.shared.acl.lock().unwrap();
+    match (&sub[..], name) {
+        (b"setuser", Some(name)) => match acl.set_user(&name, &args[3..]) {
+            Ok(()) => Frame::ok(),
+            Err(e) => Frame::Error(e),
+        },
+        (b"getuser", Some(name)) if args.len() == 3 => acl.get_user(&name),
+        (b"deluser", Some(_)) => {
+            let mut deleted = 0;
+            for name in &args[2..] {
+                if &name[..] == b"default" {
+                    return Frame::error("ERR The 'default' user cannot be removed");
+                }
+                if acl.users.remove(String::from_utf8_lossy(name).as_ref()).is_some() {
+                    deleted += 1;
+                }
+            }
+            Frame::Integer(deleted)
+        }
+        (b"list", None) => Frame::Array(acl.users.values().map(|user| Frame::bulk(user.describe())).collect()),
+        (b"users", None) => Frame::Array(acl.users.keys().map(|name| Frame::bulk(name.clone())).collect()),
+        (b"whoami", None) => Frame::bulk(ctx.client.user.clone()),
+        (b"cat", None) => Frame::Array(all_categories().into_iter().map(Frame::bulk).collect()),
+        (b"cat", Some(category)) => {
+            if !all_categories().contains(category.as_str()) {
+                return Frame::error(format!("ERR Unknown category '{}'", category));
+            }
+            Frame::Array(
+                command::COMMANDS
+                    .iter()
+                    .filter(|spec| categories(spec).contains(&category.as_str()))
+                    .map(|spec| Frame::bulk(spec.name))
+                    .collect(),
+            )
+        }
+        (b"log", None) => acl.log_frame(10),
+        (b"log", Some(arg)) if arg.eq_ignore_ascii_case("reset") => {
+            acl.log.clear();
+            Frame::ok()
+        }
+        (b"log", Some(arg)) => match arg.parse() {
+            Ok(count) => acl.log_frame(count),
+            Err(_) => Frame::error("ERR value is out of range, must be positive"),
+        },
+        (b"save", None) | (b"load", None) => {
+            let path = match &ctx.shared.config.aclfile {
+                Some(path) => path,
+                None => return Frame::error("ERR This Redis instance is not configured to use an ACL file."),
+            };
+            let result = if &sub[..] == b"save" {
+                acl.save(path)
+            } else {
+                acl.load(path)
+            };
+            match result {
+                Ok(()) => Frame::ok(),
+                Err(e) => Frame::error(format!("ERR {}", e)),
+            }
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            String::from_utf8_lossy(&args[1])
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(rules: &[&str]) -> User {
+        let mut user = User::new("alice");
+        for rule in rules {
+            user.set_rule(rule).unwrap();
+        }
+        user
+    }
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
+    }
+
+    // 以 alice 的身份检查命令，返回拒绝的原因
+    fn check(acl: &Acl, command: &[&str]) -> Result<(), &'static str> {
+        let spec = command::lookup(command[0].as_bytes()).unwrap();
+        acl.check("alice", spec, &args(command)).map_err(|denied| denied.reason)
+    }
+
+    fn acl_with(user: User) -> Acl {
+        let mut acl = Acl::new(None);
+        acl.users.insert(user.name.clone(), user);
+        acl
+    }
+
+    #[test]
+    fn on_off_and_passwords() {
+        let mut alice = user(&["on", ">secret", ">other"]);
+        assert!(alice.enabled && alice.check_password(b"secret") && alice.check_password(b"other"));
+        assert!(!alice.check_password(b"wrong"));
+
+        alice.set_rule("<other").unwrap();
+        assert!(!alice.check_password(b"other"));
+        assert_eq!(alice.set_rule("<other").unwrap_err(), "no such password");
+
+        // #hash 直接保存 sha256，!hash 按哈希删除
+        let hash = hash_password(b"hashed");
+        alice.set_rule(&format!("#{}", hash.to_uppercase())).unwrap();
+        assert!(alice.check_password(b"hashed"));
+        alice.set_rule(&format!("!{}", hash)).unwrap();
+        assert!(!alice.check_password(b"hashed"));
+        assert!(alice.set_rule("#abc").is_err());
+
+        alice.set_rule("nopass").unwrap();
+        assert!(alice.check_password(b"anything") && alice.passwords.is_empty());
+        // 设置密码会取消 nopass
+        alice.set_rule(">secret").unwrap();
+        assert!(!alice.check_password(b"anything"));
+        alice.set_rule("resetpass").unwrap();
+        assert!(!alice.check_password(b"secret"));
+
+        alice.set_rule("off").unwrap();
+        let acl = acl_with(alice.clone());
+        assert!(!acl.authenticate("alice", b"secret"));
+        assert_eq!(check(&acl, &["ping"]), Err("auth"));
+        assert!(!acl_with(user(&["off", "nopass"])).authenticate("alice", b""));
+        assert!(acl_with(user(&["on", "nopass"])).authenticate("alice", b""));
+        assert_eq!(alice.set_rule("bogus").unwrap_err(), "Syntax error");
+    }
+
+    #[test]
+    fn constant_time_eq_compares_whole_input() {
+        assert!(constant_time_eq(b"abcdef", b"abcdef"));
+        assert!(!constant_time_eq(b"abcdef", b"abcdeg"));
+        assert!(!constant_time_eq(b"xbcdef", b"abcdef"));
+        assert!(!constant_time_eq(b"abc", b"abcdef"));
+        assert!(constant_time_eq(b"", b""));
+    }
+
+    #[test]
+    fn command_rules_and_categories() {
+        let acl = acl_with(user(&["on", "nopass", "~*", "+@read", "-hgetall", "+set"]));
+        assert_eq!(check(&acl, &["get", "k"]), Ok(()));
+        assert_eq!(check(&acl, &["hget", "k", "f"]), Ok(()));
+        assert_eq!(check(&acl, &["hgetall", "k"]), Err("command"));
+        assert_eq!(check(&acl, &["set", "k", "v"]), Ok(()));
+        assert_eq!(check(&acl, &["del", "k"]), Err("command"));
+
+        let acl = acl_with(user(&["on", "nopass", "~*", "+@all", "-@dangerous"]));
+        assert_eq!(check(&acl, &["get", "k"]), Ok(()));
+        assert_eq!(check(&acl, &["config", "get", "maxmemory"]), Err("command"));
+        assert_eq!(check(&acl, &["info"]), Err("command"));
+
+        // 后面的 +@all 清掉之前记录的规则
+        let alice = user(&["+get", "-@all", "+@hash", "+@all"]);
+        assert_eq!(alice.commands_description(), "+@all");
+        assert!(user(&[]).set_rule("+@nope").unwrap_err().contains("Unknown command category"));
+        assert!(user(&[]).set_rule("+nope").unwrap_err().contains("Unknown command"));
+    }
+
+    #[test]
+    fn categories_follow_redis() {
+        let of = |name: &str| categories(command::lookup(name.as_bytes()).unwrap());
+        assert_eq!(of("get"), ["all", "fast", "read", "string"]);
+        assert_eq!(of("hset"), ["all", "fast", "hash", "write"]);
+        assert_eq!(of("del"), ["all", "keyspace", "slow", "write"]);
+        assert_eq!(of("config"), ["admin", "all", "dangerous", "slow"]);
+        assert_eq!(of("zadd"), ["all", "fast", "sortedset", "write"]);
+        assert_eq!(of("ping"), ["all", "connection", "fast"]);
+        // 有 key 的命令不会都算进 keyspace，也没有 Redis 里不存在的分类
+        assert!(!of("lpush").contains(&"keyspace"));
+        let all = all_categories();
+        assert!(!all.contains("server") && !all.contains("cluster"));
+        for category in ["keyspace", "string", "list", "set", "sortedset", "hash", "bitmap", "hyperloglog", "geo"] {
+            assert!(all.contains(category), "missing @{}", category);
+        }
+    }
+
+    #[test]
+    fn key_patterns_and_permissions() {
+        let acl = acl_with(user(&["on", "nopass", "+@all", "~user:*", "%R~cache:*", "%W~log:*", "%RW~both:?"]));
+        assert_eq!(check(&acl, &["get", "user:1"]), Ok(()));
+        assert_eq!(check(&acl, &["set", "user:1", "v"]), Ok(()));
+        assert_eq!(check(&acl, &["get", "other"]), Err("key"));
+
+        // %R~ 只能读，%W~ 只能写
+        assert_eq!(check(&acl, &["get", "cache:1"]), Ok(()));
+        assert_eq!(check(&acl, &["set", "cache:1", "v"]), Err("key"));
+        assert_eq!(check(&acl, &["rpush", "log:1", "v"]), Ok(()));
+        assert_eq!(check(&acl, &["lrange", "log:1", "0", "-1"]), Err("key"));
+        assert_eq!(check(&acl, &["set", "both:1", "v"]), Ok(()));
+        assert_eq!(check(&acl, &["get", "both:12"]), Err("key"));
+
+        // 每个 key 都要检查
+        assert_eq!(check(&acl, &["del", "user:1", "log:1", "other"]), Err("key"));
+        assert_eq!(check(&acl, &["exists", "user:1", "cache:2"]), Ok(()));
+
+        let mut alice = user(&["~a*", "%R~b*"]);
+        assert_eq!(alice.keys_description(), "~a* %R~b*");
+        alice.set_rule("resetkeys").unwrap();
+        assert!(alice.key_patterns.is_empty() && alice.keys_description().is_empty());
+        alice.set_rule("allkeys").unwrap();
+        assert!(alice.can_access_key(b"anything", true));
+        // allkeys 之后再加的模式没有意义
+        alice.set_rule("%R~x").unwrap();
+        assert_eq!(alice.keys_description(), "~*");
+        assert!(user(&[]).set_rule("%X~a").is_err());
+        assert!(user(&[]).set_rule("%R").is_err());
+    }
+
+    #[test]
+    fn log_merges_repeated_denials() {
+        let mut acl = Acl::new(None);
+        acl.log_denied("command", "config", "alice", "addr=1");
+        acl.log_denied("key", "secret", "alice", "addr=1");
+        acl.log_denied("command", "config", "alice", "addr=2");
+        acl.log_denied("command", "config", "bob", "addr=3");
+
+        let Frame::Array(entries) = acl.log_frame(10) else { panic!() };
+        assert_eq!(entries.len(), 3);
+        // 最新的在前面，相同的拒绝只增加计数并更新 client-info
+        let field = |entry: &Frame, name: &str| {
+            let Frame::Array(fields) = entry else { panic!() };
+            let at = fields.iter().position(|field| *field == Frame::bulk(name.to_string())).unwrap();
+            fields[at + 1].clone()
+        };
+        assert_eq!(field(&entries[0], "username"), Frame::bulk("bob"));
+        assert_eq!(field(&entries[1], "reason"), Frame::bulk("key"));
+        assert_eq!(field(&entries[1], "object"), Frame::bulk("secret"));
+        assert_eq!(field(&entries[2], "count"), Frame::Integer(2));
+        assert_eq!(field(&entries[2], "client-info"), Frame::bulk("addr=2"));
+
+        let Frame::Array(entries) = acl.log_frame(1) else { panic!() };
+        assert_eq!(entries.len(), 1);
+        for i in 0..ACL_LOG_MAX_LEN + 10 {
+            acl.log_denied("key", &i.to_string(), "alice", "addr=1");
+        }
+        assert_eq!(acl.log.len(), ACL_LOG_MAX_LEN);
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = std::env::temp_dir().join(format!("mini-redis-acl-{}.acl", std::process::id()));
+        let mut acl = Acl::new(Some("root"));
+        let rules = args(&["on", ">secret", "~user:*", "%R~cache:*", "+@read", "-hgetall", "+set"]);
+        acl.set_user("alice", &rules).unwrap();
+        acl.set_user("bob", &args(&["off", "nopass"])).unwrap();
+        acl.save(&path).unwrap();
+
+        let mut loaded = Acl::new(None);
+        loaded.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let list = |acl: &Acl| acl.users.values().map(User::describe).collect::<Vec<_>>();
+        assert_eq!(list(&loaded), list(&acl));
+        assert!(loaded.authenticate("alice", b"secret") && loaded.authenticate("default", b"root"));
+        assert!(!loaded.authenticate("bob", b""));
+        assert_eq!(check(&loaded, &["get", "cache:1"]), Ok(()));
+        assert_eq!(check(&loaded, &["set", "cache:1", "v"]), Err("key"));
+        assert_eq!(check(&loaded, &["hgetall", "user:1"]), Err("command"));
+    }
+
+    #[test]
+    fn load_rejects_bad_files_without_changing_users() {
+        let path = std::env::temp_dir().join(format!("mini-redis-acl-bad-{}.acl", std::process::id()));
+        std::fs::write(&path, "user alice on nopass +@all\nuser bob on +nope\n").unwrap();
+        let mut acl = Acl::new(None);
+        let err = acl.load(&path).unwrap_err().to_string();
+        std::fs::remove_file(&path).unwrap();
+        assert!(err.contains(":2:"), "{}", err);
+        assert_eq!(acl.users.keys().collect::<Vec<_>>(), ["default"]);
+    }
+
+    #[test]
+    fn setuser_is_atomic() {
+        let mut acl = Acl::new(None);
+        acl.set_user("alice", &args(&["on", ">secret"])).unwrap();
+        let err = acl.set_user("alice", &args(&["off", "+nope"])).unwrap_err();
+        assert!(err.starts_with("ERR Error in ACL SETUSER modifier '+nope'"));
+        assert!(acl.users["alice"].enabled);
+    }
+}
@@ -0,0 +1,482 @@
+use bytes::{Bytes, BytesMut};
+
+use crate::command::{syntax_error, wrong_type, Context};
+use crate::db::Value;
+use crate::keyspace::parse_i64;
+use crate::notify;
+use mini_redis::Frame;
+
+// 位图直接操作字符串的字节，第 0 位是第一个字节的最高位，和 Redis 一致
+
+// 字符串最大 512MB，所以位偏移不能超过 2^32 - 1
+const MAX_BIT_OFFSET: u64 = 512 * 1024 * 1024 * 8 - 1;
+
+fn parse_offset(arg: &[u8]) -> Result<u64, Frame> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|n| n.parse::<u64>().ok())
+        .filter(|n| *n <= MAX_BIT_OFFSET)
+        .ok_or_else(|| Frame::error("ERR bit offset is not an integer or out of range"))
+}
+
+fn parse_bit(arg: &[u8]) -> Result<bool, Frame> {
+    match arg {
+        b"0" => Ok(false),
+        b"1" => Ok(true),
+        _ => Err(Frame::error("ERR bit is not an integer or out of range")),
+    }
+}
+
+fn get_bit(data: &[u8], offset: u64) -> bool {
+    let byte = (offset / 8) as usize;
+    byte < data.len() && data[byte] & (0x80 >> (offset % 8)) != 0
+}
+
+// 调用方保证 data 足够长
+fn set_bit(data: &mut [u8], offset: u64, bit: bool) {
+    let byte = (offset / 8) as usize;
+    let mask = 0x80 >> (offset % 8);
+    if bit {
+        data[byte] |= mask;
+    } else {
+        data[byte] &= !mask;
+    }
+}
+
+// 读出字符串，key 不存在时返回 Ok(None)
+fn read(ctx: &Context<'_>, key: &[u8]) -> Result<Option<Bytes>, Frame> {
+    let mut db = ctx.shared.db.lock().unwrap();
+    match db.get(key) {
+        None => Ok(None),
+        Some(entry) => match entry.value.as_string() {
+            Some(value) => Ok(Some(value.clone())),
+            None => Err(wrong_type()),
+        },
+    }
+}
+
+/// 在一次加锁里原地修改字符串，过期时间不变，key 不存在时从空字符串开始。
+/// f 返回（结果，是否修改），没有修改时不会创建 key
+fn update<R>(
+    ctx: &Context<'_>,
+    key: &Bytes,
+    event: &str,
+    f: impl FnOnce(&mut BytesMut) -> (R, bool),
+) -> Result<R, Frame> {
+    let mut db = ctx.shared.db.lock().unwrap();
+    let exists = match db.get(key) {
+        Some(entry) if entry.value.as_string().is_none() => return Err(wrong_type()),
+        found => found.is_some(),
+    };
+    let (result, changed) = if exists {
+        db.modify(key, |value| {
+            let Value::String(value) = value else { unreachable!() };
+            // 没有其它地方引用这个值时转换不会复制，只有第一次修改别处还在用的值时才复制一份
+            let mut data = BytesMut::from(std::mem::take(value));
+            let result = f(&mut data);
+            *value = data.freeze();
+            result
+        })
+        .unwrap()
+    } else {
+        let mut data = BytesMut::new();
+        let (result, changed) = f(&mut data);
+        if changed {
+            db.set(key.clone(), data.freeze(), None);
+        }
+        (result, changed)
+    };
+    drop(db);
+
+    if changed {
+        ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+        if !exists {
+            notify::keyspace_event(ctx.shared, notify::NEW, "new", key);
+        }
+        notify::keyspace_event(ctx.shared, notify::STRING, event, key);
+    }
+    Ok(result)
+}
+
+// SETBIT key offset value，返回原来的位
+pub fn setbit(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let offset = match parse_offset(&args[2]) {
+        Ok(offset) => offset,
+        Err(err) => return err,
+    };
+    let bit = match parse_bit(&args[3]) {
+        Ok(bit) => bit,
+        Err(err) => return err,
+    };
+    let old = update(ctx, &args[1], "setbit", |data| {
+        let old = get_bit(data, offset);
+        let len = (offset / 8) as usize + 1;
+        if data.len() < len {
+            data.resize(len, 0);
+        }
+        set_bit(data, offset, bit);
+        (old, true)
+    });
+    match old {
+        Ok(old) => Frame::Integer(old as i64),
+        Err(err) => err,
+    }
+}
+
+// GETBIT key offset
+pub fn getbit(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let offset = match parse_offset(&args[2]) {
+        Ok(offset) => offset,
+        Err(err) => return err,
+    };
+    let value = match read(ctx, &args[1]) {
+        Ok(found) => found.unwrap_or_default(),
+        Err(err) => return err,
+    };
+    Frame::Integer(get_bit(&value, offset) as i64)
+}
+
+/// 把可以为负数（从末尾算起）的闭区间换算成 [start, end]，区间为空时返回 None
+fn range(start: i64, end: i64, len: i64) -> Option<(i64, i64)> {
+    let start = if start < 0 { (len + start).max(0) } else { start };
+    let end = if end < 0 { (len + end).max(0) } else { end.min(len - 1) };
+    (len > 0 && start <= end).then_some((start, end))
+}
+
+// 解析 [start end [BYTE|BIT]] 里的单位，返回是否按位计算
+fn parse_unit(arg: Option<&Bytes>) -> Result<bool, Frame> {
+    match arg.map(|arg| arg.to_ascii_lowercase()) {
+        None => Ok(false),
+        Some(unit) if unit == b"byte" => Ok(false),
+        Some(unit) if unit == b"bit" => Ok(true),
+        Some(_) => Err(syntax_error()),
+    }
+}
+
+fn count_bits(data: &[u8], start: u64, end: u64) -> u64 {
+    (start..=end).filter(|i| get_bit(data, *i)).count() as u64
+}
+
+// BITCOUNT key [start end [BYTE|BIT]]
+pub fn bitcount(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    if args.len() == 3 || args.len() > 5 {
+        return syntax_error();
+    }
+    let bits = match parse_unit(args.get(4)) {
+        Ok(bits) => bits,
+        Err(err) => return err,
+    };
+    let bounds = match args.get(2..4) {
+        Some([start, end]) => match (parse_i64(start), parse_i64(end)) {
+            (Ok(start), Ok(end)) => Some((start, end)),
+            (Err(err), _) | (_, Err(err)) => return err,
+        },
+        _ => None,
+    };
+    let value = match read(ctx, &args[1]) {
+        Ok(found) => found.unwrap_or_default(),
+        Err(err) => return err,
+    };
+    let len = value.len() as i64 * if bits { 8 } else { 1 };
+    let (start, end) = match range(bounds.map_or(0, |b| b.0), bounds.map_or(-1, |b| b.1), len) {
+        Some(range) => range,
+        None => return Frame::Integer(0),
+    };
+    let count = if bits {
+        count_bits(&value, start as u64, end as u64)
+    } else {
+        value[start as usize..=end as usize].iter().map(|b| b.count_ones() as u64).sum()
+    };
+    Frame::Integer(count as i64)
+}
+
+// BITPOS key bit [start [end [BYTE|BIT]]]
+pub fn bitpos(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    if args.len() > 6 {
+        return syntax_error();
+    }
+    let bit = match parse_bit(&args[2]) {
+        Ok(bit) => bit,
+        Err(err) => return err,
+    };
+    let bits = match parse_unit(args.get(5)) {
+        Ok(bits) => bits,
+        Err(err) => return err,
+    };
+    let start = match args.get(3).map(|arg| parse_i64(arg)).transpose() {
+        Ok(start) => start.unwrap_or(0),
+        Err(err) => return err,
+    };
+    let end = match args.get(4).map(|arg| parse_i64(arg)).transpose() {
+        Ok(end) => end,
+        Err(err) => return err,
+    };
+    let value = match read(ctx, &args[1]) {
+        Ok(Some(value)) => value,
+        // 不存在的 key 相当于全 0 的字符串
+        Ok(None) => return Frame::Integer(if bit { -1 } else { 0 }),
+        Err(err) => return err,
+    };
+    let unit = if bits { 1 } else { 8 };
+    let len = value.len() as i64 * 8 / unit;
+    let (first, last) = match range(start, end.unwrap_or(-1), len) {
+        Some((first, last)) => (first * unit, last * unit + unit - 1),
+        None => return Frame::Integer(-1),
+    };
+    match (first..=last).find(|i| get_bit(&value, *i as u64) == bit) {
+        Some(pos) => Frame::Integer(pos),
+        // 找 0 并且没有指定结尾时，认为字符串右边补了无限个 0
+        None if !bit && end.is_none() => Frame::Integer(last + 1),
+        None => Frame::Integer(-1),
+    }
+}
+
+// BITOP AND|OR|XOR|NOT destkey key [key ...]，返回结果的长度
+pub fn bitop(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let op = args[1].to_ascii_lowercase();
+    let sources = &args[3..];
+    if !matches!(&op[..], b"and" | b"or" | b"xor" | b"not") {
+        return syntax_error();
+    }
+    if op == b"not" && sources.len() != 1 {
+        return Frame::error("ERR BITOP NOT must be called with a single source key.");
+    }
+
+    // 读源 key 和写目标 key 在同一次加锁里完成，中间不会插进别的写入
+    let dest = &args[2];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let mut values = Vec::with_capacity(sources.len());
+    for key in sources {
+        match db.get(key).map(|entry| entry.value.as_string().cloned()) {
+            Some(Some(value)) => values.push(value),
+            Some(None) => return wrong_type(),
+            None => values.push(Bytes::new()),
+        }
+    }
+    let len = values.iter().map(Bytes::len).max().unwrap_or(0);
+    // 短的字符串按右边补 0 处理
+    let byte = |value: &Bytes, i: usize| value.get(i).copied().unwrap_or(0);
+    let result: Vec<u8> = (0..len)
+        .map(|i| {
+            let mut bytes = values.iter().map(|value| byte(value, i));
+            let first = bytes.next().unwrap_or(0);
+            match &op[..] {
+                b"and" => bytes.fold(first, |acc, b| acc & b),
+                b"or" => bytes.fold(first, |acc, b| acc | b),
+                b"xor" => bytes.fold(first, |acc, b| acc ^ b),
+                _ => !first,
+            }
+        })
+        .collect();
+
+    if result.is_empty() {
+        let removed = db.remove(dest);
+        drop(db);
+        if removed.is_some() {
+            ctx.shared.signal_modified_key(dest, Some(ctx.client.id));
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "del", dest);
+        }
+        return Frame::Integer(0);
+    }
+    db.set(dest.clone(), Bytes::from(result), None);
+    drop(db);
+    ctx.shared.signal_modified_key(dest, Some(ctx.client.id));
+    notify::keyspace_event(ctx.shared, notify::STRING, "set", dest);
+    Frame::Integer(len as i64)
+}
+
+#[derive(Clone, Copy)]
+enum Overflow {
+    Wrap,
+    Sat,
+    Fail,
+}
+
+#[derive(Clone, Copy)]
+struct Field {
+    signed: bool,
+    bits: u32,
+    offset: u64,
+}
+
+enum Op {
+    Get(Field),
+    Set(Field, i64, Overflow),
+    IncrBy(Field, i64, Overflow),
+}
+
+impl Field {
+    fn parse(kind: &[u8], offset: &[u8]) -> Result<Field, Frame> {
+        let invalid = || Frame::error("ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.");
+        let (signed, width) = match kind.split_first() {
+            Some((b'i' | b'I', width)) => (true, width),
+            Some((b'u' | b'U', width)) => (false, width),
+            _ => return Err(invalid()),
+        };
+        let bits: u32 = std::str::from_utf8(width).ok().and_then(|w| w.parse().ok()).ok_or_else(invalid)?;
+        if bits == 0 || bits > if signed { 64 } else { 63 } {
+            return Err(invalid());
+        }
+        // #N 表示第 N 个这种类型的整数
+        let offset = match offset.strip_prefix(b"#") {
+            Some(index) => parse_offset(index)?.checked_mul(bits as u64),
+            None => Some(parse_offset(offset)?),
+        };
+        match offset {
+            Some(offset) if offset + bits as u64 - 1 <= MAX_BIT_OFFSET => Ok(Field { signed, bits, offset }),
+            _ => Err(Frame::error("ERR bit offset is not an integer or out of range")),
+        }
+    }
+
+    fn get(&self, data: &[u8]) -> i64 {
+        let mut value: u64 = 0;
+        for i in 0..self.bits as u64 {
+            value = value << 1 | get_bit(data, self.offset + i) as u64;
+        }
+        if self.signed && self.bits < 64 && value & (1 << (self.bits - 1)) != 0 {
+            // 符号扩展
+            value |= u64::MAX << self.bits;
+        }
+        value as i64
+    }
+
+    fn set(&self, data: &mut BytesMut, value: i64) {
+        let len = ((self.offset + self.bits as u64 - 1) / 8) as usize + 1;
+        if data.len() < len {
+            data.resize(len, 0);
+        }
+        let value = value as u64;
+        for i in 0..self.bits as u64 {
+            let bit = value >> (self.bits as u64 - 1 - i) & 1 == 1;
+            set_bit(data, self.offset + i, bit);
+        }
+    }
+
+    /// 按溢出策略把结果放进这个类型的范围，FAIL 时返回 None
+    fn fit(&self, value: i128, overflow: Overflow) -> Option<i64> {
+        let (min, max) = if self.signed {
+            (-(1i128 << (self.bits - 1)), (1i128 << (self.bits - 1)) - 1)
+        } else {
+            (0, (1i128 << self.bits) - 1)
+        };
+        if (min..=max).contains(&value) {
+            return Some(value as i64);
+        }
+        match overflow {
+            Overflow::Wrap => {
+                let mut wrapped = value.rem_euclid(1i128 << self.bits);
+                if wrapped > max {
+                    wrapped -= 1i128 << self.bits;
+                }
+                Some(wrapped as i64)
+            }
+            Overflow::Sat => Some(if value > max { max } else { min } as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+// BITFIELD key [GET type offset] [SET type offset value] [INCRBY type offset increment] [OVERFLOW WRAP|SAT|FAIL]
+pub fn bitfield(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    bitfield_generic(ctx, args, false)
+}
+
+pub fn bitfield_ro(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    bitfield_generic(ctx, args, true)
+}
+
+fn bitfield_generic(ctx: &mut Context<'_>, args: &[Bytes], readonly: bool) -> Frame {
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::Wrap;
+    let mut i = 2;
+    while i < args.len() {
+        let sub = args[i].to_ascii_lowercase();
+        let needed = match &sub[..] {
+            b"get" => 2,
+            b"set" | b"incrby" => 3,
+            b"overflow" => 1,
+            _ => return syntax_error(),
+        };
+        if i + needed >= args.len() {
+            return syntax_error();
+        }
+        if readonly && sub != b"get" {
+            return Frame::error("ERR BITFIELD_RO only supports the GET subcommand");
+        }
+        if sub == b"overflow" {
+            overflow = match &args[i + 1].to_ascii_lowercase()[..] {
+                b"wrap" => Overflow::Wrap,
+                b"sat" => Overflow::Sat,
+                b"fail" => Overflow::Fail,
+                _ => return Frame::error("ERR Invalid OVERFLOW type specified"),
+            };
+            i += 2;
+            continue;
+        }
+        let field = match Field::parse(&args[i + 1], &args[i + 2]) {
+            Ok(field) => field,
+            Err(err) => return err,
+        };
+        ops.push(match &sub[..] {
+            b"get" => Op::Get(field),
+            _ => {
+                let value = match parse_i64(&args[i + 3]) {
+                    Ok(value) => value,
+                    Err(err) => return err,
+                };
+                if sub == b"set" {
+                    Op::Set(field, value, overflow)
+                } else {
+                    Op::IncrBy(field, value, overflow)
+                }
+            }
+        });
+        i += needed + 1;
+    }
+
+    // 只有 GET 时不加写，免得修改 CAS
+    if ops.iter().all(|op| matches!(op, Op::Get(_))) {
+        let value = match read(ctx, &args[1]) {
+            Ok(found) => found.unwrap_or_default(),
+            Err(err) => return err,
+        };
+        let get = |op: &Op| match op {
+            Op::Get(field) => Frame::Integer(field.get(&value)),
+            _ => unreachable!(),
+        };
+        return Frame::Array(ops.iter().map(get).collect());
+    }
+    let replies = update(ctx, &args[1], "setbit", |data| {
+        let mut changed = false;
+        let mut replies = Vec::with_capacity(ops.len());
+        for op in ops {
+            replies.push(match op {
+                Op::Get(field) => Frame::Integer(field.get(data)),
+                Op::Set(field, value, overflow) => match field.fit(value as i128, overflow) {
+                    Some(value) => {
+                        let old = field.get(data);
+                        field.set(data, value);
+                        changed = true;
+                        Frame::Integer(old)
+                    }
+                    None => Frame::Null,
+                },
+                Op::IncrBy(field, increment, overflow) => {
+                    match field.fit(field.get(data) as i128 + increment as i128, overflow) {
+                        Some(value) => {
+                            field.set(data, value);
+                            changed = true;
+                            Frame::Integer(value)
+                        }
+                        None => Frame::Null,
+                    }
+                }
+            });
+        }
+        (replies, changed)
+    });
+    match replies {
+        Ok(replies) => Frame::Array(replies),
+        Err(err) => err,
+    }
+}
@@ -0,0 +1,260 @@
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::time::Instant;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::command::{syntax_error, Context};
+use crate::ratelimit::TokenBucket;
+use crate::{acl, tracking};
+use mini_redis::Frame;
+
+/// 单个连接的状态
+pub struct Client {
+    pub id: u64,
+    pub addr: String,
+    pub laddr: String,
+    // tcp / tls / unix
+    pub kind: &'static str,
+    pub name: String,
+    pub user: String,
+    pub authenticated: bool,
+    pub asking: bool,
+    pub created: Instant,
+    pub last_interaction: Instant,
+    pub last_cmd: String,
+    // 2 或 3，由 HELLO 切换
+    pub resp: u8,
+    // 其他连接通过它给这个连接推送消息（订阅消息、缓存失效通知）
+    pub push: UnboundedSender<Frame>,
+    pub inbox: Option<UnboundedReceiver<Frame>>,
+    // 一条命令有多个回复时（例如 SUBSCRIBE 多个频道），排在最终回复之前发送
+    pub replies: Vec<Frame>,
+    pub channels: HashSet<Bytes>,
+    pub patterns: HashSet<Bytes>,
+    pub tracking: Option<tracking::Options>,
+    // CLIENT CACHING yes|no，只对下一条命令有效
+    pub caching: Option<bool>,
+    // client-rate-limit 的令牌桶
+    pub bucket: TokenBucket,
+    // 副本上执行主节点转发命令的伪连接
+    pub master: bool,
+    // 执行过 PSYNC 的副本连接，以及它通过 REPLCONF listening-port 报告的端口
+    pub replica: bool,
+    pub replica_port: u16,
+    // 这条命令不需要回复，例如 REPLCONF ACK
+    pub skip_reply: bool,
+}
+
+/// 登记在 Shared 里的连接快照，供 CLIENT LIST 查看其他连接
+#[derive(Clone)]
+pub struct ClientInfo {
+    id: u64,
+    addr: String,
+    laddr: String,
+    kind: &'static str,
+    name: String,
+    user: String,
+    created: Instant,
+    last_interaction: Instant,
+    last_cmd: String,
+    resp: u8,
+    subscriptions: usize,
+    psubscriptions: usize,
+    tracking: bool,
+    replica: bool,
+    pub push: UnboundedSender<Frame>,
+}
+
+impl Client {
+    pub fn new(id: u64, addr: String, laddr: String, kind: &'static str) -> Client {
+        let now = Instant::now();
+        let (push, inbox) = mpsc::unbounded_channel();
+        Client {
+            id,
+            addr,
+            laddr,
+            kind,
+            name: String::new(),
+            user: "default".to_string(),
+            authenticated: false,
+            asking: false,
+            created: now,
+            last_interaction: now,
+            last_cmd: "NULL".to_string(),
+            resp: 2,
+            push,
+            inbox: Some(inbox),
+            replies: Vec::new(),
+            channels: HashSet::new(),
+            patterns: HashSet::new(),
+            tracking: None,
+            caching: None,
+            bucket: TokenBucket::default(),
+            master: false,
+            replica: false,
+            replica_port: 0,
+            skip_reply: false,
+        }
+    }
+
+    /// 订阅了频道或模式时，RESP2 连接只能执行订阅相关的命令
+    pub fn subscribed(&self) -> bool {
+        !self.channels.is_empty() || !self.patterns.is_empty()
+    }
+
+    pub fn info(&self) -> ClientInfo {
+        ClientInfo {
+            id: self.id,
+            addr: self.addr.clone(),
+            laddr: self.laddr.clone(),
+            kind: self.kind,
+            name: self.name.clone(),
+            user: self.user.clone(),
+            created: self.created,
+            last_interaction: self.last_interaction,
+            last_cmd: self.last_cmd.clone(),
+            resp: self.resp,
+            subscriptions: self.channels.len(),
+            psubscriptions: self.patterns.len(),
+            tracking: self.tracking.is_some(),
+            replica: self.replica,
+            push: self.push.clone(),
+        }
+    }
+}
+
+impl ClientInfo {
+    fn describe(&self) -> String {
+        let now = Instant::now();
+        let mut flags = String::new();
+        if self.subscriptions + self.psubscriptions > 0 {
+            flags.push('P');
+        }
+        if self.replica {
+            flags.push('S');
+        }
+        if self.tracking {
+            flags.push('t');
+        }
+        if self.kind == "unix" {
+            flags.push('U');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db=0 sub={} psub={} user={} resp={} cmd={} kind={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name,
+            now.duration_since(self.created).as_secs(),
+            now.duration_since(self.last_interaction).as_secs(),
+            flags,
+            self.subscriptions,
+            self.psubscriptions,
+            self.user,
+            self.resp,
+            self.last_cmd,
+            self.kind,
+        )
+    }
+}
+
+pub fn client(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    match (&sub[..], args.len()) {
+        (b"id", 2) => Frame::Integer(ctx.client.id as i64),
+        (b"info", 2) => Frame::bulk(format!("{}\n", ctx.client.info().describe())),
+        (b"list", 2) => {
+            let clients = ctx.shared.clients.lock().unwrap();
+            let mut out = String::new();
+            for info in clients.values() {
+                // 自己的快照要到命令执行完才更新，这里直接用当前状态
+                let line = if info.id == ctx.client.id {
+                    ctx.client.info().describe()
+                } else {
+                    info.describe()
+                };
+                out.push_str(&line);
+                out.push('\n');
+            }
+            Frame::bulk(out)
+        }
+        (b"getname", 2) => {
+            if ctx.client.name.is_empty() {
+                Frame::Null
+            } else {
+                Frame::bulk(ctx.client.name.clone())
+            }
+        }
+        (b"setname", 3) => {
+            let name = String::from_utf8_lossy(&args[2]).into_owned();
+            if name.contains(|c: char| c <= ' ' || c > '~') {
+                return Frame::error("ERR Client names cannot contain spaces, newlines or special characters.");
+            }
+            ctx.client.name = name;
+            Frame::ok()
+        }
+        (b"tracking", _) => tracking::client_tracking(ctx, &args[2..]),
+        (b"caching", 3) => tracking::client_caching(ctx, &args[2]),
+        (b"getredir", 2) => match &ctx.client.tracking {
+            Some(options) => Frame::Integer(options.redirect.map_or(0, |id| id as i64)),
+            None => Frame::Integer(-1),
+        },
+        (b"trackinginfo", 2) => tracking::client_trackinginfo(ctx),
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            String::from_utf8_lossy(&args[1])
+        )),
+    }
+}
+
+// HELLO [protover [AUTH username password] [SETNAME clientname]]
+pub fn hello(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut resp = ctx.client.resp;
+    if let Some(version) = args.get(1) {
+        resp = match &version[..] {
+            b"2" => 2,
+            b"3" => 3,
+            _ => return Frame::error("NOPROTO unsupported protocol version"),
+        };
+    }
+    let mut name = None;
+    let mut i = 2;
+    while i < args.len() {
+        match &args[i].to_ascii_lowercase()[..] {
+            b"auth" if i + 2 < args.len() => {
+                let username = String::from_utf8_lossy(&args[i + 1]).into_owned();
+                if let Err(err) = acl::login(ctx, username, &args[i + 2]) {
+                    return err;
+                }
+                i += 3;
+            }
+            b"setname" if i + 1 < args.len() => {
+                name = Some(String::from_utf8_lossy(&args[i + 1]).into_owned());
+                i += 2;
+            }
+            _ => return syntax_error(),
+        }
+    }
+    if !ctx.client.authenticated {
+        return Frame::error("NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time");
+    }
+    if let Some(name) = name {
+        ctx.client.name = name;
+    }
+    ctx.client.resp = resp;
+
+    let mode = if ctx.shared.cluster.is_some() { "cluster" } else { "standalone" };
+    let role = if ctx.shared.replication.lock().unwrap().is_replica() { "replica" } else { "master" };
+    Frame::Map(vec![
+        (Frame::bulk("server"), Frame::bulk("mini-redis")),
+        (Frame::bulk("version"), Frame::bulk(env!("CARGO_PKG_VERSION"))),
+        (Frame::bulk("proto"), Frame::Integer(resp as i64)),
+        (Frame::bulk("id"), Frame::Integer(ctx.client.id as i64)),
+        (Frame::bulk("mode"), Frame::bulk(mode)),
+        (Frame::bulk("role"), Frame::bulk(role)),
+        (Frame::bulk("modules"), Frame::Array(Vec::new())),
+    ])
+}
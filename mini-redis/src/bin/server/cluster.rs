@@ -0,0 +1,647 @@
+use bytes::Bytes;
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::command::{CommandSpec, Context};
+use crate::config::Config;
+use crate::Shared;
+use mini_redis::client::{self, Client};
+use mini_redis::Frame;
+
+pub const SLOTS: usize = 16384;
+
+#[derive(Clone)]
+struct Node {
+    id: String,
+    addr: String,
+    epoch: u64,
+    connected: bool,
+}
+
+pub struct Cluster {
+    myself: String,
+    nodes: BTreeMap<String, Node>,
+    // CLUSTER MEET 过但还没拿到 id 的地址
+    pending: Vec<String>,
+    slots: Vec<Option<String>>,
+    migrating: HashMap<u16, String>,
+    importing: HashMap<u16, String>,
+    current_epoch: u64,
+    config_file: PathBuf,
+}
+
+/// CRC16/XMODEM，和 Redis Cluster 使用的算法一致
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// 告诉其它节点的地址。bind 0.0.0.0 时对方无法连回来，这时没有配置 cluster-announce-ip
+/// 的话，由收到 MEET 的节点换成连接的来源地址
+pub fn announce_addr(config: &Config) -> String {
+    let ip = config.cluster_announce_ip.as_deref().unwrap_or(&config.bind);
+    format!("{}:{}", ip, config.port)
+}
+
+/// 如果 key 中有非空的 `{...}`，只用花括号里的内容计算槽位
+pub fn key_slot(key: &[u8]) -> u16 {
+    let key = match key.iter().position(|&c| c == b'{') {
+        Some(start) => match key[start + 1..].iter().position(|&c| c == b'}') {
+            Some(len) if len > 0 => &key[start + 1..start + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16(key) % SLOTS as u16
+}
+
+fn random_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40)
+        .map(|_| std::char::from_digit(rng.gen_range(0..16), 16).unwrap())
+        .collect()
+}
+
+fn parse_slot(arg: &[u8]) -> Result<u16, Frame> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|s| s.parse::<u16>().ok())
+        .filter(|slot| (*slot as usize) < SLOTS)
+        .ok_or_else(|| Frame::error("ERR Invalid or out of range slot"))
+}
+
+/// 把一行 CLUSTER NODES 输出解析成 (节点, 是否是发送方自己, 它声明拥有的槽位)
+fn parse_node_line(line: &str) -> Option<(Node, bool, Vec<u16>)> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    if parts.len() < 8 {
+        return None;
+    }
+    let addr = parts[1].split('@').next()?.to_string();
+    let myself = parts[2].split(',').any(|flag| flag == "myself");
+    let mut slots = Vec::new();
+    for range in &parts[8..] {
+        // 迁移状态形如 [slot->-id]，不属于槽位归属
+        if range.starts_with('[') {
+            continue;
+        }
+        let (start, end): (u16, u16) = match range.split_once('-') {
+            Some((start, end)) => (start.parse().ok()?, end.parse().ok()?),
+            None => {
+                let slot = range.parse().ok()?;
+                (slot, slot)
+            }
+        };
+        if end as usize >= SLOTS {
+            return None;
+        }
+        slots.extend(start..=end);
+    }
+    let node = Node {
+        id: parts[0].to_string(),
+        addr,
+        epoch: parts[6].parse().ok()?,
+        connected: parts[7] == "connected",
+    };
+    Some((node, myself, slots))
+}
+
+impl Cluster {
+    pub fn load_or_create(config_file: &Path, addr: String) -> crate::Result<Cluster> {
+        let mut cluster = Cluster {
+            myself: String::new(),
+            nodes: BTreeMap::new(),
+            pending: Vec::new(),
+            slots: vec![None; SLOTS],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+            current_epoch: 0,
+            config_file: config_file.to_path_buf(),
+        };
+        if config_file.exists() {
+            let content = std::fs::read_to_string(config_file)?;
+            for line in content.lines() {
+                let (node, myself, slots) = parse_node_line(line)
+                    .ok_or_else(|| format!("{}: invalid line '{}'", config_file.display(), line))?;
+                if myself {
+                    cluster.myself = node.id.clone();
+                }
+                for slot in slots {
+                    cluster.slots[slot as usize] = Some(node.id.clone());
+                }
+                cluster.current_epoch = cluster.current_epoch.max(node.epoch);
+                cluster.nodes.insert(node.id.clone(), node);
+            }
+        }
+        if cluster.myself.is_empty() {
+            cluster.myself = random_id();
+        }
+        let myself = cluster.myself.clone();
+        let node = cluster.nodes.entry(myself.clone()).or_insert(Node {
+            id: myself,
+            addr: String::new(),
+            epoch: 0,
+            connected: true,
+        });
+        // 地址以当前启动参数为准
+        node.addr = addr;
+        cluster.save()?;
+        Ok(cluster)
+    }
+
+    fn save(&self) -> crate::Result<()> {
+        let tmp = self.config_file.with_extension("tmp");
+        std::fs::write(&tmp, self.describe_nodes())?;
+        std::fs::rename(&tmp, &self.config_file)?;
+        Ok(())
+    }
+
+    fn save_or_log(&self) {
+        if let Err(err) = self.save() {
+            tracing::error!(path = %self.config_file.display(), %err, "failed to save cluster config");
+        }
+    }
+
+    fn addr_of(&self, id: &str) -> &str {
+        self.nodes.get(id).map_or("", |node| node.addr.as_str())
+    }
+
+    /// 节点获得新的槽位时递增 epoch，其他节点同步时以更大的 epoch 为准
+    fn bump_epoch(&mut self) {
+        self.current_epoch += 1;
+        let epoch = self.current_epoch;
+        if let Some(node) = self.nodes.get_mut(&self.myself) {
+            node.epoch = epoch;
+        }
+    }
+
+    fn slot_ranges(&self, id: &str) -> Vec<(usize, usize)> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for slot in 0..SLOTS {
+            if self.slots[slot].as_deref() != Some(id) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == slot => *end = slot,
+                _ => ranges.push((slot, slot)),
+            }
+        }
+        ranges
+    }
+
+    pub fn describe_nodes(&self) -> String {
+        let mut out = String::new();
+        for node in self.nodes.values() {
+            let flags = if node.id == self.myself {
+                "myself,master"
+            } else if node.connected {
+                "master"
+            } else {
+                "master,fail?"
+            };
+            let port = node
+                .addr
+                .rsplit_once(':')
+                .and_then(|(_, port)| port.parse::<u32>().ok())
+                .unwrap_or(0);
+            out.push_str(&format!(
+                "{} {}@{} {} - 0 0 {} {}",
+                node.id,
+                node.addr,
+                port + 10000,
+                flags,
+                node.epoch,
+                if node.connected { "connected" } else { "disconnected" },
+            ));
+            for (start, end) in self.slot_ranges(&node.id) {
+                if start == end {
+                    out.push_str(&format!(" {}", start));
+                } else {
+                    out.push_str(&format!(" {}-{}", start, end));
+                }
+            }
+            if node.id == self.myself {
+                for (slot, target) in &self.migrating {
+                    out.push_str(&format!(" [{}->-{}]", slot, target));
+                }
+                for (slot, source) in &self.importing {
+                    out.push_str(&format!(" [{}-<-{}]", slot, source));
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn slots_frame(&self) -> Frame {
+        let mut out = Vec::new();
+        for node in self.nodes.values() {
+            let (host, port) = node.addr.rsplit_once(':').unwrap_or((&node.addr, "0"));
+            for (start, end) in self.slot_ranges(&node.id) {
+                out.push(Frame::Array(vec![
+                    Frame::Integer(start as i64),
+                    Frame::Integer(end as i64),
+                    Frame::Array(vec![
+                        Frame::bulk(host.to_string()),
+                        Frame::Integer(port.parse().unwrap_or(0)),
+                        Frame::bulk(node.id.clone()),
+                    ]),
+                ]));
+            }
+        }
+        out.sort_by_key(|frame| match frame {
+            Frame::Array(parts) => match parts[0] {
+                Frame::Integer(start) => start,
+                _ => 0,
+            },
+            _ => 0,
+        });
+        Frame::Array(out)
+    }
+
+    /// 所有槽都有节点负责
+    pub fn state_ok(&self) -> bool {
+        self.slots.iter().all(|owner| owner.is_some())
+    }
+
+    fn info(&self) -> String {
+        let assigned = self.slots.iter().filter(|owner| owner.is_some()).count();
+        let size = self
+            .nodes
+            .keys()
+            .filter(|id| self.slots.iter().any(|owner| owner.as_deref() == Some(id.as_str())))
+            .count();
+        let my_epoch = self.nodes.get(&self.myself).map_or(0, |node| node.epoch);
+        format!(
+            "cluster_enabled:1\r\ncluster_state:{}\r\ncluster_slots_assigned:{}\r\ncluster_known_nodes:{}\r\ncluster_size:{}\r\ncluster_current_epoch:{}\r\ncluster_my_epoch:{}\r\n",
+            if self.state_ok() { "ok" } else { "fail" },
+            assigned,
+            self.nodes.len(),
+            size,
+            self.current_epoch,
+            my_epoch,
+        )
+    }
+
+    fn meet(&mut self, addr: String) {
+        let known = self.nodes.values().any(|node| node.addr == addr) || self.pending.contains(&addr);
+        if !known {
+            self.pending.push(addr);
+        }
+    }
+
+    /// 自己监听在通配地址上时，用对方连进来的本地地址作为自己的地址
+    fn learn_own_ip(&mut self, laddr: &str) {
+        let local = match laddr.parse::<SocketAddr>() {
+            Ok(local) => local,
+            Err(_) => return,
+        };
+        let node = match self.nodes.get_mut(&self.myself) {
+            Some(node) => node,
+            None => return,
+        };
+        if let Some((ip, port)) = node.addr.rsplit_once(':') {
+            if ip.parse::<IpAddr>().is_ok_and(|ip| ip.is_unspecified()) {
+                let port = port.parse().unwrap_or(0);
+                node.addr = SocketAddr::new(local.ip(), port).to_string();
+            }
+        }
+    }
+
+    /// 合并从 addr 拉取到的 CLUSTER NODES，返回本地视图是否发生变化
+    fn merge(&mut self, addr: &str, text: &str) -> bool {
+        let mut changed = false;
+        for line in text.lines() {
+            let (mut node, myself, slots) = match parse_node_line(line) {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            if node.id == self.myself {
+                continue;
+            }
+            self.current_epoch = self.current_epoch.max(node.epoch);
+            if !myself {
+                // 其他节点只用来发现新成员，槽位以节点自己的声明为准
+                if !self.nodes.contains_key(&node.id) {
+                    node.connected = false;
+                    self.nodes.insert(node.id.clone(), node);
+                    changed = true;
+                }
+                continue;
+            }
+
+            node.addr = addr.to_string();
+            node.connected = true;
+            self.pending.retain(|pending| pending != addr);
+            let id = node.id.clone();
+            let epoch = node.epoch;
+            if self.nodes.get(&id).is_none_or(|old| old.addr != node.addr || old.epoch != node.epoch) {
+                changed = true;
+            }
+            self.nodes.insert(id.clone(), node);
+
+            let mut claimed = vec![false; SLOTS];
+            for &slot in &slots {
+                claimed[slot as usize] = true;
+            }
+            for (owner, claimed) in self.slots.iter_mut().zip(&claimed) {
+                if !claimed && owner.as_deref() == Some(id.as_str()) {
+                    *owner = None;
+                    changed = true;
+                }
+            }
+            for slot in slots {
+                let owner = self.slots[slot as usize].clone();
+                let take = match &owner {
+                    None => true,
+                    Some(owner) if *owner == id => false,
+                    Some(owner) => self.nodes.get(owner).is_none_or(|old| old.epoch < epoch),
+                };
+                if take {
+                    self.slots[slot as usize] = Some(id.clone());
+                    self.migrating.remove(&slot);
+                    self.importing.remove(&slot);
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    fn mark_disconnected(&mut self, addr: &str) {
+        for node in self.nodes.values_mut() {
+            if node.addr == addr && node.id != self.myself {
+                node.connected = false;
+            }
+        }
+    }
+}
+
+/// 不在集群模式下返回 Ok；否则检查 key 是否由本节点负责，不是则返回 MOVED/ASK 重定向
+pub fn route(shared: &Shared, spec: &CommandSpec, args: &[Bytes], asking: bool) -> Result<(), Frame> {
+    let cluster = match &shared.cluster {
+        Some(cluster) => cluster.lock().unwrap(),
+        None => return Ok(()),
+    };
+    let keys = spec.keys(args);
+    let slot = match keys.first() {
+        Some(key) => key_slot(key),
+        None => return Ok(()),
+    };
+    if keys.iter().any(|key| key_slot(key) != slot) {
+        return Err(Frame::error("CROSSSLOT Keys in request don't hash to the same slot"));
+    }
+    let owner = match &cluster.slots[slot as usize] {
+        Some(owner) => owner,
+        None => return Err(Frame::error(format!("CLUSTERDOWN Hash slot {} not served", slot))),
+    };
+    if *owner != cluster.myself {
+        if asking && cluster.importing.contains_key(&slot) {
+            return Ok(());
+        }
+        return Err(Frame::error(format!("MOVED {} {}", slot, cluster.addr_of(owner))));
+    }
+    if let Some(target) = cluster.migrating.get(&slot) {
+        // 迁移中的槽位，MIGRATE 自己总是在本地执行
+        if spec.name == "migrate" {
+            return Ok(());
+        }
+        let mut db = shared.db.lock().unwrap();
+        let missing = keys.iter().any(|key| !db.contains(key));
+        if missing {
+            return Err(Frame::error(format!("ASK {} {}", slot, cluster.addr_of(target))));
+        }
+    }
+    Ok(())
+}
+
+pub fn asking(ctx: &mut Context<'_>, _args: &[Bytes]) -> Frame {
+    if ctx.shared.cluster.is_none() {
+        return Frame::error("ERR This instance has cluster support disabled");
+    }
+    ctx.client.asking = true;
+    Frame::ok()
+}
+
+pub fn cluster(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    if &sub[..] == b"keyslot" && args.len() == 3 {
+        return Frame::Integer(key_slot(&args[2]) as i64);
+    }
+    let mut cluster = match &ctx.shared.cluster {
+        Some(cluster) => cluster.lock().unwrap(),
+        None => return Frame::error("ERR This instance has cluster support disabled"),
+    };
+    let result = match (&sub[..], args.len()) {
+        (b"myid", 2) => Ok(Frame::bulk(cluster.myself.clone())),
+        (b"nodes", 2) => Ok(Frame::bulk(cluster.describe_nodes())),
+        (b"slots", 2) => Ok(cluster.slots_frame()),
+        (b"info", 2) => Ok(Frame::bulk(cluster.info())),
+        (b"meet", 4) => {
+            let ip = String::from_utf8_lossy(&args[2]);
+            match std::str::from_utf8(&args[3]).ok().and_then(|port| port.parse::<u16>().ok()) {
+                Some(port) => {
+                    // 对方监听在通配地址上，改用这条连接的来源地址
+                    let peer = ctx.client.addr.parse::<SocketAddr>().ok();
+                    let addr = match (ip.parse::<IpAddr>(), peer) {
+                        (Ok(ip), Some(peer)) if ip.is_unspecified() => SocketAddr::new(peer.ip(), port).to_string(),
+                        _ => format!("{}:{}", ip, port),
+                    };
+                    cluster.meet(addr);
+                    cluster.learn_own_ip(&ctx.client.laddr);
+                    Ok(Frame::ok())
+                }
+                None => Err(Frame::error("ERR Invalid node address specified")),
+            }
+        }
+        (b"addslots", n) if n > 2 => add_slots(&mut cluster, &args[2..]),
+        (b"delslots", n) if n > 2 => del_slots(&mut cluster, &args[2..]),
+        (b"setslot", n) if n >= 4 => set_slot(&mut cluster, &args[2..]),
+        (b"countkeysinslot", 3) => parse_slot(&args[2]).map(|slot| {
+            let db = ctx.shared.db.lock().unwrap();
+            let count = db.keys().filter(|key| key_slot(key) == slot).count();
+            Frame::Integer(count as i64)
+        }),
+        (b"getkeysinslot", 4) => parse_slot(&args[2]).and_then(|slot| {
+            let count: usize = std::str::from_utf8(&args[3])
+                .ok()
+                .and_then(|count| count.parse().ok())
+                .ok_or_else(|| Frame::error("ERR Invalid number of keys"))?;
+            let db = ctx.shared.db.lock().unwrap();
+            Ok(Frame::Array(
+                db.keys()
+                    .filter(|key| key_slot(key) == slot)
+                    .take(count)
+                    .map(|key| Frame::Bulk(key.clone()))
+                    .collect(),
+            ))
+        }),
+        _ => Err(Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            String::from_utf8_lossy(&args[1])
+        ))),
+    };
+    result.unwrap_or_else(|err| err)
+}
+
+fn add_slots(cluster: &mut Cluster, args: &[Bytes]) -> Result<Frame, Frame> {
+    let slots = args.iter().map(|arg| parse_slot(arg)).collect::<Result<Vec<_>, _>>()?;
+    for slot in &slots {
+        if cluster.slots[*slot as usize].is_some() {
+            return Err(Frame::error(format!("ERR Slot {} is already busy", slot)));
+        }
+    }
+    for slot in slots {
+        cluster.slots[slot as usize] = Some(cluster.myself.clone());
+    }
+    cluster.bump_epoch();
+    cluster.save_or_log();
+    Ok(Frame::ok())
+}
+
+fn del_slots(cluster: &mut Cluster, args: &[Bytes]) -> Result<Frame, Frame> {
+    let slots = args.iter().map(|arg| parse_slot(arg)).collect::<Result<Vec<_>, _>>()?;
+    for slot in &slots {
+        if cluster.slots[*slot as usize].is_none() {
+            return Err(Frame::error(format!("ERR Slot {} is already unassigned", slot)));
+        }
+    }
+    for slot in slots {
+        cluster.slots[slot as usize] = None;
+    }
+    cluster.save_or_log();
+    Ok(Frame::ok())
+}
+
+// CLUSTER SETSLOT slot IMPORTING id | MIGRATING id | NODE id | STABLE
+fn set_slot(cluster: &mut Cluster, args: &[Bytes]) -> Result<Frame, Frame> {
+    let slot = parse_slot(&args[0])?;
+    let action = args[1].to_ascii_lowercase();
+    let id = args.get(2).map(|id| String::from_utf8_lossy(id).into_owned());
+    let mine = cluster.slots[slot as usize].as_deref() == Some(cluster.myself.as_str());
+    match (&action[..], id) {
+        (b"stable", None) => {
+            cluster.migrating.remove(&slot);
+            cluster.importing.remove(&slot);
+        }
+        (b"migrating", Some(id)) => {
+            if !mine {
+                return Err(Frame::error(format!("ERR I'm not the owner of hash slot {}", slot)));
+            }
+            if !cluster.nodes.contains_key(&id) {
+                return Err(Frame::error(format!("ERR I don't know about node {}", id)));
+            }
+            cluster.migrating.insert(slot, id);
+        }
+        (b"importing", Some(id)) => {
+            if mine {
+                return Err(Frame::error(format!("ERR I'm already the owner of hash slot {}", slot)));
+            }
+            if !cluster.nodes.contains_key(&id) {
+                return Err(Frame::error(format!("ERR I don't know about node {}", id)));
+            }
+            cluster.importing.insert(slot, id);
+        }
+        (b"node", Some(id)) => {
+            if !cluster.nodes.contains_key(&id) {
+                return Err(Frame::error(format!("ERR Unknown node {}", id)));
+            }
+            cluster.migrating.remove(&slot);
+            cluster.importing.remove(&slot);
+            let gained = id == cluster.myself && !mine;
+            cluster.slots[slot as usize] = Some(id);
+            if gained {
+                cluster.bump_epoch();
+            }
+        }
+        _ => return Err(crate::command::syntax_error()),
+    }
+    cluster.save_or_log();
+    Ok(Frame::ok())
+}
+
+/// 定期向已知节点拉取 CLUSTER NODES，用来发现新节点和同步槽位归属
+pub async fn gossip(shared: Arc<Shared>) {
+    let cluster = match &shared.cluster {
+        Some(cluster) => cluster,
+        None => return,
+    };
+    let announced = announce_addr(&shared.config);
+    let (ip, port) = announced.rsplit_once(':').unwrap();
+    let mut links: HashMap<String, Client> = HashMap::new();
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let targets: Vec<String> = {
+            let cluster = cluster.lock().unwrap();
+            cluster
+                .nodes
+                .values()
+                .filter(|node| node.id != cluster.myself)
+                .map(|node| node.addr.clone())
+                .chain(cluster.pending.iter().cloned())
+                .collect()
+        };
+        for addr in targets {
+            let fetched = tokio::time::timeout(
+                Duration::from_secs(1),
+                fetch_nodes(&mut links, &addr, ip, port, &shared.config),
+            )
+            .await;
+            let mut cluster = cluster.lock().unwrap();
+            match fetched {
+                Ok(Ok(text)) => {
+                    if cluster.merge(&addr, &text) {
+                        cluster.save_or_log();
+                    }
+                }
+                _ => {
+                    links.remove(&addr);
+                    cluster.mark_disconnected(&addr);
+                }
+            }
+        }
+    }
+}
+
+async fn fetch_nodes(
+    links: &mut HashMap<String, Client>,
+    addr: &str,
+    ip: &str,
+    port: &str,
+    config: &Config,
+) -> crate::Result<String> {
+    if !links.contains_key(addr) {
+        let mut link = client::connect(addr).await?;
+        // 其它节点开启了认证时，和复制一样用 masteruser / masterauth 登录
+        if let Some(password) = &config.masterauth {
+            let mut auth = vec![Bytes::from_static(b"AUTH")];
+            auth.extend(config.masteruser.clone().map(Bytes::from));
+            auth.push(Bytes::from(password.clone()));
+            if let Frame::Error(msg) = link.call(&auth).await? {
+                return Err(format!("AUTH failed: {}", msg).into());
+            }
+        }
+        // 让对方也认识自己，这样 MEET 只需要在一侧执行
+        link.request(&[b"CLUSTER", b"MEET", ip.as_bytes(), port.as_bytes()]).await?;
+        links.insert(addr.to_string(), link);
+    }
+    let link = links.get_mut(addr).unwrap();
+    match link.request(&[b"CLUSTER", b"NODES"]).await? {
+        Frame::Bulk(text) => Ok(String::from_utf8(text.to_vec())?),
+        frame => Err(format!("unexpected CLUSTER NODES reply: {:?}", frame).into()),
+    }
+}
@@ -0,0 +1,604 @@
+use bytes::Bytes;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Instant;
+
+use mini_redis::Frame;
+use crate::client::{self, Client};
+use crate::{
+    acl, bitmap, cluster, config, dump, geo, hash, hll, keyspace, list, migrate, pubsub, replication, script, set, string, tracking,
+    zset, Shared,
+};
+
+pub const WRITE: u32 = 1 << 0;
+pub const READONLY: u32 = 1 << 1;
+pub const FAST: u32 = 1 << 2;
+pub const BLOCKING: u32 = 1 << 3;
+pub const ADMIN: u32 = 1 << 4;
+pub const NO_AUTH: u32 = 1 << 5;
+// key 的位置无法用 first/last/step 描述，需要看具体参数
+pub const MOVABLE_KEYS: u32 = 1 << 6;
+// 不能在脚本里调用
+pub const NOSCRIPT: u32 = 1 << 7;
+// 脚本执行太久时仍然可以执行，例如 SCRIPT KILL
+pub const ALLOW_BUSY: u32 = 1 << 8;
+
+const FLAG_NAMES: &[(u32, &str)] = &[
+    (WRITE, "write"),
+    (READONLY, "readonly"),
+    (FAST, "fast"),
+    (BLOCKING, "blocking"),
+    (ADMIN, "admin"),
+    (NO_AUTH, "no_auth"),
+    (MOVABLE_KEYS, "movablekeys"),
+    (NOSCRIPT, "noscript"),
+    (ALLOW_BUSY, "allow_busy"),
+];
+
+pub struct Context<'a> {
+    pub shared: &'a Arc<Shared>,
+    pub client: &'a mut Client,
+}
+
+pub type BoxFuture = Pin<Box<dyn Future<Output = Frame> + Send>>;
+
+pub enum Handler {
+    Sync(fn(&mut Context<'_>, &[Bytes]) -> Frame),
+    // 需要等待网络等 IO 的命令，不持有连接状态
+    Async(fn(Arc<Shared>, Vec<Bytes>) -> BoxFuture),
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    // 正数表示参数个数必须相等，负数表示至少需要 -arity 个（都包含命令名本身）
+    pub arity: i64,
+    pub flags: u32,
+    pub first_key: i64,
+    pub last_key: i64,
+    pub step: i64,
+    pub group: &'static str,
+    pub summary: &'static str,
+    pub handler: Handler,
+}
+
+macro_rules! command {
+    ($name:expr, $arity:expr, $flags:expr, ($first:expr, $last:expr, $step:expr), $group:expr, async $handler:path, $summary:expr) => {
+        CommandSpec {
+            name: $name,
+            arity: $arity,
+            flags: $flags,
+            first_key: $first,
+            last_key: $last,
+            step: $step,
+            group: $group,
+            summary: $summary,
+            handler: Handler::Async(|shared, args| Box::pin($handler(shared, args))),
+        }
+    };
+    ($name:expr, $arity:expr, $flags:expr, ($first:expr, $last:expr, $step:expr), $group:expr, $handler:path, $summary:expr) => {
+        CommandSpec {
+            name: $name,
+            arity: $arity,
+            flags: $flags,
+            first_key: $first,
+            last_key: $last,
+            step: $step,
+            group: $group,
+            summary: $summary,
+            handler: Handler::Sync($handler),
+        }
+    };
+}
+
+pub static COMMANDS: &[CommandSpec] = &[
+    command!("asking", 1, FAST, (0, 0, 0), "cluster", cluster::asking, "Sent by cluster clients after an -ASK redirect"),
+    command!("acl", -2, ADMIN | NOSCRIPT, (0, 0, 0), "server", acl::acl, "Manage access control users and permissions"),
+    command!("auth", -2, NO_AUTH | FAST | NOSCRIPT, (0, 0, 0), "connection", acl::auth, "Authenticate to the server"),
+    command!("bitcount", -2, READONLY, (1, 1, 1), "bitmap", bitmap::bitcount, "Count set bits in a string"),
+    command!("bitfield", -2, WRITE, (1, 1, 1), "bitmap", bitmap::bitfield, "Perform arbitrary bitfield integer operations on strings"),
+    command!("bitfield_ro", -2, READONLY | FAST, (1, 1, 1), "bitmap", bitmap::bitfield_ro, "Perform arbitrary read-only bitfield integer operations on strings"),
+    command!("bitop", -4, WRITE, (2, -1, 1), "bitmap", bitmap::bitop, "Perform bitwise operations between strings"),
+    command!("bitpos", -3, READONLY, (1, 1, 1), "bitmap", bitmap::bitpos, "Find the first bit set or clear in a string"),
+    command!("client", -2, NOSCRIPT, (0, 0, 0), "connection", client::client, "Inspect and manage client connections"),
+    command!("cluster", -2, ADMIN, (0, 0, 0), "cluster", cluster::cluster, "Inspect and manage the cluster"),
+    command!("command", -1, 0, (0, 0, 0), "server", command, "Get array of command details"),
+    command!("config", -2, ADMIN | NOSCRIPT, (0, 0, 0), "server", config::config, "Get or set configuration parameters"),
+    command!("copy", -3, WRITE, (1, 2, 1), "keyspace", keyspace::copy, "Copy the value stored at the source key to the destination key"),
+    command!("dbsize", 1, READONLY | FAST, (0, 0, 0), "server", keyspace::dbsize, "Return the number of keys in the database"),
+    command!("decr", 2, WRITE | FAST, (1, 1, 1), "string", string::incr, "Decrement the integer value of a key by one"),
+    command!("decrby", 3, WRITE | FAST, (1, 1, 1), "string", string::incr, "Decrement the integer value of a key by a number"),
+    command!("del", -2, WRITE, (1, -1, 1), "keyspace", keyspace::del, "Delete keys"),
+    command!("dump", 2, READONLY, (1, 1, 1), "keyspace", dump::dump, "Return a serialized version of the value stored at a key"),
+    command!("eval", -3, NOSCRIPT | MOVABLE_KEYS, (0, 0, 0), "scripting", script::eval, "Execute a server-side script"),
+    command!("evalsha", -3, NOSCRIPT | MOVABLE_KEYS, (0, 0, 0), "scripting", script::evalsha, "Execute a cached server-side script by its SHA1 digest"),
+    command!("exists", -2, READONLY | FAST, (1, -1, 1), "keyspace", keyspace::exists, "Determine how many of the keys exist"),
+    command!("expire", -3, WRITE | FAST, (1, 1, 1), "keyspace", keyspace::expire, "Set a key's time to live in seconds"),
+    command!("expireat", -3, WRITE | FAST, (1, 1, 1), "keyspace", keyspace::expireat, "Set the expiration for a key as a UNIX timestamp"),
+    command!("expiretime", 2, READONLY | FAST, (1, 1, 1), "keyspace", keyspace::expiretime, "Get the expiration UNIX timestamp of a key"),
+    command!("getbit", 3, READONLY | FAST, (1, 1, 1), "bitmap", bitmap::getbit, "Return the bit value at offset in the string value stored at key"),
+    command!("hdel", -3, WRITE | FAST, (1, 1, 1), "hash", hash::hdel, "Delete one or more fields from a hash"),
+    command!("hexists", 3, READONLY | FAST, (1, 1, 1), "hash", hash::hexists, "Determine whether a field exists in a hash"),
+    command!("hget", 3, READONLY | FAST, (1, 1, 1), "hash", hash::hget, "Return the value of a field in a hash"),
+    command!("hgetall", 2, READONLY, (1, 1, 1), "hash", hash::hgetall, "Return all fields and values in a hash"),
+    command!("hincrby", 4, WRITE | FAST, (1, 1, 1), "hash", hash::hincrby, "Increment the integer value of a field in a hash by a number"),
+    command!("hkeys", 2, READONLY, (1, 1, 1), "hash", hash::hgetall, "Return all fields in a hash"),
+    command!("hlen", 2, READONLY | FAST, (1, 1, 1), "hash", hash::hlen, "Return the number of fields in a hash"),
+    command!("hmget", -3, READONLY | FAST, (1, 1, 1), "hash", hash::hmget, "Return the values of all the given fields in a hash"),
+    command!("hmset", -4, WRITE | FAST, (1, 1, 1), "hash", hash::hset, "Deprecated alias of HSET that replies OK"),
+    command!("hset", -4, WRITE | FAST, (1, 1, 1), "hash", hash::hset, "Set the values of one or more fields in a hash"),
+    command!("hsetnx", 4, WRITE | FAST, (1, 1, 1), "hash", hash::hsetnx, "Set the value of a field in a hash only when the field doesn't exist"),
+    command!("hstrlen", 3, READONLY | FAST, (1, 1, 1), "hash", hash::hstrlen, "Return the length of the value of a field in a hash"),
+    command!("hvals", 2, READONLY, (1, 1, 1), "hash", hash::hgetall, "Return all values in a hash"),
+    command!("incr", 2, WRITE | FAST, (1, 1, 1), "string", string::incr, "Increment the integer value of a key by one"),
+    command!("incrby", 3, WRITE | FAST, (1, 1, 1), "string", string::incr, "Increment the integer value of a key by a number"),
+    command!("info", -1, 0, (0, 0, 0), "server", info, "Get information and statistics about the server"),
+    command!("hello", -1, NO_AUTH | FAST | NOSCRIPT, (0, 0, 0), "connection", client::hello, "Handshake with the server and switch the protocol version"),
+    command!("geoadd", -5, WRITE, (1, 1, 1), "geo", geo::geoadd, "Add geospatial items to a key"),
+    command!("geodist", -4, READONLY, (1, 1, 1), "geo", geo::geodist, "Return the distance between two members of a geospatial index"),
+    command!("geohash", -2, READONLY, (1, 1, 1), "geo", geo::geohash, "Return members of a geospatial index as geohash strings"),
+    command!("geopos", -2, READONLY, (1, 1, 1), "geo", geo::geopos, "Return the longitude and latitude of members of a geospatial index"),
+    command!("geosearch", -7, READONLY, (1, 1, 1), "geo", geo::geosearch, "Query a geospatial index for members inside an area of a box or a circle"),
+    command!("geosearchstore", -8, WRITE, (1, 2, 1), "geo", geo::geosearchstore, "Query a geospatial index and store the result in a key"),
+    command!("get", 2, READONLY | FAST, (1, 1, 1), "string", string::get, "Get the value of a key"),
+    command!("lindex", 3, READONLY, (1, 1, 1), "list", list::lindex, "Return an element from a list by its index"),
+    command!("linsert", 5, WRITE, (1, 1, 1), "list", list::linsert, "Insert an element before or after another element in a list"),
+    command!("llen", 2, READONLY | FAST, (1, 1, 1), "list", list::llen, "Return the length of a list"),
+    command!("lpop", -2, WRITE | FAST, (1, 1, 1), "list", list::pop, "Remove and return the first elements of a list"),
+    command!("lpush", -3, WRITE | FAST, (1, 1, 1), "list", list::push, "Prepend one or more elements to a list"),
+    command!("lpushx", -3, WRITE | FAST, (1, 1, 1), "list", list::push, "Prepend one or more elements to a list only when the list exists"),
+    command!("lrange", 4, READONLY, (1, 1, 1), "list", list::lrange, "Return a range of elements from a list"),
+    command!("lrem", 4, WRITE, (1, 1, 1), "list", list::lrem, "Remove elements from a list"),
+    command!("lset", 4, WRITE, (1, 1, 1), "list", list::lset, "Set the value of an element in a list by its index"),
+    command!("ltrim", 4, WRITE, (1, 1, 1), "list", list::ltrim, "Remove elements from both ends of a list"),
+    command!("memory", -2, READONLY, (2, 2, 1), "server", keyspace::memory, "Inspect the memory usage of a key"),
+    command!("migrate", -6, WRITE | MOVABLE_KEYS, (3, 3, 1), "keyspace", async migrate::migrate, "Atomically transfer keys to another instance"),
+    command!("persist", 2, WRITE | FAST, (1, 1, 1), "keyspace", keyspace::persist, "Remove the expiration from a key"),
+    command!("pexpire", -3, WRITE | FAST, (1, 1, 1), "keyspace", keyspace::pexpire, "Set a key's time to live in milliseconds"),
+    command!("pexpireat", -3, WRITE | FAST, (1, 1, 1), "keyspace", keyspace::pexpireat, "Set the expiration for a key as a UNIX timestamp in milliseconds"),
+    command!("pexpiretime", 2, READONLY | FAST, (1, 1, 1), "keyspace", keyspace::pexpiretime, "Get the expiration UNIX timestamp of a key in milliseconds"),
+    command!("pfadd", -2, WRITE | FAST, (1, 1, 1), "hyperloglog", hll::pfadd, "Add elements to a HyperLogLog key"),
+    command!("pfcount", -2, READONLY, (1, -1, 1), "hyperloglog", hll::pfcount, "Return the approximated cardinality of the union of HyperLogLog keys"),
+    command!("pfdebug", 3, ADMIN, (2, 2, 1), "hyperloglog", hll::pfdebug, "Internal commands for debugging HyperLogLog values"),
+    command!("pfmerge", -2, WRITE, (1, -1, 1), "hyperloglog", hll::pfmerge, "Merge HyperLogLog keys into a single one"),
+    command!("pfselftest", 1, ADMIN, (0, 0, 0), "hyperloglog", hll::pfselftest, "Run the HyperLogLog self tests"),
+    command!("object", -2, READONLY, (2, 2, 1), "keyspace", keyspace::object, "Inspect the internals of the value stored at a key"),
+    command!("ping", -1, FAST, (0, 0, 0), "connection", ping, "Ping the server"),
+    command!("psync", -3, ADMIN | NOSCRIPT, (0, 0, 0), "server", replication::psync, "An internal command used in replication"),
+    command!("psubscribe", -2, NOSCRIPT, (0, 0, 0), "pubsub", pubsub::psubscribe, "Listen for messages published to channels matching the patterns"),
+    command!("pttl", 2, READONLY | FAST, (1, 1, 1), "keyspace", keyspace::pttl, "Get the time to live for a key in milliseconds"),
+    command!("publish", 3, FAST, (0, 0, 0), "pubsub", pubsub::publish_command, "Post a message to a channel"),
+    command!("pubsub", -2, 0, (0, 0, 0), "pubsub", pubsub::pubsub, "Inspect the state of the Pub/Sub subsystem"),
+    command!("punsubscribe", -1, NOSCRIPT, (0, 0, 0), "pubsub", pubsub::punsubscribe, "Stop listening for messages posted to channels matching the patterns"),
+    command!("randomkey", 1, READONLY, (0, 0, 0), "keyspace", keyspace::randomkey, "Return a random key name from the database"),
+    command!("rename", 3, WRITE, (1, 2, 1), "keyspace", keyspace::rename, "Rename a key and overwrite the destination"),
+    command!("renamenx", 3, WRITE | FAST, (1, 2, 1), "keyspace", keyspace::renamenx, "Rename a key only when the target key name doesn't exist"),
+    command!("replconf", -2, ADMIN | NOSCRIPT, (0, 0, 0), "server", replication::replconf, "An internal command for configuring the replication stream"),
+    command!("replicaof", 3, ADMIN | NOSCRIPT, (0, 0, 0), "server", replication::replicaof, "Configure a server as replica of another, or promote it to a master"),
+    command!("restore", -4, WRITE, (1, 1, 1), "keyspace", dump::restore, "Create a key from the serialized representation of a value"),
+    command!("role", 1, FAST | NOSCRIPT, (0, 0, 0), "server", replication::role, "Return the role of the instance in the context of replication"),
+    command!("rpop", -2, WRITE | FAST, (1, 1, 1), "list", list::pop, "Remove and return the last elements of a list"),
+    command!("rpush", -3, WRITE | FAST, (1, 1, 1), "list", list::push, "Append one or more elements to a list"),
+    command!("rpushx", -3, WRITE | FAST, (1, 1, 1), "list", list::push, "Append one or more elements to a list only when the list exists"),
+    command!("sadd", -3, WRITE | FAST, (1, 1, 1), "set", set::sadd, "Add one or more members to a set"),
+    command!("scard", 2, READONLY | FAST, (1, 1, 1), "set", set::scard, "Return the number of members in a set"),
+    command!("script", -2, NOSCRIPT | ALLOW_BUSY, (0, 0, 0), "scripting", script::script, "Manage the server-side script cache"),
+    command!("sdiff", -2, READONLY, (1, -1, 1), "set", set::combine, "Return the difference of multiple sets"),
+    command!("set", -3, WRITE, (1, 1, 1), "string", string::set, "Set the string value of a key"),
+    command!("sinter", -2, READONLY, (1, -1, 1), "set", set::combine, "Return the intersection of multiple sets"),
+    command!("sismember", 3, READONLY | FAST, (1, 1, 1), "set", set::sismember, "Determine whether a member belongs to a set"),
+    command!("setbit", 4, WRITE, (1, 1, 1), "bitmap", bitmap::setbit, "Set or clear the bit at offset in the string value stored at key"),
+    command!("smembers", 2, READONLY, (1, 1, 1), "set", set::smembers, "Return all members of a set"),
+    command!("smismember", -3, READONLY | FAST, (1, 1, 1), "set", set::smismember, "Determine whether multiple members belong to a set"),
+    command!("srem", -3, WRITE | FAST, (1, 1, 1), "set", set::srem, "Remove one or more members from a set"),
+    command!("slaveof", 3, ADMIN | NOSCRIPT, (0, 0, 0), "server", replication::replicaof, "Deprecated alias of REPLICAOF"),
+    command!("subscribe", -2, NOSCRIPT, (0, 0, 0), "pubsub", pubsub::subscribe, "Listen for messages published to the channels"),
+    command!("sunion", -2, READONLY, (1, -1, 1), "set", set::combine, "Return the union of multiple sets"),
+    command!("touch", -2, READONLY | FAST, (1, -1, 1), "keyspace", keyspace::touch, "Update the last access time of keys and return the number of existing keys"),
+    command!("ttl", 2, READONLY | FAST, (1, 1, 1), "keyspace", keyspace::ttl, "Get the time to live for a key in seconds"),
+    command!("type", 2, READONLY | FAST, (1, 1, 1), "keyspace", keyspace::key_type, "Determine the type of value stored at a key"),
+    command!("unlink", -2, WRITE | FAST, (1, -1, 1), "keyspace", keyspace::unlink, "Asynchronously delete keys"),
+    command!("unsubscribe", -1, NOSCRIPT, (0, 0, 0), "pubsub", pubsub::unsubscribe, "Stop listening for messages posted to the channels"),
+    command!("wait", 3, BLOCKING | NOSCRIPT, (0, 0, 0), "keyspace", async replication::wait, "Wait for the replication of all preceding write commands"),
+    command!("zadd", -4, WRITE | FAST, (1, 1, 1), "sortedset", zset::zadd, "Add one or more members to a sorted set, or update their scores"),
+    command!("zcard", 2, READONLY | FAST, (1, 1, 1), "sortedset", zset::zcard, "Return the number of members in a sorted set"),
+    command!("zincrby", 4, WRITE | FAST, (1, 1, 1), "sortedset", zset::zincrby, "Increment the score of a member in a sorted set"),
+    command!("zrange", -4, READONLY, (1, 1, 1), "sortedset", zset::zrange, "Return members in a sorted set within a range of indexes"),
+    command!("zrem", -3, WRITE | FAST, (1, 1, 1), "sortedset", zset::zrem, "Remove one or more members from a sorted set"),
+    command!("zscore", 3, READONLY | FAST, (1, 1, 1), "sortedset", zset::zscore, "Return the score of a member in a sorted set"),
+];
+
+// RESP2 连接进入订阅状态后只能执行这些命令
+const SUBSCRIBED_COMMANDS: &[&str] = &["ping", "psubscribe", "punsubscribe", "subscribe", "unsubscribe"];
+
+pub fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    COMMANDS
+        .iter()
+        .find(|spec| spec.name.as_bytes().eq_ignore_ascii_case(name))
+}
+
+impl CommandSpec {
+    pub fn has_flag(&self, flag: u32) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn check_arity(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    /// 按 first_key/last_key/step 取出参数中的 key，供 ACL、复制和集群路由复用
+    pub fn keys<'a>(&self, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+        if self.has_flag(MOVABLE_KEYS) {
+            return movable_keys(self.name, args);
+        }
+        if self.first_key <= 0 || self.step <= 0 {
+            return Vec::new();
+        }
+        let last = if self.last_key < 0 {
+            args.len() as i64 + self.last_key
+        } else {
+            self.last_key.min(args.len() as i64 - 1)
+        };
+        (self.first_key..=last)
+            .step_by(self.step as usize)
+            .filter_map(|i| args.get(i as usize))
+            .collect()
+    }
+
+    fn flag_names(&self) -> Vec<Frame> {
+        FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.has_flag(*flag))
+            .map(|(_, name)| Frame::Simple(name.to_string()))
+            .collect()
+    }
+
+    fn info(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::bulk(self.name),
+            Frame::Integer(self.arity),
+            Frame::Array(self.flag_names()),
+            Frame::Integer(self.first_key),
+            Frame::Integer(self.last_key),
+            Frame::Integer(self.step),
+        ])
+    }
+
+    fn docs(&self) -> Frame {
+        Frame::Array(vec![
+            Frame::bulk("summary"),
+            Frame::bulk(self.summary),
+            Frame::bulk("group"),
+            Frame::bulk(self.group),
+        ])
+    }
+}
+
+fn movable_keys<'a>(name: &str, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+    match name {
+        // MIGRATE host port key|"" db timeout [COPY] [REPLACE] [AUTH password | AUTH2 username password] [KEYS key ...]
+        "migrate" => {
+            let mut i = 6;
+            while i < args.len() {
+                // 跳过密码，免得密码恰好是 "keys"
+                match &args[i].to_ascii_lowercase()[..] {
+                    b"auth" => i += 1,
+                    b"auth2" => i += 2,
+                    b"keys" if args[3].is_empty() => return args[i + 1..].iter().collect(),
+                    _ => {}
+                }
+                i += 1;
+            }
+            args.get(3).filter(|key| !key.is_empty()).into_iter().collect()
+        }
+        // EVAL script numkeys [key ...] [arg ...]
+        "eval" | "evalsha" => {
+            let numkeys = std::str::from_utf8(&args[2]).ok().and_then(|n| n.parse::<usize>().ok());
+            match numkeys {
+                Some(n) if n <= args.len() - 3 => args[3..3 + n].iter().collect(),
+                _ => Vec::new(),
+            }
+        }
+        _ => Vec::new(),
+    }
+}
+
+pub fn parse_args(frame: Frame) -> Result<Vec<Bytes>, String> {
+    let parts = match frame {
+        Frame::Array(parts) => parts,
+        frame => return Err(format!("ERR Protocol error: expected array, got {:?}", frame)),
+    };
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Frame::Bulk(data) => Ok(data),
+            Frame::Simple(s) => Ok(Bytes::from(s)),
+            Frame::Integer(n) => Ok(Bytes::from(n.to_string())),
+            _ => Err("ERR Protocol error: expected bulk string".to_string()),
+        })
+        .collect()
+}
+
+pub async fn execute(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let name = match args.first() {
+        Some(name) => name,
+        None => return Frame::error("ERR empty command"),
+    };
+    let spec = match lookup(name) {
+        Some(spec) => spec,
+        None => {
+            return Frame::error(format!(
+                "ERR unknown command '{}'",
+                String::from_utf8_lossy(name)
+            ))
+        }
+    };
+    if !spec.check_arity(args.len()) {
+        return wrong_arity(spec.name);
+    }
+    // ASKING 只对紧接着的下一条命令有效
+    let asking = spec.name != "asking" && std::mem::take(&mut ctx.client.asking);
+    if !spec.has_flag(NO_AUTH) {
+        if !ctx.client.authenticated {
+            return Frame::error("NOAUTH Authentication required.");
+        }
+        if let Err(denied) = check_permission(ctx, spec, args) {
+            return denied;
+        }
+    }
+    if let Err(err) = replication::check(ctx, spec) {
+        return err;
+    }
+    if ctx.client.resp < 3 && ctx.client.subscribed() && !SUBSCRIBED_COMMANDS.contains(&spec.name) {
+        return Frame::error(format!(
+            "ERR Can't execute '{}': only (P)SUBSCRIBE / (P)UNSUBSCRIBE / PING are allowed in this context",
+            spec.name
+        ));
+    }
+    if let Err(redirect) = cluster::route(ctx.shared, spec, args, asking) {
+        return redirect;
+    }
+    let _guard = match script::lock(ctx.shared, spec).await {
+        Ok(guard) => guard,
+        Err(busy) => return busy,
+    };
+    if spec.has_flag(WRITE) && !ctx.shared.evict() {
+        return Frame::error("OOM command not allowed when used memory > 'maxmemory'.");
+    }
+    let start = Instant::now();
+    let response = match spec.handler {
+        Handler::Sync(handler) => handler(ctx, args),
+        Handler::Async(handler) => handler(ctx.shared.clone(), args.to_vec()).await,
+    };
+    let (elapsed, failed) = (start.elapsed(), matches!(response, Frame::Error(_)));
+    // MIGRATE 自己转发被迁移走的 key 的 DEL
+    if spec.has_flag(WRITE) && !failed && spec.name != "migrate" {
+        replication::propagate(ctx.shared, args);
+    }
+    ctx.shared.stats.record(spec.name, elapsed, failed);
+    tracing::debug!(?elapsed, failed, "command executed");
+    ctx.shared.flush_expired();
+    // CLIENT CACHING 只影响紧接着的下一条命令
+    let caching_command = spec.name == "client" && args[1].eq_ignore_ascii_case(b"caching");
+    if ctx.client.tracking.is_some() && !caching_command {
+        let caching = ctx.client.caching.take();
+        if spec.has_flag(READONLY) && !matches!(response, Frame::Error(_)) {
+            tracking::remember(ctx, spec, args, caching);
+        }
+    }
+    response
+}
+
+/// 按 ACL 检查当前用户能否执行这条命令、访问这些 key
+pub fn check_permission(ctx: &Context<'_>, spec: &CommandSpec, args: &[Bytes]) -> Result<(), Frame> {
+    let mut acl = ctx.shared.acl.lock().unwrap();
+    if let Err(denied) = acl.check(&ctx.client.user, spec, args) {
+        acl.log_denied(denied.reason, &denied.object, &ctx.client.user, &ctx.client.addr);
+        return Err(match denied.reason {
+            "key" => Frame::error("NOPERM No permissions to access a key"),
+            _ => Frame::error(format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                ctx.client.user, spec.name
+            )),
+        });
+    }
+    Ok(())
+}
+
+pub fn wrong_arity(name: &str) -> Frame {
+    Frame::error(format!(
+        "ERR wrong number of arguments for '{}' command",
+        name
+    ))
+}
+
+pub fn syntax_error() -> Frame {
+    Frame::error("ERR syntax error")
+}
+
+pub fn wrong_type() -> Frame {
+    Frame::error("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+fn ping(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    // 订阅状态下的 RESP2 连接回复 ["pong", message]
+    if ctx.client.resp < 3 && ctx.client.subscribed() {
+        let message = args.get(1).cloned().unwrap_or_default();
+        return Frame::Array(vec![Frame::bulk("pong"), Frame::Bulk(message)]);
+    }
+    match args.len() {
+        1 => Frame::Simple("PONG".to_string()),
+        2 => Frame::Bulk(args[1].clone()),
+        _ => wrong_arity("ping"),
+    }
+}
+
+// INFO [section ...]，目前只有 replication 一节
+fn info(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let all = args.len() == 1
+        || args[1..].iter().any(|arg| matches!(&arg.to_ascii_lowercase()[..], b"all" | b"default" | b"everything"));
+    let mut out = String::new();
+    let sections = [
+        ("Memory", keyspace::memory_info as fn(&Shared) -> String),
+        ("Replication", replication::info),
+    ];
+    for (title, section) in sections {
+        if all || args[1..].iter().any(|arg| arg.eq_ignore_ascii_case(title.as_bytes())) {
+            if !out.is_empty() {
+                out.push_str("\r\n");
+            }
+            out.push_str(&format!("# {}\r\n", title));
+            out.push_str(&section(ctx.shared));
+        }
+    }
+    Frame::bulk(out)
+}
+
+fn command(_ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    if args.len() == 1 {
+        return Frame::Array(COMMANDS.iter().map(CommandSpec::info).collect());
+    }
+    let sub = args[1].to_ascii_lowercase();
+    let names = &args[2..];
+    match &sub[..] {
+        b"count" if names.is_empty() => Frame::Integer(COMMANDS.len() as i64),
+        b"info" => {
+            if names.is_empty() {
+                return Frame::Array(COMMANDS.iter().map(CommandSpec::info).collect());
+            }
+            Frame::Array(
+                names
+                    .iter()
+                    .map(|name| lookup(name).map_or(Frame::Null, CommandSpec::info))
+                    .collect(),
+            )
+        }
+        b"docs" => {
+            let mut out = Vec::new();
+            let specs: Vec<&CommandSpec> = if names.is_empty() {
+                COMMANDS.iter().collect()
+            } else {
+                names.iter().filter_map(|name| lookup(name)).collect()
+            };
+            for spec in specs {
+                out.push(Frame::bulk(spec.name));
+                out.push(spec.docs());
+            }
+            Frame::Array(out)
+        }
+        b"getkeys" if !names.is_empty() => {
+            let spec = match lookup(&names[0]) {
+                Some(spec) => spec,
+                None => return Frame::error("ERR Invalid command specified"),
+            };
+            if !spec.check_arity(names.len()) {
+                return Frame::error("ERR Invalid number of arguments specified for command");
+            }
+            let keys = spec.keys(names);
+            if keys.is_empty() {
+                return Frame::error("ERR The command has no key arguments");
+            }
+            Frame::Array(keys.into_iter().map(|key| Frame::Bulk(key.clone())).collect())
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand '{}'",
+            String::from_utf8_lossy(&args[1])
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<Bytes> {
+        args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect()
+    }
+
+    fn keys(command: &[&str]) -> Vec<String> {
+        let args = args(command);
+        let spec = lookup(command[0].as_bytes()).unwrap();
+        spec.keys(&args).into_iter().map(|key| String::from_utf8_lossy(key).into_owned()).collect()
+    }
+
+    #[test]
+    fn arity() {
+        // 正数要求参数个数相等，负数是最少个数，都包含命令名
+        let get = lookup(b"GET").unwrap();
+        assert!(!get.check_arity(1) && get.check_arity(2) && !get.check_arity(3));
+        let del = lookup(b"del").unwrap();
+        assert!(!del.check_arity(1) && del.check_arity(2) && del.check_arity(10));
+        let ping = lookup(b"ping").unwrap();
+        assert!(ping.check_arity(1) && ping.check_arity(2));
+        assert!(lookup(b"nope").is_none());
+    }
+
+    #[test]
+    fn first_last_step_keys() {
+        assert_eq!(keys(&["get", "a"]), ["a"]);
+        assert_eq!(keys(&["set", "a", "v", "EX", "10"]), ["a"]);
+        // last 为负数时从末尾数
+        assert_eq!(keys(&["del", "a", "b", "c"]), ["a", "b", "c"]);
+        assert_eq!(keys(&["bitop", "AND", "dest", "x", "y"]), ["dest", "x", "y"]);
+        assert_eq!(keys(&["copy", "a", "b", "REPLACE"]), ["a", "b"]);
+        assert_eq!(keys(&["memory", "usage", "a"]), ["a"]);
+        assert!(keys(&["ping"]).is_empty());
+
+        // step 大于 1 时跳过中间的值
+        let mset = CommandSpec {
+            name: "mset",
+            arity: -3,
+            flags: WRITE,
+            first_key: 1,
+            last_key: -1,
+            step: 2,
+            group: "string",
+            summary: "",
+            handler: Handler::Sync(|_, _| Frame::ok()),
+        };
+        let command = args(&["mset", "a", "1", "b", "2", "c", "3"]);
+        assert_eq!(mset.keys(&command), [&command[1], &command[3], &command[5]]);
+    }
+
+    #[test]
+    fn movable_keys() {
+        assert_eq!(keys(&["eval", "return 1", "2", "a", "b", "arg"]), ["a", "b"]);
+        assert_eq!(keys(&["evalsha", "abc", "1", "a"]), ["a"]);
+        assert!(keys(&["eval", "return 1", "0", "arg"]).is_empty());
+        // numkeys 超过参数个数或者不是数字时没有 key
+        assert!(keys(&["eval", "return 1", "3", "a"]).is_empty());
+        assert!(keys(&["eval", "return 1", "x", "a"]).is_empty());
+
+        assert_eq!(keys(&["migrate", "host", "6379", "a", "0", "1000"]), ["a"]);
+        assert_eq!(keys(&["migrate", "host", "6379", "", "0", "1000", "COPY", "KEYS", "a", "b"]), ["a", "b"]);
+        // 密码恰好是 keys 时不能当成 KEYS 选项
+        assert_eq!(
+            keys(&["migrate", "host", "6379", "", "0", "1000", "AUTH", "keys", "KEYS", "a"]),
+            ["a"]
+        );
+        assert_eq!(
+            keys(&["migrate", "host", "6379", "", "0", "1000", "AUTH2", "user", "keys", "KEYS", "a", "b"]),
+            ["a", "b"]
+        );
+        assert!(keys(&["migrate", "host", "6379", "", "0", "1000"]).is_empty());
+    }
+
+    #[test]
+    fn every_command_is_consistent() {
+        for spec in COMMANDS {
+            assert_eq!(spec.name, spec.name.to_ascii_lowercase());
+            assert!(spec.arity != 0, "{}", spec.name);
+            assert!(!(spec.has_flag(WRITE) && spec.has_flag(READONLY)), "{}", spec.name);
+            if spec.first_key > 0 && !spec.has_flag(MOVABLE_KEYS) {
+                assert!(spec.step > 0, "{}", spec.name);
+                assert!(spec.last_key < 0 || spec.last_key >= spec.first_key, "{}", spec.name);
+            }
+            assert_eq!(COMMANDS.iter().filter(|other| other.name == spec.name).count(), 1, "{}", spec.name);
+        }
+    }
+}
@@ -0,0 +1,458 @@
+use bytes::Bytes;
+use mini_redis::tls::ClientAuth;
+use mini_redis::Frame;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::command::Context;
+use crate::db::{Policy, Thresholds};
+use crate::glob::glob_match;
+use crate::logging::{self, Format};
+use crate::notify;
+use crate::ratelimit::Action;
+
+/// 启动参数，既可以写在配置文件里（每行 `name value`），也可以用 `--name value` 覆盖
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind: String,
+    pub port: u16,
+    pub requirepass: Option<String>,
+    pub aclfile: Option<PathBuf>,
+    pub tls_port: Option<u16>,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    pub tls_ca_cert_file: Option<PathBuf>,
+    pub tls_auth_clients: ClientAuth,
+    pub unixsocket: Option<PathBuf>,
+    pub unixsocketperm: Option<u32>,
+    pub cluster_enabled: bool,
+    pub cluster_config_file: PathBuf,
+    // 告诉其它节点的地址，None 时用 bind
+    pub cluster_announce_ip: Option<String>,
+    pub notify_keyspace_events: u32,
+    // 0 表示不限制
+    pub maxmemory: usize,
+    pub maxmemory_policy: Policy,
+    // 毫秒
+    pub busy_reply_threshold: u64,
+    // 提供 /metrics 和 /health 的 HTTP 端口，None 表示不开启
+    pub metrics_port: Option<u16>,
+    // memcached 文本协议的端口，None 表示不开启
+    pub memcache_port: Option<u16>,
+    // HTTP/JSON 网关的端口，None 表示不开启
+    pub http_port: Option<u16>,
+    // tracing 的过滤规则，loglevel 会被转换成这种格式
+    pub loglevel: String,
+    // None 时写到标准输出
+    pub logfile: Option<PathBuf>,
+    pub log_format: Format,
+    pub maxclients: usize,
+    // 空闲多少秒后断开连接，0 表示不断开
+    pub timeout: u64,
+    // 每个连接、每个 ACL 用户每秒的命令数，0 表示不限制
+    pub client_rate_limit: u64,
+    pub user_rate_limit: u64,
+    pub rate_limit_burst: u64,
+    pub rate_limit_action: Action,
+    // 启动时就作为副本复制这个主节点
+    pub replicaof: Option<(String, u16)>,
+    // 连接主节点时使用的认证信息
+    pub masterauth: Option<String>,
+    pub masteruser: Option<String>,
+    pub replica_read_only: bool,
+    pub replica_serve_stale_data: bool,
+    // *-max-listpack-* 这组紧凑编码的阈值
+    pub thresholds: Thresholds,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "127.0.0.1".to_string(),
+            port: 6379,
+            requirepass: None,
+            aclfile: None,
+            tls_port: None,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: ClientAuth::No,
+            unixsocket: None,
+            unixsocketperm: None,
+            cluster_enabled: false,
+            cluster_config_file: PathBuf::from("nodes.conf"),
+            cluster_announce_ip: None,
+            notify_keyspace_events: 0,
+            maxmemory: 0,
+            maxmemory_policy: Policy::NoEviction,
+            busy_reply_threshold: 5000,
+            metrics_port: None,
+            memcache_port: None,
+            http_port: None,
+            loglevel: "info".to_string(),
+            logfile: None,
+            log_format: Format::Text,
+            maxclients: 10000,
+            timeout: 0,
+            client_rate_limit: 0,
+            user_rate_limit: 0,
+            rate_limit_burst: 0,
+            rate_limit_action: Action::Throttle,
+            replicaof: None,
+            masterauth: None,
+            masteruser: None,
+            replica_read_only: true,
+            replica_serve_stale_data: true,
+            thresholds: Thresholds::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn from_args() -> crate::Result<Config> {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1).peekable();
+
+        if let Some(path) = args.next_if(|arg| !arg.starts_with("--")) {
+            let content = std::fs::read_to_string(&path)?;
+            for (lineno, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                config
+                    .set(name, value.trim())
+                    .map_err(|e| format!("{}:{}: {}", path, lineno + 1, e))?;
+            }
+        }
+
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => return Err(format!("unexpected argument '{}'", arg).into()),
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| format!("missing value for '--{}'", name))?;
+            config.set(name, &value)?;
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> crate::Result<()> {
+        match name.to_ascii_lowercase().as_str() {
+            "bind" => self.bind = value.to_string(),
+            "port" => self.port = value.parse()?,
+            "requirepass" => self.requirepass = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "aclfile" => self.aclfile = Some(PathBuf::from(value)),
+            "tls-port" => self.tls_port = Some(value.parse()?).filter(|port| *port != 0),
+            "tls-cert-file" => self.tls_cert_file = Some(PathBuf::from(value)),
+            "tls-key-file" => self.tls_key_file = Some(PathBuf::from(value)),
+            "tls-ca-cert-file" => self.tls_ca_cert_file = Some(PathBuf::from(value)),
+            "tls-auth-clients" => {
+                self.tls_auth_clients = match value.to_ascii_lowercase().as_str() {
+                    "yes" => ClientAuth::Required,
+                    "optional" => ClientAuth::Optional,
+                    "no" => ClientAuth::No,
+                    _ => return Err(format!("invalid tls-auth-clients '{}'", value).into()),
+                }
+            }
+            "unixsocket" => self.unixsocket = Some(PathBuf::from(value)),
+            // 和 chmod 一样用八进制，例如 700
+            "unixsocketperm" => self.unixsocketperm = Some(u32::from_str_radix(value, 8)?),
+            "cluster-enabled" => self.cluster_enabled = parse_bool(value)?,
+            "cluster-config-file" => self.cluster_config_file = PathBuf::from(value),
+            "cluster-announce-ip" => self.cluster_announce_ip = Some(value.to_string()),
+            "notify-keyspace-events" => {
+                self.notify_keyspace_events = notify::parse_flags(value)
+                    .ok_or_else(|| format!("invalid notify-keyspace-events '{}'", value))?
+            }
+            "maxmemory" => self.maxmemory = parse_memory(value)?,
+            "maxmemory-policy" => {
+                self.maxmemory_policy =
+                    Policy::parse(value).ok_or_else(|| format!("invalid maxmemory-policy '{}'", value))?
+            }
+            // lua-time-limit 是旧名字
+            "busy-reply-threshold" | "lua-time-limit" => self.busy_reply_threshold = value.parse()?,
+            "metrics-port" => self.metrics_port = Some(value.parse()?).filter(|port| *port != 0),
+            "memcache-port" => self.memcache_port = Some(value.parse()?).filter(|port| *port != 0),
+            "http-port" => self.http_port = Some(value.parse()?).filter(|port| *port != 0),
+            "loglevel" => self.loglevel = logging::directive(value)?,
+            "logfile" => self.logfile = Some(PathBuf::from(value)).filter(|path| !path.as_os_str().is_empty()),
+            "log-format" => {
+                self.log_format = Format::parse(value).ok_or_else(|| format!("invalid log-format '{}'", value))?
+            }
+            "maxclients" => {
+                self.maxclients = value.parse()?;
+                if self.maxclients == 0 {
+                    return Err("maxclients must be at least 1".into());
+                }
+            }
+            "timeout" => self.timeout = value.parse()?,
+            "client-rate-limit" => self.client_rate_limit = value.parse()?,
+            "user-rate-limit" => self.user_rate_limit = value.parse()?,
+            "rate-limit-burst" => self.rate_limit_burst = value.parse()?,
+            "rate-limit-action" => {
+                self.rate_limit_action =
+                    Action::parse(value).ok_or_else(|| format!("invalid rate-limit-action '{}'", value))?
+            }
+            // 配置文件里是 `replicaof host port`，命令行参数写成 `--replicaof "host port"`
+            "replicaof" | "slaveof" => {
+                self.replicaof = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                    [host, port] if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") => None,
+                    [host, port] => Some((host.to_string(), port.parse()?)),
+                    _ => return Err(format!("invalid replicaof '{}', expected host and port", value).into()),
+                }
+            }
+            "masterauth" => self.masterauth = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "masteruser" => self.masteruser = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "replica-read-only" | "slave-read-only" => self.replica_read_only = parse_bool(value)?,
+            "replica-serve-stale-data" | "slave-serve-stale-data" => self.replica_serve_stale_data = parse_bool(value)?,
+            // ziplist 是 Redis 7 之前的名字
+            "zset-max-listpack-entries" | "zset-max-ziplist-entries" => self.thresholds.zset_max_listpack_entries = value.parse()?,
+            "zset-max-listpack-value" | "zset-max-ziplist-value" => self.thresholds.zset_max_listpack_value = value.parse()?,
+            "hash-max-listpack-entries" | "hash-max-ziplist-entries" => self.thresholds.hash_max_listpack_entries = value.parse()?,
+            "hash-max-listpack-value" | "hash-max-ziplist-value" => self.thresholds.hash_max_listpack_value = value.parse()?,
+            "set-max-intset-entries" => self.thresholds.set_max_intset_entries = value.parse()?,
+            "set-max-listpack-entries" => self.thresholds.set_max_listpack_entries = value.parse()?,
+            "set-max-listpack-value" => self.thresholds.set_max_listpack_value = value.parse()?,
+            "list-max-listpack-size" | "list-max-ziplist-size" => {
+                self.thresholds.list_max_listpack_size = match value.parse()? {
+                    0 | ..-5 => return Err(format!("invalid list-max-listpack-size '{}', expected a positive count or -1 to -5", value).into()),
+                    size => size,
+                }
+            }
+            _ => return Err(format!("unknown config option '{}'", name).into()),
+        }
+        Ok(())
+    }
+}
+
+// 和 redis.conf 一样支持 1k、1kb、1m、1mb、1g、1gb
+fn parse_memory(value: &str) -> crate::Result<usize> {
+    let lower = value.to_ascii_lowercase();
+    let split = lower.find(|c: char| !c.is_ascii_digit()).unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(split);
+    let multiplier = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid memory size '{}'", value).into()),
+    };
+    number
+        .parse::<usize>()?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("memory size '{}' is too large", value).into())
+}
+
+fn parse_bool(value: &str) -> crate::Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(format!("expected yes or no, got '{}'", value).into()),
+    }
+}
+
+/// 运行中可以通过 CONFIG SET 修改的参数，其余参数只能在启动时指定
+fn runtime_params(ctx: &Context<'_>) -> Vec<(&'static str, String)> {
+    let db = ctx.shared.db.lock().unwrap();
+    let limits = ctx.shared.limits.lock().unwrap();
+    let replication = ctx.shared.replication.lock().unwrap();
+    let yes_no = |value: bool| if value { "yes" } else { "no" }.to_string();
+    vec![
+        (
+            "notify-keyspace-events",
+            notify::format_flags(ctx.shared.notify_flags.load(Ordering::Relaxed)),
+        ),
+        ("maxmemory", db.maxmemory.to_string()),
+        ("maxmemory-policy", db.policy.name().to_string()),
+        (
+            "busy-reply-threshold",
+            ctx.shared.scripts.lock().unwrap().busy_threshold.as_millis().to_string(),
+        ),
+        ("maxclients", ctx.shared.maxclients.load(Ordering::Relaxed).to_string()),
+        ("timeout", ctx.shared.timeout.load(Ordering::Relaxed).to_string()),
+        ("client-rate-limit", limits.per_client.to_string()),
+        ("user-rate-limit", limits.per_user.to_string()),
+        ("rate-limit-burst", limits.burst.to_string()),
+        ("rate-limit-action", limits.action.name().to_string()),
+        ("replica-read-only", yes_no(replication.read_only)),
+        ("replica-serve-stale-data", yes_no(replication.serve_stale_data)),
+        ("zset-max-listpack-entries", db.thresholds.zset_max_listpack_entries.to_string()),
+        ("zset-max-listpack-value", db.thresholds.zset_max_listpack_value.to_string()),
+        ("hash-max-listpack-entries", db.thresholds.hash_max_listpack_entries.to_string()),
+        ("hash-max-listpack-value", db.thresholds.hash_max_listpack_value.to_string()),
+        ("set-max-intset-entries", db.thresholds.set_max_intset_entries.to_string()),
+        ("set-max-listpack-entries", db.thresholds.set_max_listpack_entries.to_string()),
+        ("set-max-listpack-value", db.thresholds.set_max_listpack_value.to_string()),
+        ("list-max-listpack-size", db.thresholds.list_max_listpack_size.to_string()),
+    ]
+}
+
+fn static_params(config: &Config) -> Vec<(&'static str, String)> {
+    let path = |path: &Option<PathBuf>| path.as_ref().map(|p| p.display().to_string()).unwrap_or_default();
+    vec![
+        ("bind", config.bind.clone()),
+        ("port", config.port.to_string()),
+        ("requirepass", config.requirepass.clone().unwrap_or_default()),
+        ("aclfile", path(&config.aclfile)),
+        ("tls-port", config.tls_port.unwrap_or(0).to_string()),
+        ("unixsocket", path(&config.unixsocket)),
+        ("cluster-enabled", if config.cluster_enabled { "yes" } else { "no" }.to_string()),
+        ("cluster-config-file", config.cluster_config_file.display().to_string()),
+        ("cluster-announce-ip", config.cluster_announce_ip.clone().unwrap_or_default()),
+        ("masteruser", config.masteruser.clone().unwrap_or_default()),
+    ]
+}
+
+// CONFIG GET pattern [pattern ...] | CONFIG SET name value [name value ...]
+pub fn config(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    match &sub[..] {
+        b"get" if args.len() > 2 => {
+            let mut params = runtime_params(ctx);
+            params.extend(static_params(&ctx.shared.config));
+            let mut out = Vec::new();
+            for (name, value) in params {
+                if args[2..].iter().any(|pattern| glob_match(&pattern.to_ascii_lowercase(), name.as_bytes())) {
+                    out.push((Frame::bulk(name), Frame::bulk(value)));
+                }
+            }
+            Frame::Map(out)
+        }
+        b"set" if args.len() > 2 && args.len().is_multiple_of(2) => {
+            // 先全部检查一遍，保证要么都生效要么都不生效
+            let mut changes = Vec::new();
+            for pair in args[2..].chunks(2) {
+                let name = String::from_utf8_lossy(&pair[0]).to_ascii_lowercase();
+                let value = String::from_utf8_lossy(&pair[1]).into_owned();
+                let mut config = Config::default();
+                let runtime = matches!(
+                    name.as_str(),
+                    "notify-keyspace-events"
+                        | "maxmemory"
+                        | "maxmemory-policy"
+                        | "busy-reply-threshold"
+                        | "lua-time-limit"
+                        | "maxclients"
+                        | "timeout"
+                        | "client-rate-limit"
+                        | "user-rate-limit"
+                        | "rate-limit-burst"
+                        | "rate-limit-action"
+                        | "replica-read-only"
+                        | "replica-serve-stale-data"
+                        | "zset-max-listpack-entries"
+                        | "zset-max-ziplist-entries"
+                        | "zset-max-listpack-value"
+                        | "zset-max-ziplist-value"
+                        | "hash-max-listpack-entries"
+                        | "hash-max-ziplist-entries"
+                        | "hash-max-listpack-value"
+                        | "hash-max-ziplist-value"
+                        | "set-max-intset-entries"
+                        | "set-max-listpack-entries"
+                        | "set-max-listpack-value"
+                        | "list-max-listpack-size"
+                        | "list-max-ziplist-size"
+                );
+                if !runtime || config.set(&name, &value).is_err() {
+                    return Frame::error(format!(
+                        "ERR CONFIG SET failed (possibly related to argument '{}') - {}",
+                        name,
+                        if runtime { "argument couldn't be parsed" } else { "unsupported or read-only parameter" }
+                    ));
+                }
+                changes.push((name, config));
+            }
+            for (name, config) in changes {
+                match name.as_str() {
+                    "notify-keyspace-events" => ctx
+                        .shared
+                        .notify_flags
+                        .store(config.notify_keyspace_events, Ordering::Relaxed),
+                    "maxmemory" => ctx.shared.db.lock().unwrap().maxmemory = config.maxmemory,
+                    "busy-reply-threshold" | "lua-time-limit" => {
+                        ctx.shared.scripts.lock().unwrap().busy_threshold =
+                            Duration::from_millis(config.busy_reply_threshold)
+                    }
+                    "maxmemory-policy" => ctx.shared.db.lock().unwrap().policy = config.maxmemory_policy,
+                    "maxclients" => ctx.shared.maxclients.store(config.maxclients, Ordering::Relaxed),
+                    "timeout" => ctx.shared.timeout.store(config.timeout, Ordering::Relaxed),
+                    "client-rate-limit" => ctx.shared.limits.lock().unwrap().per_client = config.client_rate_limit,
+                    "user-rate-limit" => ctx.shared.limits.lock().unwrap().per_user = config.user_rate_limit,
+                    "rate-limit-burst" => ctx.shared.limits.lock().unwrap().burst = config.rate_limit_burst,
+                    "rate-limit-action" => ctx.shared.limits.lock().unwrap().action = config.rate_limit_action,
+                    "replica-read-only" => ctx.shared.replication.lock().unwrap().read_only = config.replica_read_only,
+                    "replica-serve-stale-data" => {
+                        ctx.shared.replication.lock().unwrap().serve_stale_data = config.replica_serve_stale_data
+                    }
+                    // 只影响之后的插入，已有的集合保持原来的编码
+                    "zset-max-listpack-entries" | "zset-max-ziplist-entries" => {
+                        ctx.shared.db.lock().unwrap().thresholds.zset_max_listpack_entries =
+                            config.thresholds.zset_max_listpack_entries
+                    }
+                    "zset-max-listpack-value" | "zset-max-ziplist-value" => {
+                        ctx.shared.db.lock().unwrap().thresholds.zset_max_listpack_value =
+                            config.thresholds.zset_max_listpack_value
+                    }
+                    "hash-max-listpack-entries" | "hash-max-ziplist-entries" => {
+                        ctx.shared.db.lock().unwrap().thresholds.hash_max_listpack_entries =
+                            config.thresholds.hash_max_listpack_entries
+                    }
+                    "hash-max-listpack-value" | "hash-max-ziplist-value" => {
+                        ctx.shared.db.lock().unwrap().thresholds.hash_max_listpack_value =
+                            config.thresholds.hash_max_listpack_value
+                    }
+                    "set-max-intset-entries" => {
+                        ctx.shared.db.lock().unwrap().thresholds.set_max_intset_entries =
+                            config.thresholds.set_max_intset_entries
+                    }
+                    "set-max-listpack-entries" => {
+                        ctx.shared.db.lock().unwrap().thresholds.set_max_listpack_entries =
+                            config.thresholds.set_max_listpack_entries
+                    }
+                    "set-max-listpack-value" => {
+                        ctx.shared.db.lock().unwrap().thresholds.set_max_listpack_value =
+                            config.thresholds.set_max_listpack_value
+                    }
+                    "list-max-listpack-size" | "list-max-ziplist-size" => {
+                        ctx.shared.db.lock().unwrap().thresholds.list_max_listpack_size =
+                            config.thresholds.list_max_listpack_size
+                    }
+                    _ => unreachable!("checked above"),
+                }
+            }
+            Frame::ok()
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            String::from_utf8_lossy(&args[1])
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_memory;
+
+    #[test]
+    fn memory_units() {
+        assert_eq!(parse_memory("100").unwrap(), 100);
+        assert_eq!(parse_memory("1k").unwrap(), 1000);
+        assert_eq!(parse_memory("2MB").unwrap(), 2 * 1024 * 1024);
+        assert!(parse_memory("1tb").is_err());
+    }
+
+    #[test]
+    fn memory_overflow_is_an_error() {
+        assert!(parse_memory(&format!("{}gb", usize::MAX / 1024)).is_err());
+        assert!(parse_memory(&format!("{}b", usize::MAX)).is_ok());
+    }
+}
@@ -0,0 +1,600 @@
+use bytes::Bytes;
+use rand::Rng;
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::hash::Hash;
+use crate::list::List;
+use crate::set::Set;
+use crate::zset::ZSet;
+
+// 估算内存时每个 key 额外计入的固定开销（哈希表槽位、Entry 本身等）
+const ENTRY_OVERHEAD: usize = 64;
+// 淘汰时每轮随机抽取的候选 key 数
+const EVICTION_SAMPLES: usize = 5;
+// LFU 计数器的初始值、增长的对数因子和衰减周期，取 Redis 的默认值
+const LFU_INIT: u8 = 5;
+const LFU_LOG_FACTOR: f64 = 10.0;
+const LFU_DECAY: Duration = Duration::from_secs(60);
+
+pub struct Entry {
+    pub value: Value,
+    pub expires_at: Option<Instant>,
+    // 最近一次被命令访问的时间，OBJECT IDLETIME 和 LRU 淘汰使用
+    pub accessed: Instant,
+    // 对数增长的访问计数，没有访问时每分钟减一
+    freq: u8,
+    // memcached 客户端保存的 flags，RESP 写入的值是 0
+    pub flags: u32,
+    // 值每次被修改都会换一个新的版本号，memcached 的 gets / cas 使用
+    pub cas: u64,
+    // 在 Db::keys 和 Db::volatile 里的下标，随机抽样用
+    slot: usize,
+    volatile_slot: Option<usize>,
+}
+
+impl Entry {
+    fn new(value: Value, expires_at: Option<Instant>) -> Entry {
+        Entry { value, expires_at, accessed: Instant::now(), freq: LFU_INIT, flags: 0, cas: 0, slot: 0, volatile_slot: None }
+    }
+
+    /// 衰减之后的 LFU 计数，OBJECT FREQ 返回这个值
+    pub fn frequency(&self) -> u8 {
+        let periods = self.accessed.elapsed().as_secs() / LFU_DECAY.as_secs();
+        self.freq.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    fn touch(&mut self) {
+        let mut freq = self.frequency();
+        // 计数越大，增长的概率越小，255 可以表示上百万次访问
+        let p = 1.0 / (freq.saturating_sub(LFU_INIT) as f64 * LFU_LOG_FACTOR + 1.0);
+        if freq < u8::MAX && rand::thread_rng().gen::<f64>() < p {
+            freq += 1;
+        }
+        self.freq = freq;
+        self.accessed = Instant::now();
+    }
+}
+
+#[derive(Clone)]
+pub enum Value {
+    String(Bytes),
+    Hash(Hash),
+    List(List),
+    Set(Set),
+    ZSet(ZSet),
+}
+
+/// 哈希、列表和集合，写命令通过 keyspace::update 统一处理新建 key 和删除变空的 key
+pub trait Collection: Default + Into<Value> {
+    fn cast(value: &Value) -> Option<&Self>;
+    fn cast_mut(value: &mut Value) -> Option<&mut Self>;
+    fn is_empty(&self) -> bool;
+}
+
+impl Value {
+    pub fn as_string(&self) -> Option<&Bytes> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_zset(&self) -> Option<&ZSet> {
+        match self {
+            Value::ZSet(zset) => Some(zset),
+            _ => None,
+        }
+    }
+
+    pub fn as_zset_mut(&mut self) -> Option<&mut ZSet> {
+        match self {
+            Value::ZSet(zset) => Some(zset),
+            _ => None,
+        }
+    }
+
+    /// OBJECT ENCODING 返回的内部编码
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::String(value) if value.len() <= 20 && std::str::from_utf8(value).is_ok_and(|v| v.parse::<i64>().is_ok()) => {
+                "int"
+            }
+            Value::String(value) if value.len() <= 44 => "embstr",
+            Value::String(_) => "raw",
+            Value::Hash(hash) => hash.encoding(),
+            Value::List(list) => list.encoding(),
+            Value::Set(set) => set.encoding(),
+            Value::ZSet(zset) => zset.encoding(),
+        }
+    }
+
+    /// 释放这个值需要回收的内存块数，UNLINK 据此决定是否交给后台释放
+    pub fn free_effort(&self) -> usize {
+        match self {
+            Value::String(_) => 1,
+            Value::Hash(hash) => hash.len(),
+            Value::List(list) => list.len(),
+            Value::Set(set) => set.len(),
+            Value::ZSet(zset) => zset.len(),
+        }
+    }
+
+    /// TYPE 命令返回的类型名
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Hash(_) => "hash",
+            Value::List(_) => "list",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Value::String(value) => value.len(),
+            Value::Hash(hash) => hash.size(),
+            Value::List(list) => list.size(),
+            Value::Set(set) => set.size(),
+            Value::ZSet(zset) => zset.size(),
+        }
+    }
+}
+
+impl From<Bytes> for Value {
+    fn from(value: Bytes) -> Value {
+        Value::String(value)
+    }
+}
+
+/// 达到 maxmemory 之后的淘汰策略
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Policy {
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl Policy {
+    pub fn parse(value: &str) -> Option<Policy> {
+        match value.to_ascii_lowercase().as_str() {
+            "noeviction" => Some(Policy::NoEviction),
+            "allkeys-lru" => Some(Policy::AllKeysLru),
+            "volatile-lru" => Some(Policy::VolatileLru),
+            "allkeys-lfu" => Some(Policy::AllKeysLfu),
+            "volatile-lfu" => Some(Policy::VolatileLfu),
+            "allkeys-random" => Some(Policy::AllKeysRandom),
+            "volatile-random" => Some(Policy::VolatileRandom),
+            "volatile-ttl" => Some(Policy::VolatileTtl),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Policy::NoEviction => "noeviction",
+            Policy::AllKeysLru => "allkeys-lru",
+            Policy::VolatileLru => "volatile-lru",
+            Policy::AllKeysLfu => "allkeys-lfu",
+            Policy::VolatileLfu => "volatile-lfu",
+            Policy::AllKeysRandom => "allkeys-random",
+            Policy::VolatileRandom => "volatile-random",
+            Policy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    pub fn is_lfu(self) -> bool {
+        matches!(self, Policy::AllKeysLfu | Policy::VolatileLfu)
+    }
+}
+
+/// 小集合使用紧凑编码的上限，超过任意一项就转成普通编码，之后不再转回去。
+/// 每次插入都要检查，所以和 maxmemory 一样放在 Db 里，持有锁的时候直接读取
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub zset_max_listpack_entries: usize,
+    pub zset_max_listpack_value: usize,
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    // 集合的成员都是整数时使用 intset 编码
+    pub set_max_intset_entries: usize,
+    pub set_max_listpack_entries: usize,
+    pub set_max_listpack_value: usize,
+    // 正数是每个 listpack 的项数上限，-1 到 -5 是 4KB 到 64KB 的字节数上限
+    pub list_max_listpack_size: i64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Thresholds {
+        Thresholds {
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            set_max_intset_entries: 512,
+            set_max_listpack_entries: 128,
+            set_max_listpack_value: 64,
+            list_max_listpack_size: -2,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Stats {
+    pub expired_keys: u64,
+    pub evicted_keys: u64,
+}
+
+/// 键空间，读取时顺带删除已经过期的 key（被动过期），后台任务定期清理其余的（主动过期）
+pub struct Db {
+    entries: HashMap<Bytes, Entry>,
+    // 按过期时间排序，主动过期时从头开始扫描
+    expirations: BTreeSet<(Instant, Bytes)>,
+    // 所有 key 和有过期时间的 key，可以按下标随机访问，淘汰抽样和 RANDOMKEY 不用遍历哈希表
+    keys: Vec<Bytes>,
+    volatile: Vec<Bytes>,
+    used_memory: usize,
+    pub maxmemory: usize,
+    pub policy: Policy,
+    pub thresholds: Thresholds,
+    // 被动过期删除的 key，等命令执行完后统一发通知
+    expired: Vec<Bytes>,
+    // 最近分配的 cas 版本号
+    cas: u64,
+    pub stats: Stats,
+}
+
+impl Db {
+    pub fn new(maxmemory: usize, policy: Policy, thresholds: Thresholds) -> Db {
+        Db {
+            entries: HashMap::new(),
+            expirations: BTreeSet::new(),
+            keys: Vec::new(),
+            volatile: Vec::new(),
+            used_memory: 0,
+            maxmemory,
+            policy,
+            thresholds,
+            expired: Vec::new(),
+            cas: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    /// 读取 key 并更新访问时间和访问计数
+    pub fn get(&mut self, key: &[u8]) -> Option<&Entry> {
+        self.expire_if_needed(key);
+        let entry = self.entries.get_mut(key)?;
+        entry.touch();
+        Some(entry)
+    }
+
+    /// 读取 key 但不算作一次访问，给 OBJECT、TTL 这类查看元信息的命令使用
+    pub fn peek(&mut self, key: &[u8]) -> Option<&Entry> {
+        self.expire_if_needed(key);
+        self.entries.get(key)
+    }
+
+    pub fn contains(&mut self, key: &[u8]) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// 写入 key，返回旧的值（已过期的不算）
+    pub fn set(&mut self, key: Bytes, value: impl Into<Value>, expires_at: Option<Instant>) -> Option<Entry> {
+        let previous = self.remove(&key);
+        self.insert(key, Entry::new(value.into(), expires_at));
+        previous
+    }
+
+    fn insert(&mut self, key: Bytes, mut entry: Entry) {
+        self.cas += 1;
+        entry.cas = self.cas;
+        if let Some(when) = entry.expires_at {
+            self.expirations.insert((when, key.clone()));
+            entry.volatile_slot = Some(self.volatile.len());
+            self.volatile.push(key.clone());
+        } else {
+            entry.volatile_slot = None;
+        }
+        entry.slot = self.keys.len();
+        self.keys.push(key.clone());
+        self.used_memory += entry_size(&key, &entry.value);
+        self.entries.insert(key, entry);
+    }
+
+    // 从哈希表和各个索引里删除 key，不检查是否过期
+    fn unlink(&mut self, key: &[u8]) -> Option<(Bytes, Entry)> {
+        let (key, entry) = self.entries.remove_entry(key)?;
+        if let Some(when) = entry.expires_at {
+            self.expirations.remove(&(when, key.clone()));
+        }
+        if let Some(moved) = swap_remove(&mut self.keys, entry.slot) {
+            self.entries.get_mut(moved).unwrap().slot = entry.slot;
+        }
+        if let Some(slot) = entry.volatile_slot {
+            if let Some(moved) = swap_remove(&mut self.volatile, slot) {
+                self.entries.get_mut(moved).unwrap().volatile_slot = Some(slot);
+            }
+        }
+        self.used_memory -= entry_size(&key, &entry.value);
+        Some((key, entry))
+    }
+
+    /// 把 from 连同过期时间和访问信息一起改名为 to，返回被覆盖的旧值
+    pub fn rename(&mut self, from: &[u8], to: Bytes) -> Option<Entry> {
+        let entry = self.remove(from)?;
+        let previous = self.remove(&to);
+        self.insert(to, entry);
+        previous
+    }
+
+    /// 随机返回一个没有过期的 key
+    pub fn random_key(&mut self) -> Option<Bytes> {
+        loop {
+            let key = pick(&self.keys)?.clone();
+            // 抽到已经过期的 key 时顺便删掉，重新抽
+            if self.contains(&key) {
+                return Some(key);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Entry> {
+        self.expire_if_needed(key);
+        self.unlink(key).map(|(_, entry)| entry)
+    }
+
+    /// 原地修改 key 的值并更新内存统计，key 不存在时返回 None
+    pub fn modify<R>(&mut self, key: &[u8], f: impl FnOnce(&mut Value) -> R) -> Option<R> {
+        let result = self.rewrite(key, f)?;
+        self.cas += 1;
+        self.entries.get_mut(key)?.cas = self.cas;
+        Some(result)
+    }
+
+    /// 和 modify 一样原地修改，但不算内容变化，不换 cas 版本号。
+    /// 给 PFCOUNT 回写缓存的基数这类只改内部缓存的读命令使用
+    pub fn rewrite<R>(&mut self, key: &[u8], f: impl FnOnce(&mut Value) -> R) -> Option<R> {
+        self.expire_if_needed(key);
+        let entry = self.entries.get_mut(key)?;
+        let before = entry.value.size();
+        let result = f(&mut entry.value);
+        self.used_memory = self.used_memory - before + entry.value.size();
+        Some(result)
+    }
+
+    /// 设置 RESTORE 带来的访问信息：空闲时间或者 LFU 计数
+    pub fn set_access(&mut self, key: &[u8], idle: Option<Duration>, freq: Option<u8>) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            if let Some(accessed) = idle.and_then(|idle| Instant::now().checked_sub(idle)) {
+                entry.accessed = accessed;
+            }
+            if let Some(freq) = freq {
+                entry.freq = freq;
+            }
+        }
+    }
+
+    pub fn set_flags(&mut self, key: &[u8], flags: u32) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.flags = flags;
+        }
+    }
+
+    /// 修改过期时间，key 不存在时返回 false
+    pub fn set_expiry(&mut self, key: &[u8], expires_at: Option<Instant>) -> bool {
+        self.expire_if_needed(key);
+        let (key, entry) = match self.entries.get_key_value(key) {
+            Some((key, entry)) => (key.clone(), entry),
+            None => return false,
+        };
+        let (previous, volatile_slot) = (entry.expires_at, entry.volatile_slot);
+        if let Some(when) = previous {
+            self.expirations.remove(&(when, key.clone()));
+        }
+        if let Some(when) = expires_at {
+            self.expirations.insert((when, key.clone()));
+        }
+        let volatile_slot = match (volatile_slot, expires_at) {
+            (None, Some(_)) => {
+                self.volatile.push(key.clone());
+                Some(self.volatile.len() - 1)
+            }
+            (Some(slot), None) => {
+                if let Some(moved) = swap_remove(&mut self.volatile, slot) {
+                    self.entries.get_mut(moved).unwrap().volatile_slot = Some(slot);
+                }
+                None
+            }
+            (slot, _) => slot,
+        };
+        let entry = self.entries.get_mut(&key).unwrap();
+        entry.expires_at = expires_at;
+        entry.volatile_slot = volatile_slot;
+        true
+    }
+
+    /// 所有没有过期的 key
+    pub fn keys(&self) -> impl Iterator<Item = &Bytes> {
+        let now = Instant::now();
+        self.entries
+            .iter()
+            .filter(move |(_, entry)| entry.expires_at.is_none_or(|when| when > now))
+            .map(|(key, _)| key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn expires(&self) -> usize {
+        self.expirations.len()
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
+    }
+
+    /// 一个 key 估算占用的内存，和 used_memory 的算法一致
+    pub fn usage(&mut self, key: &[u8]) -> Option<usize> {
+        self.peek(key).map(|entry| entry_size(key, &entry.value))
+    }
+
+    fn expire_if_needed(&mut self, key: &[u8]) {
+        let expired = self
+            .entries
+            .get(key)
+            .and_then(|entry| entry.expires_at)
+            .is_some_and(|when| when <= Instant::now());
+        if expired {
+            let (key, _) = self.unlink(key).unwrap();
+            self.stats.expired_keys += 1;
+            self.expired.push(key);
+        }
+    }
+
+    /// 取出被动过期删除的 key
+    pub fn take_expired(&mut self) -> Vec<Bytes> {
+        std::mem::take(&mut self.expired)
+    }
+
+    /// 删除所有到期的 key，返回被删除的 key
+    pub fn purge_expired(&mut self, now: Instant) -> Vec<Bytes> {
+        let mut purged = self.take_expired();
+        while let Some((when, key)) = self.expirations.first().cloned() {
+            if when > now {
+                break;
+            }
+            self.unlink(&key);
+            self.stats.expired_keys += 1;
+            purged.push(key);
+        }
+        purged
+    }
+
+    /// 超过 maxmemory 时按策略淘汰，返回被淘汰的 key；无法降到限制以下时返回 Err
+    pub fn evict(&mut self) -> Result<Vec<Bytes>, Vec<Bytes>> {
+        let mut evicted = Vec::new();
+        while self.maxmemory > 0 && self.used_memory > self.maxmemory {
+            let victim = match self.policy {
+                Policy::NoEviction => None,
+                // 抽样几个 key，淘汰最久没有访问的，或者访问计数最小的
+                Policy::AllKeysLru | Policy::VolatileLru => {
+                    self.sample(self.policy == Policy::VolatileLru).into_iter().min_by_key(|key| self.entries[key].accessed)
+                }
+                Policy::AllKeysLfu | Policy::VolatileLfu => {
+                    self.sample(self.policy == Policy::VolatileLfu).into_iter().min_by_key(|key| {
+                        let entry = &self.entries[key];
+                        (entry.frequency(), entry.accessed)
+                    })
+                }
+                Policy::AllKeysRandom => pick(&self.keys).cloned(),
+                Policy::VolatileRandom => pick(&self.volatile).cloned(),
+                // 抽样几个有过期时间的 key，淘汰最快过期的
+                Policy::VolatileTtl => self.sample(true).into_iter().min_by_key(|key| self.entries[key].expires_at),
+            };
+            let key = match victim {
+                Some(key) => key,
+                None => return Err(evicted),
+            };
+            self.remove(&key);
+            self.stats.evicted_keys += 1;
+            evicted.push(key);
+        }
+        Ok(evicted)
+    }
+
+    // 随机抽取淘汰候选，可能重复，volatile 时只从有过期时间的 key 里抽
+    fn sample(&self, volatile: bool) -> Vec<Bytes> {
+        let keys = if volatile { &self.volatile } else { &self.keys };
+        (0..EVICTION_SAMPLES).filter_map(|_| pick(keys).cloned()).collect()
+    }
+}
+
+fn pick(keys: &[Bytes]) -> Option<&Bytes> {
+    if keys.is_empty() {
+        return None;
+    }
+    Some(&keys[rand::thread_rng().gen_range(0..keys.len())])
+}
+
+// 和最后一个元素交换后删除，返回被换到 index 位置上的 key
+fn swap_remove(keys: &mut Vec<Bytes>, index: usize) -> Option<&Bytes> {
+    keys.swap_remove(index);
+    keys.get(index)
+}
+
+fn entry_size(key: &[u8], value: &Value) -> usize {
+    key.len() + value.size() + ENTRY_OVERHEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 两个抽样数组必须和哈希表、过期索引保持一致
+    fn check(db: &Db) {
+        assert_eq!(db.keys.len(), db.entries.len());
+        assert_eq!(db.volatile.len(), db.expirations.len());
+        for (i, key) in db.keys.iter().enumerate() {
+            assert_eq!(db.entries[key].slot, i);
+        }
+        for (i, key) in db.volatile.iter().enumerate() {
+            assert_eq!(db.entries[key].volatile_slot, Some(i));
+        }
+        for entry in db.entries.values() {
+            assert_eq!(entry.volatile_slot.is_some(), entry.expires_at.is_some());
+        }
+    }
+
+    #[test]
+    fn sampling_indexes_follow_every_mutation() {
+        let mut db = Db::new(0, Policy::NoEviction, Thresholds::default());
+        let mut rng = rand::thread_rng();
+        let later = Instant::now() + Duration::from_secs(3600);
+        for _ in 0..5000 {
+            let key = Bytes::from(format!("key:{}", rng.gen_range(0..64)));
+            match rng.gen_range(0..5) {
+                0 => drop(db.set(key, Bytes::from_static(b"v"), None)),
+                1 => drop(db.set(key, Bytes::from_static(b"v"), Some(later))),
+                2 => drop(db.remove(&key)),
+                3 => drop(db.set_expiry(&key, [None, Some(later)][rng.gen_range(0..2)])),
+                _ => drop(db.rename(&key, Bytes::from(format!("key:{}", rng.gen_range(0..64))))),
+            }
+            check(&db);
+        }
+    }
+
+    #[test]
+    fn expired_keys_leave_the_indexes() {
+        let mut db = Db::new(0, Policy::NoEviction, Thresholds::default());
+        let past = Instant::now();
+        for i in 0..10 {
+            db.set(Bytes::from(format!("gone:{}", i)), Bytes::from_static(b"v"), Some(past));
+            db.set(Bytes::from(format!("kept:{}", i)), Bytes::from_static(b"v"), None);
+        }
+        assert!(db.random_key().unwrap().starts_with(b"kept:"));
+        db.purge_expired(Instant::now());
+        check(&db);
+        assert_eq!(db.len(), 10);
+        assert!(db.volatile.is_empty());
+    }
+
+    #[test]
+    fn eviction_samples_from_the_right_keys() {
+        let mut db = Db::new(1, Policy::VolatileRandom, Thresholds::default());
+        db.set(Bytes::from_static(b"persistent"), Bytes::from_static(b"v"), None);
+        db.set(Bytes::from_static(b"volatile"), Bytes::from_static(b"v"), Some(Instant::now() + Duration::from_secs(60)));
+        assert_eq!(db.evict().unwrap_err(), vec![Bytes::from_static(b"volatile")]);
+        check(&db);
+        db.policy = Policy::AllKeysLru;
+        assert_eq!(db.evict().unwrap(), vec![Bytes::from_static(b"persistent")]);
+        check(&db);
+    }
+}
@@ -0,0 +1,439 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use std::time::Duration;
+
+use crate::command::{syntax_error, Context};
+use crate::db::{Thresholds, Value};
+use crate::hash::Hash;
+use crate::keyspace::{deadline, parse_i64};
+use crate::list::List;
+use crate::notify;
+use crate::set::Set;
+use crate::zset::ZSet;
+use mini_redis::Frame;
+
+// DUMP 的格式和 Redis 一致：类型、RDB 编码的值、2 字节的 RDB 版本、8 字节的 CRC64，版本和校验和都是小端
+const RDB_VERSION: u16 = 11;
+const TYPE_STRING: u8 = 0;
+// 集合类型都用最基本的格式写出，不依赖内部编码，Redis 加载时会按自己的阈值重新选择编码
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+
+// 长度编码的最高两位
+const LEN_6BIT: u8 = 0;
+const LEN_14BIT: u8 = 1;
+const LEN_ENCODED: u8 = 3;
+const LEN_32BIT: u8 = 0x80;
+const LEN_64BIT: u8 = 0x81;
+// 特殊编码的字符串：整数和 LZF 压缩
+const ENC_INT8: usize = 0;
+const ENC_INT16: usize = 1;
+const ENC_INT32: usize = 2;
+
+const BAD_PAYLOAD: &str = "ERR DUMP payload version or checksum are wrong";
+const BAD_FORMAT: &str = "ERR Bad data format";
+
+/// Redis 使用的 CRC-64/Jones（反射输入输出，初始值 0）
+fn crc64(data: &[u8]) -> u64 {
+    const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u64, |crc, _| if crc & 1 == 1 { crc >> 1 ^ POLY } else { crc >> 1 })
+    })
+}
+
+fn put_len(buf: &mut BytesMut, len: usize) {
+    if len < 1 << 6 {
+        buf.put_u8((LEN_6BIT << 6) | len as u8);
+    } else if len < 1 << 14 {
+        buf.put_u16(((LEN_14BIT as u16) << 14) | len as u16);
+    } else if len <= u32::MAX as usize {
+        buf.put_u8(LEN_32BIT);
+        buf.put_u32(len as u32);
+    } else {
+        buf.put_u8(LEN_64BIT);
+        buf.put_u64(len as u64);
+    }
+}
+
+fn put_string(buf: &mut BytesMut, value: &[u8]) {
+    put_len(buf, value.len());
+    buf.put_slice(value);
+}
+
+pub fn serialize(value: &Value) -> Bytes {
+    let mut buf = BytesMut::new();
+    match value {
+        Value::String(value) => {
+            buf.put_u8(TYPE_STRING);
+            put_string(&mut buf, value);
+        }
+        Value::Hash(hash) => {
+            buf.put_u8(TYPE_HASH);
+            put_len(&mut buf, hash.len());
+            for (field, value) in hash.iter() {
+                put_string(&mut buf, &field);
+                put_string(&mut buf, &value);
+            }
+        }
+        Value::List(list) => {
+            buf.put_u8(TYPE_LIST);
+            put_len(&mut buf, list.len());
+            for value in list.iter() {
+                put_string(&mut buf, &value);
+            }
+        }
+        Value::Set(set) => {
+            buf.put_u8(TYPE_SET);
+            put_len(&mut buf, set.len());
+            for member in set.iter() {
+                put_string(&mut buf, &member);
+            }
+        }
+        Value::ZSet(zset) => {
+            buf.put_u8(TYPE_ZSET_2);
+            put_len(&mut buf, zset.len());
+            // Redis 按分数从大到小写入，加载时每次插在表头最快
+            for (member, score) in zset.iter().rev() {
+                put_string(&mut buf, &member);
+                buf.put_f64_le(score);
+            }
+        }
+    }
+    buf.put_u16_le(RDB_VERSION);
+    let crc = crc64(&buf);
+    buf.put_u64_le(crc);
+    buf.freeze()
+}
+
+/// 校验版本和校验和之后解析出值，集合按 thresholds 选择编码
+pub fn deserialize(payload: &[u8], thresholds: &Thresholds) -> Result<Value, Frame> {
+    if payload.len() < 10 {
+        return Err(Frame::error(BAD_PAYLOAD));
+    }
+    let (body, crc) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]);
+    if version > RDB_VERSION || crc64(body) != u64::from_le_bytes(crc.try_into().unwrap()) {
+        return Err(Frame::error(BAD_PAYLOAD));
+    }
+    let mut reader = Reader { data: &body[..body.len() - 2], thresholds };
+    let value = reader.value().ok_or_else(|| Frame::error(BAD_FORMAT))?;
+    if !reader.data.is_empty() {
+        return Err(Frame::error(BAD_FORMAT));
+    }
+    Ok(value)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    thresholds: &'a Thresholds,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        if self.data.len() < n {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(n);
+        self.data = rest;
+        Some(head)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    /// 返回（长度，是否是特殊编码）
+    fn len(&mut self) -> Option<(usize, bool)> {
+        let first = self.u8()?;
+        match first >> 6 {
+            LEN_6BIT => Some(((first & 0x3f) as usize, false)),
+            LEN_14BIT => Some(((((first & 0x3f) as usize) << 8) | self.u8()? as usize, false)),
+            LEN_ENCODED => Some(((first & 0x3f) as usize, true)),
+            _ if first == LEN_32BIT => Some((u32::from_be_bytes(self.take(4)?.try_into().ok()?) as usize, false)),
+            _ if first == LEN_64BIT => Some((u64::from_be_bytes(self.take(8)?.try_into().ok()?) as usize, false)),
+            _ => None,
+        }
+    }
+
+    fn string(&mut self) -> Option<Bytes> {
+        let n = match self.len()? {
+            (len, false) => return self.take(len).map(Bytes::copy_from_slice),
+            (ENC_INT8, true) => self.u8()? as i8 as i64,
+            (ENC_INT16, true) => i16::from_le_bytes(self.take(2)?.try_into().ok()?) as i64,
+            (ENC_INT32, true) => i32::from_le_bytes(self.take(4)?.try_into().ok()?) as i64,
+            // LZF 压缩的字符串只有 RDB 文件里才会出现
+            _ => return None,
+        };
+        Some(Bytes::from(n.to_string()))
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        match self.u8()? {
+            TYPE_STRING => Some(Value::String(self.string()?)),
+            // 空的集合不是合法的值，重复的成员或字段也不是
+            TYPE_LIST => {
+                let (len @ 1.., false) = self.len()? else { return None };
+                let mut list = List::new();
+                for _ in 0..len {
+                    list.push(&self.string()?, false, self.thresholds);
+                }
+                Some(Value::List(list))
+            }
+            TYPE_SET => {
+                let (len @ 1.., false) = self.len()? else { return None };
+                let mut set = Set::new();
+                for _ in 0..len {
+                    if !set.insert(self.string()?, self.thresholds) {
+                        return None;
+                    }
+                }
+                Some(Value::Set(set))
+            }
+            TYPE_HASH => {
+                let (len @ 1.., false) = self.len()? else { return None };
+                let mut hash = Hash::new();
+                for _ in 0..len {
+                    let field = self.string()?;
+                    if !hash.insert(field, self.string()?, self.thresholds) {
+                        return None;
+                    }
+                }
+                Some(Value::Hash(hash))
+            }
+            TYPE_ZSET_2 => {
+                let (len @ 1.., false) = self.len()? else { return None };
+                let mut zset = ZSet::new();
+                for _ in 0..len {
+                    let member = self.string()?;
+                    let score = f64::from_le_bytes(self.take(8)?.try_into().ok()?);
+                    if score.is_nan() {
+                        return None;
+                    }
+                    if !zset.insert(member, score, self.thresholds) {
+                        return None;
+                    }
+                }
+                Some(Value::ZSet(zset))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn dump(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut db = ctx.shared.db.lock().unwrap();
+    match db.get(&args[1]) {
+        Some(entry) => Frame::Bulk(serialize(&entry.value)),
+        None => Frame::Null,
+    }
+}
+
+// RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+pub fn restore(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (mut replace, mut absttl) = (false, false);
+    let (mut idle, mut freq) = (None, None);
+    let mut i = 4;
+    while i < args.len() {
+        match &args[i].to_ascii_lowercase()[..] {
+            b"replace" => replace = true,
+            b"absttl" => absttl = true,
+            b"idletime" if i + 1 < args.len() && freq.is_none() => {
+                match parse_i64(&args[i + 1]) {
+                    Ok(secs) if secs >= 0 => idle = Some(Duration::from_secs(secs as u64)),
+                    Ok(_) => return Frame::error("ERR Invalid IDLETIME value, must be >= 0"),
+                    Err(err) => return err,
+                }
+                i += 1;
+            }
+            b"freq" if i + 1 < args.len() && idle.is_none() => {
+                match parse_i64(&args[i + 1]) {
+                    Ok(n) if (0..=255).contains(&n) => freq = Some(n as u8),
+                    Ok(_) => return Frame::error("ERR Invalid FREQ value, must be >= 0 and <= 255"),
+                    Err(err) => return err,
+                }
+                i += 1;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+    let ttl = match parse_i64(&args[2]) {
+        Ok(ttl) if ttl >= 0 => ttl,
+        Ok(_) => return Frame::error("ERR Invalid TTL value, must be >= 0"),
+        Err(err) => return err,
+    };
+    let thresholds = ctx.shared.db.lock().unwrap().thresholds;
+    let value = match deserialize(&args[3], &thresholds) {
+        Ok(value) => value,
+        Err(err) => return err,
+    };
+
+    let key = &args[1];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let exists = db.contains(key);
+    if exists && !replace {
+        return Frame::error("BUSYKEY Target key name already exists.");
+    }
+    let expires_at = match ttl {
+        0 => None,
+        ttl => match deadline(ttl, absttl) {
+            Some(when) => Some(when),
+            // ABSTTL 指定的时间已经过去，相当于恢复后立即过期
+            None => {
+                db.remove(key);
+                drop(db);
+                if exists {
+                    ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+                    notify::keyspace_event(ctx.shared, notify::GENERIC, "del", key);
+                }
+                return Frame::ok();
+            }
+        },
+    };
+    let replaced = db.set(key.clone(), value, expires_at);
+    db.set_access(key, idle, freq);
+    drop(db);
+    drop(replaced);
+
+    ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+    if !exists {
+        notify::keyspace_event(ctx.shared, notify::NEW, "new", key);
+    }
+    notify::keyspace_event(ctx.shared, notify::GENERIC, "restore", key);
+    Frame::ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    // 在 body 后面补上版本号和校验和
+    fn payload(body: &[u8], version: u16) -> Vec<u8> {
+        let mut payload = body.to_vec();
+        payload.extend_from_slice(&version.to_le_bytes());
+        let crc = crc64(&payload);
+        payload.extend_from_slice(&crc.to_le_bytes());
+        payload
+    }
+
+    fn round_trip(value: &Value) -> Value {
+        deserialize(&serialize(value), &Thresholds::default()).unwrap()
+    }
+
+    #[test]
+    fn crc64_check_value() {
+        // CRC-64/Jones 的标准校验值，Redis 的 crc64 自测用的也是它
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+        assert_eq!(crc64(b""), 0);
+    }
+
+    #[test]
+    fn string_round_trip() {
+        for value in [&b""[..], b"hello", &[0u8, 255, 10, 13][..], &vec![b'x'; 20_000][..]] {
+            let Value::String(restored) = round_trip(&Value::String(Bytes::copy_from_slice(value))) else { panic!() };
+            assert_eq!(restored, value);
+        }
+        // 和 Redis 写出的格式完全一致
+        let dumped = serialize(&Value::String(Bytes::from("bar")));
+        assert_eq!(&dumped[..7], b"\x00\x03bar\x0b\x00");
+    }
+
+    #[test]
+    fn collections_round_trip() {
+        let thresholds = Thresholds::default();
+        let members: Vec<Bytes> = (0..300).map(|i| Bytes::from(format!("m{}", i))).collect();
+        // 成员少的用紧凑编码，多的超过阈值，两种编码都要覆盖
+        for n in [3, 300] {
+            let mut hash = Hash::new();
+            let mut list = List::new();
+            let mut set = Set::new();
+            let mut zset = ZSet::new();
+            for (i, member) in members[..n].iter().enumerate() {
+                hash.insert(member.clone(), Bytes::from(i.to_string()), &thresholds);
+                list.push(member, false, &thresholds);
+                set.insert(member.clone(), &thresholds);
+                zset.insert(member.clone(), i as f64 / 3.0 - 50.0, &thresholds);
+            }
+
+            let Value::Hash(restored) = round_trip(&Value::Hash(hash.clone())) else { panic!() };
+            assert_eq!(restored.iter().collect::<BTreeSet<_>>(), hash.iter().collect::<BTreeSet<_>>());
+            assert_eq!(restored.len(), n);
+            let Value::List(restored) = round_trip(&Value::List(list.clone())) else { panic!() };
+            assert_eq!(restored.iter().collect::<Vec<_>>(), list.iter().collect::<Vec<_>>());
+            let Value::Set(restored) = round_trip(&Value::Set(set.clone())) else { panic!() };
+            assert_eq!(restored.iter().collect::<BTreeSet<_>>(), set.iter().collect::<BTreeSet<_>>());
+            let Value::ZSet(restored) = round_trip(&Value::ZSet(zset.clone())) else { panic!() };
+            assert_eq!(restored.iter().collect::<Vec<_>>(), zset.iter().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn int_encoded_strings() {
+        let thresholds = Thresholds::default();
+        let cases: [(&[u8], &str); 4] = [
+            (&[TYPE_STRING, 0xc0, 0xfe], "-2"),
+            (&[TYPE_STRING, 0xc1, 0x39, 0x30], "12345"),
+            (&[TYPE_STRING, 0xc2, 0x00, 0x00, 0x00, 0x80], "-2147483648"),
+            (&[TYPE_STRING, 0xc0, 0x7f], "127"),
+        ];
+        for (body, expected) in cases {
+            let Ok(Value::String(value)) = deserialize(&payload(body, RDB_VERSION), &thresholds) else { panic!() };
+            assert_eq!(value, expected);
+        }
+        // 集合里的成员也可以是整数编码
+        let body = [TYPE_SET, 2, 0xc0, 1, 0xc1, 0x00, 0x01];
+        let Ok(Value::Set(set)) = deserialize(&payload(&body, RDB_VERSION), &thresholds) else { panic!() };
+        assert_eq!(set.iter().collect::<BTreeSet<_>>(), BTreeSet::from([Bytes::from("1"), Bytes::from("256")]));
+        // LZF 压缩的字符串不支持
+        assert!(deserialize(&payload(&[TYPE_STRING, 0xc3, 1, 1, 0, b'a'], RDB_VERSION), &thresholds).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_checksum_and_version() {
+        let thresholds = Thresholds::default();
+        let error = |result: Result<Value, Frame>| match result {
+            Err(Frame::Error(message)) => message,
+            _ => panic!("payload should be rejected"),
+        };
+        let mut dumped = serialize(&Value::String(Bytes::from("value"))).to_vec();
+        assert!(deserialize(&dumped, &thresholds).is_ok());
+
+        let last = dumped.len() - 1;
+        dumped[last] ^= 1;
+        assert_eq!(error(deserialize(&dumped, &thresholds)), BAD_PAYLOAD);
+        dumped[last] ^= 1;
+        dumped[2] = b'V';
+        assert_eq!(error(deserialize(&dumped, &thresholds)), BAD_PAYLOAD);
+
+        // 更新的 RDB 版本不认识，旧版本可以加载
+        assert_eq!(error(deserialize(&payload(&[TYPE_STRING, 1, b'a'], RDB_VERSION + 1), &thresholds)), BAD_PAYLOAD);
+        assert!(deserialize(&payload(&[TYPE_STRING, 1, b'a'], 9), &thresholds).is_ok());
+        assert_eq!(error(deserialize(b"short", &thresholds)), BAD_PAYLOAD);
+        // 校验和正确但内容不对
+        assert_eq!(error(deserialize(&payload(&[TYPE_STRING, 5, b'a'], RDB_VERSION), &thresholds)), BAD_FORMAT);
+        assert_eq!(error(deserialize(&payload(&[TYPE_STRING, 1, b'a', b'b'], RDB_VERSION), &thresholds)), BAD_FORMAT);
+        assert_eq!(error(deserialize(&payload(&[9, 1, b'a'], RDB_VERSION), &thresholds)), BAD_FORMAT);
+    }
+
+    #[test]
+    fn rejects_duplicates_and_empty_collections() {
+        let thresholds = Thresholds::default();
+        let rejected = |body: &[u8]| deserialize(&payload(body, RDB_VERSION), &thresholds).is_err();
+        let mut zset = vec![TYPE_ZSET_2, 2];
+        for _ in 0..2 {
+            zset.extend_from_slice(&[1, b'm']);
+            zset.extend_from_slice(&1.5f64.to_le_bytes());
+        }
+        assert!(rejected(&zset));
+        assert!(rejected(&[TYPE_SET, 2, 1, b'a', 1, b'a']));
+        assert!(rejected(&[TYPE_HASH, 2, 1, b'f', 1, b'1', 1, b'f', 1, b'2']));
+        for kind in [TYPE_LIST, TYPE_SET, TYPE_HASH, TYPE_ZSET_2] {
+            assert!(rejected(&[kind, 0]));
+        }
+        let mut nan = vec![TYPE_ZSET_2, 1, 1, b'm'];
+        nan.extend_from_slice(&f64::NAN.to_le_bytes());
+        assert!(rejected(&nan));
+    }
+}
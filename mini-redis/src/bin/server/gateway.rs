@@ -0,0 +1,301 @@
+use bytes::Bytes;
+use serde_json::{json, Value as Json};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info_span, Instrument};
+
+use crate::client::Client;
+use crate::http::{self, percent_decode, Request};
+use crate::{dispatch, Shared};
+use mini_redis::Frame;
+
+// 请求体上限和读取请求的超时，订阅的连接读完请求之后不受超时限制
+const MAX_BODY: usize = 16 * 1024 * 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+// SSE 连接上没有消息时定期发送注释行，避免被代理当成空闲连接断开
+const KEEPALIVE: Duration = Duration::from_secs(15);
+
+const JSON: &str = "application/json";
+
+/// HTTP/JSON 网关，所有请求都转成命令交给和 RESP 连接相同的 dispatch 执行
+pub async fn serve(listener: TcpListener, shared: Arc<Shared>) -> crate::Result<()> {
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        let shared = shared.clone();
+        let span = info_span!("http", peer = %addr);
+        tokio::spawn(
+            async move {
+                if let Err(err) = handle(socket, shared).await {
+                    debug!(%err, "http connection error");
+                }
+            }
+            .instrument(span),
+        );
+    }
+}
+
+async fn handle(mut socket: TcpStream, shared: Arc<Shared>) -> crate::Result<()> {
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, http::read_request(&mut socket, MAX_BODY)).await {
+        Ok(Ok(Some(request))) => request,
+        Ok(Ok(None)) | Err(_) => return Ok(()),
+        Ok(Err(err)) => return reply(&mut socket, "400 Bad Request", &json!({ "error": err.to_string() })).await,
+    };
+    let mut client = shared.new_client(socket.peer_addr()?.to_string(), socket.local_addr()?.to_string(), "http");
+    // RESP3 的 map、double 这些类型能直接对应到 JSON
+    client.resp = 3;
+    if !shared.register(&client) {
+        let body = json!({ "error": "ERR max number of clients reached" });
+        return reply(&mut socket, "503 Service Unavailable", &body).await;
+    }
+    let result = route(&mut socket, &request, &mut client, &shared).await;
+    shared.unregister(&mut client);
+    result
+}
+
+async fn route(socket: &mut TcpStream, request: &Request, client: &mut Client, shared: &Arc<Shared>) -> crate::Result<()> {
+    if let Err(err) = authenticate(request, client, shared).await {
+        return reply(socket, "401 Unauthorized", &json!({ "error": err })).await;
+    }
+    let key = request.path.strip_prefix("/keys/").filter(|key| !key.is_empty()).map(|key| Bytes::from(percent_decode(key)));
+    let args = match (request.method.as_str(), request.path.as_str(), key) {
+        ("GET", _, Some(key)) => vec![Bytes::from_static(b"GET"), key],
+        ("PUT", _, Some(key)) => {
+            let mut args = vec![Bytes::from_static(b"SET"), key, Bytes::from(request.body.clone())];
+            // ?ex=10、?px=100、?nx、?xx 对应 SET 的选项
+            for (name, takes_value) in [("ex", true), ("px", true), ("exat", true), ("pxat", true), ("nx", false), ("xx", false)] {
+                for value in request.query_values(name) {
+                    args.push(Bytes::from(name.to_ascii_uppercase()));
+                    if takes_value {
+                        args.push(Bytes::from(value));
+                    }
+                }
+            }
+            args
+        }
+        ("DELETE", _, Some(key)) => vec![Bytes::from_static(b"DEL"), key],
+        ("POST", "/cmd", _) => return command(socket, request, client, shared).await,
+        ("GET", "/subscribe", _) => return subscribe(socket, request, client, shared).await,
+        (_, "/cmd" | "/subscribe", _) | (_, _, Some(_)) => {
+            return reply(socket, "405 Method Not Allowed", &json!({ "error": "method not allowed" })).await
+        }
+        _ => return reply(socket, "404 Not Found", &json!({ "error": "not found" })).await,
+    };
+    let response = dispatch(shared, client, &args).await;
+    let status = match &response {
+        Frame::Null => "404 Not Found",
+        Frame::Error(msg) => error_status(msg),
+        _ => "200 OK",
+    };
+    reply(socket, status, &result(response)).await
+}
+
+/// `Authorization: Bearer <password>` 以 default 用户登录，`Basic` 可以指定用户名
+async fn authenticate(request: &Request, client: &mut Client, shared: &Arc<Shared>) -> Result<(), String> {
+    let Some(authorization) = request.header("authorization") else { return Ok(()) };
+    let args = match authorization.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => {
+            vec![Bytes::from_static(b"AUTH"), Bytes::from(token.trim().to_string())]
+        }
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("basic") => {
+            let decoded = base64_decode(token.trim()).ok_or("invalid basic credentials")?;
+            let split = decoded.iter().position(|b| *b == b':').ok_or("invalid basic credentials")?;
+            let (user, pass) = (&decoded[..split], &decoded[split + 1..]);
+            vec![Bytes::from_static(b"AUTH"), Bytes::copy_from_slice(user), Bytes::copy_from_slice(pass)]
+        }
+        _ => return Err("unsupported authorization scheme".to_string()),
+    };
+    match dispatch(shared, client, &args).await {
+        Frame::Error(msg) => Err(msg),
+        _ => Ok(()),
+    }
+}
+
+// POST /cmd，请求体是一条命令 ["SET", "k", "v"]，或者多条命令组成的数组，按顺序在同一个连接上执行
+async fn command(socket: &mut TcpStream, request: &Request, client: &mut Client, shared: &Arc<Shared>) -> crate::Result<()> {
+    let bad_request = |msg: &str| json!({ "error": format!("ERR {}", msg) });
+    let body: Json = match serde_json::from_slice(&request.body) {
+        Ok(body) => body,
+        Err(err) => return reply(socket, "400 Bad Request", &bad_request(&format!("invalid JSON: {}", err))).await,
+    };
+    let (commands, pipeline) = match body {
+        Json::Array(items) if !items.is_empty() && items.iter().all(Json::is_array) => (items, true),
+        Json::Array(items) => (vec![Json::Array(items)], false),
+        _ => return reply(socket, "400 Bad Request", &bad_request("expected a JSON array")).await,
+    };
+    let mut parsed = Vec::with_capacity(commands.len());
+    for command in commands {
+        match arguments(command) {
+            Some(args) => parsed.push(args),
+            None => {
+                let body = bad_request("command arguments must be strings, numbers or booleans");
+                return reply(socket, "400 Bad Request", &body).await;
+            }
+        }
+    }
+
+    let mut results = Vec::with_capacity(parsed.len());
+    for args in parsed {
+        let response = dispatch(shared, client, &args).await;
+        // SUBSCRIBE 这类命令的其它回复在请求结束后就没有意义了
+        client.replies.clear();
+        results.push(response);
+    }
+    if pipeline {
+        let body = Json::Array(results.into_iter().map(result).collect());
+        return reply(socket, "200 OK", &body).await;
+    }
+    let response = results.pop().unwrap();
+    let status = match &response {
+        Frame::Error(msg) => error_status(msg),
+        _ => "200 OK",
+    };
+    reply(socket, status, &result(response)).await
+}
+
+fn arguments(command: Json) -> Option<Vec<Bytes>> {
+    let Json::Array(items) = command else { return None };
+    items
+        .into_iter()
+        .map(|item| match item {
+            Json::String(s) => Some(Bytes::from(s)),
+            Json::Number(n) => Some(Bytes::from(n.to_string())),
+            Json::Bool(b) => Some(Bytes::from(b.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+// GET /subscribe?channel=a&channel=b&pattern=news.*，用 Server-Sent Events 推送消息
+async fn subscribe(socket: &mut TcpStream, request: &Request, client: &mut Client, shared: &Arc<Shared>) -> crate::Result<()> {
+    let channels: Vec<Bytes> = request.query_values("channel").map(Bytes::from).collect();
+    let patterns: Vec<Bytes> = request.query_values("pattern").map(Bytes::from).collect();
+    if channels.is_empty() && patterns.is_empty() {
+        let body = json!({ "error": "ERR at least one channel or pattern is required" });
+        return reply(socket, "400 Bad Request", &body).await;
+    }
+    for (command, names) in [("SUBSCRIBE", channels), ("PSUBSCRIBE", patterns)] {
+        if names.is_empty() {
+            continue;
+        }
+        let mut args = vec![Bytes::from_static(command.as_bytes())];
+        args.extend(names);
+        if let Frame::Error(msg) = dispatch(shared, client, &args).await {
+            return reply(socket, error_status(&msg), &json!({ "error": msg })).await;
+        }
+    }
+    client.replies.clear();
+
+    let (mut reader, mut writer) = socket.split();
+    writer
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n")
+        .await?;
+    let mut inbox = client.inbox.take().expect("client inbox already taken");
+    let mut keepalive = tokio::time::interval(KEEPALIVE);
+    let mut buf = [0; 512];
+    loop {
+        tokio::select! {
+            Some(push) = inbox.recv() => {
+                if let Some(event) = event(push) {
+                    writer.write_all(event.as_bytes()).await?;
+                }
+            }
+            _ = keepalive.tick() => writer.write_all(b": keepalive\n\n").await?,
+            // 对端关闭连接时退出，取消订阅由 unregister 完成
+            n = reader.read(&mut buf) => {
+                if n? == 0 {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// 把订阅消息转成一个 SSE 事件，事件名是 message 或 pmessage，数据是一行 JSON
+fn event(push: Frame) -> Option<String> {
+    let Frame::Push(items) = push else { return None };
+    let text = |frame: &Frame| match frame {
+        Frame::Bulk(data) => Some(String::from_utf8_lossy(data).into_owned()),
+        _ => None,
+    };
+    let (kind, data) = match items.as_slice() {
+        [kind, channel, message] if text(kind)? == "message" => {
+            ("message", json!({ "channel": text(channel)?, "message": text(message)? }))
+        }
+        [kind, pattern, channel, message] if text(kind)? == "pmessage" => (
+            "pmessage",
+            json!({ "pattern": text(pattern)?, "channel": text(channel)?, "message": text(message)? }),
+        ),
+        _ => return None,
+    };
+    Some(format!("event: {}\ndata: {}\n\n", kind, data))
+}
+
+fn error_status(msg: &str) -> &'static str {
+    match msg.split(' ').next() {
+        Some("NOAUTH" | "WRONGPASS") => "401 Unauthorized",
+        Some("NOPERM") => "403 Forbidden",
+        Some("MOVED" | "ASK") => "421 Misdirected Request",
+        Some("BUSY" | "LOADING") => "503 Service Unavailable",
+        _ => "400 Bad Request",
+    }
+}
+
+// 命令的回复放在 result 里，错误放在 error 里
+fn result(frame: Frame) -> Json {
+    match frame {
+        Frame::Error(msg) => json!({ "error": msg }),
+        frame => json!({ "result": to_json(frame) }),
+    }
+}
+
+/// 二进制数据按 UTF-8 有损转换，map 的 key 不是字符串时转成 [key, value] 数组
+fn to_json(frame: Frame) -> Json {
+    match frame {
+        Frame::Simple(s) | Frame::BigNumber(s) => Json::String(s),
+        Frame::Error(msg) => json!({ "error": msg }),
+        Frame::Integer(n) => Json::from(n),
+        Frame::Bulk(data) | Frame::Verbatim(_, data) => Json::String(String::from_utf8_lossy(&data).into_owned()),
+        Frame::Null => Json::Null,
+        Frame::Double(d) => serde_json::Number::from_f64(d).map_or_else(|| Json::String(d.to_string()), Json::Number),
+        Frame::Boolean(b) => Json::Bool(b),
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => Json::Array(items.into_iter().map(to_json).collect()),
+        Frame::Map(pairs) => {
+            let pairs: Vec<(Json, Json)> = pairs.into_iter().map(|(k, v)| (to_json(k), to_json(v))).collect();
+            if pairs.iter().all(|(k, _)| k.is_string()) {
+                Json::Object(pairs.into_iter().map(|(k, v)| (k.as_str().unwrap().to_string(), v)).collect())
+            } else {
+                Json::Array(pairs.into_iter().map(|(k, v)| json!([k, v])).collect())
+            }
+        }
+    }
+}
+
+async fn reply(socket: &mut TcpStream, status: &str, body: &Json) -> crate::Result<()> {
+    http::respond(socket, status, JSON, body.to_string().as_bytes()).await?;
+    Ok(())
+}
+
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let input = input.trim_end_matches('=').as_bytes();
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for &c in input {
+        acc = ((acc << 6) | value(c)? as u32) & 0xffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Some(out)
+}
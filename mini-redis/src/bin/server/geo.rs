@@ -0,0 +1,495 @@
+use bytes::Bytes;
+
+use crate::command::{syntax_error, wrong_type, Context};
+use crate::db::Value;
+use crate::keyspace::{parse_f64, parse_i64};
+use crate::notify;
+use crate::zset::ZSet;
+use mini_redis::Frame;
+
+// 位置存在有序集合里，分数是 52 位的 geohash（经纬度各 26 位交错），和 Redis 的格式一致。
+// 纬度范围是 Web Mercator 能表示的范围，GEOHASH 输出标准 geohash 时再换回 [-90, 90]
+const LON_MIN: f64 = -180.0;
+const LON_MAX: f64 = 180.0;
+const LAT_MIN: f64 = -85.05112878;
+const LAT_MAX: f64 = 85.05112878;
+const STEP_MAX: u32 = 26;
+
+const EARTH_RADIUS: f64 = 6372797.560856;
+const BASE32: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+type Point = (f64, f64);
+
+// 纬度放在偶数位，经度放在奇数位
+fn interleave(lat: u64, lon: u64, step: u32) -> u64 {
+    (0..step).fold(0, |bits, i| bits | (lat >> i & 1) << (2 * i) | (lon >> i & 1) << (2 * i + 1))
+}
+
+fn deinterleave(bits: u64, step: u32) -> (u64, u64) {
+    (0..step).fold((0, 0), |(lat, lon), i| (lat | (bits >> (2 * i) & 1) << i, lon | (bits >> (2 * i + 1) & 1) << i))
+}
+
+/// 把坐标编码成 step * 2 位的 geohash
+fn encode((lon, lat): Point, step: u32, lat_min: f64, lat_max: f64) -> u64 {
+    let cells = 1u64 << step;
+    let index = |value: f64, min: f64, max: f64| (((value - min) / (max - min) * cells as f64) as u64).min(cells - 1);
+    interleave(index(lat, lat_min, lat_max), index(lon, LON_MIN, LON_MAX), step)
+}
+
+/// 分数对应格子的中心点
+fn decode(score: f64) -> Point {
+    let (lat, lon) = deinterleave(score as u64, STEP_MAX);
+    let cells = (1u64 << STEP_MAX) as f64;
+    let center = |index: u64, min: f64, max: f64| (min + (index as f64 + 0.5) * (max - min) / cells).clamp(min, max);
+    (center(lon, LON_MIN, LON_MAX), center(lat, LAT_MIN, LAT_MAX))
+}
+
+fn score(point: Point) -> f64 {
+    encode(point, STEP_MAX, LAT_MIN, LAT_MAX) as f64
+}
+
+/// 两点之间的球面距离（haversine），单位米
+fn distance((lon1, lat1): Point, (lon2, lat2): Point) -> f64 {
+    let u = ((lat2 - lat1).to_radians() / 2.0).sin();
+    let v = ((lon2 - lon1).to_radians() / 2.0).sin();
+    2.0 * EARTH_RADIUS * (u * u + lat1.to_radians().cos() * lat2.to_radians().cos() * v * v).sqrt().asin()
+}
+
+fn parse_point(lon: &[u8], lat: &[u8]) -> Result<Point, Frame> {
+    let (lon, lat) = (parse_f64(lon)?, parse_f64(lat)?);
+    if !(LON_MIN..=LON_MAX).contains(&lon) || !(LAT_MIN..=LAT_MAX).contains(&lat) {
+        return Err(Frame::error(format!("ERR invalid longitude,latitude pair {:.6},{:.6}", lon, lat)));
+    }
+    Ok((lon, lat))
+}
+
+// 返回一个单位等于多少米
+fn parse_unit(unit: &[u8]) -> Result<f64, Frame> {
+    match &unit.to_ascii_lowercase()[..] {
+        b"m" => Ok(1.0),
+        b"km" => Ok(1000.0),
+        b"ft" => Ok(0.3048),
+        b"mi" => Ok(1609.34),
+        _ => Err(Frame::error("ERR unsupported unit provided. please use M, KM, FT, MI")),
+    }
+}
+
+// 在 key 对应的有序集合上执行 f，key 不存在时返回 Ok(None)
+fn with_zset<R>(ctx: &Context<'_>, key: &[u8], f: impl FnOnce(&ZSet) -> R) -> Result<Option<R>, Frame> {
+    let mut db = ctx.shared.db.lock().unwrap();
+    match db.get(key) {
+        None => Ok(None),
+        Some(entry) => entry.value.as_zset().map(|zset| Some(f(zset))).ok_or_else(wrong_type),
+    }
+}
+
+fn coordinates((lon, lat): Point) -> Frame {
+    Frame::Array(vec![Frame::bulk(lon.to_string()), Frame::bulk(lat.to_string())])
+}
+
+// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+pub fn geoadd(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (mut nx, mut xx, mut ch) = (false, false, false);
+    let mut i = 2;
+    while i < args.len() {
+        match &args[i].to_ascii_lowercase()[..] {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            b"ch" => ch = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    if nx && xx {
+        return Frame::error("ERR XX and NX options at the same time are not compatible");
+    }
+    let items = &args[i..];
+    if items.is_empty() || !items.len().is_multiple_of(3) {
+        return Frame::error("ERR syntax error. Try GEOADD key [x1] [y1] [name1] [x2] [y2] [name2] ... ");
+    }
+    let mut members = Vec::with_capacity(items.len() / 3);
+    for item in items.chunks(3) {
+        match parse_point(&item[0], &item[1]) {
+            Ok(point) => members.push((item[2].clone(), score(point))),
+            Err(err) => return err,
+        }
+    }
+
+    let key = &args[1];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let created = match db.get(key) {
+        Some(entry) if entry.value.as_zset().is_none() => return wrong_type(),
+        Some(_) => false,
+        None if xx => return Frame::Integer(0),
+        None => {
+            db.set(key.clone(), Value::ZSet(ZSet::new()), None);
+            true
+        }
+    };
+    let thresholds = db.thresholds;
+    let (added, changed) = db
+        .modify(key, |value| {
+            let zset = value.as_zset_mut().unwrap();
+            let (mut added, mut changed) = (0, 0);
+            for (member, score) in members {
+                match zset.score(&member) {
+                    Some(old) if !nx && old != score => {
+                        zset.insert(member, score, &thresholds);
+                        changed += 1;
+                    }
+                    None if !xx => {
+                        zset.insert(member, score, &thresholds);
+                        added += 1;
+                    }
+                    _ => {}
+                }
+            }
+            (added, changed)
+        })
+        .unwrap();
+    drop(db);
+
+    if added + changed > 0 {
+        ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+        if created {
+            notify::keyspace_event(ctx.shared, notify::NEW, "new", key);
+        }
+        notify::keyspace_event(ctx.shared, notify::ZSET, "zadd", key);
+    }
+    Frame::Integer(if ch { added + changed } else { added })
+}
+
+// GEODIST key member1 member2 [M | KM | FT | MI]
+pub fn geodist(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    if args.len() > 5 {
+        return syntax_error();
+    }
+    let unit = match args.get(4).map(|unit| parse_unit(unit)).transpose() {
+        Ok(unit) => unit.unwrap_or(1.0),
+        Err(err) => return err,
+    };
+    let scores = with_zset(ctx, &args[1], |zset| Some((zset.score(&args[2])?, zset.score(&args[3])?)));
+    match scores {
+        Ok(Some(Some((a, b)))) => Frame::bulk(format!("{:.4}", distance(decode(a), decode(b)) / unit)),
+        Ok(_) => Frame::Null,
+        Err(err) => err,
+    }
+}
+
+// GEOPOS key [member ...]
+pub fn geopos(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let scores = with_zset(ctx, &args[1], |zset| args[2..].iter().map(|member| zset.score(member)).collect());
+    match scores {
+        Ok(scores) => Frame::Array(
+            scores
+                .unwrap_or_else(|| vec![None; args.len() - 2])
+                .into_iter()
+                .map(|score| score.map_or(Frame::Null, |score| coordinates(decode(score))))
+                .collect(),
+        ),
+        Err(err) => err,
+    }
+}
+
+// GEOHASH key [member ...]，返回 11 个字符的标准 geohash
+pub fn geohash(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let scores = with_zset(ctx, &args[1], |zset| args[2..].iter().map(|member| zset.score(member)).collect());
+    let hash = |score: f64| {
+        let bits = encode(decode(score), STEP_MAX, -90.0, 90.0);
+        // 52 位只够 10 个字符，第 11 个字符按 0 补齐
+        let hash: Vec<u8> = (0..11)
+            .map(|i| if i == 10 { 0 } else { (bits >> (52 - (i + 1) * 5)) & 0x1f })
+            .map(|index| BASE32[index as usize])
+            .collect();
+        Frame::Bulk(Bytes::from(hash))
+    };
+    match scores {
+        Ok(scores) => Frame::Array(
+            scores
+                .unwrap_or_else(|| vec![None; args.len() - 2])
+                .into_iter()
+                .map(|score| score.map_or(Frame::Null, hash))
+                .collect(),
+        ),
+        Err(err) => err,
+    }
+}
+
+enum Center {
+    Member(Bytes),
+    Point(Point),
+}
+
+// 半径或者宽高，单位米
+enum Shape {
+    Radius(f64),
+    Box(f64, f64),
+}
+
+#[derive(Default)]
+struct Search {
+    center: Option<Center>,
+    shape: Option<Shape>,
+    // 回复里的距离使用的单位
+    unit: f64,
+    desc: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+    store_dist: bool,
+}
+
+struct Found {
+    member: Bytes,
+    score: f64,
+    distance: f64,
+    point: Point,
+}
+
+impl Search {
+    // FROMMEMBER member | FROMLONLAT longitude latitude, BYRADIUS radius unit | BYBOX width height unit,
+    // [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH] 或者 GEOSEARCHSTORE 的 [STOREDIST]
+    fn parse(args: &[Bytes], store: bool) -> Result<Search, Frame> {
+        let center_error = || Frame::error("ERR exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH");
+        let shape_error = || Frame::error("ERR exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH");
+        let mut search = Search::default();
+        let mut i = 0;
+        while i < args.len() {
+            let rest = args.len() - i - 1;
+            match &args[i].to_ascii_lowercase()[..] {
+                b"frommember" if rest >= 1 => {
+                    if search.center.is_some() {
+                        return Err(center_error());
+                    }
+                    search.center = Some(Center::Member(args[i + 1].clone()));
+                    i += 1;
+                }
+                b"fromlonlat" if rest >= 2 => {
+                    if search.center.is_some() {
+                        return Err(center_error());
+                    }
+                    search.center = Some(Center::Point(parse_point(&args[i + 1], &args[i + 2])?));
+                    i += 2;
+                }
+                b"byradius" if rest >= 2 => {
+                    if search.shape.is_some() {
+                        return Err(shape_error());
+                    }
+                    let radius = parse_f64(&args[i + 1])?;
+                    if radius < 0.0 {
+                        return Err(Frame::error("ERR radius cannot be negative"));
+                    }
+                    search.unit = parse_unit(&args[i + 2])?;
+                    search.shape = Some(Shape::Radius(radius * search.unit));
+                    i += 2;
+                }
+                b"bybox" if rest >= 3 => {
+                    if search.shape.is_some() {
+                        return Err(shape_error());
+                    }
+                    let (width, height) = (parse_f64(&args[i + 1])?, parse_f64(&args[i + 2])?);
+                    if width < 0.0 || height < 0.0 {
+                        return Err(Frame::error("ERR height or width cannot be negative"));
+                    }
+                    search.unit = parse_unit(&args[i + 3])?;
+                    search.shape = Some(Shape::Box(width * search.unit, height * search.unit));
+                    i += 3;
+                }
+                b"asc" => search.desc = Some(false),
+                b"desc" => search.desc = Some(true),
+                b"count" if rest >= 1 => {
+                    match parse_i64(&args[i + 1])? {
+                        count if count > 0 => search.count = Some(count as usize),
+                        _ => return Err(Frame::error("ERR COUNT must be > 0")),
+                    }
+                    i += 1;
+                }
+                b"any" => search.any = true,
+                b"withcoord" if !store => search.with_coord = true,
+                b"withdist" if !store => search.with_dist = true,
+                b"withhash" if !store => search.with_hash = true,
+                b"storedist" if store => search.store_dist = true,
+                _ => return Err(syntax_error()),
+            }
+            i += 1;
+        }
+        if search.center.is_none() {
+            return Err(center_error());
+        }
+        if search.shape.is_none() {
+            return Err(shape_error());
+        }
+        if search.any && search.count.is_none() {
+            return Err(Frame::error("ERR the ANY argument requires COUNT argument"));
+        }
+        Ok(search)
+    }
+
+    /// 在有序集合里找出范围内的成员，按要求排序和截断
+    fn run(&self, zset: &ZSet) -> Result<Vec<Found>, Frame> {
+        let center = match self.center.as_ref().unwrap() {
+            Center::Point(point) => *point,
+            Center::Member(member) => match zset.score(member) {
+                Some(score) => decode(score),
+                None => return Err(Frame::error("ERR could not decode requested zset member")),
+            },
+        };
+        let shape = self.shape.as_ref().unwrap();
+        let (width, height) = match *shape {
+            Shape::Radius(radius) => (radius * 2.0, radius * 2.0),
+            Shape::Box(width, height) => (width, height),
+        };
+        // COUNT ANY 找够数量就停止，不保证是最近的
+        let limit = self.count.filter(|_| self.any);
+        let mut found = Vec::new();
+        'scan: for (min, max) in covering_ranges(center, width, height) {
+            for (member, score) in zset.range(min, max) {
+                let point = decode(score);
+                let inside = match *shape {
+                    Shape::Radius(radius) => distance(center, point) <= radius,
+                    Shape::Box(width, height) => {
+                        EARTH_RADIUS * (point.1 - center.1).to_radians().abs() <= height / 2.0
+                            && distance(point, (center.0, point.1)) <= width / 2.0
+                    }
+                };
+                if inside {
+                    found.push(Found { member, score, distance: distance(center, point), point });
+                    if limit == Some(found.len()) {
+                        break 'scan;
+                    }
+                }
+            }
+        }
+        // 指定 COUNT 但没有 ANY 时默认从近到远
+        let desc = self.desc.or((self.count.is_some() && !self.any).then_some(false));
+        if let Some(desc) = desc {
+            found.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            if desc {
+                found.reverse();
+            }
+        }
+        if let Some(count) = self.count {
+            found.truncate(count);
+        }
+        Ok(found)
+    }
+}
+
+/// 覆盖以 center 为中心、width x height 米的区域的 geohash 分数区间。
+/// 选一个格子不小于区域一半宽高的精度，中心所在的格子加上周围 8 个格子就能覆盖整个区域
+fn covering_ranges(center: Point, width: f64, height: f64) -> Vec<(f64, f64)> {
+    let lat_delta = (height / 2.0 / EARTH_RADIUS).to_degrees();
+    let lon_delta = |lat: f64| (width / 2.0 / EARTH_RADIUS / lat.clamp(-90.0, 90.0).to_radians().cos()).to_degrees();
+    let lon_delta = lon_delta(center.1 + lat_delta).max(lon_delta(center.1 - lat_delta));
+
+    let cell = |step: u32| {
+        let cells = (1u64 << step) as f64;
+        ((LON_MAX - LON_MIN) / cells, (LAT_MAX - LAT_MIN) / cells)
+    };
+    let mut step = STEP_MAX;
+    while step > 0 && (cell(step).0 < lon_delta || cell(step).1 < lat_delta) {
+        step -= 1;
+    }
+    if step == 0 {
+        return vec![(0.0, (1u64 << (2 * STEP_MAX)) as f64)];
+    }
+
+    let (lon_cell, lat_cell) = cell(step);
+    let shift = 2 * (STEP_MAX - step);
+    let mut ranges = Vec::with_capacity(9);
+    for dlat in [-1.0, 0.0, 1.0] {
+        let lat = center.1 + dlat * lat_cell;
+        if !(LAT_MIN..=LAT_MAX).contains(&lat) {
+            continue;
+        }
+        for dlon in [-1.0, 0.0, 1.0] {
+            let mut lon = center.0 + dlon * lon_cell;
+            if lon < LON_MIN {
+                lon += 360.0;
+            } else if lon > LON_MAX {
+                lon -= 360.0;
+            }
+            let hash = encode((lon, lat), step, LAT_MIN, LAT_MAX);
+            let range = ((hash << shift) as f64, ((hash + 1) << shift) as f64);
+            if !ranges.contains(&range) {
+                ranges.push(range);
+            }
+        }
+    }
+    ranges
+}
+
+// GEOSEARCH key <FROMMEMBER member | FROMLONLAT longitude latitude> <BYRADIUS radius unit | BYBOX width height unit>
+//   [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+pub fn geosearch(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let search = match Search::parse(&args[2..], false) {
+        Ok(search) => search,
+        Err(err) => return err,
+    };
+    let found = match with_zset(ctx, &args[1], |zset| search.run(zset)) {
+        Ok(Some(Ok(found))) => found,
+        Ok(Some(Err(err))) | Err(err) => return err,
+        Ok(None) => Vec::new(),
+    };
+    let plain = !search.with_coord && !search.with_dist && !search.with_hash;
+    Frame::Array(
+        found
+            .into_iter()
+            .map(|found| {
+                if plain {
+                    return Frame::Bulk(found.member);
+                }
+                let mut item = vec![Frame::Bulk(found.member)];
+                if search.with_dist {
+                    item.push(Frame::bulk(format!("{:.4}", found.distance / search.unit)));
+                }
+                if search.with_hash {
+                    item.push(Frame::Integer(found.score as i64));
+                }
+                if search.with_coord {
+                    item.push(coordinates(found.point));
+                }
+                Frame::Array(item)
+            })
+            .collect(),
+    )
+}
+
+// GEOSEARCHSTORE destination source ... [STOREDIST]，返回写入的成员数
+pub fn geosearchstore(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let search = match Search::parse(&args[3..], true) {
+        Ok(search) => search,
+        Err(err) => return err,
+    };
+    let dest = &args[1];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let found = match db.get(&args[2]).map(|entry| entry.value.as_zset().map(|zset| search.run(zset))) {
+        None => Vec::new(),
+        Some(Some(Ok(found))) => found,
+        Some(Some(Err(err))) => return err,
+        Some(None) => return wrong_type(),
+    };
+    let count = found.len();
+    if found.is_empty() {
+        let existed = db.remove(dest).is_some();
+        drop(db);
+        if existed {
+            ctx.shared.signal_modified_key(dest, Some(ctx.client.id));
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "del", dest);
+        }
+        return Frame::Integer(0);
+    }
+    let mut zset = ZSet::new();
+    for found in found {
+        let score = if search.store_dist { found.distance / search.unit } else { found.score };
+        zset.insert(found.member, score, &db.thresholds);
+    }
+    db.set(dest.clone(), Value::ZSet(zset), None);
+    drop(db);
+
+    ctx.shared.signal_modified_key(dest, Some(ctx.client.id));
+    notify::keyspace_event(ctx.shared, notify::ZSET, "geosearchstore", dest);
+    Frame::Integer(count as i64)
+}
@@ -0,0 +1,361 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+
+use crate::command::Context;
+use crate::db::{Collection, Thresholds, Value};
+use crate::keyspace::{self, parse_i64};
+use crate::listpack::Listpack;
+use crate::notify;
+use mini_redis::Frame;
+
+// 估算内存时每个字段额外计入的固定开销（哈希表槽位和两个 Bytes）
+const FIELD_OVERHEAD: usize = 48;
+
+/// 哈希：字段少而且字段和值都不长时用 listpack 编码，字段和值交替存放；
+/// 超过阈值后转成哈希表编码，之后不再转回去
+#[derive(Debug, Clone)]
+pub struct Hash {
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone)]
+enum Encoding {
+    Listpack(Listpack),
+    Hashtable(Hashtable),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Hashtable {
+    fields: HashMap<Bytes, Bytes>,
+    size: usize,
+}
+
+impl Default for Hash {
+    fn default() -> Hash {
+        Hash { encoding: Encoding::Listpack(Listpack::default()) }
+    }
+}
+
+impl Hash {
+    pub fn new() -> Hash {
+        Hash::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.len() / 2,
+            Encoding::Hashtable(table) => table.fields.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 估算占用的内存，增删字段时维护
+    pub fn size(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.bytes(),
+            Encoding::Hashtable(table) => table.size,
+        }
+    }
+
+    /// OBJECT ENCODING 返回的内部编码
+    pub fn encoding(&self) -> &'static str {
+        match &self.encoding {
+            Encoding::Listpack(_) => "listpack",
+            Encoding::Hashtable(_) => "hashtable",
+        }
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<Bytes> {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => find(listpack, field).map(|i| Bytes::copy_from_slice(listpack.get(i + 1).unwrap())),
+            Encoding::Hashtable(table) => table.fields.get(field).cloned(),
+        }
+    }
+
+    /// 写入字段，返回是否是新字段。超过 hash-max-listpack-entries 或 hash-max-listpack-value 时转成哈希表编码
+    pub fn insert(&mut self, field: Bytes, value: Bytes, thresholds: &Thresholds) -> bool {
+        if let Encoding::Listpack(listpack) = &self.encoding {
+            let limit = thresholds.hash_max_listpack_value;
+            let too_long = field.len() > limit || value.len() > limit;
+            let full = listpack.len() / 2 >= thresholds.hash_max_listpack_entries;
+            if too_long || (full && find(listpack, &field).is_none()) {
+                self.convert(thresholds);
+            }
+        }
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) => match find(listpack, &field) {
+                Some(i) => {
+                    listpack.splice(i + 1..i + 2, &[&value]);
+                    false
+                }
+                None => {
+                    listpack.splice(listpack.len()..listpack.len(), &[&field, &value]);
+                    true
+                }
+            },
+            Encoding::Hashtable(table) => {
+                table.size += value.len();
+                match table.fields.insert(field.clone(), value) {
+                    Some(old) => {
+                        table.size -= old.len();
+                        false
+                    }
+                    None => {
+                        table.size += field.len() + FIELD_OVERHEAD;
+                        true
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> bool {
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) => match find(listpack, field) {
+                Some(i) => {
+                    listpack.splice(i..i + 2, &[]);
+                    true
+                }
+                None => false,
+            },
+            Encoding::Hashtable(table) => match table.fields.remove_entry(field) {
+                Some((field, value)) => {
+                    table.size -= field.len() + value.len() + FIELD_OVERHEAD;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// 按编码内部的顺序遍历（字段，值）
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (Bytes, Bytes)> + '_> {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => {
+                let mut items = listpack.iter().map(Bytes::copy_from_slice);
+                Box::new(std::iter::from_fn(move || Some((items.next()?, items.next()?))))
+            }
+            Encoding::Hashtable(table) => Box::new(table.fields.iter().map(|(field, value)| (field.clone(), value.clone()))),
+        }
+    }
+
+    fn convert(&mut self, thresholds: &Thresholds) {
+        let Encoding::Listpack(_) = &self.encoding else { return };
+        let pairs: Vec<_> = self.iter().collect();
+        self.encoding = Encoding::Hashtable(Hashtable::default());
+        for (field, value) in pairs {
+            self.insert(field, value, thresholds);
+        }
+    }
+}
+
+impl Collection for Hash {
+    fn cast(value: &Value) -> Option<&Hash> {
+        match value {
+            Value::Hash(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    fn cast_mut(value: &mut Value) -> Option<&mut Hash> {
+        match value {
+            Value::Hash(hash) => Some(hash),
+            _ => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        Hash::is_empty(self)
+    }
+}
+
+impl From<Hash> for Value {
+    fn from(hash: Hash) -> Value {
+        Value::Hash(hash)
+    }
+}
+
+/// listpack 里字段在偶数位置，返回字段所在的下标
+fn find(listpack: &Listpack, field: &[u8]) -> Option<usize> {
+    listpack.iter().step_by(2).position(|entry| entry == field).map(|i| i * 2)
+}
+
+// HSET key field value [field value ...]，HMSET 相同但回复 OK
+pub fn hset(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    if !args.len().is_multiple_of(2) {
+        return Frame::error(format!("ERR wrong number of arguments for '{}' command", String::from_utf8_lossy(&args[0])));
+    }
+    let pairs = &args[2..];
+    let added = keyspace::update(ctx, &args[1], true, notify::HASH, "hset", |hash: &mut Hash, thresholds| {
+        let added = pairs.chunks(2).filter(|pair| hash.insert(pair[0].clone(), pair[1].clone(), thresholds)).count();
+        (added, true)
+    });
+    match added {
+        Ok(_) if args[0].eq_ignore_ascii_case(b"hmset") => Frame::ok(),
+        Ok(added) => Frame::Integer(added.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+// HSETNX key field value
+pub fn hsetnx(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let result = keyspace::update(ctx, &args[1], true, notify::HASH, "hset", |hash: &mut Hash, thresholds| {
+        let added = hash.get(&args[2]).is_none() && hash.insert(args[2].clone(), args[3].clone(), thresholds);
+        (added, added)
+    });
+    match result {
+        Ok(added) => Frame::Integer(added.unwrap_or(false) as i64),
+        Err(err) => err,
+    }
+}
+
+// HINCRBY key field increment
+pub fn hincrby(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let increment = match parse_i64(&args[3]) {
+        Ok(increment) => increment,
+        Err(err) => return err,
+    };
+    let result = keyspace::update(ctx, &args[1], true, notify::HASH, "hincrby", |hash: &mut Hash, thresholds| {
+        let current = match hash.get(&args[2]) {
+            Some(value) => match parse_i64(&value) {
+                Ok(n) => n,
+                Err(_) => return (Err(Frame::error("ERR hash value is not an integer")), false),
+            },
+            None => 0,
+        };
+        match current.checked_add(increment) {
+            Some(n) => {
+                hash.insert(args[2].clone(), Bytes::from(n.to_string()), thresholds);
+                (Ok(n), true)
+            }
+            None => (Err(Frame::error("ERR increment or decrement would overflow")), false),
+        }
+    });
+    match result {
+        Ok(Some(Ok(n))) => Frame::Integer(n),
+        Ok(Some(Err(err))) | Err(err) => err,
+        Ok(None) => unreachable!(),
+    }
+}
+
+// HDEL key field [field ...]
+pub fn hdel(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let result = keyspace::update(ctx, &args[1], false, notify::HASH, "hdel", |hash: &mut Hash, _| {
+        let removed = args[2..].iter().filter(|field| hash.remove(field)).count();
+        (removed, removed > 0)
+    });
+    match result {
+        Ok(removed) => Frame::Integer(removed.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn hget(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |hash: &Hash| hash.get(&args[2])) {
+        Ok(value) => value.flatten().map_or(Frame::Null, Frame::Bulk),
+        Err(err) => err,
+    }
+}
+
+pub fn hmget(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let fields = &args[2..];
+    let values = keyspace::read(ctx, &args[1], |hash: &Hash| fields.iter().map(|field| hash.get(field)).collect());
+    match values {
+        Ok(values) => Frame::Array(
+            values.unwrap_or_else(|| vec![None; fields.len()]).into_iter().map(|value| value.map_or(Frame::Null, Frame::Bulk)).collect(),
+        ),
+        Err(err) => err,
+    }
+}
+
+pub fn hexists(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |hash: &Hash| hash.get(&args[2]).is_some()) {
+        Ok(exists) => Frame::Integer(exists.unwrap_or(false) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn hlen(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |hash: &Hash| hash.len()) {
+        Ok(len) => Frame::Integer(len.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn hstrlen(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |hash: &Hash| hash.get(&args[2]).map_or(0, |value| value.len())) {
+        Ok(len) => Frame::Integer(len.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+// HGETALL、HKEYS 和 HVALS
+pub fn hgetall(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let pairs = match keyspace::read(ctx, &args[1], |hash: &Hash| hash.iter().collect::<Vec<_>>()) {
+        Ok(pairs) => pairs.unwrap_or_default(),
+        Err(err) => return err,
+    };
+    match &args[0].to_ascii_lowercase()[..] {
+        b"hkeys" => Frame::Array(pairs.into_iter().map(|(field, _)| Frame::Bulk(field)).collect()),
+        b"hvals" => Frame::Array(pairs.into_iter().map(|(_, value)| Frame::Bulk(value)).collect()),
+        _ => Frame::Map(pairs.into_iter().map(|(field, value)| (Frame::Bulk(field), Frame::Bulk(value))).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_past_thresholds() {
+        let thresholds = Thresholds::default();
+        let mut hash = Hash::new();
+        for i in 0..128 {
+            assert!(hash.insert(Bytes::from(format!("f{}", i)), Bytes::from("v"), &thresholds));
+        }
+        assert!(!hash.insert(Bytes::from("f0"), Bytes::from("w"), &thresholds));
+        assert_eq!(hash.encoding(), "listpack");
+        assert_eq!(hash.get(b"f0").as_deref(), Some(&b"w"[..]));
+
+        assert!(hash.insert(Bytes::from("f128"), Bytes::from("v"), &thresholds));
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.len(), 129);
+        assert_eq!(hash.get(b"f0").as_deref(), Some(&b"w"[..]));
+
+        // 之后删到很少也不转回去
+        for i in 0..128 {
+            assert!(hash.remove(format!("f{}", i).as_bytes()));
+        }
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.iter().collect::<Vec<_>>(), [(Bytes::from("f128"), Bytes::from("v"))]);
+    }
+
+    #[test]
+    fn long_value_converts() {
+        let thresholds = Thresholds::default();
+        let mut hash = Hash::new();
+        hash.insert(Bytes::from("a"), Bytes::from("1"), &thresholds);
+        hash.insert(Bytes::from("b"), Bytes::from(vec![b'x'; 65]), &thresholds);
+        assert_eq!(hash.encoding(), "hashtable");
+        assert_eq!(hash.get(b"a").as_deref(), Some(&b"1"[..]));
+        assert_eq!(hash.len(), 2);
+    }
+
+    #[test]
+    fn listpack_is_smaller() {
+        let thresholds = Thresholds::default();
+        let mut compact = Hash::new();
+        let mut table = Hash { encoding: Encoding::Hashtable(Hashtable::default()) };
+        for i in 0..100 {
+            compact.insert(Bytes::from(format!("field:{}", i)), Bytes::from(i.to_string()), &thresholds);
+            table.insert(Bytes::from(format!("field:{}", i)), Bytes::from(i.to_string()), &thresholds);
+        }
+        assert!(compact.size() * 2 < table.size());
+        compact.remove(b"field:7");
+        table.remove(b"field:7");
+        assert_eq!(compact.len(), table.len());
+    }
+}
@@ -0,0 +1,601 @@
+use bytes::{Bytes, BytesMut};
+use rand::Rng;
+
+use crate::command::{syntax_error, Context};
+use crate::db::{Db, Value};
+use crate::notify;
+use mini_redis::Frame;
+
+// 和 Redis 相同的 HyperLogLog 字符串格式，可以直接 GET/SET 原始字节：
+// "HYLL" + 编码(1 字节) + 3 字节保留 + 8 字节基数缓存（小端，最高位置 1 表示缓存失效） + 寄存器。
+// 稠密编码是 16384 个 6 位寄存器；稀疏编码是游程编码：
+//   ZERO  00xxxxxx           连续 1~64 个 0
+//   XZERO 01xxxxxx yyyyyyyy  连续 1~16384 个 0
+//   VAL   1vvvvvxx           连续 1~4 个值为 1~32 的寄存器
+
+const P: u32 = 14;
+const REGISTERS: usize = 1 << P;
+// 哈希里除去寄存器下标剩下的位数
+const Q: u32 = 64 - P;
+const BITS: usize = 6;
+const MAX_REGISTER: u8 = (1 << BITS) - 1;
+const HEADER_SIZE: usize = 16;
+const DENSE_SIZE: usize = HEADER_SIZE + (REGISTERS * BITS).div_ceil(8);
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+// 稀疏编码超过这个大小就转成稠密编码
+const SPARSE_MAX_BYTES: usize = 3000;
+// 稀疏编码能表示的最大寄存器值
+const SPARSE_MAX_VALUE: u8 = 32;
+const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+
+const WRONGTYPE: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+
+/// 解析并校验过的 HyperLogLog
+struct Hll {
+    bytes: Vec<u8>,
+}
+
+impl Hll {
+    fn new() -> Hll {
+        let mut bytes = header(SPARSE);
+        bytes.extend_from_slice(&xzero(REGISTERS));
+        let mut hll = Hll { bytes };
+        hll.set_cached_count(Some(0));
+        hll
+    }
+
+    fn parse(value: &[u8]) -> Option<Hll> {
+        if value.len() < HEADER_SIZE || &value[..4] != b"HYLL" {
+            return None;
+        }
+        let valid = match value[4] {
+            DENSE => value.len() == DENSE_SIZE,
+            SPARSE => decode_sparse(&value[HEADER_SIZE..]).is_some(),
+            _ => false,
+        };
+        valid.then(|| Hll { bytes: value.to_vec() })
+    }
+
+    fn is_sparse(&self) -> bool {
+        self.bytes[4] == SPARSE
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        if self.is_sparse() {
+            decode_sparse(&self.bytes[HEADER_SIZE..]).expect("validated when parsed")
+        } else {
+            (0..REGISTERS).map(|i| get_register(&self.bytes[HEADER_SIZE..], i)).collect()
+        }
+    }
+
+    fn cached_count(&self) -> Option<u64> {
+        let card = u64::from_le_bytes(self.bytes[8..16].try_into().unwrap());
+        (card & (1 << 63) == 0).then_some(card)
+    }
+
+    fn set_cached_count(&mut self, count: Option<u64>) {
+        let card = count.unwrap_or(1 << 63);
+        self.bytes[8..16].copy_from_slice(&card.to_le_bytes());
+    }
+
+    /// 加入一个元素，返回是否有寄存器变大
+    fn add(&mut self, element: &[u8]) -> bool {
+        self.add_all([element])
+    }
+
+    /// 加入多个元素，返回是否有寄存器变大
+    fn add_all<'a>(&mut self, elements: impl IntoIterator<Item = &'a [u8]>) -> bool {
+        let mut updated = false;
+        if !self.is_sparse() {
+            let registers = &mut self.bytes[HEADER_SIZE..];
+            for element in elements {
+                let (index, count) = pattern(element);
+                if get_register(registers, index) < count {
+                    set_register(registers, index, count);
+                    updated = true;
+                }
+            }
+            if updated {
+                self.set_cached_count(None);
+            }
+            return updated;
+        }
+        // 稀疏编码只解开一次，全部加完再重新编码，放不下时转成稠密编码
+        let mut registers = self.registers();
+        for element in elements {
+            let (index, count) = pattern(element);
+            if registers[index] < count {
+                registers[index] = count;
+                updated = true;
+            }
+        }
+        if updated {
+            *self = Hll::from_registers(&registers);
+        }
+        updated
+    }
+
+    fn from_registers(registers: &[u8]) -> Hll {
+        match encode_sparse(registers) {
+            Some(sparse) => {
+                let mut bytes = header(SPARSE);
+                bytes.extend_from_slice(&sparse);
+                Hll { bytes }
+            }
+            None => Hll::dense(registers),
+        }
+    }
+
+    fn dense(registers: &[u8]) -> Hll {
+        let mut bytes = header(DENSE);
+        bytes.resize(DENSE_SIZE, 0);
+        for (i, value) in registers.iter().enumerate() {
+            set_register(&mut bytes[HEADER_SIZE..], i, *value);
+        }
+        Hll { bytes }
+    }
+
+    fn count(&mut self) -> u64 {
+        if let Some(count) = self.cached_count() {
+            return count;
+        }
+        let count = estimate(&self.registers());
+        self.set_cached_count(Some(count));
+        count
+    }
+}
+
+fn header(encoding: u8) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_SIZE);
+    bytes.extend_from_slice(b"HYLL");
+    bytes.extend_from_slice(&[encoding, 0, 0, 0]);
+    bytes.extend_from_slice(&(1u64 << 63).to_le_bytes());
+    bytes
+}
+
+fn xzero(len: usize) -> [u8; 2] {
+    let len = len - 1;
+    [0x40 | (len >> 8) as u8, len as u8]
+}
+
+fn get_register(registers: &[u8], index: usize) -> u8 {
+    let byte = index * BITS / 8;
+    let shift = index * BITS % 8;
+    let low = registers[byte] as u16;
+    let high = registers.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((high << 8 | low) >> shift) as u8) & MAX_REGISTER
+}
+
+fn set_register(registers: &mut [u8], index: usize, value: u8) {
+    let byte = index * BITS / 8;
+    let shift = index * BITS % 8;
+    let mask = (MAX_REGISTER as u16) << shift;
+    let value = (value as u16) << shift;
+    registers[byte] = (registers[byte] & !(mask as u8)) | value as u8;
+    if let Some(next) = registers.get_mut(byte + 1) {
+        *next = (*next & !((mask >> 8) as u8)) | (value >> 8) as u8;
+    }
+}
+
+fn decode_sparse(data: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut i = 0;
+    while i < data.len() {
+        let op = data[i];
+        let (value, run) = match op & 0xc0 {
+            0x00 => (0, (op & 0x3f) as usize + 1),
+            0x40 => {
+                let low = *data.get(i + 1)?;
+                i += 1;
+                (0, (((op & 0x3f) as usize) << 8 | low as usize) + 1)
+            }
+            _ => (((op >> 2) & 0x1f) + 1, (op & 0x03) as usize + 1),
+        };
+        i += 1;
+        if registers.len() + run > REGISTERS {
+            return None;
+        }
+        registers.resize(registers.len() + run, value);
+    }
+    (registers.len() == REGISTERS).then_some(registers)
+}
+
+// 有寄存器超过 32 或者编码后太大时返回 None
+fn encode_sparse(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        let run = registers[i..].iter().take_while(|v| **v == value).count();
+        i += run;
+        if value > SPARSE_MAX_VALUE {
+            return None;
+        }
+        let mut left = run;
+        while left > 0 {
+            if value == 0 && left > 64 {
+                let len = left.min(REGISTERS);
+                out.extend_from_slice(&xzero(len));
+                left -= len;
+            } else if value == 0 {
+                out.push((left - 1) as u8);
+                left = 0;
+            } else {
+                let len = left.min(4);
+                out.push(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+                left -= len;
+            }
+        }
+        if out.len() > SPARSE_MAX_BYTES {
+            return None;
+        }
+    }
+    Some(out)
+}
+
+/// 元素对应的寄存器下标和应该记录的值（哈希剩余位里第一个 1 的位置）
+fn pattern(element: &[u8]) -> (usize, u8) {
+    let hash = murmurhash64a(element, 0xadc8_3b19);
+    let index = (hash as usize) & (REGISTERS - 1);
+    // 补一个哨兵位，结果最大是 Q + 1
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn murmurhash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let chunks = key.chunks_exact(8);
+    let tail = chunks.remainder();
+    for chunk in chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+// Otmar Ertl 的改进估计算法，和 Redis 一样，不需要针对小基数和大基数单独修正
+fn estimate(registers: &[u8]) -> u64 {
+    let mut histogram = [0u32; 64];
+    for value in registers {
+        histogram[*value as usize] += 1;
+    }
+    let m = REGISTERS as f64;
+    let q = Q as usize;
+    let mut z = m * tau((m - histogram[q + 1] as f64) / m);
+    for j in (1..=q).rev() {
+        z += histogram[j] as f64;
+        z *= 0.5;
+    }
+    z += m * sigma(histogram[0] as f64 / m);
+    (ALPHA_INF * m * m / z).round() as u64
+}
+
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if previous == z {
+            return z / 3.0;
+        }
+    }
+}
+
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if previous == z {
+            return z;
+        }
+    }
+}
+
+// 读出 key 里的 HyperLogLog，key 不存在时返回 Ok(None)
+fn lookup(db: &mut Db, key: &[u8]) -> Result<Option<Hll>, Frame> {
+    match db.get(key) {
+        None => Ok(None),
+        Some(entry) => match entry.value.as_string().and_then(|value| Hll::parse(value)) {
+            Some(hll) => Ok(Some(hll)),
+            None => Err(Frame::error(WRONGTYPE)),
+        },
+    }
+}
+
+// 写回 HyperLogLog，已有的 key 保留过期时间
+fn store(db: &mut Db, key: &Bytes, hll: Hll) {
+    let value = Bytes::from(hll.bytes);
+    if db.modify(key, |old| *old = Value::String(value.clone())).is_none() {
+        db.set(key.clone(), value, None);
+    }
+}
+
+fn modified(ctx: &Context<'_>, key: &Bytes) {
+    ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+    notify::keyspace_event(ctx.shared, notify::STRING, "pfadd", key);
+}
+
+// PFADD key [element ...]
+pub fn pfadd(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let (mut hll, created) = match lookup(&mut db, key) {
+        Ok(Some(hll)) => (hll, false),
+        Ok(None) => (Hll::new(), true),
+        Err(err) => return err,
+    };
+    let updated = hll.add_all(args[2..].iter().map(|element| &element[..])) || created;
+    if updated {
+        store(&mut db, key, hll);
+    }
+    drop(db);
+    if updated {
+        modified(ctx, key);
+    }
+    Frame::Integer(updated as i64)
+}
+
+// PFCOUNT key [key ...]，多个 key 时返回并集的基数
+pub fn pfcount(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut db = ctx.shared.db.lock().unwrap();
+    if args.len() == 2 {
+        let key = &args[1];
+        let hll = match lookup(&mut db, key) {
+            Ok(Some(hll)) => hll,
+            Ok(None) => return Frame::Integer(0),
+            Err(err) => return err,
+        };
+        if let Some(count) = hll.cached_count() {
+            return Frame::Integer(count as i64);
+        }
+        let count = estimate(&hll.registers());
+        // 原地回写缓存的基数，内容没有变化，不换 CAS 版本号也不通知
+        db.rewrite(key, |value| {
+            if let Value::String(value) = value {
+                let mut data = BytesMut::from(std::mem::take(value));
+                data[8..16].copy_from_slice(&count.to_le_bytes());
+                *value = data.freeze();
+            }
+        });
+        return Frame::Integer(count as i64);
+    }
+    match union(&mut db, &args[1..]) {
+        Ok(registers) => Frame::Integer(estimate(&registers) as i64),
+        Err(err) => err,
+    }
+}
+
+fn union(db: &mut Db, keys: &[Bytes]) -> Result<Vec<u8>, Frame> {
+    let mut registers = vec![0u8; REGISTERS];
+    for key in keys {
+        if let Some(hll) = lookup(db, key)? {
+            for (max, value) in registers.iter_mut().zip(hll.registers()) {
+                *max = (*max).max(value);
+            }
+        }
+    }
+    Ok(registers)
+}
+
+// PFMERGE destkey [sourcekey ...]，destkey 原有的内容也参与合并
+pub fn pfmerge(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    // 读源 key 和写目标 key 在同一次加锁里完成
+    let mut db = ctx.shared.db.lock().unwrap();
+    let registers = match union(&mut db, &args[1..]) {
+        Ok(registers) => registers,
+        Err(err) => return err,
+    };
+    store(&mut db, key, Hll::from_registers(&registers));
+    drop(db);
+    modified(ctx, key);
+    Frame::ok()
+}
+
+// PFDEBUG GETREG|ENCODING|TODENSE key
+pub fn pfdebug(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let key = &args[2];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let hll = match lookup(&mut db, key) {
+        Ok(Some(hll)) => hll,
+        Ok(None) => return Frame::error("ERR The specified key does not exist"),
+        Err(err) => return err,
+    };
+    match &args[1].to_ascii_lowercase()[..] {
+        b"getreg" => Frame::Array(hll.registers().into_iter().map(|v| Frame::Integer(v as i64)).collect()),
+        b"encoding" => Frame::Simple(if hll.is_sparse() { "sparse" } else { "dense" }.to_string()),
+        b"todense" => {
+            let converted = hll.is_sparse();
+            if converted {
+                let mut dense = Hll::dense(&hll.registers());
+                dense.bytes[8..16].copy_from_slice(&hll.bytes[8..16]);
+                store(&mut db, key, dense);
+            }
+            Frame::Integer(converted as i64)
+        }
+        _ => syntax_error(),
+    }
+}
+
+// PFSELFTEST：检查寄存器读写、两种编码的一致性，并统计估计误差是否在理论范围内
+pub fn pfselftest(_ctx: &mut Context<'_>, _args: &[Bytes]) -> Frame {
+    match selftest() {
+        Ok(()) => Frame::ok(),
+        Err(message) => Frame::error(format!("ERR TESTFAILED {}", message)),
+    }
+}
+
+fn selftest() -> Result<(), String> {
+    let mut rng = rand::thread_rng();
+
+    // 稠密编码的寄存器读写，相邻寄存器共享字节，写一个不能影响别的
+    let mut bytes = vec![0u8; DENSE_SIZE - HEADER_SIZE];
+    let expected: Vec<u8> = (0..REGISTERS).map(|_| rng.gen_range(0..=MAX_REGISTER)).collect();
+    for (i, value) in expected.iter().enumerate() {
+        set_register(&mut bytes, i, *value);
+    }
+    for (i, value) in expected.iter().enumerate() {
+        if get_register(&bytes, i) != *value {
+            return Err(format!("register {} should be {}", i, value));
+        }
+    }
+
+    // 同样的元素加到稀疏和稠密编码里，寄存器必须完全一样
+    let mut sparse = Hll::new();
+    let mut dense = Hll::dense(&[0; REGISTERS]);
+    for i in 0..5000 {
+        let element = format!("element:{}", i);
+        if sparse.add(element.as_bytes()) != dense.add(element.as_bytes()) {
+            return Err(format!("sparse and dense encodings disagree after {} elements", i + 1));
+        }
+        if sparse.registers() != dense.registers() {
+            return Err(format!("sparse and dense registers differ after {} elements", i + 1));
+        }
+    }
+    if sparse.is_sparse() {
+        return Err("sparse encoding was not promoted to dense".to_string());
+    }
+
+    // 标准误差是 1.04/sqrt(m)，约 0.81%。每个检查点的误差不能超过 5 倍标准误差，
+    // 多轮试验的均方根误差不能超过 2 倍标准误差
+    let std_error = 1.04 / (REGISTERS as f64).sqrt();
+    let checkpoints = [10u64, 100, 1000, 10_000, 100_000, 200_000];
+    let trials = 5;
+    let mut squares = 0.0;
+    let mut samples = 0;
+    for trial in 0..trials {
+        let prefix: u64 = rng.gen();
+        let mut hll = Hll::dense(&[0; REGISTERS]);
+        let mut added = 0;
+        for &checkpoint in &checkpoints {
+            while added < checkpoint {
+                hll.add(format!("{}:{}", prefix, added).as_bytes());
+                added += 1;
+            }
+            let count = hll.count();
+            let error = (count as f64 - checkpoint as f64) / checkpoint as f64;
+            if error.abs() > 5.0 * std_error && (count as i64 - checkpoint as i64).abs() > 1 {
+                return Err(format!(
+                    "trial {}: estimated {} for {} elements, relative error {:.4}",
+                    trial, count, checkpoint, error
+                ));
+            }
+            if checkpoint >= 1000 {
+                squares += error * error;
+                samples += 1;
+            }
+        }
+    }
+    let rms = (squares / samples as f64).sqrt();
+    if rms > 2.0 * std_error {
+        return Err(format!("RMS relative error {:.4} exceeds {:.4}", rms, 2.0 * std_error));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 标准误差约 0.81%，单个检查点允许 4 倍标准误差；元素是固定的，结果可以复现
+    fn assert_close(count: u64, actual: u64) {
+        let std_error = 1.04 / (REGISTERS as f64).sqrt();
+        let error = (count as f64 - actual as f64).abs() / actual as f64;
+        assert!(
+            error <= 4.0 * std_error || count.abs_diff(actual) <= 1,
+            "estimated {} for {} elements, relative error {:.4}",
+            count,
+            actual,
+            error
+        );
+    }
+
+    fn filled(prefix: &str, range: std::ops::Range<u64>) -> Hll {
+        let mut hll = Hll::new();
+        for i in range {
+            hll.add(format!("{}:{}", prefix, i).as_bytes());
+        }
+        hll
+    }
+
+    #[test]
+    fn selftest_passes() {
+        selftest().unwrap();
+    }
+
+    #[test]
+    fn relative_error_across_cardinalities() {
+        let checkpoints = [1u64, 10, 100, 500, 1000, 5000, 10_000, 50_000, 100_000, 500_000];
+        let mut hll = Hll::new();
+        let mut added = 0;
+        for checkpoint in checkpoints {
+            while added < checkpoint {
+                hll.add(format!("card:{}", added).as_bytes());
+                added += 1;
+            }
+            assert_close(hll.count(), checkpoint);
+        }
+        assert!(!hll.is_sparse());
+    }
+
+    #[test]
+    fn estimate_is_continuous_across_sparse_to_dense() {
+        let mut hll = Hll::new();
+        let mut dense = Hll::dense(&[0; REGISTERS]);
+        let mut added = 0u64;
+        while hll.is_sparse() {
+            let element = format!("promote:{}", added);
+            hll.add(element.as_bytes());
+            dense.add(element.as_bytes());
+            added += 1;
+        }
+        // 转换时寄存器原样搬过去，估算值和一直用稠密编码的完全一样
+        assert!(added > 100, "promoted after only {} elements", added);
+        assert_eq!(hll.registers(), dense.registers());
+        assert_eq!(hll.count(), dense.count());
+        assert_close(hll.count(), added);
+        // 转换后的内容可以重新解析
+        let parsed = Hll::parse(&hll.bytes).unwrap();
+        assert_eq!(parsed.registers(), hll.registers());
+    }
+
+    #[test]
+    fn sparse_and_dense_round_trip() {
+        for hll in [filled("small", 0..50), filled("large", 0..20_000)] {
+            let parsed = Hll::parse(&hll.bytes).unwrap();
+            assert_eq!(parsed.is_sparse(), hll.is_sparse());
+            assert_eq!(parsed.registers(), hll.registers());
+        }
+        assert!(Hll::parse(b"HYLL").is_none());
+        assert!(Hll::parse(&Hll::new().bytes[..HEADER_SIZE + 1]).is_none());
+    }
+}
@@ -0,0 +1,117 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+// /metrics 和 HTTP 网关共用的最小 HTTP/1.1 实现：每个连接只处理一个请求，回复后关闭
+const MAX_HEADER: usize = 16 * 1024;
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    // 名字都转成小写
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    /// 查询参数里所有叫 name 的值，已经解码
+    pub fn query_values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Vec<u8>> + 'a {
+        self.query
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .filter(move |(n, _)| *n == name)
+            .map(|(_, value)| percent_decode(&value.replace('+', " ")))
+    }
+}
+
+/// 读取一个请求，对端在发送任何数据之前就关闭连接时返回 None
+pub async fn read_request<S: AsyncRead + Unpin>(socket: &mut S, max_body: usize) -> crate::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        if buf.len() > MAX_HEADER {
+            return Err("request header too large".into());
+        }
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            return Err("connection closed in the middle of a request".into());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = std::str::from_utf8(&buf[..header_end]).map_err(|_| "request header is not valid utf-8")?;
+    let mut lines = head.split("\r\n");
+    let mut start = lines.next().unwrap_or_default().split(' ');
+    let (method, target) = match (start.next(), start.next()) {
+        (Some(method), Some(target)) if !method.is_empty() => (method.to_string(), target),
+        _ => return Err("malformed request line".into()),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let (path, query) = (path.to_string(), query.to_string());
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let len = match headers.iter().find(|(name, _)| name == "content-length") {
+        Some((_, len)) => len.parse::<usize>().map_err(|_| "invalid content-length")?,
+        None => 0,
+    };
+    if len > max_body {
+        return Err("request body too large".into());
+    }
+    let mut body = buf.split_off(header_end);
+    while body.len() < len {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Err("connection closed in the middle of a request".into());
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+    body.truncate(len);
+    Ok(Some(Request { method, path, query, headers, body }))
+}
+
+/// 写一个完整的响应并关闭写方向
+pub async fn respond<S: AsyncWrite + Unpin>(socket: &mut S, status: &str, content_type: &str, body: &[u8]) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    socket.write_all(head.as_bytes()).await?;
+    socket.write_all(body).await?;
+    socket.shutdown().await
+}
+
+/// 解码 URL 中的 %XX 转义
+pub fn percent_decode(value: &str) -> Vec<u8> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                (Some(hi), Some(lo)) => {
+                    out.push((hi * 16 + lo) as u8);
+                    i += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    out
+}
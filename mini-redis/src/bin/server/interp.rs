@@ -0,0 +1,1685 @@
+use bytes::Bytes;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use mini_redis::Frame;
+
+// EVAL 脚本用的解释器，语法是 Lua 的一个子集：
+// local/if/while/repeat/数值 for/ipairs 和 pairs 的泛型 for/return/break，
+// 表、字符串、数字运算，以及 redis.call 等少量内置函数。不支持自定义函数和多返回值。
+
+// 表达式嵌套深度上限，防止构造出的脚本把解析器的栈撑爆
+const MAX_DEPTH: usize = 200;
+// 每执行这么多步检查一次是否被 SCRIPT KILL
+const CHECK_INTERVAL: u64 = 1000;
+// 单个字符串的长度上限
+const MAX_STRING: usize = 512 * 1024 * 1024;
+
+/// 脚本访问服务器的接口
+pub trait Host {
+    /// 执行一条命令，返回它的回复（包括错误回复）
+    fn call(&mut self, args: Vec<Bytes>) -> Frame;
+    /// 定期调用，返回 true 时中止脚本
+    fn interrupted(&mut self) -> bool;
+}
+
+pub enum Error {
+    // 脚本本身的错误，例如对 nil 做运算、访问不存在的全局变量
+    Runtime { line: usize, message: String },
+    // redis.call 执行的命令返回了错误，或者 error() 抛出了 error_reply
+    Reply { line: usize, message: String },
+    Killed,
+}
+
+// ---------- 词法分析 ----------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Name(String),
+    Number(f64),
+    Str(Bytes),
+    // 关键字和运算符
+    Op(&'static str),
+    Eof,
+}
+
+const KEYWORDS: &[&str] = &[
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in", "local", "nil",
+    "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+// 长的放前面，保证优先匹配
+const SYMBOLS: &[&str] = &[
+    "...", "..", "==", "~=", "<=", ">=", "+", "-", "*", "/", "%", "^", "#", "<", ">", "=", "(", ")", "{", "}",
+    "[", "]", ";", ":", ",", ".",
+];
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Number(n) => write!(f, "{}", format_number(*n)),
+            Token::Str(s) => write!(f, "{}", String::from_utf8_lossy(s)),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Eof => write!(f, "<eof>"),
+        }
+    }
+}
+
+struct Lexer<'a> {
+    src: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Lexer<'_> {
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        format!("user_script:{}: {}", self.line, message)
+    }
+
+    fn peek(&self, offset: usize) -> u8 {
+        self.src.get(self.pos + offset).copied().unwrap_or(0)
+    }
+
+    fn tokenize(mut self) -> Result<Vec<(Token, usize)>, String> {
+        let mut tokens = Vec::new();
+        loop {
+            self.skip_space()?;
+            let line = self.line;
+            let c = self.peek(0);
+            let token = if self.pos >= self.src.len() {
+                Token::Eof
+            } else if c.is_ascii_alphabetic() || c == b'_' {
+                let start = self.pos;
+                while self.peek(0).is_ascii_alphanumeric() || self.peek(0) == b'_' {
+                    self.pos += 1;
+                }
+                let word = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+                match KEYWORDS.iter().find(|keyword| **keyword == word) {
+                    Some(keyword) => Token::Op(keyword),
+                    None => Token::Name(word.to_string()),
+                }
+            } else if c.is_ascii_digit() || (c == b'.' && self.peek(1).is_ascii_digit()) {
+                self.number()?
+            } else if c == b'"' || c == b'\'' {
+                self.string(c)?
+            } else if c == b'[' && matches!(self.peek(1), b'[' | b'=') {
+                match self.long_bracket()? {
+                    Some(s) => Token::Str(s.into()),
+                    None => {
+                        self.pos += 1;
+                        Token::Op("[")
+                    }
+                }
+            } else {
+                let rest = &self.src[self.pos..];
+                match SYMBOLS.iter().find(|symbol| rest.starts_with(symbol.as_bytes())) {
+                    Some(symbol) => {
+                        self.pos += symbol.len();
+                        Token::Op(symbol)
+                    }
+                    None => return Err(self.error(format!("unexpected symbol near '{}'", c as char))),
+                }
+            };
+            let eof = token == Token::Eof;
+            tokens.push((token, line));
+            if eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn skip_space(&mut self) -> Result<(), String> {
+        loop {
+            match self.peek(0) {
+                b'\n' => {
+                    self.line += 1;
+                    self.pos += 1;
+                }
+                c if c.is_ascii_whitespace() => self.pos += 1,
+                b'-' if self.peek(1) == b'-' => {
+                    self.pos += 2;
+                    if self.peek(0) == b'[' && self.long_bracket()?.is_some() {
+                        continue;
+                    }
+                    while self.pos < self.src.len() && self.peek(0) != b'\n' {
+                        self.pos += 1;
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // [[...]]、[==[...]==]，不是长括号时返回 None 且不移动位置
+    fn long_bracket(&mut self) -> Result<Option<Vec<u8>>, String> {
+        let mut level = 0;
+        while self.peek(1 + level) == b'=' {
+            level += 1;
+        }
+        if self.peek(1 + level) != b'[' {
+            return Ok(None);
+        }
+        self.pos += level + 2;
+        // 紧跟在开括号后面的换行不算内容
+        if self.peek(0) == b'\n' {
+            self.line += 1;
+            self.pos += 1;
+        }
+        let close = format!("]{}]", "=".repeat(level));
+        let start = self.pos;
+        loop {
+            if self.pos >= self.src.len() {
+                return Err(self.error("unfinished long string"));
+            }
+            if self.src[self.pos..].starts_with(close.as_bytes()) {
+                let content = self.src[start..self.pos].to_vec();
+                self.pos += close.len();
+                return Ok(Some(content));
+            }
+            if self.peek(0) == b'\n' {
+                self.line += 1;
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn number(&mut self) -> Result<Token, String> {
+        let start = self.pos;
+        if self.peek(0) == b'0' && matches!(self.peek(1), b'x' | b'X') {
+            self.pos += 2;
+            while self.peek(0).is_ascii_hexdigit() {
+                self.pos += 1;
+            }
+            let digits = std::str::from_utf8(&self.src[start + 2..self.pos]).unwrap();
+            return match i64::from_str_radix(digits, 16) {
+                Ok(n) => Ok(Token::Number(n as f64)),
+                Err(_) => Err(self.error(format!("malformed number near '0x{}'", digits))),
+            };
+        }
+        while self.peek(0).is_ascii_alphanumeric() || self.peek(0) == b'.' {
+            let c = self.peek(0);
+            self.pos += 1;
+            if matches!(c, b'e' | b'E') && matches!(self.peek(0), b'+' | b'-') {
+                self.pos += 1;
+            }
+        }
+        let text = std::str::from_utf8(&self.src[start..self.pos]).unwrap();
+        parse_number(text)
+            .map(Token::Number)
+            .ok_or_else(|| self.error(format!("malformed number near '{}'", text)))
+    }
+
+    fn string(&mut self, quote: u8) -> Result<Token, String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let c = self.peek(0);
+            if self.pos >= self.src.len() || c == b'\n' {
+                return Err(self.error("unfinished string"));
+            }
+            self.pos += 1;
+            if c == quote {
+                return Ok(Token::Str(out.into()));
+            }
+            if c != b'\\' {
+                out.push(c);
+                continue;
+            }
+            let escape = self.peek(0);
+            self.pos += 1;
+            match escape {
+                b'n' => out.push(b'\n'),
+                b't' => out.push(b'\t'),
+                b'r' => out.push(b'\r'),
+                b'a' => out.push(0x07),
+                b'b' => out.push(0x08),
+                b'f' => out.push(0x0c),
+                b'v' => out.push(0x0b),
+                b'\\' | b'"' | b'\'' => out.push(escape),
+                b'\n' => {
+                    self.line += 1;
+                    out.push(b'\n');
+                }
+                b'x' => {
+                    let hex = std::str::from_utf8(self.src.get(self.pos..self.pos + 2).unwrap_or_default()).unwrap_or("");
+                    match u8::from_str_radix(hex, 16) {
+                        Ok(byte) if hex.len() == 2 => out.push(byte),
+                        _ => return Err(self.error("hexadecimal digit expected")),
+                    }
+                    self.pos += 2;
+                }
+                // \ddd，最多三位十进制
+                b'0'..=b'9' => {
+                    let mut value = (escape - b'0') as u32;
+                    for _ in 0..2 {
+                        if !self.peek(0).is_ascii_digit() {
+                            break;
+                        }
+                        value = value * 10 + (self.peek(0) - b'0') as u32;
+                        self.pos += 1;
+                    }
+                    match u8::try_from(value) {
+                        Ok(byte) => out.push(byte),
+                        Err(_) => return Err(self.error("decimal escape too large")),
+                    }
+                }
+                _ => return Err(self.error("invalid escape sequence")),
+            }
+        }
+    }
+}
+
+// ---------- 语法分析 ----------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Concat,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum UnOp {
+    Neg,
+    Not,
+    Len,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(Bytes),
+    Name(String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    // None 表示按顺序排列的数组元素
+    Table(Vec<(Option<Expr>, Expr)>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+}
+
+#[derive(Debug)]
+enum Stmt {
+    Local(Vec<String>, Vec<Expr>),
+    Assign(Vec<Expr>, Vec<Expr>),
+    Call(Expr),
+    If(Vec<(Expr, Block)>, Option<Block>),
+    While(Expr, Block),
+    Repeat(Block, Expr),
+    NumericFor(String, Expr, Expr, Option<Expr>, Block),
+    GenericFor(Vec<String>, Expr, Block),
+    Do(Block),
+    Return(Option<Expr>),
+    Break,
+}
+
+// 每条语句带上行号，出错时报告
+type Block = Vec<(Stmt, usize)>;
+
+/// 编译好的脚本
+#[derive(Debug)]
+pub struct Script {
+    body: Block,
+}
+
+/// 编译脚本，语法错误返回 `user_script:<line>: <message>`
+pub fn compile(src: &[u8]) -> Result<Script, String> {
+    let tokens = Lexer { src, pos: 0, line: 1 }.tokenize()?;
+    let mut parser = Parser { tokens, pos: 0, depth: 0 };
+    let body = parser.block()?;
+    if parser.peek() != &Token::Eof {
+        return Err(parser.error(format!("'<eof>' expected near '{}'", parser.peek())));
+    }
+    Ok(Script { body })
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if token != Token::Eof {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn check(&self, op: &str) -> bool {
+        matches!(self.peek(), Token::Op(o) if *o == op)
+    }
+
+    fn accept(&mut self, op: &str) -> bool {
+        let found = self.check(op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.accept(op) {
+            Ok(())
+        } else {
+            Err(self.error(format!("'{}' expected near '{}'", op, self.peek())))
+        }
+    }
+
+    fn error(&self, message: impl std::fmt::Display) -> String {
+        format!("user_script:{}: {}", self.line(), message)
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next() {
+            Token::Name(name) => Ok(name),
+            token => {
+                self.pos -= 1;
+                Err(self.error(format!("<name> expected near '{}'", token)))
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Block, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("chunk has too many syntax levels"));
+        }
+        let mut block = Vec::new();
+        loop {
+            if matches!(self.peek(), Token::Eof) || ["end", "else", "elseif", "until"].iter().any(|op| self.check(op)) {
+                self.depth -= 1;
+                return Ok(block);
+            }
+            let line = self.line();
+            if self.accept(";") {
+                continue;
+            }
+            let last = self.check("return");
+            block.push((self.statement()?, line));
+            // return 必须是块里的最后一条语句
+            if last {
+                self.accept(";");
+                self.depth -= 1;
+                return Ok(block);
+            }
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        let token = self.next();
+        match token {
+            Token::Op("if") => {
+                let mut branches = Vec::new();
+                let condition = self.expr(0)?;
+                self.expect("then")?;
+                branches.push((condition, self.block()?));
+                let mut otherwise = None;
+                loop {
+                    if self.accept("elseif") {
+                        let condition = self.expr(0)?;
+                        self.expect("then")?;
+                        branches.push((condition, self.block()?));
+                    } else if self.accept("else") {
+                        otherwise = Some(self.block()?);
+                        self.expect("end")?;
+                        break;
+                    } else {
+                        self.expect("end")?;
+                        break;
+                    }
+                }
+                Ok(Stmt::If(branches, otherwise))
+            }
+            Token::Op("while") => {
+                let condition = self.expr(0)?;
+                self.expect("do")?;
+                let body = self.block()?;
+                self.expect("end")?;
+                Ok(Stmt::While(condition, body))
+            }
+            Token::Op("repeat") => {
+                let body = self.block()?;
+                self.expect("until")?;
+                Ok(Stmt::Repeat(body, self.expr(0)?))
+            }
+            Token::Op("do") => {
+                let body = self.block()?;
+                self.expect("end")?;
+                Ok(Stmt::Do(body))
+            }
+            Token::Op("for") => {
+                let first = self.name()?;
+                if self.accept("=") {
+                    let start = self.expr(0)?;
+                    self.expect(",")?;
+                    let limit = self.expr(0)?;
+                    let step = if self.accept(",") { Some(self.expr(0)?) } else { None };
+                    self.expect("do")?;
+                    let body = self.block()?;
+                    self.expect("end")?;
+                    return Ok(Stmt::NumericFor(first, start, limit, step, body));
+                }
+                let mut names = vec![first];
+                while self.accept(",") {
+                    names.push(self.name()?);
+                }
+                self.expect("in")?;
+                let iterator = self.expr(0)?;
+                self.expect("do")?;
+                let body = self.block()?;
+                self.expect("end")?;
+                Ok(Stmt::GenericFor(names, iterator, body))
+            }
+            Token::Op("local") => {
+                if self.check("function") {
+                    return Err(self.error("function definitions are not supported"));
+                }
+                let mut names = vec![self.name()?];
+                while self.accept(",") {
+                    names.push(self.name()?);
+                }
+                let values = if self.accept("=") { self.expr_list()? } else { Vec::new() };
+                Ok(Stmt::Local(names, values))
+            }
+            Token::Op("return") => {
+                if matches!(self.peek(), Token::Eof)
+                    || ["end", "else", "elseif", "until", ";"].iter().any(|op| self.check(op))
+                {
+                    return Ok(Stmt::Return(None));
+                }
+                let value = self.expr(0)?;
+                if self.check(",") {
+                    return Err(self.error("multiple return values are not supported"));
+                }
+                Ok(Stmt::Return(Some(value)))
+            }
+            Token::Op("break") => Ok(Stmt::Break),
+            Token::Op("function") => Err(self.error("function definitions are not supported")),
+            _ => {
+                self.pos -= 1;
+                let target = self.suffixed()?;
+                if self.check("=") || self.check(",") {
+                    let mut targets = vec![target];
+                    while self.accept(",") {
+                        targets.push(self.suffixed()?);
+                    }
+                    if targets.iter().any(|target| !matches!(target, Expr::Name(_) | Expr::Index(..))) {
+                        return Err(self.error("syntax error near '='"));
+                    }
+                    self.expect("=")?;
+                    return Ok(Stmt::Assign(targets, self.expr_list()?));
+                }
+                match target {
+                    Expr::Call(..) => Ok(Stmt::Call(target)),
+                    _ => Err(self.error(format!("syntax error near '{}'", self.peek()))),
+                }
+            }
+        }
+    }
+
+    fn expr_list(&mut self) -> Result<Vec<Expr>, String> {
+        let mut list = vec![self.expr(0)?];
+        while self.accept(",") {
+            list.push(self.expr(0)?);
+        }
+        Ok(list)
+    }
+
+    // 运算符优先级和 Lua 一致，返回（左优先级，右优先级），右结合的运算符右边更低
+    fn binary_op(&self) -> Option<(BinOp, u8, u8)> {
+        let op = match self.peek() {
+            Token::Op(op) => *op,
+            _ => return None,
+        };
+        Some(match op {
+            "or" => (BinOp::Or, 1, 1),
+            "and" => (BinOp::And, 2, 2),
+            "<" => (BinOp::Lt, 3, 3),
+            ">" => (BinOp::Gt, 3, 3),
+            "<=" => (BinOp::Le, 3, 3),
+            ">=" => (BinOp::Ge, 3, 3),
+            "~=" => (BinOp::Ne, 3, 3),
+            "==" => (BinOp::Eq, 3, 3),
+            ".." => (BinOp::Concat, 9, 8),
+            "+" => (BinOp::Add, 10, 10),
+            "-" => (BinOp::Sub, 10, 10),
+            "*" => (BinOp::Mul, 11, 11),
+            "/" => (BinOp::Div, 11, 11),
+            "%" => (BinOp::Mod, 11, 11),
+            "^" => (BinOp::Pow, 14, 13),
+            _ => return None,
+        })
+    }
+
+    fn expr(&mut self, limit: u8) -> Result<Expr, String> {
+        const UNARY_PRIORITY: u8 = 12;
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error("expression too complex"));
+        }
+        let unary = match self.peek() {
+            Token::Op("-") => Some(UnOp::Neg),
+            Token::Op("not") => Some(UnOp::Not),
+            Token::Op("#") => Some(UnOp::Len),
+            _ => None,
+        };
+        let mut left = match unary {
+            Some(op) => {
+                self.pos += 1;
+                Expr::Unary(op, Box::new(self.expr(UNARY_PRIORITY)?))
+            }
+            None => self.simple()?,
+        };
+        while let Some((op, left_priority, right_priority)) = self.binary_op() {
+            if left_priority <= limit {
+                break;
+            }
+            self.pos += 1;
+            let right = self.expr(right_priority)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        self.depth -= 1;
+        Ok(left)
+    }
+
+    fn simple(&mut self) -> Result<Expr, String> {
+        let expr = match self.peek().clone() {
+            Token::Op("nil") => Expr::Nil,
+            Token::Op("true") => Expr::Bool(true),
+            Token::Op("false") => Expr::Bool(false),
+            Token::Number(n) => Expr::Number(n),
+            Token::Str(s) => Expr::Str(s),
+            Token::Op("{") => return self.table(),
+            Token::Op("function") => return Err(self.error("function definitions are not supported")),
+            Token::Op("...") => return Err(self.error("varargs are not supported")),
+            _ => return self.suffixed(),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+
+    fn table(&mut self) -> Result<Expr, String> {
+        self.expect("{")?;
+        let mut fields = Vec::new();
+        while !self.check("}") {
+            if self.accept("[") {
+                let key = self.expr(0)?;
+                self.expect("]")?;
+                self.expect("=")?;
+                fields.push((Some(key), self.expr(0)?));
+            } else if matches!(self.peek(), Token::Name(_)) && matches!(self.tokens[self.pos + 1].0, Token::Op("=")) {
+                let name = self.name()?;
+                self.pos += 1;
+                fields.push((Some(Expr::Str(name.into())), self.expr(0)?));
+            } else {
+                fields.push((None, self.expr(0)?));
+            }
+            if !self.accept(",") && !self.accept(";") {
+                break;
+            }
+        }
+        self.expect("}")?;
+        Ok(Expr::Table(fields))
+    }
+
+    fn suffixed(&mut self) -> Result<Expr, String> {
+        let mut expr = match self.next() {
+            Token::Name(name) => Expr::Name(name),
+            Token::Op("(") => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                expr
+            }
+            token => {
+                self.pos -= 1;
+                return Err(self.error(format!("unexpected symbol near '{}'", token)));
+            }
+        };
+        loop {
+            match self.peek().clone() {
+                Token::Op(".") => {
+                    self.pos += 1;
+                    let name = self.name()?;
+                    expr = Expr::Index(Box::new(expr), Box::new(Expr::Str(name.into())));
+                }
+                Token::Op("[") => {
+                    self.pos += 1;
+                    let key = self.expr(0)?;
+                    self.expect("]")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(key));
+                }
+                Token::Op("(") => {
+                    self.pos += 1;
+                    let args = if self.check(")") { Vec::new() } else { self.expr_list()? };
+                    self.expect(")")?;
+                    expr = Expr::Call(Box::new(expr), args);
+                }
+                // f"str" 和 f{...} 形式的调用
+                Token::Str(s) => {
+                    self.pos += 1;
+                    expr = Expr::Call(Box::new(expr), vec![Expr::Str(s)]);
+                }
+                Token::Op("{") => {
+                    let table = self.table()?;
+                    expr = Expr::Call(Box::new(expr), vec![table]);
+                }
+                Token::Op(":") => return Err(self.error("method calls are not supported")),
+                _ => return Ok(expr),
+            }
+        }
+    }
+}
+
+// ---------- 执行 ----------
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Builtin {
+    Call,
+    Pcall,
+    ErrorReply,
+    StatusReply,
+    Sha1Hex,
+    Log,
+    ToNumber,
+    ToString,
+    Type,
+    Error,
+    Ipairs,
+    Pairs,
+    TableInsert,
+    TableRemove,
+    TableConcat,
+    StringLen,
+    StringSub,
+    StringUpper,
+    StringLower,
+    StringRep,
+    MathFloor,
+    MathCeil,
+    MathAbs,
+    MathMax,
+    MathMin,
+}
+
+#[derive(Clone)]
+enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    Str(Bytes),
+    Table(Rc<RefCell<Table>>),
+    Builtin(Builtin),
+    // ipairs/pairs 的返回值，只能用在泛型 for 里
+    Iter(Rc<RefCell<Table>>, bool),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Str(Bytes),
+    Int(i64),
+    Float(u64),
+    Bool(bool),
+}
+
+#[derive(Default)]
+struct Table {
+    // 1..=n 的连续整数下标
+    array: Vec<Value>,
+    hash: HashMap<Key, Value>,
+    readonly: bool,
+}
+
+impl Table {
+    fn get(&self, key: &Key) -> Value {
+        if let Key::Int(i) = key {
+            if *i >= 1 && (*i as usize) <= self.array.len() {
+                return self.array[*i as usize - 1].clone();
+            }
+        }
+        self.hash.get(key).cloned().unwrap_or(Value::Nil)
+    }
+
+    fn set(&mut self, key: Key, value: Value) {
+        if let Key::Int(i) = key {
+            let len = self.array.len() as i64;
+            if i >= 1 && i <= len {
+                self.array[i as usize - 1] = value;
+                while matches!(self.array.last(), Some(Value::Nil)) {
+                    self.array.pop();
+                }
+                return;
+            }
+            if i == len + 1 && !matches!(value, Value::Nil) {
+                self.array.push(value);
+                // 原来放在哈希部分的后续下标挪到数组部分
+                while let Some(next) = self.hash.remove(&Key::Int(self.array.len() as i64 + 1)) {
+                    self.array.push(next);
+                }
+                return;
+            }
+        }
+        match value {
+            Value::Nil => self.hash.remove(&key),
+            value => self.hash.insert(key, value),
+        };
+    }
+
+    fn field(&self, name: &'static str) -> Value {
+        self.get(&Key::Str(Bytes::from_static(name.as_bytes())))
+    }
+}
+
+fn new_table(table: Table) -> Value {
+    Value::Table(Rc::new(RefCell::new(table)))
+}
+
+fn library(functions: &[(&'static str, Value)]) -> Value {
+    let mut table = Table::default();
+    for (name, value) in functions {
+        table.set(Key::Str(Bytes::from_static(name.as_bytes())), value.clone());
+    }
+    table.readonly = true;
+    new_table(table)
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::Str(_) => "string",
+            Value::Table(_) => "table",
+            Value::Builtin(_) | Value::Iter(..) => "function",
+        }
+    }
+
+    // 字符串会像 Lua 一样自动转换成数字
+    fn to_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::Str(s) => std::str::from_utf8(s).ok().and_then(|s| parse_number(s.trim())),
+            _ => None,
+        }
+    }
+
+    fn to_bytes(&self) -> Option<Bytes> {
+        match self {
+            Value::Str(s) => Some(s.clone()),
+            Value::Number(n) => Some(Bytes::from(format_number(*n))),
+            _ => None,
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16).ok().map(|n| n as f64);
+    }
+    // 排除 Rust 能解析但 Lua 不认的 inf、nan 等写法
+    if text.is_empty() || !text.bytes().all(|c| c.is_ascii_digit() || matches!(c, b'.' | b'e' | b'E' | b'+' | b'-')) {
+        return None;
+    }
+    text.parse().ok()
+}
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else if n.is_nan() {
+        "nan".to_string()
+    } else if n.is_infinite() {
+        if n > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        format!("{}", n)
+    }
+}
+
+/// 命令的回复转换成脚本里的值
+fn from_frame(frame: Frame) -> Value {
+    match frame.into_resp2() {
+        Frame::Integer(n) => Value::Number(n as f64),
+        Frame::Bulk(s) => Value::Str(s),
+        Frame::Simple(s) => {
+            let mut table = Table::default();
+            table.set(Key::Str(Bytes::from_static(b"ok")), Value::Str(s.into()));
+            new_table(table)
+        }
+        Frame::Error(s) => {
+            let mut table = Table::default();
+            table.set(Key::Str(Bytes::from_static(b"err")), Value::Str(s.into()));
+            new_table(table)
+        }
+        Frame::Array(items) => new_table(Table {
+            array: items.into_iter().map(from_frame).collect(),
+            ..Table::default()
+        }),
+        _ => Value::Bool(false),
+    }
+}
+
+/// 脚本的返回值转换成回复：数字截断成整数，数组在第一个 nil 处截止，false 和 nil 是空回复。
+/// 表可以引用自己，所以要限制嵌套深度
+fn to_frame(value: &Value, depth: usize) -> Result<Frame, String> {
+    if depth > MAX_DEPTH {
+        return Err("reached lua stack limit".to_string());
+    }
+    Ok(match value {
+        Value::Number(n) => Frame::Integer(*n as i64),
+        Value::Str(s) => Frame::Bulk(s.clone()),
+        Value::Bool(true) => Frame::Integer(1),
+        Value::Table(table) => {
+            let table = table.borrow();
+            if let Value::Str(err) = table.field("err") {
+                return Ok(Frame::Error(String::from_utf8_lossy(&err).into_owned()));
+            }
+            if let Value::Str(ok) = table.field("ok") {
+                return Ok(Frame::Simple(String::from_utf8_lossy(&ok).into_owned()));
+            }
+            Frame::Array(
+                table
+                    .array
+                    .iter()
+                    .take_while(|value| !matches!(value, Value::Nil))
+                    .map(|value| to_frame(value, depth + 1))
+                    .collect::<Result<_, _>>()?,
+            )
+        }
+        _ => Frame::Null,
+    })
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Return(Value),
+}
+
+struct Interp<'a> {
+    host: &'a mut dyn Host,
+    globals: HashMap<&'static str, Value>,
+    scopes: Vec<Vec<(String, Value)>>,
+    line: usize,
+    steps: u64,
+}
+
+impl Script {
+    /// 执行脚本，KEYS 和 ARGV 从 1 开始编号
+    pub fn run(&self, host: &mut dyn Host, keys: &[Bytes], argv: &[Bytes]) -> Result<Frame, Error> {
+        let strings = |args: &[Bytes]| {
+            new_table(Table {
+                array: args.iter().cloned().map(Value::Str).collect(),
+                ..Table::default()
+            })
+        };
+        let mut globals = HashMap::new();
+        globals.insert("KEYS", strings(keys));
+        globals.insert("ARGV", strings(argv));
+        globals.insert(
+            "redis",
+            library(&[
+                ("call", Value::Builtin(Builtin::Call)),
+                ("pcall", Value::Builtin(Builtin::Pcall)),
+                ("error_reply", Value::Builtin(Builtin::ErrorReply)),
+                ("status_reply", Value::Builtin(Builtin::StatusReply)),
+                ("sha1hex", Value::Builtin(Builtin::Sha1Hex)),
+                ("log", Value::Builtin(Builtin::Log)),
+                ("LOG_DEBUG", Value::Number(0.0)),
+                ("LOG_VERBOSE", Value::Number(1.0)),
+                ("LOG_NOTICE", Value::Number(2.0)),
+                ("LOG_WARNING", Value::Number(3.0)),
+            ]),
+        );
+        globals.insert(
+            "table",
+            library(&[
+                ("insert", Value::Builtin(Builtin::TableInsert)),
+                ("remove", Value::Builtin(Builtin::TableRemove)),
+                ("concat", Value::Builtin(Builtin::TableConcat)),
+            ]),
+        );
+        globals.insert(
+            "string",
+            library(&[
+                ("len", Value::Builtin(Builtin::StringLen)),
+                ("sub", Value::Builtin(Builtin::StringSub)),
+                ("upper", Value::Builtin(Builtin::StringUpper)),
+                ("lower", Value::Builtin(Builtin::StringLower)),
+                ("rep", Value::Builtin(Builtin::StringRep)),
+            ]),
+        );
+        globals.insert(
+            "math",
+            library(&[
+                ("floor", Value::Builtin(Builtin::MathFloor)),
+                ("ceil", Value::Builtin(Builtin::MathCeil)),
+                ("abs", Value::Builtin(Builtin::MathAbs)),
+                ("max", Value::Builtin(Builtin::MathMax)),
+                ("min", Value::Builtin(Builtin::MathMin)),
+                ("huge", Value::Number(f64::INFINITY)),
+            ]),
+        );
+        for (name, builtin) in [
+            ("tonumber", Builtin::ToNumber),
+            ("tostring", Builtin::ToString),
+            ("type", Builtin::Type),
+            ("error", Builtin::Error),
+            ("ipairs", Builtin::Ipairs),
+            ("pairs", Builtin::Pairs),
+        ] {
+            globals.insert(name, Value::Builtin(builtin));
+        }
+
+        let mut interp = Interp { host, globals, scopes: Vec::new(), line: 1, steps: 0 };
+        match interp.block(&self.body)? {
+            Flow::Return(value) => to_frame(&value, 0).map_err(|message| interp.error(message)),
+            _ => Ok(Frame::Null),
+        }
+    }
+}
+
+impl Interp<'_> {
+    fn error(&self, message: impl Into<String>) -> Error {
+        Error::Runtime { line: self.line, message: message.into() }
+    }
+
+    fn step(&mut self) -> Result<(), Error> {
+        self.steps += 1;
+        if self.steps.is_multiple_of(CHECK_INTERVAL) && self.host.interrupted() {
+            return Err(Error::Killed);
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Result<Value, Error> {
+        for scope in self.scopes.iter().rev() {
+            if let Some((_, value)) = scope.iter().rev().find(|(n, _)| n == name) {
+                return Ok(value.clone());
+            }
+        }
+        match self.globals.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(self.error(format!("Script attempted to access nonexistent global variable '{}'", name))),
+        }
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        self.scopes.last_mut().unwrap().push((name.to_string(), value));
+    }
+
+    fn block(&mut self, block: &Block) -> Result<Flow, Error> {
+        self.scopes.push(Vec::new());
+        let result = self.statements(block);
+        self.scopes.pop();
+        result
+    }
+
+    fn statements(&mut self, block: &Block) -> Result<Flow, Error> {
+        for (stmt, line) in block {
+            self.line = *line;
+            self.step()?;
+            match self.statement(stmt)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    // 循环体的返回：Some 表示要跳出循环
+    fn loop_body(&mut self, body: &Block, vars: Vec<(String, Value)>) -> Result<Option<Flow>, Error> {
+        self.step()?;
+        self.scopes.push(vars);
+        let flow = self.statements(body);
+        self.scopes.pop();
+        match flow? {
+            Flow::Normal => Ok(None),
+            Flow::Break => Ok(Some(Flow::Normal)),
+            flow => Ok(Some(flow)),
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<Flow, Error> {
+        match stmt {
+            Stmt::Local(names, exprs) => {
+                let values = self.expr_list(exprs, names.len())?;
+                for (name, value) in names.iter().zip(values) {
+                    self.declare(name, value);
+                }
+            }
+            Stmt::Assign(targets, exprs) => {
+                // 先算出所有的值和下标，再依次赋值
+                let mut places = Vec::new();
+                for target in targets {
+                    places.push(match target {
+                        Expr::Index(table, key) => Some((self.expr(table)?, self.expr(key)?)),
+                        _ => None,
+                    });
+                }
+                let values = self.expr_list(exprs, targets.len())?;
+                for ((target, place), value) in targets.iter().zip(places).zip(values) {
+                    match (target, place) {
+                        (_, Some((table, key))) => self.set_index(&table, &key, value)?,
+                        (Expr::Name(name), None) => self.assign(name, value)?,
+                        _ => unreachable!("assignment target checked by the parser"),
+                    }
+                }
+            }
+            Stmt::Call(expr) => {
+                self.expr(expr)?;
+            }
+            Stmt::If(branches, otherwise) => {
+                for (condition, body) in branches {
+                    if self.expr(condition)?.truthy() {
+                        return self.block(body);
+                    }
+                }
+                if let Some(body) = otherwise {
+                    return self.block(body);
+                }
+            }
+            Stmt::While(condition, body) => {
+                while self.expr(condition)?.truthy() {
+                    if let Some(flow) = self.loop_body(body, Vec::new())? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Stmt::Repeat(body, condition) => loop {
+                // until 里可以访问循环体内声明的局部变量
+                self.step()?;
+                self.scopes.push(Vec::new());
+                let flow = match self.statements(body) {
+                    Ok(Flow::Normal) => self.expr(condition).map(|done| done.truthy().then_some(Flow::Normal)),
+                    Ok(Flow::Break) => Ok(Some(Flow::Normal)),
+                    Ok(flow) => Ok(Some(flow)),
+                    Err(err) => Err(err),
+                };
+                self.scopes.pop();
+                if let Some(flow) = flow? {
+                    return Ok(flow);
+                }
+            },
+            Stmt::NumericFor(name, start, limit, step, body) => {
+                let number = |interp: &mut Self, expr: &Expr, what: &str| {
+                    interp
+                        .expr(expr)?
+                        .to_number()
+                        .ok_or_else(|| interp.error(format!("'for' {} must be a number", what)))
+                };
+                let start = number(self, start, "initial value")?;
+                let limit = number(self, limit, "limit")?;
+                let step = match step {
+                    Some(step) => number(self, step, "step")?,
+                    None => 1.0,
+                };
+                if step == 0.0 {
+                    return Err(self.error("'for' step is zero"));
+                }
+                let mut i = start;
+                while (step > 0.0 && i <= limit) || (step < 0.0 && i >= limit) {
+                    if let Some(flow) = self.loop_body(body, vec![(name.clone(), Value::Number(i))])? {
+                        return Ok(flow);
+                    }
+                    i += step;
+                }
+            }
+            Stmt::GenericFor(names, iterator, body) => {
+                let (table, ordered) = match self.expr(iterator)? {
+                    Value::Iter(table, ordered) => (table, ordered),
+                    value => {
+                        return Err(self.error(format!("attempt to call a {} value", value.type_name())));
+                    }
+                };
+                let vars = |key: Value, value: Value| {
+                    names
+                        .iter()
+                        .zip([key, value].into_iter().chain(std::iter::repeat(Value::Nil)))
+                        .map(|(name, value)| (name.clone(), value))
+                        .collect::<Vec<_>>()
+                };
+                if ordered {
+                    // ipairs 遇到第一个 nil 停止，每轮重新读取，循环体里修改表也能看到
+                    let mut i = 1;
+                    loop {
+                        let value = table.borrow().get(&Key::Int(i));
+                        if matches!(value, Value::Nil) {
+                            break;
+                        }
+                        if let Some(flow) = self.loop_body(body, vars(Value::Number(i as f64), value))? {
+                            return Ok(flow);
+                        }
+                        i += 1;
+                    }
+                } else {
+                    let keys: Vec<Key> = {
+                        let table = table.borrow();
+                        (1..=table.array.len() as i64).map(Key::Int).chain(table.hash.keys().cloned()).collect()
+                    };
+                    for key in keys {
+                        let value = table.borrow().get(&key);
+                        if matches!(value, Value::Nil) {
+                            continue;
+                        }
+                        let key = match key {
+                            Key::Str(s) => Value::Str(s),
+                            Key::Int(i) => Value::Number(i as f64),
+                            Key::Float(bits) => Value::Number(f64::from_bits(bits)),
+                            Key::Bool(b) => Value::Bool(b),
+                        };
+                        if let Some(flow) = self.loop_body(body, vars(key, value))? {
+                            return Ok(flow);
+                        }
+                    }
+                }
+            }
+            Stmt::Do(body) => return self.block(body),
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(expr) => self.expr(expr)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::Break => return Ok(Flow::Break),
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn assign(&mut self, name: &str, value: Value) -> Result<(), Error> {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some((_, slot)) = scope.iter_mut().rev().find(|(n, _)| n == name) {
+                *slot = value;
+                return Ok(());
+            }
+        }
+        if self.globals.contains_key(name) {
+            return Err(self.error("Attempt to modify a readonly table"));
+        }
+        Err(self.error(format!("Script attempted to create global variable '{}'", name)))
+    }
+
+    // 值的个数不够时补 nil，多出来的丢掉（但仍然会求值）
+    fn expr_list(&mut self, exprs: &[Expr], count: usize) -> Result<Vec<Value>, Error> {
+        let mut values = Vec::with_capacity(exprs.len().max(count));
+        for expr in exprs {
+            values.push(self.expr(expr)?);
+        }
+        values.resize(count, Value::Nil);
+        Ok(values)
+    }
+
+    fn key(&self, key: &Value) -> Result<Key, Error> {
+        match key {
+            Value::Nil => Err(self.error("table index is nil")),
+            Value::Number(n) if n.is_nan() => Err(self.error("table index is NaN")),
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < 9e15 => Ok(Key::Int(*n as i64)),
+            Value::Number(n) => Ok(Key::Float(n.to_bits())),
+            Value::Str(s) => Ok(Key::Str(s.clone())),
+            Value::Bool(b) => Ok(Key::Bool(*b)),
+            value => Err(self.error(format!("{} values can't be used as table keys", value.type_name()))),
+        }
+    }
+
+    fn index(&self, table: &Value, key: &Value) -> Result<Value, Error> {
+        match table {
+            Value::Table(table) => Ok(table.borrow().get(&self.key(key)?)),
+            value => Err(self.error(format!("attempt to index a {} value", value.type_name()))),
+        }
+    }
+
+    fn set_index(&self, table: &Value, key: &Value, value: Value) -> Result<(), Error> {
+        match table {
+            Value::Table(table) => {
+                let key = self.key(key)?;
+                let mut table = table.borrow_mut();
+                if table.readonly {
+                    return Err(self.error("Attempt to modify a readonly table"));
+                }
+                table.set(key, value);
+                Ok(())
+            }
+            value => Err(self.error(format!("attempt to index a {} value", value.type_name()))),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<Value, Error> {
+        Ok(match expr {
+            Expr::Nil => Value::Nil,
+            Expr::Bool(b) => Value::Bool(*b),
+            Expr::Number(n) => Value::Number(*n),
+            Expr::Str(s) => Value::Str(s.clone()),
+            Expr::Name(name) => self.lookup(name)?,
+            Expr::Index(table, key) => {
+                let table = self.expr(table)?;
+                let key = self.expr(key)?;
+                self.index(&table, &key)?
+            }
+            Expr::Call(function, args) => {
+                let function = match self.expr(function)? {
+                    Value::Builtin(builtin) => builtin,
+                    value => return Err(self.error(format!("attempt to call a {} value", value.type_name()))),
+                };
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.expr(arg)?);
+                }
+                self.call(function, values)?
+            }
+            Expr::Table(fields) => {
+                let mut table = Table::default();
+                let mut next = 1;
+                for (key, value) in fields {
+                    let key = match key {
+                        Some(key) => {
+                            let key = self.expr(key)?;
+                            self.key(&key)?
+                        }
+                        None => {
+                            next += 1;
+                            Key::Int(next - 1)
+                        }
+                    };
+                    let value = self.expr(value)?;
+                    table.set(key, value);
+                }
+                new_table(table)
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.expr(operand)?;
+                match op {
+                    UnOp::Not => Value::Bool(!value.truthy()),
+                    UnOp::Neg => Value::Number(-self.arith_operand(&value)?),
+                    UnOp::Len => match &value {
+                        Value::Str(s) => Value::Number(s.len() as f64),
+                        Value::Table(table) => Value::Number(table.borrow().array.len() as f64),
+                        value => {
+                            return Err(self.error(format!("attempt to get length of a {} value", value.type_name())))
+                        }
+                    },
+                }
+            }
+            Expr::Binary(BinOp::And, left, right) => {
+                let left = self.expr(left)?;
+                if left.truthy() {
+                    self.expr(right)?
+                } else {
+                    left
+                }
+            }
+            Expr::Binary(BinOp::Or, left, right) => {
+                let left = self.expr(left)?;
+                if left.truthy() {
+                    left
+                } else {
+                    self.expr(right)?
+                }
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.expr(left)?;
+                let right = self.expr(right)?;
+                self.binary(*op, &left, &right)?
+            }
+        })
+    }
+
+    fn arith_operand(&self, value: &Value) -> Result<f64, Error> {
+        value
+            .to_number()
+            .ok_or_else(|| self.error(format!("attempt to perform arithmetic on a {} value", value.type_name())))
+    }
+
+    fn binary(&self, op: BinOp, left: &Value, right: &Value) -> Result<Value, Error> {
+        let arith = |f: fn(f64, f64) -> f64| -> Result<Value, Error> {
+            Ok(Value::Number(f(self.arith_operand(left)?, self.arith_operand(right)?)))
+        };
+        match op {
+            BinOp::Add => arith(|a, b| a + b),
+            BinOp::Sub => arith(|a, b| a - b),
+            BinOp::Mul => arith(|a, b| a * b),
+            BinOp::Div => arith(|a, b| a / b),
+            BinOp::Mod => arith(|a, b| a - (a / b).floor() * b),
+            BinOp::Pow => arith(f64::powf),
+            BinOp::Concat => {
+                let mut out = Vec::new();
+                for value in [left, right] {
+                    match value.to_bytes() {
+                        Some(s) => out.extend_from_slice(&s),
+                        None => {
+                            return Err(self.error(format!("attempt to concatenate a {} value", value.type_name())))
+                        }
+                    }
+                }
+                if out.len() > MAX_STRING {
+                    return Err(self.error("string length overflow"));
+                }
+                Ok(Value::Str(out.into()))
+            }
+            BinOp::Eq => Ok(Value::Bool(equals(left, right))),
+            BinOp::Ne => Ok(Value::Bool(!equals(left, right))),
+            BinOp::Lt => self.less(left, right, false).map(Value::Bool),
+            BinOp::Le => self.less(left, right, true).map(Value::Bool),
+            BinOp::Gt => self.less(right, left, false).map(Value::Bool),
+            BinOp::Ge => self.less(right, left, true).map(Value::Bool),
+            BinOp::And | BinOp::Or => unreachable!("short-circuit operators are evaluated in expr"),
+        }
+    }
+
+    fn less(&self, left: &Value, right: &Value, or_equal: bool) -> Result<bool, Error> {
+        let ordering = match (left, right) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+            (a, b) if a.type_name() == b.type_name() => {
+                return Err(self.error(format!("attempt to compare two {} values", a.type_name())))
+            }
+            (a, b) => {
+                return Err(self.error(format!("attempt to compare {} with {}", a.type_name(), b.type_name())))
+            }
+        };
+        Ok(match ordering {
+            Some(std::cmp::Ordering::Less) => true,
+            Some(std::cmp::Ordering::Equal) => or_equal,
+            _ => false,
+        })
+    }
+
+    fn call(&mut self, function: Builtin, args: Vec<Value>) -> Result<Value, Error> {
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(Value::Nil);
+        let string = |i: usize, name: &str| {
+            arg(i).to_bytes().ok_or_else(|| {
+                self.error(format!("bad argument #{} to '{}' (string expected, got {})", i + 1, name, arg(i).type_name()))
+            })
+        };
+        let number = |i: usize, name: &str| {
+            arg(i).to_number().ok_or_else(|| {
+                self.error(format!("bad argument #{} to '{}' (number expected, got {})", i + 1, name, arg(i).type_name()))
+            })
+        };
+        let table = |i: usize, name: &str| match arg(i) {
+            Value::Table(table) => Ok(table),
+            value => Err(self.error(format!(
+                "bad argument #{} to '{}' (table expected, got {})",
+                i + 1,
+                name,
+                value.type_name()
+            ))),
+        };
+
+        Ok(match function {
+            Builtin::Call | Builtin::Pcall => {
+                if args.is_empty() {
+                    return Err(self.error("Please specify at least one argument for this redis lib call"));
+                }
+                let mut command = Vec::with_capacity(args.len());
+                for value in &args {
+                    match value.to_bytes() {
+                        Some(arg) => command.push(arg),
+                        None => return Err(self.error("Lua redis lib command arguments must be strings or integers")),
+                    }
+                }
+                match self.host.call(command) {
+                    Frame::Error(message) if function == Builtin::Call => {
+                        return Err(Error::Reply { line: self.line, message })
+                    }
+                    frame => from_frame(frame),
+                }
+            }
+            Builtin::ErrorReply => from_frame(Frame::Error(String::from_utf8_lossy(&string(0, "error_reply")?).into_owned())),
+            Builtin::StatusReply => from_frame(Frame::Simple(String::from_utf8_lossy(&string(0, "status_reply")?).into_owned())),
+            Builtin::Sha1Hex => Value::Str(crate::script::sha1hex(&string(0, "sha1hex")?).into()),
+            Builtin::Log => {
+                let level = number(0, "log")?;
+                let message: Vec<String> = args[1..]
+                    .iter()
+                    .filter_map(Value::to_bytes)
+                    .map(|s| String::from_utf8_lossy(&s).into_owned())
+                    .collect();
+                // redis.LOG_DEBUG、LOG_VERBOSE、LOG_NOTICE、LOG_WARNING
+                let message = message.join(" ");
+                match level as i64 {
+                    i64::MIN..=0 => tracing::trace!(target: "script", "{}", message),
+                    1 => tracing::debug!(target: "script", "{}", message),
+                    2 => tracing::info!(target: "script", "{}", message),
+                    _ => tracing::warn!(target: "script", "{}", message),
+                }
+                Value::Nil
+            }
+            Builtin::ToNumber => match (arg(0), arg(1)) {
+                (value, Value::Nil) => value.to_number().map_or(Value::Nil, Value::Number),
+                (value, base) => {
+                    let base = self.arith_operand(&base)? as u32;
+                    if !(2..=36).contains(&base) {
+                        return Err(self.error("bad argument #2 to 'tonumber' (base out of range)"));
+                    }
+                    let text = value.to_bytes().unwrap_or_default();
+                    std::str::from_utf8(&text)
+                        .ok()
+                        .and_then(|text| i64::from_str_radix(text.trim(), base).ok())
+                        .map_or(Value::Nil, |n| Value::Number(n as f64))
+                }
+            },
+            Builtin::ToString => Value::Str(match arg(0) {
+                Value::Nil => Bytes::from_static(b"nil"),
+                Value::Bool(b) => Bytes::from(b.to_string()),
+                Value::Table(table) => Bytes::from(format!("table: {:p}", Rc::as_ptr(&table))),
+                Value::Builtin(builtin) => Bytes::from(format!("function: builtin: {:?}", builtin)),
+                Value::Iter(..) => Bytes::from_static(b"function: iterator"),
+                value => value.to_bytes().unwrap(),
+            }),
+            Builtin::Type => Value::Str(Bytes::from_static(arg(0).type_name().as_bytes())),
+            // error(redis.error_reply(...)) 直接把错误回复给客户端，其它值当成脚本错误
+            Builtin::Error => match arg(0) {
+                Value::Table(table) => match table.borrow().field("err") {
+                    Value::Str(message) => {
+                        return Err(Error::Reply { line: self.line, message: String::from_utf8_lossy(&message).into_owned() })
+                    }
+                    _ => return Err(self.error("error object is a table value")),
+                },
+                value => {
+                    let message = value.to_bytes().unwrap_or_else(|| Bytes::from(value.type_name()));
+                    return Err(self.error(String::from_utf8_lossy(&message)));
+                }
+            },
+            Builtin::Ipairs => Value::Iter(table(0, "ipairs")?, true),
+            Builtin::Pairs => Value::Iter(table(0, "pairs")?, false),
+            Builtin::TableInsert => {
+                let table = table(0, "insert")?;
+                if table.borrow().readonly {
+                    return Err(self.error("Attempt to modify a readonly table"));
+                }
+                let mut table = table.borrow_mut();
+                let len = table.array.len();
+                match args.len() {
+                    2 => table.array.push(arg(1)),
+                    3 => {
+                        let pos = number(1, "insert")? as usize;
+                        if pos < 1 || pos > len + 1 {
+                            return Err(self.error("bad argument #2 to 'insert' (position out of bounds)"));
+                        }
+                        table.array.insert(pos - 1, arg(2));
+                    }
+                    _ => return Err(self.error("wrong number of arguments to 'insert'")),
+                }
+                while matches!(table.array.last(), Some(Value::Nil)) {
+                    table.array.pop();
+                }
+                Value::Nil
+            }
+            Builtin::TableRemove => {
+                let table = table(0, "remove")?;
+                if table.borrow().readonly {
+                    return Err(self.error("Attempt to modify a readonly table"));
+                }
+                let mut table = table.borrow_mut();
+                let len = table.array.len();
+                let pos = if args.len() > 1 { number(1, "remove")? as usize } else { len };
+                if len == 0 || pos < 1 || pos > len {
+                    Value::Nil
+                } else {
+                    table.array.remove(pos - 1)
+                }
+            }
+            Builtin::TableConcat => {
+                let table = table(0, "concat")?;
+                let separator = match arg(1) {
+                    Value::Nil => Bytes::new(),
+                    _ => string(1, "concat")?,
+                };
+                let table = table.borrow();
+                let mut out = Vec::new();
+                for (i, value) in table.array.iter().enumerate() {
+                    if i > 0 {
+                        out.extend_from_slice(&separator);
+                    }
+                    match value.to_bytes() {
+                        Some(s) => out.extend_from_slice(&s),
+                        None => {
+                            return Err(self.error(format!(
+                                "invalid value (at index {}) in table for 'concat'",
+                                i + 1
+                            )))
+                        }
+                    }
+                    if out.len() > MAX_STRING {
+                        return Err(self.error("string length overflow"));
+                    }
+                }
+                Value::Str(out.into())
+            }
+            Builtin::StringLen => Value::Number(string(0, "len")?.len() as f64),
+            Builtin::StringSub => {
+                let s = string(0, "sub")?;
+                let len = s.len() as i64;
+                // 负数下标从末尾算起
+                let position = |n: f64| {
+                    let n = n as i64;
+                    if n < 0 {
+                        (len + n + 1).max(0)
+                    } else {
+                        n
+                    }
+                };
+                let start = position(number(1, "sub")?).max(1);
+                let end = match arg(2) {
+                    Value::Nil => len,
+                    _ => position(number(2, "sub")?).min(len),
+                };
+                if start > end {
+                    Value::Str(Bytes::new())
+                } else {
+                    Value::Str(s.slice(start as usize - 1..end as usize))
+                }
+            }
+            Builtin::StringUpper => Value::Str(string(0, "upper")?.to_ascii_uppercase().into()),
+            Builtin::StringLower => Value::Str(string(0, "lower")?.to_ascii_lowercase().into()),
+            Builtin::StringRep => {
+                let s = string(0, "rep")?;
+                let count = number(1, "rep")?.max(0.0) as usize;
+                if s.len().saturating_mul(count) > MAX_STRING {
+                    return Err(self.error("resulting string too large"));
+                }
+                Value::Str(s.repeat(count).into())
+            }
+            Builtin::MathFloor => Value::Number(number(0, "floor")?.floor()),
+            Builtin::MathCeil => Value::Number(number(0, "ceil")?.ceil()),
+            Builtin::MathAbs => Value::Number(number(0, "abs")?.abs()),
+            Builtin::MathMax | Builtin::MathMin => {
+                let name = if function == Builtin::MathMax { "max" } else { "min" };
+                let mut result = number(0, name)?;
+                for i in 1..args.len() {
+                    let n = number(i, name)?;
+                    if (function == Builtin::MathMax && n > result) || (function == Builtin::MathMin && n < result) {
+                        result = n;
+                    }
+                }
+                Value::Number(result)
+            }
+        })
+    }
+}
+
+fn equals(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Nil, Value::Nil) => true,
+        (Value::Bool(a), Value::Bool(b)) => a == b,
+        (Value::Number(a), Value::Number(b)) => a == b,
+        (Value::Str(a), Value::Str(b)) => a == b,
+        (Value::Table(a), Value::Table(b)) => Rc::ptr_eq(a, b),
+        (Value::Builtin(a), Value::Builtin(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoHost;
+
+    impl Host for NoHost {
+        fn call(&mut self, _args: Vec<Bytes>) -> Frame {
+            Frame::Null
+        }
+
+        fn interrupted(&mut self) -> bool {
+            false
+        }
+    }
+
+    fn eval(src: &str) -> Result<Frame, Error> {
+        let script = compile(src.as_bytes()).unwrap_or_else(|err| panic!("{}", err));
+        script.run(&mut NoHost, &[], &[])
+    }
+
+    #[test]
+    fn self_referencing_table_is_an_error() {
+        match eval("local t = {} t[1] = t return t") {
+            Err(Error::Runtime { message, .. }) => assert_eq!(message, "reached lua stack limit"),
+            _ => panic!("expected a runtime error"),
+        }
+    }
+
+    #[test]
+    fn nested_tables_within_limit() {
+        let frame = eval("local t = {1} for i = 1, 50 do t = {t} end return t").ok().unwrap();
+        let mut depth = 0;
+        let mut frame = &frame;
+        while let Frame::Array(items) = frame {
+            depth += 1;
+            frame = &items[0];
+        }
+        assert_eq!(depth, 51);
+        assert!(matches!(frame, Frame::Integer(1)));
+    }
+}
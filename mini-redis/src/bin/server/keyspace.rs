@@ -0,0 +1,456 @@
+use bytes::Bytes;
+use std::fmt::Write;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::command::{syntax_error, wrong_type, Context};
+use crate::db::{Collection, Thresholds, Value};
+use crate::notify;
+use crate::Shared;
+use mini_redis::Frame;
+
+// 释放时需要回收的内存块超过这个数的值，UNLINK 交给后台线程释放
+const LAZYFREE_THRESHOLD: usize = 64;
+
+pub fn parse_i64(arg: &[u8]) -> Result<i64, Frame> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| Frame::error("ERR value is not an integer or out of range"))
+}
+
+pub fn parse_f64(arg: &[u8]) -> Result<f64, Frame> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|n| n.parse::<f64>().ok())
+        .filter(|n| !n.is_nan())
+        .ok_or_else(|| Frame::error("ERR value is not a valid float"))
+}
+
+/// 把相对或绝对（unix 毫秒）时间换算成 Instant，已经过去的时间返回 None
+pub fn deadline(millis: i64, absolute: bool) -> Option<Instant> {
+    let now = Instant::now();
+    if !absolute {
+        return u64::try_from(millis).ok().filter(|ms| *ms > 0).map(|ms| now + Duration::from_millis(ms));
+    }
+    let unix_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+    let remaining = millis.checked_sub(unix_now)?;
+    (remaining > 0).then(|| now + Duration::from_millis(remaining as u64))
+}
+
+/// 剩余生存时间换算回 unix 毫秒时间戳
+pub fn unix_millis(when: Instant) -> i64 {
+    let unix_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+    unix_now + when.saturating_duration_since(Instant::now()).as_millis() as i64
+}
+
+/// 在 key 对应的集合上执行 f，key 不存在时返回 Ok(None)
+pub fn read<T: Collection, R>(ctx: &Context<'_>, key: &[u8], f: impl FnOnce(&T) -> R) -> Result<Option<R>, Frame> {
+    let mut db = ctx.shared.db.lock().unwrap();
+    match db.get(key) {
+        None => Ok(None),
+        Some(entry) => T::cast(&entry.value).map(|value| Some(f(value))).ok_or_else(wrong_type),
+    }
+}
+
+/// 集合类型写命令共用的流程：key 不存在时 create 为真就先放一个空的集合，否则返回 Ok(None)；
+/// f 返回结果和是否有修改，有修改时发出 event 通知。执行完变空的集合会删掉 key，和 Redis 一样不保留空集合
+pub fn update<T: Collection, R>(
+    ctx: &mut Context<'_>,
+    key: &Bytes,
+    create: bool,
+    class: u32,
+    event: &str,
+    f: impl FnOnce(&mut T, &Thresholds) -> (R, bool),
+) -> Result<Option<R>, Frame> {
+    let mut db = ctx.shared.db.lock().unwrap();
+    let thresholds = db.thresholds;
+    let created = match db.get(key) {
+        Some(entry) if T::cast(&entry.value).is_none() => return Err(wrong_type()),
+        Some(_) => false,
+        None if !create => return Ok(None),
+        None => {
+            db.set(key.clone(), T::default(), None);
+            true
+        }
+    };
+    let (result, changed, empty) = db
+        .modify(key, |value| {
+            let value = T::cast_mut(value).unwrap();
+            let (result, changed) = f(value, &thresholds);
+            (result, changed, value.is_empty())
+        })
+        .unwrap();
+    let removed = empty.then(|| db.remove(key));
+    drop(db);
+    drop(removed);
+
+    if changed {
+        ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+        if created && !empty {
+            notify::keyspace_event(ctx.shared, notify::NEW, "new", key);
+        }
+        notify::keyspace_event(ctx.shared, class, event, key);
+        if empty {
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "del", key);
+        }
+    }
+    Ok(Some(result))
+}
+
+pub fn del(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut removed = 0;
+    for key in &args[1..] {
+        let existed = ctx.shared.db.lock().unwrap().remove(key).is_some();
+        if existed {
+            ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "del", key);
+            removed += 1;
+        }
+    }
+    Frame::Integer(removed)
+}
+
+// UNLINK key [key ...]，和 DEL 一样立即删除 key，但大的值在锁外由后台线程释放
+pub fn unlink(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut removed = 0;
+    let mut garbage: Vec<Value> = Vec::new();
+    for key in &args[1..] {
+        let entry = ctx.shared.db.lock().unwrap().remove(key);
+        if let Some(entry) = entry {
+            ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "del", key);
+            removed += 1;
+            if entry.value.free_effort() > LAZYFREE_THRESHOLD {
+                garbage.push(entry.value);
+            }
+        }
+    }
+    if !garbage.is_empty() {
+        tokio::task::spawn_blocking(move || drop(garbage));
+    }
+    Frame::Integer(removed)
+}
+
+pub fn dbsize(ctx: &mut Context<'_>, _args: &[Bytes]) -> Frame {
+    Frame::Integer(ctx.shared.db.lock().unwrap().len() as i64)
+}
+
+pub fn exists(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut db = ctx.shared.db.lock().unwrap();
+    let count = args[1..].iter().filter(|key| db.contains(key)).count();
+    Frame::Integer(count as i64)
+}
+
+// TOUCH key [key ...]，更新访问时间，返回存在的 key 数
+pub fn touch(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut db = ctx.shared.db.lock().unwrap();
+    let count = args[1..].iter().filter(|key| db.get(key).is_some()).count();
+    Frame::Integer(count as i64)
+}
+
+pub fn key_type(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut db = ctx.shared.db.lock().unwrap();
+    let name = db.peek(&args[1]).map_or("none", |entry| entry.value.type_name());
+    Frame::Simple(name.to_string())
+}
+
+pub fn randomkey(ctx: &mut Context<'_>, _args: &[Bytes]) -> Frame {
+    let key = ctx.shared.db.lock().unwrap().random_key();
+    key.map_or(Frame::Null, Frame::Bulk)
+}
+
+pub fn rename(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    rename_generic(ctx, args, false)
+}
+
+pub fn renamenx(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    rename_generic(ctx, args, true)
+}
+
+// RENAME key newkey，过期时间跟着 key 一起移动；nx 时目标 key 存在就不改名
+fn rename_generic(ctx: &mut Context<'_>, args: &[Bytes], nx: bool) -> Frame {
+    let (from, to) = (&args[1], &args[2]);
+    let mut db = ctx.shared.db.lock().unwrap();
+    if !db.contains(from) {
+        return Frame::error("ERR no such key");
+    }
+    let done = |renamed: bool| if nx { Frame::Integer(renamed as i64) } else { Frame::ok() };
+    if from == to {
+        return done(false);
+    }
+    let exists = db.contains(to);
+    if nx && exists {
+        return done(false);
+    }
+    let replaced = db.rename(from, to.clone());
+    drop(db);
+    drop(replaced);
+
+    ctx.shared.signal_modified_key(from, Some(ctx.client.id));
+    ctx.shared.signal_modified_key(to, Some(ctx.client.id));
+    notify::keyspace_event(ctx.shared, notify::GENERIC, "rename_from", from);
+    if !exists {
+        notify::keyspace_event(ctx.shared, notify::NEW, "new", to);
+    }
+    notify::keyspace_event(ctx.shared, notify::GENERIC, "rename_to", to);
+    done(true)
+}
+
+// COPY source destination [DB destination-db] [REPLACE]
+pub fn copy(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (source, dest) = (&args[1], &args[2]);
+    let mut replace = false;
+    let mut i = 3;
+    while i < args.len() {
+        match &args[i].to_ascii_lowercase()[..] {
+            b"replace" => replace = true,
+            b"db" if i + 1 < args.len() => {
+                match parse_i64(&args[i + 1]) {
+                    Ok(0) => {}
+                    Ok(_) => return Frame::error("ERR Only database 0 is supported"),
+                    Err(err) => return err,
+                }
+                i += 1;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+    if source == dest {
+        return Frame::error("ERR source and destination objects are the same");
+    }
+
+    let mut db = ctx.shared.db.lock().unwrap();
+    let (value, expires_at) = match db.get(source) {
+        Some(entry) => (entry.value.clone(), entry.expires_at),
+        None => return Frame::Integer(0),
+    };
+    let exists = db.contains(dest);
+    if exists && !replace {
+        return Frame::Integer(0);
+    }
+    let replaced = db.set(dest.clone(), value, expires_at);
+    drop(db);
+    drop(replaced);
+
+    ctx.shared.signal_modified_key(dest, Some(ctx.client.id));
+    if !exists {
+        notify::keyspace_event(ctx.shared, notify::NEW, "new", dest);
+    }
+    notify::keyspace_event(ctx.shared, notify::GENERIC, "copy_to", dest);
+    Frame::Integer(1)
+}
+
+// OBJECT ENCODING | FREQ | IDLETIME | REFCOUNT key，或者 OBJECT HELP
+pub fn object(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    if sub == b"help" && args.len() == 2 {
+        let help = [
+            "OBJECT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "ENCODING <key>",
+            "    Return the kind of internal representation used in order to store the value",
+            "    associated with a <key>.",
+            "FREQ <key>",
+            "    Return the access frequency index of the <key>. The returned integer is",
+            "    proportional to the logarithm of the recent access frequency of the key.",
+            "IDLETIME <key>",
+            "    Return the idle time of the <key>, that is the approximated number of",
+            "    seconds elapsed since the last access to the key.",
+            "REFCOUNT <key>",
+            "    Return the number of references of the value associated with the specified",
+            "    <key>.",
+            "HELP",
+            "    Print this help.",
+        ];
+        return Frame::Array(help.iter().map(|line| Frame::Simple(line.to_string())).collect());
+    }
+    if args.len() != 3 || !matches!(&sub[..], b"encoding" | b"freq" | b"idletime" | b"refcount") {
+        return Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try OBJECT HELP.",
+            String::from_utf8_lossy(&args[1])
+        ));
+    }
+
+    let mut db = ctx.shared.db.lock().unwrap();
+    let lfu = db.policy.is_lfu();
+    let entry = match db.peek(&args[2]) {
+        Some(entry) => entry,
+        None => return Frame::Null,
+    };
+    match &sub[..] {
+        b"encoding" => Frame::bulk(entry.value.encoding()),
+        b"refcount" => Frame::Integer(1),
+        // 和 Redis 一样，LRU 和 LFU 的信息只在对应的淘汰策略下提供
+        b"idletime" if lfu => Frame::error(
+            "ERR An LFU maxmemory policy is selected, idle time not tracked. \
+             Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+        ),
+        b"idletime" => Frame::Integer(entry.accessed.elapsed().as_secs() as i64),
+        _ if !lfu => Frame::error(
+            "ERR An LFU maxmemory policy is not selected, access frequency not tracked. \
+             Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.",
+        ),
+        _ => Frame::Integer(entry.frequency() as i64),
+    }
+}
+
+// MEMORY USAGE key [SAMPLES count]，或者 MEMORY HELP
+pub fn memory(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    if sub == b"help" && args.len() == 2 {
+        let help = [
+            "MEMORY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+            "USAGE <key> [SAMPLES <count>]",
+            "    Return memory in bytes used by <key> and its value. Nested values are",
+            "    always fully counted, SAMPLES is accepted for compatibility.",
+            "HELP",
+            "    Print this help.",
+        ];
+        return Frame::Array(help.iter().map(|line| Frame::Simple(line.to_string())).collect());
+    }
+    if sub != b"usage" || !(args.len() == 3 || args.len() == 5) {
+        return Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'. Try MEMORY HELP.",
+            String::from_utf8_lossy(&args[1])
+        ));
+    }
+    // 大小是增删成员时维护的，不需要抽样
+    if args.len() == 5 && (!args[3].eq_ignore_ascii_case(b"samples") || parse_i64(&args[4]).is_err()) {
+        return syntax_error();
+    }
+    match ctx.shared.db.lock().unwrap().usage(&args[2]) {
+        Some(bytes) => Frame::Integer(bytes as i64),
+        None => Frame::Null,
+    }
+}
+
+/// INFO 的 Memory 部分
+pub fn memory_info(shared: &Shared) -> String {
+    let db = shared.db.lock().unwrap();
+    let mut out = String::new();
+    let _ = writeln!(out, "used_memory:{}\r", db.used_memory());
+    let _ = writeln!(out, "used_memory_rss:{}\r", rss());
+    let _ = writeln!(out, "maxmemory:{}\r", db.maxmemory);
+    let _ = writeln!(out, "maxmemory_policy:{}\r", db.policy.name());
+    out
+}
+
+// 进程实际占用的物理内存，只有 Linux 上能读到，其它平台返回 0
+fn rss() -> usize {
+    #[cfg(target_os = "linux")]
+    if let Some(pages) = std::fs::read_to_string("/proc/self/statm")
+        .ok()
+        .and_then(|statm| statm.split_whitespace().nth(1)?.parse::<usize>().ok())
+    {
+        return pages * unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+    }
+    0
+}
+
+pub fn expire(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    expire_generic(ctx, args, 1000, false)
+}
+
+pub fn pexpire(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    expire_generic(ctx, args, 1, false)
+}
+
+pub fn expireat(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    expire_generic(ctx, args, 1000, true)
+}
+
+pub fn pexpireat(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    expire_generic(ctx, args, 1, true)
+}
+
+// EXPIRE key seconds [NX | XX | GT | LT]，unit 是时间参数换算成毫秒的倍数
+fn expire_generic(ctx: &mut Context<'_>, args: &[Bytes], unit: i64, absolute: bool) -> Frame {
+    let key = &args[1];
+    let millis = match parse_i64(&args[2]).map(|n| n.checked_mul(unit)) {
+        Ok(Some(millis)) => millis,
+        Ok(None) => return Frame::error("ERR invalid expire time in 'expire' command"),
+        Err(err) => return err,
+    };
+    let condition = match args.get(3).map(|arg| arg.to_ascii_lowercase()) {
+        None => None,
+        Some(arg) if args.len() == 4 && matches!(&arg[..], b"nx" | b"xx" | b"gt" | b"lt") => Some(arg),
+        Some(_) => return syntax_error(),
+    };
+    let when = deadline(millis, absolute);
+
+    let mut db = ctx.shared.db.lock().unwrap();
+    let current = match db.get(key) {
+        Some(entry) => entry.expires_at,
+        None => return Frame::Integer(0),
+    };
+    // 没有过期时间视为无穷大
+    let allowed = match condition.as_deref() {
+        None => true,
+        Some(b"nx") => current.is_none(),
+        Some(b"xx") => current.is_some(),
+        Some(b"gt") => current.is_some_and(|current| when.is_some_and(|when| when > current)),
+        _ => current.is_none_or(|current| when.is_none_or(|when| when < current)),
+    };
+    if !allowed {
+        return Frame::Integer(0);
+    }
+    match when {
+        Some(when) => {
+            db.set_expiry(key, Some(when));
+            drop(db);
+            ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "expire", key);
+        }
+        // 过期时间已经过去，直接删除
+        None => {
+            db.remove(key);
+            drop(db);
+            ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "del", key);
+        }
+    }
+    Frame::Integer(1)
+}
+
+pub fn ttl(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    ttl_generic(ctx, &args[1], |left| left.as_millis().div_ceil(1000) as i64)
+}
+
+pub fn pttl(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    ttl_generic(ctx, &args[1], |left| left.as_millis() as i64)
+}
+
+pub fn expiretime(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    ttl_generic(ctx, &args[1], |left| unix_millis(Instant::now() + left) / 1000)
+}
+
+pub fn pexpiretime(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    ttl_generic(ctx, &args[1], |left| unix_millis(Instant::now() + left))
+}
+
+// key 不存在返回 -2，没有过期时间返回 -1
+fn ttl_generic(ctx: &mut Context<'_>, key: &[u8], convert: impl Fn(Duration) -> i64) -> Frame {
+    let mut db = ctx.shared.db.lock().unwrap();
+    match db.peek(key) {
+        None => Frame::Integer(-2),
+        Some(entry) => match entry.expires_at {
+            None => Frame::Integer(-1),
+            Some(when) => Frame::Integer(convert(when.saturating_duration_since(Instant::now()))),
+        },
+    }
+}
+
+pub fn persist(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let key = &args[1];
+    let mut db = ctx.shared.db.lock().unwrap();
+    match db.get(key) {
+        Some(entry) if entry.expires_at.is_some() => {
+            db.set_expiry(key, None);
+            drop(db);
+            ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+            notify::keyspace_event(ctx.shared, notify::GENERIC, "persist", key);
+            Frame::Integer(1)
+        }
+        _ => Frame::Integer(0),
+    }
+}
@@ -0,0 +1,529 @@
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+use crate::command::{syntax_error, Context};
+use crate::db::{Collection, Thresholds, Value};
+use crate::keyspace::{self, parse_i64};
+use crate::listpack::{Listpack, ENTRY_HEADER};
+use crate::notify;
+use mini_redis::Frame;
+
+// 估算内存时 quicklist 每个节点额外计入的固定开销
+const NODE_OVERHEAD: usize = 32;
+
+/// 一个 listpack 放 count 项、占 bytes 字节是否在 list-max-listpack-size 之内：
+/// 正数是最多放多少项，-1 到 -5 是最多 4KB、8KB、16KB、32KB、64KB。
+/// 整个列表放得进一个 listpack 时就用 listpack 编码，否则转成由多个 listpack 节点组成的 quicklist 编码
+fn fits(limit: i64, count: usize, bytes: usize) -> bool {
+    match limit {
+        1.. => count <= limit as usize,
+        _ => bytes <= 4096 << (limit.clamp(-5, -1).unsigned_abs() - 1),
+    }
+}
+
+/// 列表：小的时候整个放在一个 listpack 里，变大之后转成 quicklist 编码，之后不再转回去
+#[derive(Debug, Clone)]
+pub struct List {
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone)]
+enum Encoding {
+    Listpack(Listpack),
+    Quicklist(Quicklist),
+}
+
+impl Default for List {
+    fn default() -> List {
+        List { encoding: Encoding::Listpack(Listpack::default()) }
+    }
+}
+
+impl List {
+    pub fn new() -> List {
+        List::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.len(),
+            Encoding::Quicklist(quicklist) => quicklist.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 估算占用的内存，增删元素时维护
+    pub fn size(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.bytes(),
+            Encoding::Quicklist(quicklist) => quicklist.size,
+        }
+    }
+
+    /// OBJECT ENCODING 返回的内部编码
+    pub fn encoding(&self) -> &'static str {
+        match &self.encoding {
+            Encoding::Listpack(_) => "listpack",
+            Encoding::Quicklist(_) => "quicklist",
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn DoubleEndedIterator<Item = Bytes> + '_> {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => Box::new(listpack.iter().map(Bytes::copy_from_slice)),
+            Encoding::Quicklist(quicklist) => {
+                Box::new(quicklist.nodes.iter().flat_map(|node| node.iter()).map(Bytes::copy_from_slice))
+            }
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<Bytes> {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.get(index).map(Bytes::copy_from_slice),
+            Encoding::Quicklist(quicklist) => {
+                let (node, at) = quicklist.locate(index)?;
+                quicklist.nodes[node].get(at).map(Bytes::copy_from_slice)
+            }
+        }
+    }
+
+    pub fn push(&mut self, value: &[u8], front: bool, thresholds: &Thresholds) {
+        let limit = thresholds.list_max_listpack_size;
+        self.reserve(value, limit);
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) if front => listpack.push_front(value),
+            Encoding::Listpack(listpack) => listpack.push_back(value),
+            Encoding::Quicklist(quicklist) => quicklist.push(value, front, limit),
+        }
+    }
+
+    pub fn pop(&mut self, front: bool) -> Option<Bytes> {
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) if front => listpack.pop_front(),
+            Encoding::Listpack(listpack) => listpack.pop_back(),
+            Encoding::Quicklist(quicklist) => quicklist.pop(front),
+        }
+    }
+
+    /// 在下标 index 处插入，index 等于长度时放在末尾
+    pub fn insert(&mut self, index: usize, value: &[u8], thresholds: &Thresholds) {
+        let limit = thresholds.list_max_listpack_size;
+        self.reserve(value, limit);
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) => listpack.splice(index..index, &[value]),
+            Encoding::Quicklist(quicklist) => quicklist.replace(index, 0, value, limit),
+        }
+    }
+
+    /// 替换下标 index 处的元素
+    pub fn set(&mut self, index: usize, value: &[u8], thresholds: &Thresholds) {
+        let limit = thresholds.list_max_listpack_size;
+        self.reserve(value, limit);
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) => listpack.splice(index..index + 1, &[value]),
+            Encoding::Quicklist(quicklist) => quicklist.replace(index, 1, value, limit),
+        }
+    }
+
+    /// 只保留 keep 返回真的元素，keep 按从头到尾的顺序调用，编码保持不变
+    pub fn retain(&mut self, mut keep: impl FnMut(&[u8]) -> bool, thresholds: &Thresholds) {
+        let items: Vec<_> = self.iter().filter(|item| keep(item)).collect();
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) => *listpack = Listpack::default(),
+            Encoding::Quicklist(quicklist) => *quicklist = Quicklist::default(),
+        }
+        for item in items {
+            self.push(&item, false, thresholds);
+        }
+    }
+
+    /// 再放入 value 之后整个列表放不进一个 listpack 时先转成 quicklist
+    fn reserve(&mut self, value: &[u8], limit: i64) {
+        let Encoding::Listpack(listpack) = &self.encoding else { return };
+        if fits(limit, listpack.len() + 1, listpack.bytes() + value.len() + ENTRY_HEADER) {
+            return;
+        }
+        let mut quicklist = Quicklist::default();
+        for item in listpack.iter() {
+            quicklist.push(item, false, limit);
+        }
+        self.encoding = Encoding::Quicklist(quicklist);
+    }
+}
+
+/// listpack 节点组成的双端队列，两头的 push 和 pop 只修改端点上的节点
+#[derive(Debug, Clone, Default)]
+struct Quicklist {
+    nodes: VecDeque<Listpack>,
+    len: usize,
+    size: usize,
+}
+
+impl Quicklist {
+    fn push(&mut self, value: &[u8], front: bool, limit: i64) {
+        let node = if front { self.nodes.front() } else { self.nodes.back() };
+        if node.is_none_or(|node| !fits(limit, node.len() + 1, node.bytes() + value.len() + ENTRY_HEADER)) {
+            self.size += NODE_OVERHEAD;
+            if front {
+                self.nodes.push_front(Listpack::default());
+            } else {
+                self.nodes.push_back(Listpack::default());
+            }
+        }
+        let node = if front { self.nodes.front_mut() } else { self.nodes.back_mut() }.unwrap();
+        if front {
+            node.push_front(value);
+        } else {
+            node.push_back(value);
+        }
+        self.size += value.len() + ENTRY_HEADER;
+        self.len += 1;
+    }
+
+    fn pop(&mut self, front: bool) -> Option<Bytes> {
+        let node = if front { self.nodes.front_mut() } else { self.nodes.back_mut() }?;
+        let value = if front { node.pop_front() } else { node.pop_back() }?;
+        if node.is_empty() {
+            if front {
+                self.nodes.pop_front();
+            } else {
+                self.nodes.pop_back();
+            }
+            self.size -= NODE_OVERHEAD;
+        }
+        self.size -= value.len() + ENTRY_HEADER;
+        self.len -= 1;
+        Some(value)
+    }
+
+    /// 下标 index 所在的节点和在节点里的下标
+    fn locate(&self, mut index: usize) -> Option<(usize, usize)> {
+        for (i, node) in self.nodes.iter().enumerate() {
+            if index < node.len() {
+                return Some((i, index));
+            }
+            index -= node.len();
+        }
+        None
+    }
+
+    /// 用 value 替换 index 开始的 count 个元素，count 是 0 时就是插入。
+    /// 节点满了就拆成两半，这样每个节点都还在大小限制之内
+    fn replace(&mut self, index: usize, count: usize, value: &[u8], limit: i64) {
+        if count == 0 && index == self.len {
+            return self.push(value, false, limit);
+        }
+        let (i, at) = self.locate(index).unwrap();
+        let node = &mut self.nodes[i];
+        let before = node.bytes();
+        node.splice(at..at + count, &[value]);
+        self.size = self.size - before + node.bytes();
+        self.len = self.len + 1 - count;
+        if node.len() > 1 && !fits(limit, node.len(), node.bytes()) {
+            let half = node.len() / 2;
+            let mut tail = Listpack::default();
+            tail.splice(0..0, &node.iter().skip(half).collect::<Vec<_>>());
+            node.splice(half..node.len(), &[]);
+            self.nodes.insert(i + 1, tail);
+            self.size += NODE_OVERHEAD;
+        }
+    }
+}
+
+impl Collection for List {
+    fn cast(value: &Value) -> Option<&List> {
+        match value {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn cast_mut(value: &mut Value) -> Option<&mut List> {
+        match value {
+            Value::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        List::is_empty(self)
+    }
+}
+
+impl From<List> for Value {
+    fn from(list: List) -> Value {
+        Value::List(list)
+    }
+}
+
+/// 把可能是负数的下标换成从头数的下标，超出范围时返回 None
+fn normalize(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    (0..len as i64).contains(&index).then_some(index as usize)
+}
+
+/// LRANGE、LTRIM 和 ZRANGE 的范围，两端都包含，超出的部分截掉，空范围返回 None
+pub fn clamp(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { (start + len).max(0) } else { start };
+    let stop = if stop < 0 { stop + len } else { stop.min(len - 1) };
+    (start <= stop && start < len).then_some((start as usize, stop as usize))
+}
+
+// LPUSH、RPUSH、LPUSHX 和 RPUSHX key element [element ...]
+pub fn push(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let name = args[0].to_ascii_lowercase();
+    let front = name[0] == b'l';
+    let event = if front { "lpush" } else { "rpush" };
+    let result = keyspace::update(ctx, &args[1], !name.ends_with(b"x"), notify::LIST, event, |list: &mut List, thresholds| {
+        for value in &args[2..] {
+            list.push(value, front, thresholds);
+        }
+        (list.len(), true)
+    });
+    match result {
+        Ok(len) => Frame::Integer(len.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+// LPOP 和 RPOP key [count]
+pub fn pop(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    if args.len() > 3 {
+        return syntax_error();
+    }
+    let count = match args.get(2).map(|count| parse_i64(count)) {
+        Some(Ok(count)) if count < 0 => return Frame::error("ERR value is out of range, must be positive"),
+        Some(Ok(count)) => Some(count as usize),
+        Some(Err(err)) => return err,
+        None => None,
+    };
+    let front = args[0][0].eq_ignore_ascii_case(&b'l');
+    let event = if front { "lpop" } else { "rpop" };
+    let result = keyspace::update(ctx, &args[1], false, notify::LIST, event, |list: &mut List, _| {
+        let values: Vec<_> = (0..count.unwrap_or(1)).map_while(|_| list.pop(front)).collect();
+        let popped = !values.is_empty();
+        (values, popped)
+    });
+    match (result, count) {
+        (Err(err), _) => err,
+        (Ok(None), _) => Frame::Null,
+        (Ok(Some(values)), Some(_)) => Frame::Array(values.into_iter().map(Frame::Bulk).collect()),
+        (Ok(Some(values)), None) => values.into_iter().next().map_or(Frame::Null, Frame::Bulk),
+    }
+}
+
+pub fn llen(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |list: &List| list.len()) {
+        Ok(len) => Frame::Integer(len.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+// LINDEX key index
+pub fn lindex(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let index = match parse_i64(&args[2]) {
+        Ok(index) => index,
+        Err(err) => return err,
+    };
+    match keyspace::read(ctx, &args[1], |list: &List| normalize(index, list.len()).and_then(|i| list.get(i))) {
+        Ok(value) => value.flatten().map_or(Frame::Null, Frame::Bulk),
+        Err(err) => err,
+    }
+}
+
+// LRANGE key start stop
+pub fn lrange(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (start, stop) = match (parse_i64(&args[2]), parse_i64(&args[3])) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    let values = keyspace::read(ctx, &args[1], |list: &List| match clamp(start, stop, list.len()) {
+        Some((start, stop)) => list.iter().skip(start).take(stop - start + 1).map(Frame::Bulk).collect(),
+        None => Vec::new(),
+    });
+    match values {
+        Ok(values) => Frame::Array(values.unwrap_or_default()),
+        Err(err) => err,
+    }
+}
+
+// LSET key index element
+pub fn lset(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let index = match parse_i64(&args[2]) {
+        Ok(index) => index,
+        Err(err) => return err,
+    };
+    let result = keyspace::update(ctx, &args[1], false, notify::LIST, "lset", |list: &mut List, thresholds| match normalize(index, list.len()) {
+        Some(i) => {
+            list.set(i, &args[3], thresholds);
+            (true, true)
+        }
+        None => (false, false),
+    });
+    match result {
+        Ok(Some(true)) => Frame::ok(),
+        Ok(Some(false)) => Frame::error("ERR index out of range"),
+        Ok(None) => Frame::error("ERR no such key"),
+        Err(err) => err,
+    }
+}
+
+// LTRIM key start stop
+pub fn ltrim(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (start, stop) = match (parse_i64(&args[2]), parse_i64(&args[3])) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    let result = keyspace::update(ctx, &args[1], false, notify::LIST, "ltrim", |list: &mut List, thresholds| {
+        let range = clamp(start, stop, list.len());
+        let mut i = 0;
+        list.retain(
+            |_| {
+                i += 1;
+                range.is_some_and(|(start, stop)| (start..=stop).contains(&(i - 1)))
+            },
+            thresholds,
+        );
+        ((), true)
+    });
+    match result {
+        Ok(_) => Frame::ok(),
+        Err(err) => err,
+    }
+}
+
+// LREM key count element：count 大于 0 时从头删，小于 0 时从尾删，0 时全部删除
+pub fn lrem(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let count = match parse_i64(&args[2]) {
+        Ok(count) => count,
+        Err(err) => return err,
+    };
+    let element = &args[3];
+    let result = keyspace::update(ctx, &args[1], false, notify::LIST, "lrem", |list: &mut List, thresholds| {
+        let matches = list.iter().filter(|item| item == element).count();
+        let remove = if count == 0 { matches } else { matches.min(count.unsigned_abs() as usize) };
+        // 从尾删时跳过前面不删的那些
+        let mut skip = if count < 0 { matches - remove } else { 0 };
+        let mut left = remove;
+        list.retain(
+            |item| {
+                if item != &element[..] || left == 0 {
+                    return true;
+                }
+                if skip > 0 {
+                    skip -= 1;
+                    return true;
+                }
+                left -= 1;
+                false
+            },
+            thresholds,
+        );
+        (remove, remove > 0)
+    });
+    match result {
+        Ok(removed) => Frame::Integer(removed.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+// LINSERT key BEFORE | AFTER pivot element
+pub fn linsert(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let after = match &args[2].to_ascii_lowercase()[..] {
+        b"before" => false,
+        b"after" => true,
+        _ => return syntax_error(),
+    };
+    let result = keyspace::update(ctx, &args[1], false, notify::LIST, "linsert", |list: &mut List, thresholds| {
+        let pivot = list.iter().position(|item| item == args[3]);
+        match pivot {
+            Some(i) => {
+                list.insert(i + after as usize, &args[4], thresholds);
+                (list.len() as i64, true)
+            }
+            None => (-1, false),
+        }
+    });
+    match result {
+        Ok(len) => Frame::Integer(len.unwrap_or(0)),
+        Err(err) => err,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(list: &List) -> Vec<String> {
+        list.iter().map(|item| String::from_utf8(item.to_vec()).unwrap()).collect()
+    }
+
+    #[test]
+    fn converts_to_quicklist() {
+        let thresholds = Thresholds::default();
+        let mut list = List::new();
+        // 默认每个 listpack 最多 8KB
+        let value = vec![b'x'; 1015];
+        for _ in 0..8 {
+            list.push(&value, false, &thresholds);
+        }
+        assert_eq!(list.encoding(), "listpack");
+        list.push(b"head", true, &thresholds);
+        assert_eq!(list.encoding(), "quicklist");
+        list.push(b"tail", false, &thresholds);
+        assert_eq!(list.len(), 10);
+        assert_eq!(list.get(0).as_deref(), Some(&b"head"[..]));
+        assert_eq!(list.get(9).as_deref(), Some(&b"tail"[..]));
+
+        // 弹到只剩几个也不转回去
+        for _ in 0..8 {
+            list.pop(false);
+        }
+        assert_eq!(list.encoding(), "quicklist");
+        assert_eq!(items(&list), ["head", &"x".repeat(1015)]);
+        assert_eq!(list.size(), 4 + 1015 + 2 * ENTRY_HEADER + 2 * NODE_OVERHEAD);
+    }
+
+    #[test]
+    fn quicklist_nodes_stay_within_limit() {
+        let thresholds = Thresholds::default();
+        let mut list = List::new();
+        for i in 0..2000 {
+            list.push(format!("{:04}", i).as_bytes(), false, &thresholds);
+        }
+        assert_eq!(list.encoding(), "quicklist");
+        // 在中间插入和替换，塞满的节点会拆开
+        for i in 0..500 {
+            list.insert(1000, format!("i{}", i).as_bytes(), &thresholds);
+        }
+        list.set(0, b"first", &thresholds);
+        let Encoding::Quicklist(quicklist) = &list.encoding else { unreachable!() };
+        assert!(quicklist.nodes.iter().all(|node| fits(thresholds.list_max_listpack_size, node.len(), node.bytes())));
+        assert_eq!(quicklist.size, quicklist.nodes.iter().map(|node| node.bytes() + NODE_OVERHEAD).sum::<usize>());
+        assert_eq!(list.len(), 2500);
+        assert_eq!(list.get(0).as_deref(), Some(&b"first"[..]));
+        assert_eq!(list.get(1000).as_deref(), Some(&b"i499"[..]));
+        assert_eq!(list.get(1500).as_deref(), Some(&b"1000"[..]));
+        assert_eq!(list.iter().next_back().as_deref(), Some(&b"1999"[..]));
+
+        list.retain(|item| item.starts_with(b"i"), &thresholds);
+        assert_eq!(list.len(), 500);
+        assert_eq!(list.encoding(), "quicklist");
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(clamp(0, -1, 5), Some((0, 4)));
+        assert_eq!(clamp(-100, 100, 5), Some((0, 4)));
+        assert_eq!(clamp(3, 1, 5), None);
+        assert_eq!(clamp(5, 10, 5), None);
+        assert_eq!(clamp(0, -6, 5), None);
+        assert_eq!(normalize(-1, 5), Some(4));
+        assert_eq!(normalize(5, 5), None);
+        assert_eq!(normalize(-6, 5), None);
+    }
+}
@@ -0,0 +1,172 @@
+use bytes::Bytes;
+use std::ops::Range;
+
+// 每项除了内容本身，还有前后两个长度
+pub const ENTRY_HEADER: usize = 4 + 4;
+
+/// 小的哈希、集合和列表共用的紧凑编码：字符串按顺序连续放在一块内存里，每项是
+/// [长度 u32][内容][长度 u32]，末尾的长度用来从后往前遍历。
+/// 按下标或者按内容查找都要顺序扫描，中间的修改要移动后面的字节，所以只用于小集合
+#[derive(Debug, Clone, Default)]
+pub struct Listpack {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl Listpack {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 占用的字节数
+    pub fn bytes(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter { buf: &self.buf, front: 0, back: self.buf.len(), len: self.len }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        (index < self.len).then(|| {
+            let at = self.offset(index);
+            &self.buf[at + 4..at + 4 + read_u32(&self.buf, at)]
+        })
+    }
+
+    /// 第一个内容等于 value 的项的下标
+    pub fn find(&self, value: &[u8]) -> Option<usize> {
+        self.iter().position(|entry| entry == value)
+    }
+
+    pub fn push_back(&mut self, value: &[u8]) {
+        self.splice(self.len..self.len, &[value]);
+    }
+
+    pub fn push_front(&mut self, value: &[u8]) {
+        self.splice(0..0, &[value]);
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        let value = Bytes::copy_from_slice(self.iter().next_back()?);
+        self.splice(self.len - 1..self.len, &[]);
+        Some(value)
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let value = Bytes::copy_from_slice(self.iter().next()?);
+        self.splice(0..1, &[]);
+        Some(value)
+    }
+
+    /// 用 values 替换下标在 range 里的项
+    pub fn splice(&mut self, range: Range<usize>, values: &[&[u8]]) {
+        let start = self.offset(range.start);
+        let end = self.offset(range.end);
+        let mut encoded = Vec::with_capacity(values.iter().map(|value| value.len() + ENTRY_HEADER).sum());
+        for value in values {
+            encoded.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            encoded.extend_from_slice(value);
+            encoded.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        }
+        self.buf.splice(start..end, encoded);
+        self.len = self.len - range.len() + values.len();
+    }
+
+    /// 第 index 项开始的字节位置，index 等于长度时是末尾。从离得近的一端开始数
+    fn offset(&self, index: usize) -> usize {
+        if index <= self.len / 2 {
+            (0..index).fold(0, |at, _| at + read_u32(&self.buf, at) + ENTRY_HEADER)
+        } else {
+            (index..self.len).fold(self.buf.len(), |at, _| at - read_u32(&self.buf, at - 4) - ENTRY_HEADER)
+        }
+    }
+}
+
+fn read_u32(buf: &[u8], at: usize) -> usize {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap()) as usize
+}
+
+pub struct Iter<'a> {
+    buf: &'a [u8],
+    front: usize,
+    back: usize,
+    len: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.front >= self.back {
+            return None;
+        }
+        let len = read_u32(self.buf, self.front);
+        let value = &self.buf[self.front + 4..self.front + 4 + len];
+        self.front += len + ENTRY_HEADER;
+        self.len -= 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<&'a [u8]> {
+        if self.front >= self.back {
+            return None;
+        }
+        let len = read_u32(self.buf, self.back - 4);
+        self.back -= len + ENTRY_HEADER;
+        self.len -= 1;
+        Some(&self.buf[self.back + 4..self.back + 4 + len])
+    }
+}
+
+impl ExactSizeIterator for Iter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(listpack: &Listpack) -> Vec<&[u8]> {
+        listpack.iter().collect()
+    }
+
+    #[test]
+    fn push_pop_both_ends() {
+        let mut listpack = Listpack::default();
+        listpack.push_back(b"b");
+        listpack.push_front(b"a");
+        listpack.push_back(b"");
+        listpack.push_back(b"ccc");
+        assert_eq!(items(&listpack), [&b"a"[..], b"b", b"", b"ccc"]);
+        assert_eq!(listpack.iter().rev().collect::<Vec<_>>(), [&b"ccc"[..], b"", b"b", b"a"]);
+        assert_eq!(listpack.get(3), Some(&b"ccc"[..]));
+        assert_eq!(listpack.get(4), None);
+        assert_eq!(listpack.pop_front().as_deref(), Some(&b"a"[..]));
+        assert_eq!(listpack.pop_back().as_deref(), Some(&b"ccc"[..]));
+        assert_eq!(listpack.len(), 2);
+        assert_eq!(listpack.bytes(), 1 + 2 * ENTRY_HEADER);
+    }
+
+    #[test]
+    fn splice_in_the_middle() {
+        let mut listpack = Listpack::default();
+        for value in [b"1", b"2", b"3", b"4", b"5"] {
+            listpack.push_back(value);
+        }
+        listpack.splice(1..3, &[b"x", b"yy", b"zzz"]);
+        assert_eq!(items(&listpack), [&b"1"[..], b"x", b"yy", b"zzz", b"4", b"5"]);
+        assert_eq!(listpack.find(b"zzz"), Some(3));
+        listpack.splice(4..6, &[]);
+        assert_eq!(items(&listpack), [&b"1"[..], b"x", b"yy", b"zzz"]);
+        assert_eq!(listpack.find(b"5"), None);
+    }
+}
@@ -0,0 +1,104 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn parse(value: &str) -> Option<Format> {
+        match value.to_ascii_lowercase().as_str() {
+            "text" => Some(Format::Text),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+}
+
+/// 把 redis.conf 的 loglevel 转成 tracing 的过滤规则，其它值（例如 `mini_redis=debug,warn`）原样使用
+pub fn directive(loglevel: &str) -> crate::Result<String> {
+    let directive = match loglevel.to_ascii_lowercase().as_str() {
+        "debug" => "trace".to_string(),
+        "verbose" => "debug".to_string(),
+        "notice" => "info".to_string(),
+        "warning" => "warn".to_string(),
+        "nothing" => "off".to_string(),
+        _ => loglevel.to_string(),
+    };
+    EnvFilter::try_new(&directive).map_err(|err| format!("invalid loglevel '{}': {}", loglevel, err))?;
+    Ok(directive)
+}
+
+/// 追加写入的日志文件，收到 SIGHUP 时重新打开，配合 logrotate 使用
+pub struct LogFile {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl LogFile {
+    fn open(path: &Path) -> io::Result<LogFile> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(LogFile { path: path.to_path_buf(), file: Mutex::new(file) })
+    }
+
+    pub fn reopen(&self) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        *self.file.lock().unwrap() = file;
+        Ok(())
+    }
+}
+
+// tracing-subscriber 为 Arc<W> 实现了 MakeWriter，只要 &W 实现了 Write
+impl io::Write for &LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.lock().unwrap().flush()
+    }
+}
+
+/// 安装全局的 subscriber，写文件时返回日志文件，供 SIGHUP 时重新打开
+pub fn init(config: &Config) -> crate::Result<Option<Arc<LogFile>>> {
+    let logfile = match &config.logfile {
+        Some(path) => Some(Arc::new(LogFile::open(path)?)),
+        None => None,
+    };
+    let writer = match &logfile {
+        Some(file) => BoxMakeWriter::new(file.clone()),
+        None => BoxMakeWriter::new(io::stdout),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_new(&config.loglevel)?)
+        .with_writer(writer)
+        .with_ansi(logfile.is_none());
+    match config.log_format {
+        Format::Text => builder.try_init()?,
+        Format::Json => builder.json().with_current_span(true).with_span_list(true).try_init()?,
+    }
+    Ok(logfile)
+}
+
+/// 收到 SIGHUP 后重新打开日志文件
+#[cfg(unix)]
+pub async fn reopen_on_sighup(logfile: Arc<LogFile>) -> crate::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup())?;
+    while hangup.recv().await.is_some() {
+        match logfile.reopen() {
+            Ok(()) => tracing::info!(path = %logfile.path.display(), "log file reopened"),
+            Err(err) => tracing::error!(path = %logfile.path.display(), %err, "failed to reopen log file"),
+        }
+    }
+    Ok(())
+}
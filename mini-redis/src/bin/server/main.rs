@@ -0,0 +1,431 @@
+mod acl;
+mod bitmap;
+mod client;
+mod cluster;
+mod command;
+mod config;
+mod db;
+mod dump;
+mod gateway;
+mod geo;
+mod glob;
+mod hash;
+mod hll;
+mod http;
+mod interp;
+mod keyspace;
+mod list;
+mod listpack;
+mod logging;
+mod memcache;
+mod metrics;
+mod migrate;
+mod notify;
+mod pubsub;
+mod ratelimit;
+mod replication;
+mod script;
+mod set;
+mod string;
+mod tracking;
+mod zset;
+
+use bytes::Bytes;
+use mini_redis::{tls, Connection, Frame, Stream};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, info_span, warn, Instrument};
+
+use acl::Acl;
+use client::{Client, ClientInfo};
+use cluster::Cluster;
+use command::Context;
+use config::Config;
+use db::Db;
+use metrics::Stats;
+use pubsub::PubSub;
+use ratelimit::RateLimits;
+use replication::Replication;
+use script::Scripts;
+use tracking::Tracking;
+
+pub use mini_redis::{Error, Result};
+
+// 主动过期的扫描间隔
+const EXPIRE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 所有连接共享的服务器状态
+pub struct Shared {
+    pub db: Mutex<Db>,
+    pub acl: Mutex<Acl>,
+    pub config: Config,
+    pub clients: Mutex<BTreeMap<u64, ClientInfo>>,
+    // 没有开启集群模式时为 None
+    pub cluster: Option<Mutex<Cluster>>,
+    pub pubsub: Mutex<PubSub>,
+    pub tracking: Mutex<Tracking>,
+    // notify-keyspace-events，CONFIG SET 可以随时修改
+    pub notify_flags: AtomicU32,
+    pub scripts: Mutex<Scripts>,
+    // 普通命令共享、EVAL 和 MIGRATE 独占，保证脚本里的命令和迁移中的 key 不会和其它连接交错执行
+    pub script_lock: tokio::sync::RwLock<()>,
+    pub stats: Stats,
+    // 以下三项 CONFIG SET 可以随时修改
+    pub maxclients: AtomicUsize,
+    // 秒
+    pub timeout: AtomicU64,
+    pub limits: Mutex<RateLimits>,
+    pub replication: Mutex<Replication>,
+    next_client_id: AtomicU64,
+}
+
+impl Shared {
+    fn new_client(&self, addr: String, laddr: String, kind: &'static str) -> Client {
+        let id = self.next_client_id.fetch_add(1, Ordering::Relaxed);
+        self.stats.connections_received.fetch_add(1, Ordering::Relaxed);
+        let mut client = Client::new(id, addr, laddr, kind);
+        client.authenticated = self.acl.lock().unwrap().default_user_nopass();
+        client
+    }
+
+    /// 登记新连接，超过 maxclients 时拒绝并返回 false
+    fn register(&self, client: &Client) -> bool {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= self.maxclients.load(Ordering::Relaxed) {
+            drop(clients);
+            self.stats.rejected_connections.fetch_add(1, Ordering::Relaxed);
+            warn!("max number of clients reached, connection rejected");
+            return false;
+        }
+        clients.insert(client.id, client.info());
+        true
+    }
+
+    /// 连接断开后清理登记信息、订阅和缓存跟踪
+    fn unregister(&self, client: &mut Client) {
+        self.clients.lock().unwrap().remove(&client.id);
+        self.pubsub.lock().unwrap().remove_client(client.id, client.channels.drain(), client.patterns.drain());
+        self.tracking.lock().unwrap().disable(client.id);
+        if client.replica {
+            self.replication.lock().unwrap().remove_replica(client.id);
+        }
+    }
+
+    /// 给指定连接推送一条消息，连接不存在时返回 false
+    pub fn push(&self, id: u64, frame: Frame) -> bool {
+        match self.clients.lock().unwrap().get(&id) {
+            Some(info) => info.push.send(frame).is_ok(),
+            None => false,
+        }
+    }
+
+    /// 每次修改 key 之后调用，`by` 是执行修改的连接
+    pub fn signal_modified_key(&self, key: &[u8], by: Option<u64>) {
+        tracking::invalidate(self, key, by);
+    }
+
+    /// 给读取时被动删除的过期 key 发通知
+    pub fn flush_expired(&self) {
+        let expired = self.db.lock().unwrap().take_expired();
+        self.expired(expired);
+    }
+
+    fn expired(&self, keys: Vec<Bytes>) {
+        for key in keys {
+            replication::propagate_del(self, &key);
+            self.signal_modified_key(&key, None);
+            notify::keyspace_event(self, notify::EXPIRED, "expired", &key);
+        }
+    }
+
+    /// 写命令执行前调用，超过 maxmemory 时淘汰 key，无法淘汰时返回 false
+    pub fn evict(&self) -> bool {
+        let result = self.db.lock().unwrap().evict();
+        let (evicted, ok) = match result {
+            Ok(evicted) => (evicted, true),
+            Err(evicted) => (evicted, false),
+        };
+        for key in evicted {
+            replication::propagate_del(self, &key);
+            self.signal_modified_key(&key, None);
+            notify::keyspace_event(self, notify::EVICTED, "evicted", &key);
+        }
+        ok
+    }
+}
+
+/// 定期删除已经到期的 key
+async fn active_expire(shared: Arc<Shared>) {
+    let mut interval = tokio::time::interval(EXPIRE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let expired = shared.db.lock().unwrap().purge_expired(Instant::now());
+        shared.expired(expired);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let config = Config::from_args()?;
+    let logfile = logging::init(&config)?;
+    #[cfg(unix)]
+    if let Some(logfile) = logfile {
+        tokio::spawn(logging::reopen_on_sighup(logfile));
+    }
+    let mut acl = Acl::new(config.requirepass.as_deref());
+    if let Some(path) = &config.aclfile {
+        if path.exists() {
+            acl.load(path)?;
+        }
+    }
+
+    let cluster = if config.cluster_enabled {
+        let addr = cluster::announce_addr(&config);
+        Some(Mutex::new(Cluster::load_or_create(&config.cluster_config_file, addr)?))
+    } else {
+        None
+    };
+
+    let db = Db::new(config.maxmemory, config.maxmemory_policy, config.thresholds);
+    let notify_flags = AtomicU32::new(config.notify_keyspace_events);
+    let scripts = Scripts::new(Duration::from_millis(config.busy_reply_threshold));
+    let limits = RateLimits::new(
+        config.client_rate_limit,
+        config.user_rate_limit,
+        config.rate_limit_burst,
+        config.rate_limit_action,
+    );
+    let maxclients = AtomicUsize::new(config.maxclients);
+    let timeout = AtomicU64::new(config.timeout);
+    let replication = Replication::new(config.replicaof.clone(), config.replica_read_only, config.replica_serve_stale_data);
+    let shared = Arc::new(Shared {
+        db: Mutex::new(db),
+        acl: Mutex::new(acl),
+        config,
+        clients: Mutex::new(BTreeMap::new()),
+        cluster,
+        pubsub: Mutex::new(PubSub::default()),
+        tracking: Mutex::new(Tracking::default()),
+        notify_flags,
+        scripts: Mutex::new(scripts),
+        script_lock: tokio::sync::RwLock::new(()),
+        stats: Stats::new(),
+        maxclients,
+        timeout,
+        limits: Mutex::new(limits),
+        replication: Mutex::new(replication),
+        next_client_id: AtomicU64::new(1),
+    });
+    let config = &shared.config;
+
+    let mut listeners = Vec::new();
+    if config.port != 0 {
+        let listener = TcpListener::bind((config.bind.as_str(), config.port)).await?;
+        listeners.push(tokio::spawn(accept_tcp(listener, shared.clone())));
+    }
+    if let Some(port) = config.tls_port {
+        let (cert, key) = match (&config.tls_cert_file, &config.tls_key_file) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => return Err("tls-port requires tls-cert-file and tls-key-file".into()),
+        };
+        let acceptor = tls::acceptor(
+            cert,
+            key,
+            config.tls_ca_cert_file.as_deref(),
+            config.tls_auth_clients,
+        )?;
+        let listener = TcpListener::bind((config.bind.as_str(), port)).await?;
+        listeners.push(tokio::spawn(accept_tls(listener, acceptor, shared.clone())));
+    }
+    #[cfg(unix)]
+    if let Some(path) = &config.unixsocket {
+        listeners.push(tokio::spawn(accept_unix(bind_unix(path, config.unixsocketperm)?, shared.clone())));
+    }
+    if let Some(port) = config.http_port {
+        let listener = TcpListener::bind((config.bind.as_str(), port)).await?;
+        listeners.push(tokio::spawn(gateway::serve(listener, shared.clone())));
+    }
+    if let Some(port) = config.memcache_port {
+        let listener = TcpListener::bind((config.bind.as_str(), port)).await?;
+        listeners.push(tokio::spawn(memcache::serve(listener, shared.clone())));
+    }
+    if let Some(port) = config.metrics_port {
+        let listener = TcpListener::bind((config.bind.as_str(), port)).await?;
+        listeners.push(tokio::spawn(metrics::serve(listener, shared.clone())));
+    }
+    if listeners.is_empty() {
+        return Err("no listener configured, set port, tls-port or unixsocket".into());
+    }
+    info!(bind = %config.bind, port = config.port, tls_port = ?config.tls_port, unixsocket = ?config.unixsocket, "ready to accept connections");
+    if shared.cluster.is_some() {
+        tokio::spawn(cluster::gossip(shared.clone()));
+    }
+    tokio::spawn(active_expire(shared.clone()));
+    if config.replicaof.is_some() {
+        tokio::spawn(replication::replicate(shared.clone(), 0));
+    }
+
+    for listener in listeners {
+        listener.await??;
+    }
+    Ok(())
+}
+
+async fn accept_tcp(listener: TcpListener, shared: Arc<Shared>) -> Result<()> {
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        // 回复都是小包，关掉 Nagle，避免 pipeline 时和对端的延迟 ACK 互相等待
+        socket.set_nodelay(true)?;
+        let shared = shared.clone();
+        let client = shared.new_client(addr.to_string(), socket.local_addr()?.to_string(), "tcp");
+        tokio::spawn(process(socket, client, shared));
+    }
+}
+
+async fn accept_tls(listener: TcpListener, acceptor: TlsAcceptor, shared: Arc<Shared>) -> Result<()> {
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        socket.set_nodelay(true)?;
+        let acceptor = acceptor.clone();
+        let shared = shared.clone();
+        let client = shared.new_client(addr.to_string(), socket.local_addr()?.to_string(), "tls");
+        tokio::spawn(async move {
+            match acceptor.accept(socket).await {
+                Ok(stream) => process(stream, client, shared).await,
+                Err(err) => warn!(peer = %client.addr, %err, "tls handshake failed"),
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &std::path::Path, perm: Option<u32>) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    // 上次异常退出留下的 socket 文件会导致 bind 失败
+    if path.exists() {
+        std::fs::remove_file(path)?;
+    }
+    let listener = tokio::net::UnixListener::bind(path)?;
+    if let Some(perm) = perm {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+#[cfg(unix)]
+async fn accept_unix(listener: tokio::net::UnixListener, shared: Arc<Shared>) -> Result<()> {
+    let path = listener.local_addr()?;
+    let path = path.as_pathname().map(|p| p.display().to_string()).unwrap_or_default();
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let shared = shared.clone();
+        let client = shared.new_client(format!("{}:0", path), path.clone(), "unix");
+        tokio::spawn(process(socket, client, shared));
+    }
+}
+
+async fn process<S: Stream>(socket: S, mut client: Client, shared: Arc<Shared>) {
+    // 只有 db 0
+    let span = info_span!("client", id = client.id, peer = %client.addr, kind = client.kind, db = 0);
+    async {
+        if !shared.register(&client) {
+            let mut connect = Connection::new(socket);
+            let _ = connect.write_frame(&Frame::error("ERR max number of clients reached")).await;
+            return;
+        }
+        debug!("client connected");
+        let result = serve(socket, &mut client, &shared).await;
+        shared.unregister(&mut client);
+        match result {
+            Ok(()) => debug!("client closed connection"),
+            Err(err) => warn!(%err, "connection error"),
+        }
+    }
+    .instrument(span)
+    .await
+}
+
+async fn serve<S: Stream>(socket: S, client: &mut Client, shared: &Arc<Shared>) -> Result<()> {
+    let mut connect = Connection::new(socket);
+    let mut inbox = client.inbox.take().expect("client inbox already taken");
+
+    loop {
+        let deadline = idle_deadline(shared, client);
+        let frame = tokio::select! {
+            frame = connect.read_frame() => match frame? {
+                Some(frame) => frame,
+                None => return Ok(()),
+            },
+            // 订阅消息、缓存失效通知和转发给副本的写命令
+            Some(push) = inbox.recv() => {
+                connect.write_frame(&encode(push, client.resp)).await?;
+                continue;
+            }
+            _ = idle(deadline) => {
+                debug!("closing idle client");
+                return Ok(());
+            }
+        };
+        let response = match command::parse_args(frame) {
+            Ok(args) => dispatch(shared, client, &args).await,
+            Err(msg) => Frame::Error(msg),
+        };
+        for reply in std::mem::take(&mut client.replies) {
+            connect.buffer_frame(&encode(reply, client.resp)).await?;
+        }
+        if std::mem::take(&mut client.skip_reply) {
+            connect.flush().await?;
+            continue;
+        }
+        connect.write_frame(&encode(response, client.resp)).await?;
+    }
+}
+
+/// 执行一条命令：记录最近的命令、限流，执行后更新 CLIENT LIST 看到的快照
+async fn dispatch(shared: &Arc<Shared>, client: &mut Client, args: &[Bytes]) -> Frame {
+    client.last_interaction = Instant::now();
+    if let Some(name) = args.first() {
+        client.last_cmd = String::from_utf8_lossy(name).to_ascii_lowercase();
+    }
+    let span = tracing::debug_span!("command", cmd = %client.last_cmd);
+    match ratelimit::acquire(shared, client) {
+        Ok(wait) => {
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+            let mut ctx = Context { shared, client };
+            let response = command::execute(&mut ctx, args).instrument(span).await;
+            shared.clients.lock().unwrap().insert(client.id, client.info());
+            response
+        }
+        Err(err) => err,
+    }
+}
+
+/// 按 timeout 配置断开空闲连接的时间，RESP 和 memcached 连接共用。
+/// 订阅了频道的连接和副本不会因为空闲被断开
+fn idle_deadline(shared: &Shared, client: &Client) -> Option<Instant> {
+    let timeout = shared.timeout.load(Ordering::Relaxed);
+    (timeout > 0 && !client.subscribed() && !client.replica).then(|| client.last_interaction + Duration::from_secs(timeout))
+}
+
+async fn idle(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+// 没有切换到 RESP3 的连接收不到 map、push 等新类型
+fn encode(frame: Frame, resp: u8) -> Frame {
+    if resp >= 3 {
+        frame
+    } else {
+        frame.into_resp2()
+    }
+}
@@ -0,0 +1,451 @@
+use bytes::{Bytes, BytesMut};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info_span, Instrument};
+
+use crate::client::Client;
+use crate::command::{self, Context};
+use crate::keyspace::{deadline, unix_millis};
+use crate::{cluster, notify, ratelimit, replication, Shared};
+use mini_redis::Frame;
+
+// memcached 的限制：key 最长 250 字节，命令行最长 2048 字节，值最大 1MB
+const MAX_KEY: usize = 250;
+const MAX_LINE: usize = 2048;
+const MAX_ITEM: usize = 1024 * 1024;
+// 超过 30 天的过期时间是 unix 时间戳，否则是相对秒数
+const RELATIVE_EXPIRE_LIMIT: i64 = 60 * 60 * 24 * 30;
+
+/// memcached 文本协议的监听端口，和 RESP 共用同一个键空间。
+/// 连接和 RESP 一样登记在客户端列表里，受 maxclients、限流、ACL 和集群槽位的约束
+pub async fn serve(listener: TcpListener, shared: Arc<Shared>) -> crate::Result<()> {
+    loop {
+        let (socket, addr) = listener.accept().await?;
+        socket.set_nodelay(true)?;
+        let shared = shared.clone();
+        let mut client = shared.new_client(addr.to_string(), socket.local_addr()?.to_string(), "memcache");
+        let span = info_span!("memcache", id = client.id, peer = %addr);
+        tokio::spawn(
+            async move {
+                if !shared.register(&client) {
+                    let mut socket = socket;
+                    let _ = socket.write_all(b"SERVER_ERROR max number of clients reached\r\n").await;
+                    return;
+                }
+                if let Err(err) = handle(socket, &mut client, &shared).await {
+                    debug!(%err, "memcache connection error");
+                }
+                shared.unregister(&mut client);
+            }
+            .instrument(span),
+        );
+    }
+}
+
+enum Store {
+    Set,
+    Add,
+    Replace,
+    Cas(u64),
+}
+
+// 过期时间的三种情况：不过期、在某个时间点过期、已经过期（相当于删除）
+enum Expiry {
+    Never,
+    At(Instant),
+    Past,
+}
+
+fn expiry(exptime: i64) -> Expiry {
+    match exptime {
+        0 => Expiry::Never,
+        ..0 => Expiry::Past,
+        1..=RELATIVE_EXPIRE_LIMIT => Expiry::At(Instant::now() + Duration::from_secs(exptime as u64)),
+        _ => exptime.checked_mul(1000).and_then(|ms| deadline(ms, true)).map_or(Expiry::Past, Expiry::At),
+    }
+}
+
+fn parse<T: std::str::FromStr>(token: &[u8]) -> Option<T> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+fn valid_key(key: &[u8]) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY && !key.iter().any(|b| b.is_ascii_control() || *b == b' ')
+}
+
+const BAD_FORMAT: &[u8] = b"CLIENT_ERROR bad command line format\r\n";
+const READ_ONLY: &[u8] = b"SERVER_ERROR You can't write against a read only replica.\r\n";
+const UNAUTHENTICATED: &[u8] = b"CLIENT_ERROR unauthenticated\r\n";
+
+// 错误回复转换成 memcached 的错误行
+fn error_line(prefix: &str, frame: Frame) -> Vec<u8> {
+    let message = match frame {
+        Frame::Error(message) => message,
+        _ => "unexpected reply".to_string(),
+    };
+    format!("{} {}\r\n", prefix, message).into_bytes()
+}
+
+/// 和 memcached 的文本协议认证一样，需要认证时第一条命令是 set，数据是 `<username> <password>`，
+/// 只有密码时以 default 用户登录
+fn login(shared: &Shared, client: &mut Client, data: &[u8]) -> Vec<u8> {
+    let data = String::from_utf8_lossy(data);
+    let (username, password) = match data.split_once(' ') {
+        Some((username, password)) => (username.to_string(), password),
+        None => ("default".to_string(), data.as_ref()),
+    };
+    let mut acl = shared.acl.lock().unwrap();
+    if acl.authenticate(&username, password.as_bytes()) {
+        client.user = username;
+        client.authenticated = true;
+        b"STORED\r\n".to_vec()
+    } else {
+        acl.log_denied("auth", "AUTH", &username, &client.addr);
+        b"CLIENT_ERROR authentication failure\r\n".to_vec()
+    }
+}
+
+/// 按等价的 RESP 命令做限流、ACL 和集群槽位检查，不允许执行时返回错误行
+async fn gate(shared: &Arc<Shared>, client: &mut Client, name: &'static str, keys: &[&[u8]]) -> Option<Vec<u8>> {
+    match ratelimit::acquire(shared, client) {
+        Ok(wait) if !wait.is_zero() => tokio::time::sleep(wait).await,
+        Ok(_) => {}
+        Err(err) => return Some(error_line("SERVER_ERROR", err)),
+    }
+    let spec = command::lookup(name.as_bytes()).expect("memcache commands map to known commands");
+    let name = Bytes::from_static(name.as_bytes());
+    let ctx = Context { shared, client };
+    if keys.is_empty() {
+        if let Err(err) = command::check_permission(&ctx, spec, std::slice::from_ref(&name)) {
+            return Some(error_line("CLIENT_ERROR", err));
+        }
+    }
+    for key in keys {
+        let args = [name.clone(), Bytes::copy_from_slice(key)];
+        if let Err(err) = command::check_permission(&ctx, spec, &args) {
+            return Some(error_line("CLIENT_ERROR", err));
+        }
+        if let Err(err) = cluster::route(shared, spec, &args, false) {
+            return Some(error_line("SERVER_ERROR", err));
+        }
+    }
+    None
+}
+
+async fn handle(socket: TcpStream, client: &mut Client, shared: &Arc<Shared>) -> crate::Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let deadline = crate::idle_deadline(shared, client);
+        let mut limited = (&mut reader).take(MAX_LINE as u64);
+        let read = tokio::select! {
+            read = limited.read_until(b'\n', &mut line) => read?,
+            _ = crate::idle(deadline) => {
+                debug!("closing idle memcache client");
+                return Ok(());
+            }
+        };
+        if read == 0 {
+            return Ok(());
+        }
+        if !line.ends_with(b"\n") {
+            writer.write_all(b"CLIENT_ERROR line too long\r\n").await?;
+            return Ok(());
+        }
+        let tokens: Vec<&[u8]> = line.split(|b| b.is_ascii_whitespace()).filter(|t| !t.is_empty()).collect();
+        let Some((command, args)) = tokens.split_first() else {
+            writer.write_all(b"ERROR\r\n").await?;
+            continue;
+        };
+        let noreply = args.last() == Some(&&b"noreply"[..]);
+        let name = command.to_ascii_lowercase();
+        client.last_interaction = Instant::now();
+        client.last_cmd = String::from_utf8_lossy(&name).into_owned();
+        if !client.authenticated && !matches!(&name[..], b"set" | b"quit") {
+            writer.write_all(UNAUTHENTICATED).await?;
+            continue;
+        }
+        let reply = match &name[..] {
+            op @ (b"get" | b"gets") if !args.is_empty() => match gate(shared, client, "get", args).await {
+                Some(denied) => denied,
+                None => get(shared, args, op == b"gets"),
+            },
+            op @ (b"set" | b"add" | b"replace" | b"cas") => {
+                let cas = op == b"cas";
+                let fields = if cas { 5 } else { 4 };
+                if args.len() != fields && !(args.len() == fields + 1 && noreply) {
+                    writer.write_all(BAD_FORMAT).await?;
+                    continue;
+                }
+                let (flags, exptime, len) = (parse::<u32>(args[1]), parse::<i64>(args[2]), parse::<usize>(args[3]));
+                let unique = if cas { parse::<u64>(args[4]) } else { Some(0) };
+                let (Some(flags), Some(exptime), Some(len), Some(unique)) = (flags, exptime, len, unique) else {
+                    writer.write_all(BAD_FORMAT).await?;
+                    continue;
+                };
+                // 不管请求是否合法，数据块都要读掉，否则会被当成下一条命令
+                if len > MAX_ITEM {
+                    tokio::io::copy(&mut (&mut reader).take(len as u64 + 2), &mut tokio::io::sink()).await?;
+                    writer.write_all(b"SERVER_ERROR object too large for cache\r\n").await?;
+                    continue;
+                }
+                let mut data = vec![0; len + 2];
+                reader.read_exact(&mut data).await?;
+                if !data.ends_with(b"\r\n") {
+                    writer.write_all(b"CLIENT_ERROR bad data chunk\r\n").await?;
+                    continue;
+                }
+                data.truncate(len);
+                let mode = match op {
+                    b"set" => Store::Set,
+                    b"add" => Store::Add,
+                    b"replace" => Store::Replace,
+                    _ => Store::Cas(unique),
+                };
+                if !client.authenticated {
+                    // 认证结果总是要回复，否则客户端不知道是否登录成功
+                    writer.write_all(&login(shared, client, &data)).await?;
+                    continue;
+                }
+                if !valid_key(args[0]) {
+                    BAD_FORMAT.to_vec()
+                } else if let Some(denied) = gate(shared, client, "set", &args[..1]).await {
+                    denied
+                } else {
+                    let _guard = shared.script_lock.read().await;
+                    store(shared, mode, args[0], flags, exptime, Bytes::from(data))
+                }
+            }
+            op @ (b"incr" | b"decr") if args.len() == 2 || (args.len() == 3 && noreply) => match parse::<u64>(args[1]) {
+                Some(delta) => match gate(shared, client, "set", &args[..1]).await {
+                    Some(denied) => denied,
+                    None => {
+                        let _guard = shared.script_lock.read().await;
+                        incr(shared, args[0], delta, op == b"incr")
+                    }
+                },
+                None => b"CLIENT_ERROR invalid numeric delta argument\r\n".to_vec(),
+            },
+            b"delete" if args.len() == 1 || (args.len() == 2 && noreply) => match gate(shared, client, "del", &args[..1]).await {
+                Some(denied) => denied,
+                None => {
+                    let _guard = shared.script_lock.read().await;
+                    delete(shared, args[0])
+                }
+            },
+            b"touch" if args.len() == 2 || (args.len() == 3 && noreply) => match parse::<i64>(args[1]) {
+                Some(exptime) => match gate(shared, client, "pexpireat", &args[..1]).await {
+                    Some(denied) => denied,
+                    None => {
+                        let _guard = shared.script_lock.read().await;
+                        touch(shared, args[0], exptime)
+                    }
+                },
+                None => BAD_FORMAT.to_vec(),
+            },
+            b"flush_all" if args.len() <= 2 => {
+                let delay = match args.first().filter(|arg| **arg != b"noreply") {
+                    Some(delay) => parse::<u64>(delay),
+                    None => Some(0),
+                };
+                // 要有删除现有每一个 key 的权限
+                let keys: Vec<Bytes> = shared.db.lock().unwrap().keys().cloned().collect();
+                let keys: Vec<&[u8]> = keys.iter().map(|key| &key[..]).collect();
+                let denied = match delay {
+                    Some(_) => gate(shared, client, "del", &keys).await,
+                    None => None,
+                };
+                match delay {
+                    Some(_) if denied.is_some() => denied.unwrap(),
+                    Some(_) if replication::read_only(shared) => READ_ONLY.to_vec(),
+                    Some(0) => {
+                        flush_all(shared).await;
+                        b"OK\r\n".to_vec()
+                    }
+                    Some(delay) => {
+                        let shared = shared.clone();
+                        tokio::spawn(async move {
+                            tokio::time::sleep(Duration::from_secs(delay)).await;
+                            flush_all(&shared).await;
+                        });
+                        b"OK\r\n".to_vec()
+                    }
+                    None => BAD_FORMAT.to_vec(),
+                }
+            }
+            b"version" => format!("VERSION mini-redis {}\r\n", env!("CARGO_PKG_VERSION")).into_bytes(),
+            b"quit" => return Ok(()),
+            _ => b"ERROR\r\n".to_vec(),
+        };
+        if !noreply {
+            writer.write_all(&reply).await?;
+        }
+        shared.clients.lock().unwrap().insert(client.id, client.info());
+    }
+}
+
+fn get(shared: &Shared, keys: &[&[u8]], with_cas: bool) -> Vec<u8> {
+    let mut out = BytesMut::new();
+    let mut db = shared.db.lock().unwrap();
+    for key in keys {
+        // 其它类型的值对 memcached 客户端来说就是不存在
+        let Some(entry) = db.get(key) else { continue };
+        let Some(value) = entry.value.as_string() else { continue };
+        out.extend_from_slice(b"VALUE ");
+        out.extend_from_slice(key);
+        out.extend_from_slice(format!(" {} {}", entry.flags, value.len()).as_bytes());
+        if with_cas {
+            out.extend_from_slice(format!(" {}", entry.cas).as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(value);
+        out.extend_from_slice(b"\r\n");
+    }
+    drop(db);
+    shared.flush_expired();
+    out.extend_from_slice(b"END\r\n");
+    out.to_vec()
+}
+
+fn store(shared: &Shared, mode: Store, key: &[u8], flags: u32, exptime: i64, data: Bytes) -> Vec<u8> {
+    if replication::read_only(shared) {
+        return READ_ONLY.to_vec();
+    }
+    if !shared.evict() {
+        return b"SERVER_ERROR out of memory storing object\r\n".to_vec();
+    }
+    let key = Bytes::copy_from_slice(key);
+    let mut db = shared.db.lock().unwrap();
+    let current = db.peek(&key).map(|entry| entry.cas);
+    let stored = match (mode, current) {
+        (Store::Add, Some(_)) | (Store::Replace, None) => return b"NOT_STORED\r\n".to_vec(),
+        (Store::Cas(_), None) => return b"NOT_FOUND\r\n".to_vec(),
+        (Store::Cas(unique), Some(cas)) if unique != cas => return b"EXISTS\r\n".to_vec(),
+        _ => match expiry(exptime) {
+            Expiry::Past => {
+                let removed = db.remove(&key);
+                drop(db);
+                if removed.is_some() {
+                    replication::propagate_del(shared, &key);
+                }
+                drop(removed);
+                false
+            }
+            expiry => {
+                let expires_at = match expiry {
+                    Expiry::At(when) => Some(when),
+                    _ => None,
+                };
+                let replaced = db.set(key.clone(), data.clone(), expires_at);
+                db.set_flags(&key, flags);
+                drop(db);
+                drop(replaced);
+                // 副本上按 RESP 命令重放，flags 只保存在本地
+                let mut set = vec![Bytes::from_static(b"SET"), key.clone(), data];
+                if let Some(when) = expires_at {
+                    set.extend([Bytes::from_static(b"PXAT"), Bytes::from(unix_millis(when).to_string())]);
+                }
+                replication::propagate(shared, &set);
+                true
+            }
+        },
+    };
+    shared.flush_expired();
+    shared.signal_modified_key(&key, None);
+    if stored {
+        if current.is_none() {
+            notify::keyspace_event(shared, notify::NEW, "new", &key);
+        }
+        notify::keyspace_event(shared, notify::STRING, "set", &key);
+    } else if current.is_some() {
+        notify::keyspace_event(shared, notify::GENERIC, "del", &key);
+    }
+    b"STORED\r\n".to_vec()
+}
+
+fn incr(shared: &Shared, key: &[u8], delta: u64, incr: bool) -> Vec<u8> {
+    if replication::read_only(shared) {
+        return READ_ONLY.to_vec();
+    }
+    let result = shared.db.lock().unwrap().modify(key, |value| {
+        let current = value.as_string().and_then(|value| parse::<u64>(value))?;
+        // incr 在 64 位无符号整数上回绕，decr 最小减到 0
+        let next = if incr { current.wrapping_add(delta) } else { current.saturating_sub(delta) };
+        *value = Bytes::from(next.to_string()).into();
+        Some(next)
+    });
+    shared.flush_expired();
+    match result {
+        None => b"NOT_FOUND\r\n".to_vec(),
+        Some(None) => b"CLIENT_ERROR cannot increment or decrement non-numeric value\r\n".to_vec(),
+        Some(Some(next)) => {
+            let next_value = Bytes::from(next.to_string());
+            let set = [Bytes::from_static(b"SET"), Bytes::copy_from_slice(key), next_value, Bytes::from_static(b"KEEPTTL")];
+            replication::propagate(shared, &set);
+            shared.signal_modified_key(key, None);
+            notify::keyspace_event(shared, notify::STRING, if incr { "incrby" } else { "decrby" }, key);
+            format!("{}\r\n", next).into_bytes()
+        }
+    }
+}
+
+fn delete(shared: &Shared, key: &[u8]) -> Vec<u8> {
+    if replication::read_only(shared) {
+        return READ_ONLY.to_vec();
+    }
+    let removed = shared.db.lock().unwrap().remove(key);
+    shared.flush_expired();
+    match removed {
+        Some(_) => {
+            replication::propagate_del(shared, &Bytes::copy_from_slice(key));
+            shared.signal_modified_key(key, None);
+            notify::keyspace_event(shared, notify::GENERIC, "del", key);
+            b"DELETED\r\n".to_vec()
+        }
+        None => b"NOT_FOUND\r\n".to_vec(),
+    }
+}
+
+fn touch(shared: &Shared, key: &[u8], exptime: i64) -> Vec<u8> {
+    if replication::read_only(shared) {
+        return READ_ONLY.to_vec();
+    }
+    let owned = Bytes::copy_from_slice(key);
+    let mut db = shared.db.lock().unwrap();
+    let (touched, event, command) = match expiry(exptime) {
+        Expiry::Past => (db.remove(key).is_some(), "del", vec![Bytes::from_static(b"DEL"), owned]),
+        Expiry::Never => (db.set_expiry(key, None), "persist", vec![Bytes::from_static(b"PERSIST"), owned]),
+        Expiry::At(when) => {
+            let at = Bytes::from(unix_millis(when).to_string());
+            (db.set_expiry(key, Some(when)), "expire", vec![Bytes::from_static(b"PEXPIREAT"), owned, at])
+        }
+    };
+    drop(db);
+    shared.flush_expired();
+    if !touched {
+        return b"NOT_FOUND\r\n".to_vec();
+    }
+    replication::propagate(shared, &command);
+    shared.signal_modified_key(key, None);
+    notify::keyspace_event(shared, notify::GENERIC, event, key);
+    b"TOUCHED\r\n".to_vec()
+}
+
+/// 清空整个键空间
+async fn flush_all(shared: &Shared) {
+    let _guard = shared.script_lock.read().await;
+    let keys: Vec<Bytes> = shared.db.lock().unwrap().keys().cloned().collect();
+    for key in keys {
+        let removed = shared.db.lock().unwrap().remove(&key);
+        if removed.is_some() {
+            replication::propagate_del(shared, &key);
+            shared.signal_modified_key(&key, None);
+            notify::keyspace_event(shared, notify::GENERIC, "del", &key);
+        }
+    }
+    shared.flush_expired();
+}
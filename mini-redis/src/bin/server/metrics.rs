@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::{http, Shared};
+
+// 命令耗时直方图的桶上界（秒）
+const BUCKETS: [f64; 10] = [0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 1.0];
+// HTTP 端口只给监控系统用，请求不会太慢
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 服务器运行以来的统计，/metrics 导出
+pub struct Stats {
+    pub started: Instant,
+    pub connections_received: AtomicU64,
+    // 超过 maxclients 被拒绝的连接
+    pub rejected_connections: AtomicU64,
+    commands: Mutex<BTreeMap<&'static str, CommandStats>>,
+}
+
+#[derive(Default)]
+struct CommandStats {
+    calls: u64,
+    // 回复是错误的调用
+    failed: u64,
+    // 每个桶单独计数，导出时再累加
+    buckets: [u64; BUCKETS.len()],
+    total: Duration,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            started: Instant::now(),
+            connections_received: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            commands: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(&self, name: &'static str, elapsed: Duration, failed: bool) {
+        let mut commands = self.commands.lock().unwrap();
+        let stats = commands.entry(name).or_default();
+        stats.calls += 1;
+        stats.failed += failed as u64;
+        stats.total += elapsed;
+        if let Some(bucket) = BUCKETS.iter().position(|le| elapsed.as_secs_f64() <= *le) {
+            stats.buckets[bucket] += 1;
+        }
+    }
+}
+
+// 写一个指标的 HELP、TYPE 和所有样本，labels 形如 `command="get"`
+fn metric<V: std::fmt::Display>(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: impl IntoIterator<Item = (String, V)>,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            let _ = writeln!(out, "{} {}", name, value);
+        } else {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+        }
+    }
+}
+
+fn single<V>(value: V) -> [(String, V); 1] {
+    [(String::new(), value)]
+}
+
+/// Prometheus 文本格式的全部指标
+pub fn render(shared: &Shared) -> String {
+    let mut out = String::new();
+    let stats = &shared.stats;
+    metric(&mut out, "mini_redis_uptime_seconds", "gauge", "Seconds since the server started.", single(stats.started.elapsed().as_secs()));
+
+    let connected = shared.clients.lock().unwrap().len();
+    metric(&mut out, "mini_redis_connected_clients", "gauge", "Number of open client connections.", single(connected));
+    let received = stats.connections_received.load(Ordering::Relaxed);
+    metric(&mut out, "mini_redis_connections_received_total", "counter", "Total number of accepted connections.", single(received));
+    let rejected = stats.rejected_connections.load(Ordering::Relaxed);
+    metric(&mut out, "mini_redis_rejected_connections_total", "counter", "Connections rejected because of maxclients.", single(rejected));
+
+    {
+        let commands = stats.commands.lock().unwrap();
+        let label = |name: &str| format!("command=\"{}\"", name);
+        metric(
+            &mut out,
+            "mini_redis_commands_total",
+            "counter",
+            "Total number of executed commands.",
+            commands.iter().map(|(name, stats)| (label(name), stats.calls)),
+        );
+        metric(
+            &mut out,
+            "mini_redis_commands_failed_total",
+            "counter",
+            "Total number of commands that replied with an error.",
+            commands.iter().map(|(name, stats)| (label(name), stats.failed)),
+        );
+        let name = "mini_redis_command_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Time spent executing commands.", name);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (command, stats) in commands.iter() {
+            let mut cumulative = 0;
+            for (le, count) in BUCKETS.iter().zip(stats.buckets) {
+                cumulative += count;
+                let _ = writeln!(out, "{}_bucket{{command=\"{}\",le=\"{}\"}} {}", name, command, le, cumulative);
+            }
+            let _ = writeln!(out, "{}_bucket{{command=\"{}\",le=\"+Inf\"}} {}", name, command, stats.calls);
+            let _ = writeln!(out, "{}_sum{{command=\"{}\"}} {}", name, command, stats.total.as_secs_f64());
+            let _ = writeln!(out, "{}_count{{command=\"{}\"}} {}", name, command, stats.calls);
+        }
+    }
+
+    let (keys, expires, used, max, evicted, expired) = {
+        let db = shared.db.lock().unwrap();
+        (db.len(), db.expires(), db.used_memory(), db.maxmemory, db.stats.evicted_keys, db.stats.expired_keys)
+    };
+    let db = |value| [("db=\"0\"".to_string(), value)];
+    metric(&mut out, "mini_redis_db_keys", "gauge", "Number of keys in the database.", db(keys));
+    metric(&mut out, "mini_redis_db_keys_expiring", "gauge", "Number of keys with an expiration.", db(expires));
+    metric(&mut out, "mini_redis_memory_used_bytes", "gauge", "Estimated memory used by keys and values.", single(used));
+    metric(&mut out, "mini_redis_memory_max_bytes", "gauge", "The maxmemory setting, 0 when unlimited.", single(max));
+    metric(&mut out, "mini_redis_evicted_keys_total", "counter", "Total number of keys evicted because of maxmemory.", single(evicted));
+    metric(&mut out, "mini_redis_expired_keys_total", "counter", "Total number of keys removed because they expired.", single(expired));
+    // 目前数据只保存在内存里，没有 RDB / AOF
+    metric(&mut out, "mini_redis_persistence_enabled", "gauge", "Whether the dataset is persisted to disk.", single(0));
+
+    let (replica, offset, replicas) = {
+        let repl = shared.replication.lock().unwrap();
+        (repl.is_replica(), repl.offset, repl.connected_replicas())
+    };
+    metric(&mut out, "mini_redis_replica", "gauge", "Whether this instance is a replica.", single(replica as u8));
+    metric(&mut out, "mini_redis_replication_offset_bytes", "gauge", "Replication offset of this instance.", single(offset));
+    metric(&mut out, "mini_redis_connected_replicas", "gauge", "Number of replicas connected to this instance.", single(replicas));
+
+    if let Some(cluster) = &shared.cluster {
+        let ok = cluster.lock().unwrap().state_ok();
+        metric(&mut out, "mini_redis_cluster_state_ok", "gauge", "Whether all cluster slots are covered.", single(ok as u8));
+    }
+    out
+}
+
+/// 就绪检查：集群模式下所有槽都有节点负责才算就绪
+fn health(shared: &Shared) -> Result<(), &'static str> {
+    match &shared.cluster {
+        Some(cluster) if !cluster.lock().unwrap().state_ok() => Err("cluster state fail\n"),
+        _ => Ok(()),
+    }
+}
+
+/// 只提供 GET /metrics 和 GET /health 的 HTTP 服务
+pub async fn serve(listener: TcpListener, shared: Arc<Shared>) -> crate::Result<()> {
+    loop {
+        let (socket, _) = listener.accept().await?;
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(socket, &shared).await {
+                tracing::debug!(%err, "metrics connection error");
+            }
+        });
+    }
+}
+
+async fn respond(mut socket: TcpStream, shared: &Shared) -> crate::Result<()> {
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, http::read_request(&mut socket, 0)).await {
+        Ok(request) => request?,
+        Err(_) => return Err("request timed out".into()),
+    };
+    let Some(request) = request else { return Ok(()) };
+    let (status, body) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => ("200 OK", render(shared)),
+        ("GET", "/health") => match health(shared) {
+            Ok(()) => ("200 OK", "ok\n".to_string()),
+            Err(reason) => ("503 Service Unavailable", reason.to_string()),
+        },
+        ("GET", _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    http::respond(&mut socket, status, "text/plain; version=0.0.4; charset=utf-8", body.as_bytes()).await?;
+    Ok(())
+}
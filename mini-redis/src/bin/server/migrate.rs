@@ -0,0 +1,123 @@
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::command::{lookup, syntax_error};
+use crate::{dump, notify, replication, Shared};
+use mini_redis::{client, Frame};
+
+// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [AUTH password | AUTH2 username password] [KEYS key ...]
+//
+// 用 RESTORE 把 DUMP 格式的值写到目标节点，成功的 key 再从本地删除。执行期间持有独占的执行锁，
+// 其它连接的命令要等迁移结束，所以任何时刻 key 要么在本地要么在目标节点上
+pub async fn migrate(shared: Arc<Shared>, args: Vec<Bytes>) -> Frame {
+    let host = String::from_utf8_lossy(&args[1]).into_owned();
+    let port = String::from_utf8_lossy(&args[2]).into_owned();
+    if &args[4][..] != b"0" {
+        return Frame::error("ERR Only database 0 is supported");
+    }
+    let timeout: u64 = match std::str::from_utf8(&args[5]).ok().and_then(|t| t.parse().ok()) {
+        Some(timeout) => timeout,
+        None => return Frame::error("ERR value is not an integer or out of range"),
+    };
+    let (mut copy, mut replace) = (false, false);
+    let mut auth: Option<Vec<Bytes>> = None;
+    let mut i = 6;
+    while i < args.len() {
+        match &args[i].to_ascii_lowercase()[..] {
+            b"copy" => copy = true,
+            b"replace" => replace = true,
+            b"auth" if i + 1 < args.len() => {
+                auth = Some(vec![Bytes::from_static(b"AUTH"), args[i + 1].clone()]);
+                i += 1;
+            }
+            b"auth2" if i + 2 < args.len() => {
+                auth = Some(vec![Bytes::from_static(b"AUTH"), args[i + 1].clone(), args[i + 2].clone()]);
+                i += 2;
+            }
+            b"keys" => {
+                if !args[3].is_empty() {
+                    return Frame::error("ERR When using MIGRATE KEYS option, the key argument must be set to the empty string");
+                }
+                break;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+    let keys = lookup(b"migrate").unwrap().keys(&args);
+
+    // （key，剩余毫秒数，序列化的值），没有过期时间的 ttl 是 0
+    let values: Vec<(Bytes, i64, Bytes)> = {
+        let mut db = shared.db.lock().unwrap();
+        let now = Instant::now();
+        keys.iter()
+            .filter_map(|key| {
+                let entry = db.get(key)?;
+                let ttl = entry.expires_at.map_or(0, |when| (when.saturating_duration_since(now).as_millis() as i64).max(1));
+                Some(((*key).clone(), ttl, dump::serialize(&entry.value)))
+            })
+            .collect()
+    };
+    if values.is_empty() {
+        return Frame::Simple("NOKEY".to_string());
+    }
+
+    // 所有 RESTORE 一次性发出去，再按顺序读回复
+    let transfer = async {
+        let mut target = client::connect(format!("{}:{}", host, port)).await?;
+        if let Some(auth) = &auth {
+            if let Frame::Error(msg) = target.call(auth).await? {
+                return Ok(Err(msg));
+            }
+        }
+        for (key, ttl, payload) in &values {
+            // 目标节点可能正处于 IMPORTING 状态，需要先发送 ASKING
+            if shared.cluster.is_some() {
+                target.send(&[Bytes::from_static(b"ASKING")]).await?;
+            }
+            let mut restore = vec![Bytes::from_static(b"RESTORE"), key.clone(), Bytes::from(ttl.to_string()), payload.clone()];
+            if replace {
+                restore.push(Bytes::from_static(b"REPLACE"));
+            }
+            target.send(&restore).await?;
+        }
+        target.flush().await?;
+        let mut replies = Vec::with_capacity(values.len());
+        for _ in &values {
+            if shared.cluster.is_some() {
+                target.read_reply().await?;
+            }
+            replies.push(match target.read_reply().await? {
+                Frame::Error(msg) => Err(msg),
+                _ => Ok(()),
+            });
+        }
+        Ok::<_, crate::Error>(Ok(replies))
+    };
+    let timeout = Duration::from_millis(timeout.max(1));
+    let replies = match tokio::time::timeout(timeout, transfer).await {
+        Ok(Ok(Ok(replies))) => replies,
+        Ok(Ok(Err(msg))) => return Frame::error(format!("ERR Target instance replied with error: {}", msg)),
+        Ok(Err(err)) => return Frame::error(format!("IOERR error or timeout writing to target instance: {}", err)),
+        Err(_) => return Frame::error("IOERR error or timeout writing to target instance"),
+    };
+
+    // 只删除目标节点确认写入的 key，失败的留在本地
+    let migrated: Vec<&Bytes> = values.iter().zip(&replies).filter(|(_, reply)| reply.is_ok()).map(|((key, ..), _)| key).collect();
+    if !copy {
+        let mut db = shared.db.lock().unwrap();
+        let removed: Vec<_> = migrated.iter().map(|key| db.remove(key)).collect();
+        drop(db);
+        drop(removed);
+        for key in &migrated {
+            replication::propagate_del(&shared, key);
+            shared.signal_modified_key(key, None);
+            notify::keyspace_event(&shared, notify::GENERIC, "del", key);
+        }
+    }
+    match replies.into_iter().find_map(Result::err) {
+        Some(msg) => Frame::error(format!("ERR Target instance replied with error: {}", msg)),
+        None => Frame::ok(),
+    }
+}
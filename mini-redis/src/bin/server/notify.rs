@@ -0,0 +1,79 @@
+use bytes::Bytes;
+use std::sync::atomic::Ordering;
+
+use crate::{pubsub, Shared};
+use mini_redis::Frame;
+
+// notify-keyspace-events 里的字母和对应的位
+pub const KEYSPACE: u32 = 1 << 0; // K
+pub const KEYEVENT: u32 = 1 << 1; // E
+pub const GENERIC: u32 = 1 << 2; // g
+pub const STRING: u32 = 1 << 3; // $
+pub const LIST: u32 = 1 << 4; // l
+pub const SET: u32 = 1 << 5; // s
+pub const HASH: u32 = 1 << 6; // h
+pub const ZSET: u32 = 1 << 7; // z
+pub const EXPIRED: u32 = 1 << 8; // x
+pub const EVICTED: u32 = 1 << 9; // e
+pub const STREAM: u32 = 1 << 10; // t
+pub const KEY_MISS: u32 = 1 << 11; // m
+pub const NEW: u32 = 1 << 12; // n
+// A 是除 m、n 以外所有类型的别名
+const ALL: u32 = GENERIC | STRING | LIST | SET | HASH | ZSET | EXPIRED | EVICTED | STREAM;
+
+const CLASSES: &[(char, u32)] = &[
+    ('g', GENERIC),
+    ('$', STRING),
+    ('l', LIST),
+    ('s', SET),
+    ('h', HASH),
+    ('z', ZSET),
+    ('x', EXPIRED),
+    ('e', EVICTED),
+    ('t', STREAM),
+    ('m', KEY_MISS),
+    ('n', NEW),
+    ('K', KEYSPACE),
+    ('E', KEYEVENT),
+];
+
+pub fn parse_flags(value: &str) -> Option<u32> {
+    let mut flags = 0;
+    for c in value.chars() {
+        flags |= match c {
+            'A' => ALL,
+            c => CLASSES.iter().find(|(name, _)| *name == c)?.1,
+        };
+    }
+    Some(flags)
+}
+
+pub fn format_flags(flags: u32) -> String {
+    let mut out = String::new();
+    if flags & ALL == ALL {
+        out.push('A');
+    }
+    for &(name, flag) in CLASSES {
+        if flags & flag != 0 && (flags & ALL != ALL || flag & ALL == 0) {
+            out.push(name);
+        }
+    }
+    out
+}
+
+/// 发布 `__keyspace@0__:<key>` 和 `__keyevent@0__:<event>` 消息，class 没有开启时什么也不做
+pub fn keyspace_event(shared: &Shared, class: u32, event: &str, key: &[u8]) {
+    let flags = shared.notify_flags.load(Ordering::Relaxed);
+    if flags & class == 0 || flags & (KEYSPACE | KEYEVENT) == 0 {
+        return;
+    }
+    if flags & KEYSPACE != 0 {
+        let mut channel = b"__keyspace@0__:".to_vec();
+        channel.extend_from_slice(key);
+        pubsub::publish(shared, &Bytes::from(channel), Frame::bulk(event.to_string()));
+    }
+    if flags & KEYEVENT != 0 {
+        let channel = Bytes::from(format!("__keyevent@0__:{}", event));
+        pubsub::publish(shared, &channel, Frame::Bulk(Bytes::copy_from_slice(key)));
+    }
+}
@@ -0,0 +1,189 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::command::Context;
+use crate::glob::glob_match;
+use crate::Shared;
+use mini_redis::Frame;
+
+/// 频道和模式的订阅关系，值是订阅者的连接 id
+#[derive(Default)]
+pub struct PubSub {
+    channels: BTreeMap<Bytes, BTreeSet<u64>>,
+    patterns: BTreeMap<Bytes, BTreeSet<u64>>,
+}
+
+impl PubSub {
+    pub fn remove_client(&mut self, id: u64, channels: impl Iterator<Item = Bytes>, patterns: impl Iterator<Item = Bytes>) {
+        for channel in channels {
+            remove(&mut self.channels, &channel, id);
+        }
+        for pattern in patterns {
+            remove(&mut self.patterns, &pattern, id);
+        }
+    }
+}
+
+fn remove(map: &mut BTreeMap<Bytes, BTreeSet<u64>>, name: &Bytes, id: u64) {
+    if let Some(ids) = map.get_mut(name) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            map.remove(name);
+        }
+    }
+}
+
+/// 把消息发给订阅了该频道或匹配模式的连接，返回收到消息的连接数
+pub fn publish(shared: &Shared, channel: &Bytes, message: Frame) -> usize {
+    let mut deliveries = Vec::new();
+    {
+        let pubsub = shared.pubsub.lock().unwrap();
+        if let Some(ids) = pubsub.channels.get(channel) {
+            for &id in ids {
+                let frame = Frame::Push(vec![Frame::bulk("message"), Frame::Bulk(channel.clone()), message.clone()]);
+                deliveries.push((id, frame));
+            }
+        }
+        for (pattern, ids) in &pubsub.patterns {
+            if !glob_match(pattern, channel) {
+                continue;
+            }
+            for &id in ids {
+                let frame = Frame::Push(vec![
+                    Frame::bulk("pmessage"),
+                    Frame::Bulk(pattern.clone()),
+                    Frame::Bulk(channel.clone()),
+                    message.clone(),
+                ]);
+                deliveries.push((id, frame));
+            }
+        }
+    }
+    deliveries
+        .into_iter()
+        .filter(|(id, frame)| shared.push(*id, frame.clone()))
+        .count()
+}
+
+fn confirmation(kind: &'static str, name: Option<Bytes>, count: usize) -> Frame {
+    Frame::Push(vec![
+        Frame::bulk(kind),
+        name.map_or(Frame::Null, Frame::Bulk),
+        Frame::Integer(count as i64),
+    ])
+}
+
+// 每个频道回复一条确认，最后一条作为命令的返回值，其余的先放进 replies
+fn reply_all(ctx: &mut Context<'_>, mut replies: Vec<Frame>) -> Frame {
+    let last = replies.pop().unwrap();
+    ctx.client.replies.extend(replies);
+    last
+}
+
+pub fn subscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    subscribe_to(ctx, args, false)
+}
+
+pub fn psubscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    subscribe_to(ctx, args, true)
+}
+
+fn subscribe_to(ctx: &mut Context<'_>, args: &[Bytes], pattern: bool) -> Frame {
+    let id = ctx.client.id;
+    let mut replies = Vec::new();
+    for name in &args[1..] {
+        let added = {
+            let mut pubsub = ctx.shared.pubsub.lock().unwrap();
+            let map = if pattern { &mut pubsub.patterns } else { &mut pubsub.channels };
+            map.entry(name.clone()).or_default().insert(id)
+        };
+        if added {
+            if pattern {
+                ctx.client.patterns.insert(name.clone());
+            } else {
+                ctx.client.channels.insert(name.clone());
+            }
+        }
+        let count = ctx.client.channels.len() + ctx.client.patterns.len();
+        let kind = if pattern { "psubscribe" } else { "subscribe" };
+        replies.push(confirmation(kind, Some(name.clone()), count));
+    }
+    reply_all(ctx, replies)
+}
+
+pub fn unsubscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    unsubscribe_from(ctx, args, false)
+}
+
+pub fn punsubscribe(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    unsubscribe_from(ctx, args, true)
+}
+
+fn unsubscribe_from(ctx: &mut Context<'_>, args: &[Bytes], pattern: bool) -> Frame {
+    let kind = if pattern { "punsubscribe" } else { "unsubscribe" };
+    // 不带参数时退订全部
+    let names: Vec<Bytes> = if args.len() > 1 {
+        args[1..].to_vec()
+    } else if pattern {
+        ctx.client.patterns.iter().cloned().collect()
+    } else {
+        ctx.client.channels.iter().cloned().collect()
+    };
+    if names.is_empty() {
+        let count = ctx.client.channels.len() + ctx.client.patterns.len();
+        return confirmation(kind, None, count);
+    }
+
+    let id = ctx.client.id;
+    let mut replies = Vec::new();
+    for name in names {
+        {
+            let mut pubsub = ctx.shared.pubsub.lock().unwrap();
+            let map = if pattern { &mut pubsub.patterns } else { &mut pubsub.channels };
+            remove(map, &name, id);
+        }
+        if pattern {
+            ctx.client.patterns.remove(&name);
+        } else {
+            ctx.client.channels.remove(&name);
+        }
+        let count = ctx.client.channels.len() + ctx.client.patterns.len();
+        replies.push(confirmation(kind, Some(name), count));
+    }
+    reply_all(ctx, replies)
+}
+
+pub fn publish_command(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let receivers = publish(ctx.shared, &args[1], Frame::Bulk(args[2].clone()));
+    Frame::Integer(receivers as i64)
+}
+
+// PUBSUB CHANNELS [pattern] | NUMSUB [channel ...] | NUMPAT
+pub fn pubsub(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    let pubsub = ctx.shared.pubsub.lock().unwrap();
+    match (&sub[..], args.len()) {
+        (b"channels", 2 | 3) => Frame::Array(
+            pubsub
+                .channels
+                .keys()
+                .filter(|channel| args.get(2).is_none_or(|pattern| glob_match(pattern, channel)))
+                .map(|channel| Frame::Bulk(channel.clone()))
+                .collect(),
+        ),
+        (b"numsub", _) => {
+            let mut out = Vec::new();
+            for channel in &args[2..] {
+                let count = pubsub.channels.get(channel).map_or(0, BTreeSet::len);
+                out.push(Frame::Bulk(channel.clone()));
+                out.push(Frame::Integer(count as i64));
+            }
+            Frame::Array(out)
+        }
+        (b"numpat", 2) => Frame::Integer(pubsub.patterns.len() as i64),
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            String::from_utf8_lossy(&args[1])
+        )),
+    }
+}
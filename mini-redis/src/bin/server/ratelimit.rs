@@ -0,0 +1,169 @@
+use mini_redis::Frame;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::client::Client;
+use crate::Shared;
+
+/// 超过速率限制时怎么处理
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    // 延迟执行，直到攒够令牌
+    Throttle,
+    // 直接回复错误
+    Error,
+}
+
+impl Action {
+    pub fn parse(value: &str) -> Option<Action> {
+        match value.to_ascii_lowercase().as_str() {
+            "throttle" => Some(Action::Throttle),
+            "error" => Some(Action::Error),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Throttle => "throttle",
+            Action::Error => "error",
+        }
+    }
+}
+
+/// 令牌桶，速率和容量在每次取令牌时传入，CONFIG SET 修改后马上生效
+#[derive(Debug, Default)]
+pub struct TokenBucket {
+    tokens: f64,
+    // None 表示还没用过，第一次使用时是满的
+    updated: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn refill(&mut self, rate: f64, burst: f64, now: Instant) {
+        self.tokens = match self.updated {
+            Some(updated) => (self.tokens + now.saturating_duration_since(updated).as_secs_f64() * rate).min(burst),
+            None => burst,
+        };
+        self.updated = Some(now);
+    }
+
+    fn available(&mut self, rate: f64, burst: f64, now: Instant) -> bool {
+        self.refill(rate, burst, now);
+        self.tokens >= 1.0
+    }
+
+    /// 预支一个令牌，返回需要等待多久才轮到这次请求
+    fn reserve(&mut self, rate: f64, burst: f64, now: Instant) -> Duration {
+        self.refill(rate, burst, now);
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / rate)
+        }
+    }
+}
+
+/// 每个连接和每个 ACL 用户每秒能执行的命令数，0 表示不限制
+pub struct RateLimits {
+    pub per_client: u64,
+    pub per_user: u64,
+    // 桶的容量，0 表示和速率相同，也就是最多允许一秒的突发
+    pub burst: u64,
+    pub action: Action,
+    users: HashMap<String, TokenBucket>,
+}
+
+impl RateLimits {
+    pub fn new(per_client: u64, per_user: u64, burst: u64, action: Action) -> RateLimits {
+        RateLimits { per_client, per_user, burst, action, users: HashMap::new() }
+    }
+
+    fn burst(&self, rate: u64) -> f64 {
+        if self.burst > 0 {
+            self.burst as f64
+        } else {
+            rate as f64
+        }
+    }
+}
+
+/// 执行命令前调用：返回需要等待的时间，或者超限时的错误回复
+pub fn acquire(shared: &Shared, client: &mut Client) -> Result<Duration, Frame> {
+    let mut limits = shared.limits.lock().unwrap();
+    let limits = &mut *limits;
+    if limits.per_client == 0 && limits.per_user == 0 {
+        return Ok(Duration::ZERO);
+    }
+    let now = Instant::now();
+    let (client_rate, user_rate) = (limits.per_client, limits.per_user);
+    let (client_burst, user_burst) = (limits.burst(client_rate), limits.burst(user_rate));
+    let mut buckets = Vec::with_capacity(2);
+    if client_rate > 0 {
+        buckets.push((&mut client.bucket, client_rate as f64, client_burst));
+    }
+    if user_rate > 0 {
+        let user = limits.users.entry(client.user.clone()).or_default();
+        buckets.push((user, user_rate as f64, user_burst));
+    }
+
+    match limits.action {
+        Action::Throttle => Ok(buckets
+            .into_iter()
+            .map(|(bucket, rate, burst)| bucket.reserve(rate, burst, now))
+            .max()
+            .unwrap_or_default()),
+        Action::Error => {
+            // 两个桶都有令牌时才扣，避免被拒绝的请求也消耗额度
+            if !buckets.iter_mut().all(|(bucket, rate, burst)| bucket.available(*rate, *burst, now)) {
+                return Err(Frame::error("ERR max request rate exceeded, try again later"));
+            }
+            for (bucket, ..) in buckets {
+                bucket.tokens -= 1.0;
+            }
+            Ok(Duration::ZERO)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_starts_full_and_refills() {
+        let mut bucket = TokenBucket::default();
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(bucket.available(10.0, 3.0, start));
+            bucket.tokens -= 1.0;
+        }
+        assert!(!bucket.available(10.0, 3.0, start));
+        // 10 个每秒，100ms 补一个
+        assert!(bucket.available(10.0, 3.0, start + Duration::from_millis(100)));
+        // 不会超过容量
+        bucket.refill(10.0, 3.0, start + Duration::from_secs(60));
+        assert_eq!(bucket.tokens, 3.0);
+    }
+
+    #[test]
+    fn reserve_returns_the_wait() {
+        let mut bucket = TokenBucket::default();
+        let now = Instant::now();
+        assert_eq!(bucket.reserve(2.0, 2.0, now), Duration::ZERO);
+        assert_eq!(bucket.reserve(2.0, 2.0, now), Duration::ZERO);
+        // 令牌用完后每个请求多等半秒
+        assert_eq!(bucket.reserve(2.0, 2.0, now), Duration::from_millis(500));
+        assert_eq!(bucket.reserve(2.0, 2.0, now), Duration::from_secs(1));
+        assert_eq!(bucket.reserve(2.0, 2.0, now + Duration::from_secs(1)), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn burst_defaults_to_rate() {
+        assert_eq!(RateLimits::new(10, 0, 0, Action::Error).burst(10), 10.0);
+        assert_eq!(RateLimits::new(10, 0, 50, Action::Error).burst(10), 50.0);
+        assert_eq!(Action::parse("THROTTLE"), Some(Action::Throttle));
+        assert_eq!(Action::parse("drop"), None);
+    }
+}
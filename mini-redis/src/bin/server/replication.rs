@@ -0,0 +1,546 @@
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use rand::Rng;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::{info, info_span, warn, Instrument};
+
+use crate::command::{self, syntax_error, CommandSpec, Context, WRITE};
+use crate::keyspace::{deadline, parse_i64, unix_millis};
+use crate::{dump, Shared};
+use mini_redis::{client, Frame};
+
+// 副本每隔这么久向主节点报告一次复制偏移量
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+// 和主节点的连接断开后隔多久重连
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 副本和主节点断开、并且 replica-serve-stale-data 为 no 时仍然可以执行的命令
+const STALE_COMMANDS: &[&str] = &[
+    "acl", "auth", "client", "command", "config", "hello", "info", "ping", "psubscribe", "publish", "punsubscribe",
+    "replconf", "replicaof", "role", "slaveof", "subscribe", "unsubscribe",
+];
+
+pub enum Role {
+    Primary,
+    Replica { host: String, port: u16 },
+}
+
+/// 连接到本节点的副本
+struct Link {
+    ip: String,
+    // REPLCONF listening-port 报告的端口
+    port: u16,
+    // 副本确认已经应用的偏移量
+    ack: u64,
+    acked_at: Instant,
+}
+
+/// 主从复制的状态。主节点把执行成功的写命令原样转发给所有副本，offset 是转发过的字节数；
+/// 副本的 offset 是已经从主节点收到并应用的字节数，两边一致说明副本已经追上
+pub struct Replication {
+    pub role: Role,
+    pub replid: String,
+    pub offset: u64,
+    // 按连接 id 记录的副本
+    replicas: BTreeMap<u64, Link>,
+    // 副本和主节点的同步状态：connect / connecting / sync / connected
+    state: &'static str,
+    // 每次 REPLICAOF 加一，旧的同步任务发现变化后退出
+    generation: u64,
+    // 以下两项 CONFIG SET 可以随时修改
+    pub read_only: bool,
+    pub serve_stale_data: bool,
+    // 收到副本的 ACK 时唤醒等待中的 WAIT
+    acked: Arc<Notify>,
+}
+
+impl Replication {
+    pub fn new(replicaof: Option<(String, u16)>, read_only: bool, serve_stale_data: bool) -> Replication {
+        Replication {
+            role: match replicaof {
+                Some((host, port)) => Role::Replica { host, port },
+                None => Role::Primary,
+            },
+            replid: random_id(),
+            offset: 0,
+            replicas: BTreeMap::new(),
+            state: "connect",
+            generation: 0,
+            read_only,
+            serve_stale_data,
+            acked: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn is_replica(&self) -> bool {
+        matches!(self.role, Role::Replica { .. })
+    }
+
+    pub fn link_up(&self) -> bool {
+        self.state == "connected"
+    }
+
+    pub fn connected_replicas(&self) -> usize {
+        self.replicas.len()
+    }
+
+    /// 确认的偏移量不小于 offset 的副本数
+    fn acked(&self, offset: u64) -> usize {
+        self.replicas.values().filter(|link| link.ack >= offset).count()
+    }
+
+    pub fn remove_replica(&mut self, id: u64) {
+        if let Some(link) = self.replicas.remove(&id) {
+            info!(ip = %link.ip, port = link.port, "replica disconnected");
+        }
+    }
+}
+
+fn random_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40)
+        .map(|_| std::char::from_digit(rng.gen_range(0..16), 16).unwrap())
+        .collect()
+}
+
+/// 命令按 RESP 数组编码后的字节数，主从两边都用它累加 offset
+fn encoded_len(args: &[Bytes]) -> u64 {
+    let header = |n: usize| 1 + n.to_string().len() + 2;
+    let len = header(args.len()) + args.iter().map(|arg| header(arg.len()) + arg.len() + 2).sum::<usize>();
+    len as u64
+}
+
+/// 把写命令转发给所有副本，副本上什么都不做
+pub fn propagate(shared: &Shared, args: &[Bytes]) {
+    let mut repl = shared.replication.lock().unwrap();
+    if repl.is_replica() {
+        return;
+    }
+    repl.offset += encoded_len(args);
+    if repl.replicas.is_empty() {
+        return;
+    }
+    let frame = Frame::Array(args.iter().cloned().map(Frame::Bulk).collect());
+    for id in repl.replicas.keys() {
+        shared.push(*id, frame.clone());
+    }
+}
+
+/// 被删除的 key（过期、淘汰、迁移走）以 DEL 的形式转发
+pub fn propagate_del(shared: &Shared, key: &Bytes) {
+    propagate(shared, &[Bytes::from_static(b"DEL"), key.clone()]);
+}
+
+/// 只读副本上拒绝写命令，和主节点断开时按 replica-serve-stale-data 决定是否继续提供读
+pub fn check(ctx: &Context<'_>, spec: &CommandSpec) -> Result<(), Frame> {
+    if ctx.client.master {
+        return Ok(());
+    }
+    let repl = ctx.shared.replication.lock().unwrap();
+    if !repl.is_replica() {
+        return Ok(());
+    }
+    if spec.has_flag(WRITE) && repl.read_only {
+        return Err(Frame::error("READONLY You can't write against a read only replica."));
+    }
+    if !repl.link_up() && !repl.serve_stale_data && !STALE_COMMANDS.contains(&spec.name) {
+        return Err(Frame::error(
+            "MASTERDOWN Link with MASTER is down and replica-serve-stale-data is set to 'no'.",
+        ));
+    }
+    Ok(())
+}
+
+/// memcached 等不经过命令表的写入口用它判断能不能写
+pub fn read_only(shared: &Shared) -> bool {
+    let repl = shared.replication.lock().unwrap();
+    repl.is_replica() && repl.read_only
+}
+
+// 全量同步的快照：每个 key 依次是 key、过期的 unix 毫秒时间戳（-1 表示不过期）、DUMP 格式的值，
+// 字符串都带 4 字节长度前缀
+fn snapshot(shared: &Shared) -> Bytes {
+    let mut db = shared.db.lock().unwrap();
+    let keys: Vec<Bytes> = db.keys().cloned().collect();
+    let mut buf = BytesMut::new();
+    for key in keys {
+        let Some(entry) = db.peek(&key) else { continue };
+        let payload = dump::serialize(&entry.value);
+        buf.put_u32(key.len() as u32);
+        buf.put_slice(&key);
+        buf.put_i64(entry.expires_at.map_or(-1, unix_millis));
+        buf.put_u32(payload.len() as u32);
+        buf.put_slice(&payload);
+    }
+    buf.freeze()
+}
+
+/// 清空本地数据并加载主节点发来的快照，返回加载的 key 数
+fn load(shared: &Shared, mut snapshot: Bytes) -> crate::Result<usize> {
+    let keys: Vec<Bytes> = shared.db.lock().unwrap().keys().cloned().collect();
+    for key in keys {
+        let removed = shared.db.lock().unwrap().remove(&key);
+        if removed.is_some() {
+            shared.signal_modified_key(&key, None);
+        }
+    }
+    let thresholds = shared.db.lock().unwrap().thresholds;
+    let mut loaded = 0;
+    while snapshot.has_remaining() {
+        let key = take(&mut snapshot)?;
+        if snapshot.remaining() < 8 {
+            return Err("truncated snapshot".into());
+        }
+        let expire = snapshot.get_i64();
+        let payload = take(&mut snapshot)?;
+        let value = dump::deserialize(&payload, &thresholds).map_err(|_| "invalid value in snapshot")?;
+        let expires_at = match expire {
+            -1 => None,
+            // 传输过程中已经过期的 key 不用加载
+            ms => match deadline(ms, true) {
+                Some(when) => Some(when),
+                None => continue,
+            },
+        };
+        shared.db.lock().unwrap().set(key, value, expires_at);
+        loaded += 1;
+    }
+    Ok(loaded)
+}
+
+fn take(snapshot: &mut Bytes) -> crate::Result<Bytes> {
+    if snapshot.remaining() < 4 {
+        return Err("truncated snapshot".into());
+    }
+    let len = snapshot.get_u32() as usize;
+    if snapshot.remaining() < len {
+        return Err("truncated snapshot".into());
+    }
+    Ok(snapshot.split_to(len))
+}
+
+/// 副本的同步任务：连接主节点、全量同步，然后持续应用主节点转发的写命令。
+/// 连接出错时重连，REPLICAOF 修改了主节点后退出
+pub async fn replicate(shared: Arc<Shared>, generation: u64) {
+    loop {
+        let (host, port) = {
+            let mut repl = shared.replication.lock().unwrap();
+            let (host, port) = match &repl.role {
+                Role::Replica { host, port } if repl.generation == generation => (host.clone(), *port),
+                _ => return,
+            };
+            repl.state = "connecting";
+            (host, port)
+        };
+        let span = info_span!("replication", primary = %format!("{}:{}", host, port));
+        let result = sync(&shared, &host, port, generation).instrument(span).await;
+        {
+            let mut repl = shared.replication.lock().unwrap();
+            if repl.generation != generation {
+                return;
+            }
+            repl.state = "connect";
+        }
+        if let Err(err) = result {
+            warn!(%host, port, %err, "lost connection to primary");
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+}
+
+fn current(shared: &Shared, generation: u64) -> bool {
+    shared.replication.lock().unwrap().generation == generation
+}
+
+async fn sync(shared: &Arc<Shared>, host: &str, port: u16, generation: u64) -> crate::Result<()> {
+    let mut primary = match tokio::time::timeout(CONNECT_TIMEOUT, client::connect((host, port))).await {
+        Ok(primary) => primary?,
+        Err(_) => return Err("timed out connecting to primary".into()),
+    };
+    let config = &shared.config;
+    if let Some(password) = &config.masterauth {
+        let mut auth = vec![Bytes::from_static(b"AUTH")];
+        auth.extend(config.masteruser.clone().map(Bytes::from));
+        auth.push(Bytes::from(password.clone()));
+        if let Frame::Error(msg) = primary.call(&auth).await? {
+            return Err(format!("AUTH failed: {}", msg).into());
+        }
+    }
+    let listening_port = Bytes::from(config.port.to_string());
+    let replconf = [Bytes::from_static(b"REPLCONF"), Bytes::from_static(b"listening-port"), listening_port];
+    if let Frame::Error(msg) = primary.call(&replconf).await? {
+        return Err(format!("REPLCONF failed: {}", msg).into());
+    }
+
+    shared.replication.lock().unwrap().state = "sync";
+    let psync = [Bytes::from_static(b"PSYNC"), Bytes::from_static(b"?"), Bytes::from_static(b"-1")];
+    let (replid, offset) = match primary.call(&psync).await? {
+        Frame::Simple(reply) => {
+            let mut parts = reply.split(' ');
+            match (parts.next(), parts.next(), parts.next().and_then(|off| off.parse::<u64>().ok())) {
+                (Some("FULLRESYNC"), Some(replid), Some(offset)) => (replid.to_string(), offset),
+                _ => return Err(format!("unexpected PSYNC reply '{}'", reply).into()),
+            }
+        }
+        Frame::Error(msg) => return Err(format!("PSYNC failed: {}", msg).into()),
+        frame => return Err(format!("unexpected PSYNC reply {:?}", frame).into()),
+    };
+    let Frame::Bulk(snapshot) = primary.read_reply().await? else {
+        return Err("expected snapshot after FULLRESYNC".into());
+    };
+    {
+        let _guard = shared.script_lock.write().await;
+        if !current(shared, generation) {
+            return Ok(());
+        }
+        let loaded = load(shared, snapshot)?;
+        let mut repl = shared.replication.lock().unwrap();
+        repl.replid = replid;
+        repl.offset = offset;
+        repl.state = "connected";
+        info!(keys = loaded, offset, "full sync with primary done");
+    }
+
+    // 执行主节点转发的命令用的伪连接，跳过认证、ACL 和只读检查
+    let mut master = shared.new_client(format!("{}:{}", host, port), String::new(), "master");
+    master.authenticated = true;
+    master.master = true;
+    let mut ack = tokio::time::interval(ACK_INTERVAL);
+    loop {
+        tokio::select! {
+            frame = primary.read_reply() => {
+                let args = command::parse_args(frame?)?;
+                if !current(shared, generation) {
+                    return Ok(());
+                }
+                let len = encoded_len(&args);
+                let getack = args.len() >= 2
+                    && args[0].eq_ignore_ascii_case(b"replconf")
+                    && args[1].eq_ignore_ascii_case(b"getack");
+                if !getack {
+                    let mut ctx = Context { shared, client: &mut master };
+                    if let Frame::Error(msg) = command::execute(&mut ctx, &args).await {
+                        warn!(%msg, "command from primary failed");
+                    }
+                }
+                let offset = {
+                    let mut repl = shared.replication.lock().unwrap();
+                    repl.offset += len;
+                    repl.offset
+                };
+                if getack {
+                    send_ack(&mut primary, offset).await?;
+                }
+            }
+            _ = ack.tick() => {
+                if !current(shared, generation) {
+                    return Ok(());
+                }
+                let offset = shared.replication.lock().unwrap().offset;
+                send_ack(&mut primary, offset).await?;
+            }
+        }
+    }
+}
+
+async fn send_ack(primary: &mut client::Client, offset: u64) -> crate::Result<()> {
+    let ack = [Bytes::from_static(b"REPLCONF"), Bytes::from_static(b"ACK"), Bytes::from(offset.to_string())];
+    primary.send(&ack).await?;
+    primary.flush().await
+}
+
+// PSYNC replid offset，不支持部分同步，总是回复 FULLRESYNC 并发送快照
+pub fn psync(ctx: &mut Context<'_>, _args: &[Bytes]) -> Frame {
+    let mut repl = ctx.shared.replication.lock().unwrap();
+    if repl.is_replica() {
+        return Frame::error("ERR Chained replication is not supported, connect to the primary instead");
+    }
+    // 快照和之后转发的命令都走推送通道，排在 FULLRESYNC 回复之后发出
+    let _ = ctx.client.push.send(Frame::Bulk(snapshot(ctx.shared)));
+    let ip = ctx.client.addr.rsplit_once(':').map_or(ctx.client.addr.as_str(), |(ip, _)| ip).to_string();
+    let link = Link { ip, port: ctx.client.replica_port, ack: 0, acked_at: Instant::now() };
+    info!(ip = %link.ip, port = link.port, offset = repl.offset, "replica starting full sync");
+    repl.replicas.insert(ctx.client.id, link);
+    ctx.client.replica = true;
+    Frame::Simple(format!("FULLRESYNC {} {}", repl.replid, repl.offset))
+}
+
+// REPLCONF listening-port port | capa ... | ACK offset | GETACK *
+pub fn replconf(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    match &sub[..] {
+        b"listening-port" if args.len() == 3 => {
+            match std::str::from_utf8(&args[2]).ok().and_then(|port| port.parse().ok()) {
+                Some(port) => {
+                    ctx.client.replica_port = port;
+                    Frame::ok()
+                }
+                None => Frame::error("ERR value is not an integer or out of range"),
+            }
+        }
+        b"capa" => Frame::ok(),
+        // ACK 不需要回复
+        b"ack" if args.len() == 3 => {
+            ctx.client.skip_reply = true;
+            let Ok(offset) = parse_i64(&args[2]) else { return Frame::ok() };
+            let mut repl = ctx.shared.replication.lock().unwrap();
+            if let Some(link) = repl.replicas.get_mut(&ctx.client.id) {
+                link.ack = link.ack.max(offset.max(0) as u64);
+                link.acked_at = Instant::now();
+                repl.acked.notify_waiters();
+            }
+            Frame::ok()
+        }
+        _ => syntax_error(),
+    }
+}
+
+// REPLICAOF host port | NO ONE，SLAVEOF 是旧名字
+pub fn replicaof(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    if ctx.shared.cluster.is_some() {
+        return Frame::error("ERR REPLICAOF not allowed in cluster mode.");
+    }
+    let mut repl = ctx.shared.replication.lock().unwrap();
+    if args[1].eq_ignore_ascii_case(b"no") && args[2].eq_ignore_ascii_case(b"one") {
+        if repl.is_replica() {
+            // 换一个复制 ID，以后连上来的副本不会把它当成原来的主节点
+            repl.role = Role::Primary;
+            repl.replid = random_id();
+            repl.generation += 1;
+            info!(offset = repl.offset, "promoted to primary");
+        }
+        return Frame::ok();
+    }
+    let host = String::from_utf8_lossy(&args[1]).into_owned();
+    let Some(port) = std::str::from_utf8(&args[2]).ok().and_then(|port| port.parse::<u16>().ok()) else {
+        return Frame::error("ERR Invalid master port");
+    };
+    if let Role::Replica { host: current, port: current_port } = &repl.role {
+        if *current == host && *current_port == port {
+            return Frame::Simple("OK Already connected to specified master".to_string());
+        }
+    }
+    // 已经连上来的副本收不到新的写命令，需要各自改为复制新的主节点
+    repl.replicas.clear();
+    info!(%host, port, "replicating from new primary");
+    repl.role = Role::Replica { host, port };
+    repl.state = "connect";
+    repl.generation += 1;
+    tokio::spawn(replicate(ctx.shared.clone(), repl.generation));
+    Frame::ok()
+}
+
+// ROLE
+pub fn role(ctx: &mut Context<'_>, _args: &[Bytes]) -> Frame {
+    let repl = ctx.shared.replication.lock().unwrap();
+    match &repl.role {
+        Role::Primary => Frame::Array(vec![
+            Frame::bulk("master"),
+            Frame::Integer(repl.offset as i64),
+            Frame::Array(
+                repl.replicas
+                    .values()
+                    .map(|link| {
+                        Frame::Array(vec![
+                            Frame::bulk(link.ip.clone()),
+                            Frame::bulk(link.port.to_string()),
+                            Frame::bulk(link.ack.to_string()),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ]),
+        Role::Replica { host, port } => Frame::Array(vec![
+            Frame::bulk("slave"),
+            Frame::bulk(host.clone()),
+            Frame::Integer(*port as i64),
+            Frame::bulk(repl.state),
+            Frame::Integer(repl.offset as i64),
+        ]),
+    }
+}
+
+// WAIT numreplicas timeout，等到至少 numreplicas 个副本确认收到了之前的所有写命令，或者超时（0 表示一直等）
+pub async fn wait(shared: Arc<Shared>, args: Vec<Bytes>) -> Frame {
+    let numreplicas = match parse_i64(&args[1]) {
+        Ok(n) => n.max(0) as usize,
+        Err(err) => return err,
+    };
+    let timeout = match parse_i64(&args[2]) {
+        Ok(ms) if ms >= 0 => ms as u64,
+        Ok(_) => return Frame::error("ERR timeout is negative"),
+        Err(err) => return err,
+    };
+    let (target, acked) = {
+        let repl = shared.replication.lock().unwrap();
+        if repl.is_replica() {
+            return Frame::error("ERR WAIT cannot be used with replica instances.");
+        }
+        (repl.offset, repl.acked.clone())
+    };
+    let deadline = (timeout > 0).then(|| tokio::time::Instant::now() + Duration::from_millis(timeout));
+    let mut asked = false;
+    loop {
+        // 先登记再检查，避免错过检查之后、等待之前到达的 ACK
+        let notified = acked.notified();
+        tokio::pin!(notified);
+        notified.as_mut().enable();
+        let count = shared.replication.lock().unwrap().acked(target);
+        if count >= numreplicas {
+            return Frame::Integer(count as i64);
+        }
+        // 让副本马上报告偏移量，不用等下一次定时 ACK
+        if !asked {
+            propagate(&shared, &[Bytes::from_static(b"REPLCONF"), Bytes::from_static(b"GETACK"), Bytes::from_static(b"*")]);
+            asked = true;
+        }
+        match deadline {
+            Some(deadline) => {
+                if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                    return Frame::Integer(shared.replication.lock().unwrap().acked(target) as i64);
+                }
+            }
+            None => notified.await,
+        }
+    }
+}
+
+/// INFO replication 的内容
+pub fn info(shared: &Shared) -> String {
+    let repl = shared.replication.lock().unwrap();
+    let mut out = String::new();
+    match &repl.role {
+        Role::Primary => {
+            let _ = writeln!(out, "role:master\r");
+            let _ = writeln!(out, "connected_slaves:{}\r", repl.replicas.len());
+            for (i, link) in repl.replicas.values().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "slave{}:ip={},port={},state=online,offset={},lag={}\r",
+                    i,
+                    link.ip,
+                    link.port,
+                    link.ack,
+                    link.acked_at.elapsed().as_secs()
+                );
+            }
+        }
+        Role::Replica { host, port } => {
+            let _ = writeln!(out, "role:slave\r");
+            let _ = writeln!(out, "master_host:{}\r", host);
+            let _ = writeln!(out, "master_port:{}\r", port);
+            let _ = writeln!(out, "master_link_status:{}\r", if repl.link_up() { "up" } else { "down" });
+            let _ = writeln!(out, "master_sync_in_progress:{}\r", (repl.state == "sync") as u8);
+            let _ = writeln!(out, "slave_repl_offset:{}\r", repl.offset);
+            let _ = writeln!(out, "slave_read_only:{}\r", repl.read_only as u8);
+            let _ = writeln!(out, "replica_serve_stale_data:{}\r", repl.serve_stale_data as u8);
+        }
+    }
+    let _ = writeln!(out, "master_replid:{}\r", repl.replid);
+    let _ = writeln!(out, "master_repl_offset:{}\r", repl.offset);
+    out
+}
@@ -0,0 +1,260 @@
+use bytes::Bytes;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use crate::command::{self, CommandSpec, Context, Handler, ALLOW_BUSY, BLOCKING, NOSCRIPT, READONLY, WRITE};
+use crate::interp::{self, Error};
+use crate::keyspace::parse_i64;
+use crate::{cluster, replication, tracking, Shared};
+use mini_redis::Frame;
+
+// 脚本执行期间，其它连接每隔这么久检查一次是否已经超过 busy-reply-threshold
+const BUSY_POLL: Duration = Duration::from_millis(100);
+
+/// 脚本缓存和正在执行的脚本
+pub struct Scripts {
+    cache: HashMap<String, Arc<interp::Script>>,
+    running: Option<Running>,
+    // 脚本执行超过这个时间后，其它连接的命令回复 BUSY，并且可以用 SCRIPT KILL 中止
+    pub busy_threshold: Duration,
+}
+
+struct Running {
+    started: Instant,
+    kill: Arc<AtomicBool>,
+    wrote: Arc<AtomicBool>,
+}
+
+impl Scripts {
+    pub fn new(busy_threshold: Duration) -> Scripts {
+        Scripts { cache: HashMap::new(), running: None, busy_threshold }
+    }
+
+    fn busy(&self) -> bool {
+        self.running
+            .as_ref()
+            .is_some_and(|running| running.started.elapsed() >= self.busy_threshold)
+    }
+}
+
+pub fn sha1hex(data: &[u8]) -> String {
+    format!("{:x}", Sha1::digest(data))
+}
+
+/// 持有期间其它连接不会和脚本交错执行
+pub enum Guard<'a> {
+    Shared { _guard: RwLockReadGuard<'a, ()> },
+    Exclusive { _guard: RwLockWriteGuard<'a, ()> },
+}
+
+/// 普通命令共享执行锁，EVAL、MIGRATE 和 PSYNC 独占；脚本执行得太久时不再等待，直接回复 BUSY
+pub async fn lock<'a>(shared: &'a Shared, spec: &CommandSpec) -> Result<Option<Guard<'a>>, Frame> {
+    if spec.has_flag(ALLOW_BUSY) {
+        return Ok(None);
+    }
+    // MIGRATE 在传输期间阻塞其它命令，key 要么在本地要么在目标节点上；
+    // PSYNC 生成快照时不能有执行了一半、还没转发给副本的写命令
+    let exclusive = matches!(spec.name, "eval" | "evalsha" | "migrate" | "psync");
+    loop {
+        if shared.scripts.lock().unwrap().busy() {
+            return Err(Frame::error(
+                "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.",
+            ));
+        }
+        // WAIT 这类阻塞命令只是在等待，持有锁会挡住 EVAL 和 PSYNC
+        if spec.has_flag(BLOCKING) {
+            return Ok(None);
+        }
+        let acquire = async {
+            if exclusive {
+                Guard::Exclusive { _guard: shared.script_lock.write().await }
+            } else {
+                Guard::Shared { _guard: shared.script_lock.read().await }
+            }
+        };
+        if let Ok(guard) = tokio::time::timeout(BUSY_POLL, acquire).await {
+            return Ok(Some(guard));
+        }
+    }
+}
+
+// EVAL script numkeys [key ...] [arg ...]
+pub fn eval(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sha = sha1hex(&args[1]);
+    let cached = ctx.shared.scripts.lock().unwrap().cache.get(&sha).cloned();
+    let script = match cached {
+        Some(script) => script,
+        None => match load(ctx.shared, &sha, &args[1]) {
+            Ok(script) => script,
+            Err(err) => return err,
+        },
+    };
+    run(ctx, &sha, &script, &args[2..])
+}
+
+// EVALSHA sha1 numkeys [key ...] [arg ...]
+pub fn evalsha(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sha = String::from_utf8_lossy(&args[1]).to_ascii_lowercase();
+    let cached = ctx.shared.scripts.lock().unwrap().cache.get(&sha).cloned();
+    match cached {
+        Some(script) => run(ctx, &sha, &script, &args[2..]),
+        None => Frame::error("NOSCRIPT No matching script. Please use EVAL."),
+    }
+}
+
+fn load(shared: &Shared, sha: &str, source: &[u8]) -> Result<Arc<interp::Script>, Frame> {
+    let script = match interp::compile(source) {
+        Ok(script) => Arc::new(script),
+        Err(message) => return Err(Frame::error(format!("ERR Error compiling script (new function): {}", message))),
+    };
+    shared.scripts.lock().unwrap().cache.insert(sha.to_string(), script.clone());
+    Ok(script)
+}
+
+fn run(ctx: &mut Context<'_>, sha: &str, script: &interp::Script, args: &[Bytes]) -> Frame {
+    let numkeys = match parse_i64(&args[0]) {
+        Ok(n) if n < 0 => return Frame::error("ERR Number of keys can't be negative"),
+        Ok(n) if n as usize > args.len() - 1 => {
+            return Frame::error("ERR Number of keys can't be greater than number of args")
+        }
+        Ok(n) => n as usize,
+        Err(err) => return err,
+    };
+    let (keys, argv) = args[1..].split_at(numkeys);
+
+    let kill = Arc::new(AtomicBool::new(false));
+    let wrote = Arc::new(AtomicBool::new(false));
+    ctx.shared.scripts.lock().unwrap().running = Some(Running {
+        started: Instant::now(),
+        kill: kill.clone(),
+        wrote: wrote.clone(),
+    });
+    let mut host = ScriptHost { ctx, kill, wrote };
+    // 脚本同步执行，让出当前工作线程上的其它任务
+    let result = tokio::task::block_in_place(|| script.run(&mut host, keys, argv));
+    host.ctx.shared.scripts.lock().unwrap().running = None;
+
+    match result {
+        Ok(reply) => reply,
+        Err(Error::Runtime { line, message }) => Frame::error(format!(
+            "ERR user_script:{}: {} script: {}, on @user_script:{}.",
+            line, message, sha, line
+        )),
+        Err(Error::Reply { line, message }) => {
+            Frame::error(format!("{} script: {}, on @user_script:{}.", message, sha, line))
+        }
+        Err(Error::Killed) => Frame::error("ERR Script killed by user with SCRIPT KILL..."),
+    }
+}
+
+struct ScriptHost<'a, 'b> {
+    ctx: &'a mut Context<'b>,
+    kill: Arc<AtomicBool>,
+    wrote: Arc<AtomicBool>,
+}
+
+impl interp::Host for ScriptHost<'_, '_> {
+    // 和 command::execute 做同样的检查，只是不经过执行锁（EVAL 已经持有了）
+    fn call(&mut self, args: Vec<Bytes>) -> Frame {
+        let ctx = &mut *self.ctx;
+        let spec = match command::lookup(&args[0]) {
+            Some(spec) => spec,
+            None => return Frame::error("ERR Unknown Redis command called from script"),
+        };
+        if !spec.check_arity(args.len()) {
+            return Frame::error("ERR Wrong number of args calling Redis command from script");
+        }
+        let handler = match spec.handler {
+            Handler::Sync(handler) if !spec.has_flag(NOSCRIPT) => handler,
+            _ => return Frame::error("ERR This Redis command is not allowed from script"),
+        };
+        if let Err(denied) = command::check_permission(ctx, spec, &args) {
+            return denied;
+        }
+        if let Err(redirect) = cluster::route(ctx.shared, spec, &args, false) {
+            return redirect;
+        }
+        if let Err(err) = replication::check(ctx, spec) {
+            return err;
+        }
+        if spec.has_flag(WRITE) {
+            if !ctx.shared.evict() {
+                return Frame::error("OOM command not allowed when used memory > 'maxmemory'.");
+            }
+            self.wrote.store(true, Ordering::Relaxed);
+        }
+        let response = handler(ctx, &args);
+        // 脚本里的写命令逐条转发，副本不需要执行脚本
+        if spec.has_flag(WRITE) && !matches!(response, Frame::Error(_)) {
+            replication::propagate(ctx.shared, &args);
+        }
+        ctx.shared.flush_expired();
+        if spec.has_flag(READONLY) && ctx.client.tracking.is_some() && !matches!(response, Frame::Error(_)) {
+            let caching = ctx.client.caching;
+            tracking::remember(ctx, spec, &args, caching);
+        }
+        response
+    }
+
+    fn interrupted(&mut self) -> bool {
+        self.kill.load(Ordering::Relaxed)
+    }
+}
+
+// SCRIPT LOAD script | EXISTS sha1 [sha1 ...] | FLUSH [ASYNC|SYNC] | KILL
+pub fn script(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let sub = args[1].to_ascii_lowercase();
+    match &sub[..] {
+        b"load" if args.len() == 3 => {
+            let sha = sha1hex(&args[2]);
+            match load(ctx.shared, &sha, &args[2]) {
+                Ok(_) => Frame::bulk(sha),
+                Err(err) => err,
+            }
+        }
+        b"exists" if args.len() > 2 => {
+            let scripts = ctx.shared.scripts.lock().unwrap();
+            Frame::Array(
+                args[2..]
+                    .iter()
+                    .map(|sha| {
+                        let sha = String::from_utf8_lossy(sha).to_ascii_lowercase();
+                        Frame::Integer(scripts.cache.contains_key(&sha) as i64)
+                    })
+                    .collect(),
+            )
+        }
+        b"flush" if args.len() <= 3 => {
+            if let Some(mode) = args.get(2) {
+                if !mode.eq_ignore_ascii_case(b"async") && !mode.eq_ignore_ascii_case(b"sync") {
+                    return command::syntax_error();
+                }
+            }
+            ctx.shared.scripts.lock().unwrap().cache.clear();
+            Frame::ok()
+        }
+        b"kill" if args.len() == 2 => {
+            let scripts = ctx.shared.scripts.lock().unwrap();
+            match &scripts.running {
+                None => Frame::error("NOTBUSY No scripts in execution right now."),
+                // 已经写过数据的脚本中途停下会破坏原子性
+                Some(running) if running.wrote.load(Ordering::Relaxed) => Frame::error(
+                    "UNKILLABLE Sorry the script already executed write commands against the dataset. \
+                     You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.",
+                ),
+                Some(running) => {
+                    running.kill.store(true, Ordering::Relaxed);
+                    Frame::ok()
+                }
+            }
+        }
+        _ => Frame::error(format!(
+            "ERR unknown subcommand or wrong number of arguments for '{}'",
+            String::from_utf8_lossy(&args[1])
+        )),
+    }
+}
@@ -0,0 +1,417 @@
+use bytes::Bytes;
+use std::collections::HashSet;
+
+use crate::command::Context;
+use crate::db::{Collection, Thresholds, Value};
+use crate::keyspace;
+use crate::listpack::Listpack;
+use crate::notify;
+use mini_redis::Frame;
+
+// 估算内存时每个成员额外计入的固定开销（哈希表槽位和 Bytes）
+const MEMBER_OVERHEAD: usize = 40;
+
+/// 集合：成员都是整数时用有序的 intset 编码，有其它成员但集合很小时用 listpack 编码，
+/// 都放不下时转成哈希表编码。和有序集合一样，转换只会朝更大的编码进行
+#[derive(Debug, Clone)]
+pub struct Set {
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone)]
+enum Encoding {
+    Intset(Intset),
+    Listpack(Listpack),
+    Hashtable(Hashtable),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Hashtable {
+    members: HashSet<Bytes>,
+    size: usize,
+}
+
+impl Default for Set {
+    fn default() -> Set {
+        Set { encoding: Encoding::Intset(Intset::default()) }
+    }
+}
+
+impl Set {
+    pub fn new() -> Set {
+        Set::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Intset(intset) => intset.len(),
+            Encoding::Listpack(listpack) => listpack.len(),
+            Encoding::Hashtable(table) => table.members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 估算占用的内存，增删成员时维护
+    pub fn size(&self) -> usize {
+        match &self.encoding {
+            Encoding::Intset(intset) => intset.buf.len(),
+            Encoding::Listpack(listpack) => listpack.bytes(),
+            Encoding::Hashtable(table) => table.size,
+        }
+    }
+
+    /// OBJECT ENCODING 返回的内部编码
+    pub fn encoding(&self) -> &'static str {
+        match &self.encoding {
+            Encoding::Intset(_) => "intset",
+            Encoding::Listpack(_) => "listpack",
+            Encoding::Hashtable(_) => "hashtable",
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.encoding {
+            Encoding::Intset(intset) => integer(member).is_some_and(|n| intset.search(n).is_ok()),
+            Encoding::Listpack(listpack) => listpack.find(member).is_some(),
+            Encoding::Hashtable(table) => table.members.contains(member),
+        }
+    }
+
+    /// 加入成员，返回是否是新成员。成员都是整数时不超过 set-max-intset-entries 个就用 intset 编码，
+    /// 否则按 set-max-listpack-entries 和 set-max-listpack-value 决定是否用 listpack 编码
+    pub fn insert(&mut self, member: Bytes, thresholds: &Thresholds) -> bool {
+        if self.contains(&member) {
+            return false;
+        }
+        let len = self.len();
+        let fits_listpack =
+            |longest: usize| len < thresholds.set_max_listpack_entries && longest <= thresholds.set_max_listpack_value;
+        match &mut self.encoding {
+            Encoding::Intset(intset) => match integer(&member) {
+                Some(n) if len < thresholds.set_max_intset_entries => {
+                    intset.insert(n);
+                    return true;
+                }
+                // 整数最长 20 个字符，都按这个长度检查
+                _ if fits_listpack(member.len().max(20)) => self.convert(false, thresholds),
+                _ => self.convert(true, thresholds),
+            },
+            Encoding::Listpack(_) if !fits_listpack(member.len()) => self.convert(true, thresholds),
+            _ => {}
+        }
+        match &mut self.encoding {
+            Encoding::Intset(_) => unreachable!(),
+            Encoding::Listpack(listpack) => listpack.push_back(&member),
+            Encoding::Hashtable(table) => {
+                table.size += member.len() + MEMBER_OVERHEAD;
+                table.members.insert(member);
+            }
+        }
+        true
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.encoding {
+            Encoding::Intset(intset) => integer(member).is_some_and(|n| intset.remove(n)),
+            Encoding::Listpack(listpack) => match listpack.find(member) {
+                Some(i) => {
+                    listpack.splice(i..i + 1, &[]);
+                    true
+                }
+                None => false,
+            },
+            Encoding::Hashtable(table) => match table.members.take(member) {
+                Some(member) => {
+                    table.size -= member.len() + MEMBER_OVERHEAD;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// 按编码内部的顺序遍历，intset 编码时从小到大
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match &self.encoding {
+            Encoding::Intset(intset) => Box::new(intset.iter().map(|n| Bytes::from(n.to_string()))),
+            Encoding::Listpack(listpack) => Box::new(listpack.iter().map(Bytes::copy_from_slice)),
+            Encoding::Hashtable(table) => Box::new(table.members.iter().cloned()),
+        }
+    }
+
+    /// 转成 listpack 编码，或者 hashtable 为真时转成哈希表编码
+    fn convert(&mut self, hashtable: bool, thresholds: &Thresholds) {
+        let members: Vec<_> = self.iter().collect();
+        self.encoding = if hashtable {
+            Encoding::Hashtable(Hashtable::default())
+        } else {
+            Encoding::Listpack(Listpack::default())
+        };
+        for member in members {
+            self.insert(member, thresholds);
+        }
+    }
+}
+
+impl Collection for Set {
+    fn cast(value: &Value) -> Option<&Set> {
+        match value {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn cast_mut(value: &mut Value) -> Option<&mut Set> {
+        match value {
+            Value::Set(set) => Some(set),
+            _ => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        Set::is_empty(self)
+    }
+}
+
+impl From<Set> for Value {
+    fn from(set: Set) -> Value {
+        Value::Set(set)
+    }
+}
+
+/// 能放进 intset 的整数：十进制表示必须和成员完全一样，"+1"、"01" 这样的还是字符串
+fn integer(member: &[u8]) -> Option<i64> {
+    let n: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (n.to_string().as_bytes() == member).then_some(n)
+}
+
+/// 从小到大排好序的整数，所有整数用同样的宽度（2、4 或 8 字节）小端存放，
+/// 宽度由最大的那个决定，加入放不下的整数时整体升级，删除时不降级
+#[derive(Debug, Clone)]
+struct Intset {
+    width: usize,
+    buf: Vec<u8>,
+}
+
+impl Default for Intset {
+    fn default() -> Intset {
+        Intset { width: 2, buf: Vec::new() }
+    }
+}
+
+impl Intset {
+    fn len(&self) -> usize {
+        self.buf.len() / self.width
+    }
+
+    fn at(&self, i: usize) -> i64 {
+        let bytes = &self.buf[i * self.width..(i + 1) * self.width];
+        match self.width {
+            2 => i16::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            4 => i32::from_le_bytes(bytes.try_into().unwrap()) as i64,
+            _ => i64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = i64> + '_ {
+        (0..self.len()).map(|i| self.at(i))
+    }
+
+    /// 二分查找，和 slice::binary_search 一样找不到时返回应该插入的位置
+    fn search(&self, n: i64) -> Result<usize, usize> {
+        let (mut low, mut high) = (0, self.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            match self.at(mid).cmp(&n) {
+                std::cmp::Ordering::Equal => return Ok(mid),
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+            }
+        }
+        Err(low)
+    }
+
+    fn insert(&mut self, n: i64) {
+        let width = if i16::try_from(n).is_ok() {
+            2
+        } else if i32::try_from(n).is_ok() {
+            4
+        } else {
+            8
+        };
+        if width > self.width {
+            let values: Vec<_> = self.iter().collect();
+            self.width = width;
+            self.buf = values.into_iter().flat_map(|value| encode(value, width)).collect();
+        }
+        if let Err(i) = self.search(n) {
+            self.buf.splice(i * self.width..i * self.width, encode(n, self.width));
+        }
+    }
+
+    fn remove(&mut self, n: i64) -> bool {
+        match self.search(n) {
+            Ok(i) => {
+                self.buf.drain(i * self.width..(i + 1) * self.width);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+fn encode(n: i64, width: usize) -> Vec<u8> {
+    n.to_le_bytes()[..width].to_vec()
+}
+
+// SADD key member [member ...]
+pub fn sadd(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let result = keyspace::update(ctx, &args[1], true, notify::SET, "sadd", |set: &mut Set, thresholds| {
+        let added = args[2..].iter().filter(|member| set.insert((*member).clone(), thresholds)).count();
+        (added, added > 0)
+    });
+    match result {
+        Ok(added) => Frame::Integer(added.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+// SREM key member [member ...]
+pub fn srem(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let result = keyspace::update(ctx, &args[1], false, notify::SET, "srem", |set: &mut Set, _| {
+        let removed = args[2..].iter().filter(|member| set.remove(member)).count();
+        (removed, removed > 0)
+    });
+    match result {
+        Ok(removed) => Frame::Integer(removed.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn scard(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |set: &Set| set.len()) {
+        Ok(len) => Frame::Integer(len.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn sismember(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |set: &Set| set.contains(&args[2])) {
+        Ok(found) => Frame::Integer(found.unwrap_or(false) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn smismember(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let members = &args[2..];
+    match keyspace::read(ctx, &args[1], |set: &Set| members.iter().map(|member| set.contains(member)).collect()) {
+        Ok(found) => Frame::Array(
+            found.unwrap_or_else(|| vec![false; members.len()]).into_iter().map(|found| Frame::Integer(found as i64)).collect(),
+        ),
+        Err(err) => err,
+    }
+}
+
+pub fn smembers(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |set: &Set| set.iter().map(Frame::Bulk).collect()) {
+        Ok(members) => Frame::Set(members.unwrap_or_default()),
+        Err(err) => err,
+    }
+}
+
+// SINTER、SUNION 和 SDIFF key [key ...]，不存在的 key 当作空集合
+pub fn combine(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let mut sets = Vec::with_capacity(args.len() - 1);
+    for key in &args[1..] {
+        match keyspace::read(ctx, key, |set: &Set| set.clone()) {
+            Ok(set) => sets.push(set.unwrap_or_default()),
+            Err(err) => return err,
+        }
+    }
+    let (first, rest) = sets.split_first().unwrap();
+    let members: Vec<Bytes> = match &args[0].to_ascii_lowercase()[..] {
+        b"sinter" => first.iter().filter(|member| rest.iter().all(|set| set.contains(member))).collect(),
+        b"sdiff" => first.iter().filter(|member| !rest.iter().any(|set| set.contains(member))).collect(),
+        _ => {
+            // 临时的集合只用来去重，用哪种编码都可以
+            let mut union = Set::new();
+            for member in sets.iter().flat_map(Set::iter) {
+                union.insert(member, &Thresholds::default());
+            }
+            union.iter().collect()
+        }
+    };
+    Frame::Set(members.into_iter().map(Frame::Bulk).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_of<T: AsRef<[u8]>>(members: impl IntoIterator<Item = T>) -> Set {
+        let mut set = Set::new();
+        for member in members {
+            set.insert(Bytes::copy_from_slice(member.as_ref()), &Thresholds::default());
+        }
+        set
+    }
+
+    #[test]
+    fn intset_widens_and_stays_sorted() {
+        let mut set = set_of(["5", "-3", "100"]);
+        assert_eq!(set.encoding(), "intset");
+        assert_eq!(set.size(), 6);
+        set.insert(Bytes::from("70000"), &Thresholds::default());
+        set.insert(Bytes::from(i64::MIN.to_string()), &Thresholds::default());
+        assert_eq!(set.encoding(), "intset");
+        assert_eq!(set.size(), 5 * 8);
+        let members: Vec<_> = set.iter().collect();
+        assert_eq!(members, [i64::MIN.to_string(), "-3".into(), "5".into(), "100".into(), "70000".into()]);
+        assert!(set.contains(b"70000"));
+        assert!(set.remove(b"-3"));
+        assert!(!set.contains(b"-3"));
+        assert!(!set.insert(Bytes::from("5"), &Thresholds::default()));
+    }
+
+    #[test]
+    fn non_canonical_integers_are_strings() {
+        let set = set_of(["1", "+1"]);
+        assert_eq!(set.encoding(), "listpack");
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(b"1") && set.contains(b"+1") && !set.contains(b"01"));
+    }
+
+    #[test]
+    fn converts_past_thresholds() {
+        let mut set = set_of((0..512).map(|i| i.to_string()));
+        assert_eq!(set.encoding(), "intset");
+        // intset 放满后再加整数，已经超过 listpack 的上限，直接转成哈希表
+        set.insert(Bytes::from("512"), &Thresholds::default());
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.len(), 513);
+        assert!(set.contains(b"0") && set.contains(b"512"));
+
+        let mut set = set_of(["1", "2", "a"]);
+        assert_eq!(set.encoding(), "listpack");
+        set.insert(Bytes::from(vec![b'x'; 65]), &Thresholds::default());
+        assert_eq!(set.encoding(), "hashtable");
+        assert_eq!(set.len(), 4);
+        assert!(set.remove(b"a") && set.remove(b"1"));
+        assert_eq!(set.encoding(), "hashtable");
+    }
+
+    #[test]
+    fn compact_encodings_are_smaller() {
+        let ints = set_of((0..100).map(|i| i.to_string()));
+        let strings = set_of((0..100).map(|i| format!("m{}", i)));
+        let mut table = Set { encoding: Encoding::Hashtable(Hashtable::default()) };
+        for i in 0..100 {
+            table.insert(Bytes::from(format!("m{}", i)), &Thresholds::default());
+        }
+        assert!(ints.size() < strings.size());
+        assert!(strings.size() * 2 < table.size());
+    }
+}
@@ -0,0 +1,142 @@
+use bytes::Bytes;
+
+use crate::command::{syntax_error, wrong_type, Context};
+use crate::db::Value;
+use crate::keyspace::{deadline, parse_i64};
+use crate::notify;
+use mini_redis::Frame;
+
+pub fn get(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let value = ctx.shared.db.lock().unwrap().get(&args[1]).map(|entry| entry.value.as_string().cloned());
+    match value {
+        Some(Some(value)) => Frame::Bulk(value),
+        Some(None) => wrong_type(),
+        None => {
+            notify::keyspace_event(ctx.shared, notify::KEY_MISS, "keymiss", &args[1]);
+            Frame::Null
+        }
+    }
+}
+
+// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL]
+pub fn set(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (mut nx, mut xx, mut get, mut keepttl) = (false, false, false, false);
+    let mut expire: Option<(i64, bool)> = None;
+    let mut i = 3;
+    while i < args.len() {
+        let option = args[i].to_ascii_lowercase();
+        match &option[..] {
+            b"nx" if !xx => nx = true,
+            b"xx" if !nx => xx = true,
+            b"get" => get = true,
+            b"keepttl" if expire.is_none() => keepttl = true,
+            b"ex" | b"px" | b"exat" | b"pxat" if expire.is_none() && !keepttl && i + 1 < args.len() => {
+                let n = match parse_i64(&args[i + 1]) {
+                    Ok(n) if n > 0 => n,
+                    Ok(_) => return Frame::error("ERR invalid expire time in 'set' command"),
+                    Err(err) => return err,
+                };
+                let millis = if option.starts_with(b"e") { n.checked_mul(1000) } else { Some(n) };
+                match millis {
+                    Some(millis) => expire = Some((millis, option.ends_with(b"at"))),
+                    None => return Frame::error("ERR invalid expire time in 'set' command"),
+                }
+                i += 1;
+            }
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    let key = &args[1];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let (exists, old_value, old_expiry) = match db.get(key) {
+        Some(entry) => (true, entry.value.as_string().cloned(), entry.expires_at),
+        None => (false, None, None),
+    };
+    // SET 可以覆盖任何类型，但 GET 选项只能读字符串
+    if get && exists && old_value.is_none() {
+        return wrong_type();
+    }
+    let reply = |written: bool| match (get, &old_value) {
+        (true, Some(value)) => Frame::Bulk(value.clone()),
+        (true, None) => Frame::Null,
+        (false, _) if written => Frame::ok(),
+        (false, _) => Frame::Null,
+    };
+    if (nx && exists) || (xx && !exists) {
+        return reply(false);
+    }
+    let expires_at = match expire {
+        None if keepttl => old_expiry,
+        None => None,
+        Some((millis, absolute)) => match deadline(millis, absolute) {
+            Some(when) => Some(when),
+            // EXAT/PXAT 指定的时间已经过去，相当于写入后立即过期
+            None => {
+                db.remove(key);
+                drop(db);
+                if exists {
+                    ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+                    notify::keyspace_event(ctx.shared, notify::GENERIC, "del", key);
+                }
+                return reply(true);
+            }
+        },
+    };
+    db.set(key.clone(), args[2].clone(), expires_at);
+    drop(db);
+
+    ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+    if !exists {
+        notify::keyspace_event(ctx.shared, notify::NEW, "new", key);
+    }
+    notify::keyspace_event(ctx.shared, notify::STRING, "set", key);
+    if expire.is_some() {
+        notify::keyspace_event(ctx.shared, notify::GENERIC, "expire", key);
+    }
+    reply(true)
+}
+
+// INCR、DECR key 和 INCRBY、DECRBY key increment，保留原来的过期时间
+pub fn incr(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let increment = match args.get(2).map(|arg| parse_i64(arg)) {
+        Some(Ok(n)) => n,
+        Some(Err(err)) => return err,
+        None => 1,
+    };
+    let increment = if args[0][0].eq_ignore_ascii_case(&b'd') {
+        match increment.checked_neg() {
+            Some(n) => n,
+            None => return Frame::error("ERR decrement would overflow"),
+        }
+    } else {
+        increment
+    };
+
+    let key = &args[1];
+    let mut db = ctx.shared.db.lock().unwrap();
+    let current = match db.get(key).map(|entry| entry.value.as_string().map(|value| parse_i64(value))) {
+        Some(Some(Ok(n))) => Some(n),
+        Some(Some(Err(err))) => return err,
+        Some(None) => return wrong_type(),
+        None => None,
+    };
+    let Some(n) = current.unwrap_or(0).checked_add(increment) else {
+        return Frame::error("ERR increment or decrement would overflow");
+    };
+    let value = Bytes::from(n.to_string());
+    if current.is_some() {
+        db.modify(key, |old| *old = Value::String(value));
+    } else {
+        db.set(key.clone(), value, None);
+    }
+    drop(db);
+
+    ctx.shared.signal_modified_key(key, Some(ctx.client.id));
+    if current.is_none() {
+        notify::keyspace_event(ctx.shared, notify::NEW, "new", key);
+    }
+    notify::keyspace_event(ctx.shared, notify::STRING, "incrby", key);
+    Frame::Integer(n)
+}
@@ -0,0 +1,311 @@
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::command::{syntax_error, CommandSpec, Context};
+use crate::Shared;
+use mini_redis::Frame;
+
+/// CLIENT TRACKING 的选项
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    // 失效通知转发给这个连接，None 表示通过 RESP3 push 发给自己
+    pub redirect: Option<u64>,
+    pub bcast: bool,
+    pub prefixes: Vec<Bytes>,
+    pub optin: bool,
+    pub optout: bool,
+    pub noloop: bool,
+}
+
+/// 服务端记录的客户端缓存：普通模式下按 key 记录读过它的连接，广播模式下按前缀记录
+#[derive(Default)]
+pub struct Tracking {
+    clients: HashMap<u64, Options>,
+    keys: HashMap<Bytes, BTreeSet<u64>>,
+    // keys 的反向索引，关闭跟踪时据此删除这个连接记录过的 key
+    tracked: HashMap<u64, HashSet<Bytes>>,
+    prefixes: BTreeMap<Bytes, BTreeSet<u64>>,
+}
+
+impl Tracking {
+    fn enable(&mut self, id: u64, options: Options) {
+        self.disable(id);
+        if options.bcast {
+            for prefix in &options.prefixes {
+                self.prefixes.entry(prefix.clone()).or_default().insert(id);
+            }
+        }
+        self.clients.insert(id, options);
+    }
+
+    /// 关闭跟踪或断开连接时调用，删除这个连接记录的所有 key 和前缀
+    pub fn disable(&mut self, id: u64) {
+        for key in self.tracked.remove(&id).unwrap_or_default() {
+            if let Some(ids) = self.keys.get_mut(&key) {
+                ids.remove(&id);
+                if ids.is_empty() {
+                    self.keys.remove(&key);
+                }
+            }
+        }
+        if let Some(options) = self.clients.remove(&id) {
+            for prefix in &options.prefixes {
+                if let Some(ids) = self.prefixes.get_mut(prefix) {
+                    ids.remove(&id);
+                    if ids.is_empty() {
+                        self.prefixes.remove(prefix);
+                    }
+                }
+            }
+        }
+    }
+
+    fn track(&mut self, id: u64, key: &Bytes) {
+        self.keys.entry(key.clone()).or_default().insert(id);
+        self.tracked.entry(id).or_default().insert(key.clone());
+    }
+
+    /// key 失效，返回缓存了它的连接，这些连接之后要重新读过才会再次记录
+    fn untrack(&mut self, key: &[u8]) -> BTreeSet<u64> {
+        let ids = self.keys.remove(key).unwrap_or_default();
+        for id in &ids {
+            if let Some(keys) = self.tracked.get_mut(id) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.tracked.remove(id);
+                }
+            }
+        }
+        ids
+    }
+}
+
+/// 只读命令执行成功后，记下这个连接读过的 key；caching 是这条命令之前 CLIENT CACHING 的设置
+pub fn remember(ctx: &mut Context<'_>, spec: &CommandSpec, args: &[Bytes], caching: Option<bool>) {
+    let options = match &ctx.client.tracking {
+        Some(options) if !options.bcast => options,
+        _ => return,
+    };
+    if (options.optin && caching != Some(true)) || (options.optout && caching == Some(false)) {
+        return;
+    }
+    let keys = spec.keys(args);
+    if keys.is_empty() {
+        return;
+    }
+    let mut tracking = ctx.shared.tracking.lock().unwrap();
+    for key in keys {
+        tracking.track(ctx.client.id, key);
+    }
+}
+
+/// key 被修改时通知缓存了它的连接，`by` 是执行修改的连接（NOLOOP 时不通知自己）
+pub fn invalidate(shared: &Shared, key: &[u8], by: Option<u64>) {
+    let mut targets = BTreeSet::new();
+    let mut deliveries = Vec::new();
+    {
+        let mut tracking = shared.tracking.lock().unwrap();
+        if tracking.keys.is_empty() && tracking.prefixes.is_empty() {
+            return;
+        }
+        targets.extend(tracking.untrack(key));
+        for (prefix, ids) in &tracking.prefixes {
+            if key.starts_with(prefix) {
+                targets.extend(ids.iter().copied());
+            }
+        }
+        for id in targets {
+            match tracking.clients.get(&id) {
+                Some(options) if !(options.noloop && by == Some(id)) => deliveries.push((id, options.redirect)),
+                _ => {}
+            }
+        }
+    }
+
+    let keys = Frame::Array(vec![Frame::Bulk(Bytes::copy_from_slice(key))]);
+    for (id, redirect) in deliveries {
+        match redirect {
+            None => {
+                shared.push(id, Frame::Push(vec![Frame::bulk("invalidate"), keys.clone()]));
+            }
+            Some(target) => {
+                let message = Frame::Push(vec![
+                    Frame::bulk("message"),
+                    Frame::bulk("__redis__:invalidate"),
+                    keys.clone(),
+                ]);
+                if !shared.push(target, message) {
+                    shared.push(id, Frame::Push(vec![Frame::bulk("tracking-redir-broken"), Frame::Integer(target as i64)]));
+                }
+            }
+        }
+    }
+}
+
+// CLIENT TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...] [BCAST] [OPTIN] [OPTOUT] [NOLOOP]
+pub fn client_tracking(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let on = match args.first().map(|arg| arg.to_ascii_lowercase()) {
+        Some(arg) if arg == b"on" => true,
+        Some(arg) if arg == b"off" => false,
+        _ => return syntax_error(),
+    };
+    let mut options = Options::default();
+    let mut i = 1;
+    while i < args.len() {
+        match &args[i].to_ascii_lowercase()[..] {
+            b"redirect" if i + 1 < args.len() => {
+                let id = match std::str::from_utf8(&args[i + 1]).ok().and_then(|id| id.parse().ok()) {
+                    Some(id) => id,
+                    None => return Frame::error("ERR value is not an integer or out of range"),
+                };
+                options.redirect = Some(id);
+                i += 1;
+            }
+            b"prefix" if i + 1 < args.len() => {
+                options.prefixes.push(args[i + 1].clone());
+                i += 1;
+            }
+            b"bcast" => options.bcast = true,
+            b"optin" => options.optin = true,
+            b"optout" => options.optout = true,
+            b"noloop" => options.noloop = true,
+            _ => return syntax_error(),
+        }
+        i += 1;
+    }
+
+    if !on {
+        ctx.shared.tracking.lock().unwrap().disable(ctx.client.id);
+        ctx.client.tracking = None;
+        ctx.client.caching = None;
+        return Frame::ok();
+    }
+    if !options.bcast && !options.prefixes.is_empty() {
+        return Frame::error("ERR PREFIX option requires BCAST mode to be enabled");
+    }
+    if options.optin && options.optout {
+        return Frame::error("ERR You can't use both OPTIN and OPTOUT");
+    }
+    if options.bcast && (options.optin || options.optout) {
+        return Frame::error("ERR OPTIN and OPTOUT are not compatible with BCAST");
+    }
+    if options.bcast && options.prefixes.is_empty() {
+        // 不指定前缀时广播所有 key 的变化
+        options.prefixes.push(Bytes::new());
+    }
+    match options.redirect {
+        Some(id) if id == ctx.client.id => {
+            return Frame::error("ERR You can't redirect tracking messages to the client itself")
+        }
+        Some(id) if !ctx.shared.clients.lock().unwrap().contains_key(&id) => {
+            return Frame::error("ERR The client ID you want redirect to does not exist")
+        }
+        None if ctx.client.resp < 3 => {
+            return Frame::error("ERR Client tracking in RESP2 requires REDIRECT, use HELLO 3 to receive invalidation pushes")
+        }
+        _ => {}
+    }
+    ctx.shared.tracking.lock().unwrap().enable(ctx.client.id, options.clone());
+    ctx.client.tracking = Some(options);
+    Frame::ok()
+}
+
+// CLIENT CACHING YES|NO
+pub fn client_caching(ctx: &mut Context<'_>, arg: &Bytes) -> Frame {
+    let yes = match &arg.to_ascii_lowercase()[..] {
+        b"yes" => true,
+        b"no" => false,
+        _ => return syntax_error(),
+    };
+    match &ctx.client.tracking {
+        Some(options) if (yes && options.optin) || (!yes && options.optout) => {
+            ctx.client.caching = Some(yes);
+            Frame::ok()
+        }
+        Some(options) if options.optin || options.optout => {
+            Frame::error("ERR CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode, CLIENT CACHING NO in OPTOUT mode")
+        }
+        _ => Frame::error("ERR CLIENT CACHING can be called only when the client is in tracking mode with OPTIN or OPTOUT mode enabled"),
+    }
+}
+
+pub fn client_trackinginfo(ctx: &mut Context<'_>) -> Frame {
+    let (flags, redirect, prefixes) = match &ctx.client.tracking {
+        None => (vec![Frame::bulk("off")], -1, Vec::new()),
+        Some(options) => {
+            let mut flags = vec![Frame::bulk("on")];
+            for (set, name) in [
+                (options.bcast, "bcast"),
+                (options.optin, "optin"),
+                (options.optout, "optout"),
+                (ctx.client.caching == Some(true), "caching-yes"),
+                (ctx.client.caching == Some(false), "caching-no"),
+                (options.noloop, "noloop"),
+            ] {
+                if set {
+                    flags.push(Frame::bulk(name));
+                }
+            }
+            let redirect = match options.redirect {
+                Some(id) => {
+                    if !ctx.shared.clients.lock().unwrap().contains_key(&id) {
+                        flags.push(Frame::bulk("broken_redirect"));
+                    }
+                    id as i64
+                }
+                None => 0,
+            };
+            let prefixes = if options.bcast {
+                options.prefixes.iter().cloned().map(Frame::Bulk).collect()
+            } else {
+                Vec::new()
+            };
+            (flags, redirect, prefixes)
+        }
+    };
+    Frame::Map(vec![
+        (Frame::bulk("flags"), Frame::Set(flags)),
+        (Frame::bulk("redirect"), Frame::Integer(redirect)),
+        (Frame::bulk("prefixes"), Frame::Array(prefixes)),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disable_forgets_tracked_keys() {
+        let mut tracking = Tracking::default();
+        tracking.enable(1, Options::default());
+        tracking.enable(2, Options::default());
+        for i in 0..100 {
+            tracking.track(1, &Bytes::from(format!("key:{}", i)));
+        }
+        tracking.track(2, &Bytes::from("key:0"));
+
+        tracking.disable(1);
+        assert_eq!(tracking.keys.len(), 1);
+        assert_eq!(tracking.keys[&b"key:0"[..]], BTreeSet::from([2]));
+        assert!(!tracking.tracked.contains_key(&1));
+
+        // 失效后两边的记录都删掉
+        assert_eq!(tracking.untrack(b"key:0"), BTreeSet::from([2]));
+        assert!(tracking.keys.is_empty() && tracking.tracked.is_empty());
+        tracking.disable(2);
+        assert!(tracking.clients.is_empty());
+    }
+
+    #[test]
+    fn reenabling_resets_prefixes_and_keys() {
+        let mut tracking = Tracking::default();
+        tracking.enable(1, Options::default());
+        tracking.track(1, &Bytes::from("a"));
+        let bcast = Options { bcast: true, prefixes: vec![Bytes::from("user:")], ..Options::default() };
+        tracking.enable(1, bcast);
+        assert!(tracking.keys.is_empty());
+        assert_eq!(tracking.prefixes.len(), 1);
+        tracking.disable(1);
+        assert!(tracking.prefixes.is_empty() && tracking.clients.is_empty());
+    }
+}
@@ -0,0 +1,530 @@
+use bytes::{BufMut, Bytes};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::{Bound, Range};
+
+use crate::command::{syntax_error, Context};
+use crate::db::{Collection, Thresholds, Value};
+use crate::keyspace::{self, parse_f64, parse_i64};
+use crate::list::clamp;
+use crate::notify;
+use mini_redis::Frame;
+
+// 估算内存时每个成员额外计入的固定开销（两份索引里的节点）
+const MEMBER_OVERHEAD: usize = 48;
+// listpack 每项除了成员本身，还有前后两个长度和分数
+const ENTRY_HEADER: usize = 4 + 8 + 4;
+
+/// 分数，按 f64::total_cmp 排序，这样才能放进 BTreeSet
+#[derive(Debug, Clone, Copy)]
+struct Score(f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Score) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+/// 有序集合：按成员查分数，按（分数，成员）有序遍历。
+/// 成员少而且都不长时用紧凑的 listpack 编码，超过阈值后转成跳表编码，之后不再转回去
+#[derive(Debug, Clone)]
+pub struct ZSet {
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone)]
+enum Encoding {
+    Listpack(Listpack),
+    Skiplist(Skiplist),
+}
+
+impl Default for ZSet {
+    fn default() -> ZSet {
+        ZSet { encoding: Encoding::Listpack(Listpack::default()) }
+    }
+}
+
+impl ZSet {
+    pub fn new() -> ZSet {
+        ZSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.len,
+            Encoding::Skiplist(skiplist) => skiplist.scores.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 估算占用的内存，增删成员时维护
+    pub fn size(&self) -> usize {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.buf.len(),
+            Encoding::Skiplist(skiplist) => skiplist.size,
+        }
+    }
+
+    /// OBJECT ENCODING 返回的内部编码
+    pub fn encoding(&self) -> &'static str {
+        match &self.encoding {
+            Encoding::Listpack(_) => "listpack",
+            Encoding::Skiplist(_) => "skiplist",
+        }
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => listpack.find(member).map(|(_, score)| score),
+            Encoding::Skiplist(skiplist) => skiplist.scores.get(member).copied(),
+        }
+    }
+
+    /// 写入成员，返回是否是新成员。超过 zset-max-listpack-entries 或 zset-max-listpack-value 时转成跳表编码
+    pub fn insert(&mut self, member: Bytes, score: f64, thresholds: &Thresholds) -> bool {
+        if let Encoding::Listpack(listpack) = &self.encoding {
+            let too_long = member.len() > thresholds.zset_max_listpack_value;
+            let full = listpack.len >= thresholds.zset_max_listpack_entries;
+            if too_long || (full && listpack.find(&member).is_none()) {
+                self.convert();
+            }
+        }
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) => listpack.insert(&member, score),
+            Encoding::Skiplist(skiplist) => skiplist.insert(member, score),
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.encoding {
+            Encoding::Listpack(listpack) => listpack.remove(member),
+            Encoding::Skiplist(skiplist) => skiplist.remove(member),
+        }
+    }
+
+    /// 按分数从小到大遍历
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (Bytes, f64)> + '_ {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => Iter::Listpack(listpack.iter()),
+            Encoding::Skiplist(skiplist) => {
+                Iter::Skiplist(skiplist.ordered.iter().map(|(score, member)| (member.clone(), score.0)))
+            }
+        }
+    }
+
+    /// 分数在 [min, max) 之间的成员
+    pub fn range(&self, min: f64, max: f64) -> impl DoubleEndedIterator<Item = (Bytes, f64)> + '_ {
+        match &self.encoding {
+            Encoding::Listpack(listpack) => Iter::Listpack(
+                listpack
+                    .iter()
+                    .filter(move |(_, score)| Score(*score) >= Score(min) && Score(*score) < Score(max)),
+            ),
+            Encoding::Skiplist(skiplist) => Iter::Skiplist(skiplist.range(min, max)),
+        }
+    }
+
+    fn convert(&mut self) {
+        let Encoding::Listpack(listpack) = &self.encoding else { return };
+        let mut skiplist = Skiplist::default();
+        for (member, score) in listpack.iter() {
+            // 复制出来，否则每个成员都会让整块 listpack 一直留在内存里
+            skiplist.insert(Bytes::copy_from_slice(&member), score);
+        }
+        self.encoding = Encoding::Skiplist(skiplist);
+    }
+}
+
+impl Collection for ZSet {
+    fn cast(value: &Value) -> Option<&ZSet> {
+        value.as_zset()
+    }
+
+    fn cast_mut(value: &mut Value) -> Option<&mut ZSet> {
+        value.as_zset_mut()
+    }
+
+    fn is_empty(&self) -> bool {
+        ZSet::is_empty(self)
+    }
+}
+
+impl From<ZSet> for Value {
+    fn from(zset: ZSet) -> Value {
+        Value::ZSet(zset)
+    }
+}
+
+/// 两种编码共用的迭代器
+enum Iter<L, S> {
+    Listpack(L),
+    Skiplist(S),
+}
+
+impl<T, L: Iterator<Item = T>, S: Iterator<Item = T>> Iterator for Iter<L, S> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self {
+            Iter::Listpack(iter) => iter.next(),
+            Iter::Skiplist(iter) => iter.next(),
+        }
+    }
+}
+
+impl<T, L: DoubleEndedIterator<Item = T>, S: DoubleEndedIterator<Item = T>> DoubleEndedIterator for Iter<L, S> {
+    fn next_back(&mut self) -> Option<T> {
+        match self {
+            Iter::Listpack(iter) => iter.next_back(),
+            Iter::Skiplist(iter) => iter.next_back(),
+        }
+    }
+}
+
+/// 成员按（分数，成员）的顺序连续放在一块内存里，每项是
+/// [成员长度 u32][成员][分数 f64][成员长度 u32]，末尾的长度用来从后往前遍历。
+/// 查找要顺序扫描，修改要复制整块内存，所以只用于小集合
+#[derive(Debug, Clone, Default)]
+struct Listpack {
+    buf: Bytes,
+    len: usize,
+}
+
+impl Listpack {
+    fn iter(&self) -> ListpackIter {
+        ListpackIter { buf: self.buf.clone(), front: 0, back: self.buf.len() }
+    }
+
+    /// 成员所在项的字节范围和分数
+    fn find(&self, member: &[u8]) -> Option<(Range<usize>, f64)> {
+        let mut at = 0;
+        while at < self.buf.len() {
+            let (range, score, end) = entry(&self.buf, at);
+            if self.buf[range] == *member {
+                return Some((at..end, score));
+            }
+            at = end;
+        }
+        None
+    }
+
+    /// 按顺序应该插入的位置
+    fn position(&self, member: &[u8], score: f64) -> usize {
+        let mut at = 0;
+        while at < self.buf.len() {
+            let (range, current, end) = entry(&self.buf, at);
+            if (Score(current), &self.buf[range]) > (Score(score), member) {
+                break;
+            }
+            at = end;
+        }
+        at
+    }
+
+    fn insert(&mut self, member: &[u8], score: f64) -> bool {
+        let new = match self.find(member) {
+            Some((_, old)) if Score(old) == Score(score) => return false,
+            Some((range, _)) => {
+                self.splice(range, None);
+                false
+            }
+            None => {
+                self.len += 1;
+                true
+            }
+        };
+        let at = self.position(member, score);
+        self.splice(at..at, Some((member, score)));
+        new
+    }
+
+    fn remove(&mut self, member: &[u8]) -> bool {
+        let Some((range, _)) = self.find(member) else { return false };
+        self.splice(range, None);
+        self.len -= 1;
+        true
+    }
+
+    /// 用新的一项（或者什么都不放）替换 range 里的字节
+    fn splice(&mut self, range: Range<usize>, entry: Option<(&[u8], f64)>) {
+        let extra = entry.map_or(0, |(member, _)| member.len() + ENTRY_HEADER);
+        let mut buf = Vec::with_capacity(self.buf.len() - range.len() + extra);
+        buf.extend_from_slice(&self.buf[..range.start]);
+        if let Some((member, score)) = entry {
+            buf.put_u32_le(member.len() as u32);
+            buf.put_slice(member);
+            buf.put_f64_le(score);
+            buf.put_u32_le(member.len() as u32);
+        }
+        buf.extend_from_slice(&self.buf[range.end..]);
+        self.buf = Bytes::from(buf);
+    }
+}
+
+/// 解析 at 开始的一项，返回成员的字节范围、分数和下一项的位置
+fn entry(buf: &[u8], at: usize) -> (Range<usize>, f64, usize) {
+    let len = read_u32(buf, at);
+    let member = at + 4..at + 4 + len;
+    let score = f64::from_le_bytes(buf[member.end..member.end + 8].try_into().unwrap());
+    (member, score, at + len + ENTRY_HEADER)
+}
+
+fn read_u32(buf: &[u8], at: usize) -> usize {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap()) as usize
+}
+
+/// 遍历时持有 listpack 的一份引用，返回的成员是它的切片，不需要复制
+struct ListpackIter {
+    buf: Bytes,
+    front: usize,
+    back: usize,
+}
+
+impl Iterator for ListpackIter {
+    type Item = (Bytes, f64);
+
+    fn next(&mut self) -> Option<(Bytes, f64)> {
+        if self.front >= self.back {
+            return None;
+        }
+        let (member, score, end) = entry(&self.buf, self.front);
+        self.front = end;
+        Some((self.buf.slice(member), score))
+    }
+}
+
+impl DoubleEndedIterator for ListpackIter {
+    fn next_back(&mut self) -> Option<(Bytes, f64)> {
+        if self.front >= self.back {
+            return None;
+        }
+        let start = self.back - read_u32(&self.buf, self.back - 4) - ENTRY_HEADER;
+        let (member, score, _) = entry(&self.buf, start);
+        self.back = start;
+        Some((self.buf.slice(member), score))
+    }
+}
+
+/// 跳表编码，这里用两份索引实现：HashMap 按成员查分数，BTreeSet 按分数排序
+#[derive(Debug, Clone, Default)]
+struct Skiplist {
+    scores: HashMap<Bytes, f64>,
+    ordered: BTreeSet<(Score, Bytes)>,
+    size: usize,
+}
+
+impl Skiplist {
+    fn insert(&mut self, member: Bytes, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            Some(old) => {
+                self.ordered.remove(&(Score(old), member.clone()));
+                self.ordered.insert((Score(score), member));
+                false
+            }
+            None => {
+                self.size += member.len() * 2 + MEMBER_OVERHEAD;
+                self.ordered.insert((Score(score), member));
+                true
+            }
+        }
+    }
+
+    fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove_entry(member) {
+            Some((member, score)) => {
+                self.size -= member.len() * 2 + MEMBER_OVERHEAD;
+                self.ordered.remove(&(Score(score), member));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn range(&self, min: f64, max: f64) -> impl DoubleEndedIterator<Item = (Bytes, f64)> + '_ {
+        let start = Bound::Included((Score(min), Bytes::new()));
+        let end = Bound::Excluded((Score(max), Bytes::new()));
+        // BTreeSet::range 遇到 start > end 会 panic
+        (min < max)
+            .then(|| self.ordered.range((start, end)))
+            .into_iter()
+            .flatten()
+            .map(|(score, member)| (member.clone(), score.0))
+    }
+}
+
+// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+pub fn zadd(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (mut nx, mut xx, mut gt, mut lt, mut ch, mut incr) = (false, false, false, false, false, false);
+    let mut i = 2;
+    while i < args.len() {
+        match &args[i].to_ascii_lowercase()[..] {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            b"gt" => gt = true,
+            b"lt" => lt = true,
+            b"ch" => ch = true,
+            b"incr" => incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+    let items = &args[i..];
+    if items.is_empty() || !items.len().is_multiple_of(2) {
+        return syntax_error();
+    }
+    if nx && xx {
+        return Frame::error("ERR XX and NX options at the same time are not compatible");
+    }
+    if (gt && lt) || (nx && (gt || lt)) {
+        return Frame::error("ERR GT, LT, and/or NX options at the same time are not compatible");
+    }
+    if incr && items.len() > 2 {
+        return Frame::error("ERR INCR option supports a single increment-element pair");
+    }
+    let mut pairs = Vec::with_capacity(items.len() / 2);
+    for pair in items.chunks(2) {
+        match parse_f64(&pair[0]) {
+            Ok(score) => pairs.push((score, pair[1].clone())),
+            Err(err) => return err,
+        }
+    }
+
+    let event = if incr { "zincr" } else { "zadd" };
+    let result = keyspace::update(ctx, &args[1], !xx, notify::ZSET, event, |zset: &mut ZSet, thresholds| {
+        let (mut added, mut updated) = (0, 0);
+        // INCR 时回复新的分数，因为 NX、XX、GT、LT 没有写入时回复 nil
+        let mut last = None;
+        for (score, member) in pairs {
+            let old = zset.score(&member);
+            let score = match old {
+                Some(old) if incr => old + score,
+                _ => score,
+            };
+            if score.is_nan() {
+                return (Err(Frame::error("ERR resulting score is not a number (NaN)")), false);
+            }
+            let skip = match old {
+                Some(old) => nx || (gt && score <= old) || (lt && score >= old),
+                None => xx,
+            };
+            if skip {
+                continue;
+            }
+            last = Some(score);
+            match old {
+                Some(old) if old == score => {}
+                Some(_) => {
+                    zset.insert(member, score, thresholds);
+                    updated += 1;
+                }
+                None => {
+                    zset.insert(member, score, thresholds);
+                    added += 1;
+                }
+            }
+        }
+        let reply = if incr { last.map_or(Frame::Null, Frame::Double) } else { Frame::Integer(added + if ch { updated } else { 0 }) };
+        (Ok(reply), added + updated > 0)
+    });
+    match result {
+        Ok(Some(Ok(reply))) => reply,
+        Ok(Some(Err(err))) | Err(err) => err,
+        Ok(None) if incr => Frame::Null,
+        Ok(None) => Frame::Integer(0),
+    }
+}
+
+// ZINCRBY key increment member
+pub fn zincrby(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let increment = match parse_f64(&args[2]) {
+        Ok(increment) => increment,
+        Err(err) => return err,
+    };
+    let result = keyspace::update(ctx, &args[1], true, notify::ZSET, "zincr", |zset: &mut ZSet, thresholds| {
+        let score = zset.score(&args[3]).unwrap_or(0.0) + increment;
+        if score.is_nan() {
+            return (Err(Frame::error("ERR resulting score is not a number (NaN)")), false);
+        }
+        zset.insert(args[3].clone(), score, thresholds);
+        (Ok(score), true)
+    });
+    match result {
+        Ok(Some(Ok(score))) => Frame::Double(score),
+        Ok(Some(Err(err))) | Err(err) => err,
+        Ok(None) => unreachable!(),
+    }
+}
+
+// ZREM key member [member ...]
+pub fn zrem(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let result = keyspace::update(ctx, &args[1], false, notify::ZSET, "zrem", |zset: &mut ZSet, _| {
+        let removed = args[2..].iter().filter(|member| zset.remove(member)).count();
+        (removed, removed > 0)
+    });
+    match result {
+        Ok(removed) => Frame::Integer(removed.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+pub fn zscore(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |zset: &ZSet| zset.score(&args[2])) {
+        Ok(score) => score.flatten().map_or(Frame::Null, Frame::Double),
+        Err(err) => err,
+    }
+}
+
+pub fn zcard(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    match keyspace::read(ctx, &args[1], |zset: &ZSet| zset.len()) {
+        Ok(len) => Frame::Integer(len.unwrap_or(0) as i64),
+        Err(err) => err,
+    }
+}
+
+// ZRANGE key start stop [REV] [WITHSCORES]，只支持按下标的范围
+pub fn zrange(ctx: &mut Context<'_>, args: &[Bytes]) -> Frame {
+    let (start, stop) = match (parse_i64(&args[2]), parse_i64(&args[3])) {
+        (Ok(start), Ok(stop)) => (start, stop),
+        (Err(err), _) | (_, Err(err)) => return err,
+    };
+    let (mut rev, mut withscores) = (false, false);
+    for arg in &args[4..] {
+        match &arg.to_ascii_lowercase()[..] {
+            b"rev" => rev = true,
+            b"withscores" => withscores = true,
+            _ => return syntax_error(),
+        }
+    }
+    let items = keyspace::read(ctx, &args[1], |zset: &ZSet| match clamp(start, stop, zset.len()) {
+        Some((start, stop)) if rev => zset.iter().rev().skip(start).take(stop - start + 1).collect(),
+        Some((start, stop)) => zset.iter().skip(start).take(stop - start + 1).collect(),
+        None => Vec::new(),
+    });
+    let items = match items {
+        Ok(items) => items.unwrap_or_default(),
+        Err(err) => return err,
+    };
+    // RESP3 下每个成员和分数是一对，RESP2 下平铺
+    Frame::Array(match (withscores, ctx.client.resp) {
+        (false, _) => items.into_iter().map(|(member, _)| Frame::Bulk(member)).collect(),
+        (true, 3) => items.into_iter().map(|(member, score)| Frame::Array(vec![Frame::Bulk(member), Frame::Double(score)])).collect(),
+        (true, _) => items.into_iter().flat_map(|(member, score)| [Frame::Bulk(member), Frame::Double(score)]).collect(),
+    })
+}
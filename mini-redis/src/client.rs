@@ -0,0 +1,254 @@
+use bytes::Bytes;
+use std::collections::VecDeque;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use crate::tls::{self, ClientOptions};
+use crate::{Connection, Frame, Stream};
+
+pub struct Client {
+    connection: Connection<Box<dyn Stream>>,
+}
+
+/// 进入订阅状态的连接，只能收消息和增减订阅
+pub struct Subscriber {
+    client: Client,
+    channels: Vec<Bytes>,
+    patterns: Vec<Bytes>,
+    // 等待订阅确认时先收到的消息
+    pending: VecDeque<Message>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub channel: Bytes,
+    // 通过 PSUBSCRIBE 收到时匹配的模式
+    pub pattern: Option<Bytes>,
+    // 普通消息是 Bulk，缓存失效通知是 key 的数组
+    pub content: Frame,
+}
+
+pub async fn connect<T: ToSocketAddrs>(addr: T) -> crate::Result<Client> {
+    let socket = TcpStream::connect(addr).await?;
+    socket.set_nodelay(true)?;
+    Ok(Client {
+        connection: Connection::new(Box::new(socket)),
+    })
+}
+
+pub async fn connect_tls<T: ToSocketAddrs>(addr: T, options: &ClientOptions) -> crate::Result<Client> {
+    let connector = tls::connector(options)?;
+    let socket = TcpStream::connect(addr).await?;
+    socket.set_nodelay(true)?;
+    let stream = connector.connect(tls::server_name(options)?, socket).await?;
+    Ok(Client {
+        connection: Connection::new(Box::new(stream)),
+    })
+}
+
+pub async fn connect_with<T: ToSocketAddrs>(addr: T, tls: Option<&ClientOptions>) -> crate::Result<Client> {
+    match tls {
+        Some(options) => connect_tls(addr, options).await,
+        None => connect(addr).await,
+    }
+}
+
+impl Client {
+    pub async fn get(&mut self, key: &str) -> crate::Result<Option<Bytes>> {
+        match self.request(&[b"GET", key.as_bytes()]).await? {
+            Frame::Bulk(value) => Ok(Some(value)),
+            Frame::Null => Ok(None),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    pub async fn set(&mut self, key: &str, value: Bytes) -> crate::Result<()> {
+        match self.request(&[b"SET", key.as_bytes(), &value]).await? {
+            Frame::Simple(_) => Ok(()),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    pub async fn ping(&mut self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        let frame = match &msg {
+            Some(msg) => self.request(&[b"PING", msg]).await?,
+            None => self.request(&[b"PING"]).await?,
+        };
+        match frame {
+            Frame::Simple(value) => Ok(value.into()),
+            Frame::Bulk(value) => Ok(value),
+            frame => Err(unexpected(frame)),
+        }
+    }
+
+    /// 发送任意命令，服务端返回的错误会转换成 Err
+    pub async fn request(&mut self, args: &[&[u8]]) -> crate::Result<Frame> {
+        let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg)).collect();
+        match self.call(&args).await? {
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Ok(frame),
+        }
+    }
+
+    pub async fn subscribe(self, channels: &[&str]) -> crate::Result<Subscriber> {
+        let mut subscriber = Subscriber {
+            client: self,
+            channels: Vec::new(),
+            patterns: Vec::new(),
+            pending: VecDeque::new(),
+        };
+        subscriber.subscribe(channels).await?;
+        Ok(subscriber)
+    }
+
+    pub async fn psubscribe(self, patterns: &[&str]) -> crate::Result<Subscriber> {
+        let mut subscriber = Subscriber {
+            client: self,
+            channels: Vec::new(),
+            patterns: Vec::new(),
+            pending: VecDeque::new(),
+        };
+        subscriber.psubscribe(patterns).await?;
+        Ok(subscriber)
+    }
+
+    /// 发送任意命令并原样返回回复，Err 只表示连接出了问题
+    pub async fn call(&mut self, args: &[Bytes]) -> crate::Result<Frame> {
+        self.send(args).await?;
+        self.flush().await?;
+        self.read_reply().await
+    }
+
+    /// 只把命令写进缓冲区，配合 flush 和 read_reply 实现 pipeline
+    pub async fn send(&mut self, args: &[Bytes]) -> crate::Result<()> {
+        let frame = Frame::Array(args.iter().cloned().map(Frame::Bulk).collect());
+        self.connection.buffer_frame(&frame).await
+    }
+
+    pub async fn flush(&mut self) -> crate::Result<()> {
+        self.connection.flush().await
+    }
+
+    pub async fn read_reply(&mut self) -> crate::Result<Frame> {
+        match self.connection.read_frame().await? {
+            Some(frame) => Ok(frame),
+            None => Err("connection reset by server".into()),
+        }
+    }
+}
+
+impl Subscriber {
+    pub fn channels(&self) -> &[Bytes] {
+        &self.channels
+    }
+
+    pub fn patterns(&self) -> &[Bytes] {
+        &self.patterns
+    }
+
+    /// 等待下一条消息，连接关闭时返回 None
+    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(Some(message));
+        }
+        loop {
+            let frame = match self.client.connection.read_frame().await? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            if let Some(message) = parse_message(&frame) {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    pub async fn subscribe(&mut self, channels: &[&str]) -> crate::Result<()> {
+        self.change(b"SUBSCRIBE", channels).await
+    }
+
+    pub async fn unsubscribe(&mut self, channels: &[&str]) -> crate::Result<()> {
+        self.change(b"UNSUBSCRIBE", channels).await
+    }
+
+    pub async fn psubscribe(&mut self, patterns: &[&str]) -> crate::Result<()> {
+        self.change(b"PSUBSCRIBE", patterns).await
+    }
+
+    pub async fn punsubscribe(&mut self, patterns: &[&str]) -> crate::Result<()> {
+        self.change(b"PUNSUBSCRIBE", patterns).await
+    }
+
+    // 发送订阅类命令，并等到每个频道的确认都收到
+    async fn change(&mut self, command: &'static [u8], names: &[&str]) -> crate::Result<()> {
+        let mut args = vec![Bytes::from_static(command)];
+        args.extend(names.iter().map(|name| Bytes::copy_from_slice(name.as_bytes())));
+        self.client.send(&args).await?;
+        self.client.flush().await?;
+
+        let kind = String::from_utf8_lossy(command).to_ascii_lowercase();
+        // 不带参数的退订要一直等到订阅数归零
+        let mut remaining = names.len();
+        loop {
+            let frame = self.client.read_reply().await?;
+            if let Some(message) = parse_message(&frame) {
+                self.pending.push_back(message);
+                continue;
+            }
+            let (confirmed, name, count) = match &frame {
+                Frame::Array(items) | Frame::Push(items) if items.len() == 3 => match (&items[0], &items[1], &items[2]) {
+                    (Frame::Bulk(confirmed), name, Frame::Integer(count)) => (confirmed.clone(), name.clone(), *count),
+                    _ => return Err(unexpected(frame)),
+                },
+                Frame::Error(msg) => return Err(msg.clone().into()),
+                _ => return Err(unexpected(frame)),
+            };
+            if !confirmed.eq_ignore_ascii_case(kind.as_bytes()) {
+                return Err(unexpected(frame));
+            }
+            let list = if kind.starts_with('p') { &mut self.patterns } else { &mut self.channels };
+            if let Frame::Bulk(name) = name {
+                if kind.contains("unsub") {
+                    list.retain(|existing| *existing != name);
+                } else if !list.contains(&name) {
+                    list.push(name);
+                }
+            }
+            if names.is_empty() {
+                if list.is_empty() || count == 0 {
+                    return Ok(());
+                }
+            } else {
+                remaining -= 1;
+                if remaining == 0 {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// message / pmessage 转换成 Message，其他回复返回 None
+fn parse_message(frame: &Frame) -> Option<Message> {
+    let items = match frame {
+        Frame::Array(items) | Frame::Push(items) => items,
+        _ => return None,
+    };
+    match items.as_slice() {
+        [Frame::Bulk(kind), Frame::Bulk(channel), content] if &kind[..] == b"message" => Some(Message {
+            channel: channel.clone(),
+            pattern: None,
+            content: content.clone(),
+        }),
+        [Frame::Bulk(kind), Frame::Bulk(pattern), Frame::Bulk(channel), content] if &kind[..] == b"pmessage" => {
+            Some(Message {
+                channel: channel.clone(),
+                pattern: Some(pattern.clone()),
+                content: content.clone(),
+            })
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn unexpected(frame: Frame) -> crate::Error {
+    format!("unexpected frame: {:?}", frame).into()
+}
@@ -0,0 +1,73 @@
+use bytes::{Buf, BytesMut};
+use std::io::{Cursor, ErrorKind};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter};
+
+use crate::frame::{self, Frame};
+
+pub struct Connection<S> {
+    stream: BufWriter<S>,
+    buffer: BytesMut,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    pub fn new(socket: S) -> Connection<S> {
+        Connection {
+            stream: BufWriter::new(socket),
+            buffer: BytesMut::with_capacity(4 * 1024),
+        }
+    }
+
+    pub async fn read_frame(&mut self) -> crate::Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = self.parse_frame()? {
+                return Ok(Some(frame));
+            }
+            let n = match self.stream.read_buf(&mut self.buffer).await {
+                Ok(n) => n,
+                // TLS 对端没有发送 close_notify 就断开，和普通 TCP 的 EOF 同样处理
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => 0,
+                Err(e) => return Err(e.into()),
+            };
+            if n == 0 {
+                if self.buffer.is_empty() {
+                    return Ok(None);
+                } else {
+                    return Err("connection reset by peer".into());
+                }
+            }
+        }
+    }
+
+    fn parse_frame(&mut self) -> crate::Result<Option<Frame>> {
+        let mut buf = Cursor::new(&self.buffer[..]);
+        match Frame::check(&mut buf) {
+            Ok(_) => {
+                let len = buf.position() as usize;
+                buf.set_position(0);
+                let frame = Frame::parse(&mut buf)?;
+                self.buffer.advance(len);
+                Ok(Some(frame))
+            }
+            Err(frame::Error::Incomplete) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn write_frame(&mut self, frame: &Frame) -> crate::Result<()> {
+        self.buffer_frame(frame).await?;
+        self.flush().await
+    }
+
+    /// 只写入缓冲区不 flush，用于一次发送多条命令（pipeline）
+    pub async fn buffer_frame(&mut self, frame: &Frame) -> crate::Result<()> {
+        let mut out = Vec::new();
+        frame.encode(&mut out);
+        self.stream.write_all(&out).await?;
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> crate::Result<()> {
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
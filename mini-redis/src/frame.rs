@@ -0,0 +1,414 @@
+use bytes::{Buf, Bytes};
+use std::fmt;
+use std::io::Cursor;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Bytes),
+    Null,
+    Array(Vec<Frame>),
+    // 以下是 RESP3 新增的类型，只有 HELLO 3 之后才会出现
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    // 格式（txt / mkd）和内容
+    Verbatim(String, Bytes),
+    Map(Vec<(Frame, Frame)>),
+    Set(Vec<Frame>),
+    Push(Vec<Frame>),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Incomplete,
+    Other(crate::Error),
+}
+
+impl Frame {
+    pub fn ok() -> Frame {
+        Frame::Simple("OK".to_string())
+    }
+
+    pub fn error(msg: impl Into<String>) -> Frame {
+        Frame::Error(msg.into())
+    }
+
+    pub fn bulk(value: impl Into<Bytes>) -> Frame {
+        Frame::Bulk(value.into())
+    }
+
+    /// 把 RESP3 独有的类型转换成 RESP2 里对应的表示，发给没有 HELLO 3 的客户端
+    pub fn into_resp2(self) -> Frame {
+        match self {
+            Frame::Double(val) => Frame::Bulk(Bytes::from(val.to_string())),
+            Frame::Boolean(val) => Frame::Integer(val as i64),
+            Frame::BigNumber(val) => Frame::Bulk(Bytes::from(val)),
+            Frame::Verbatim(_, val) => Frame::Bulk(val),
+            Frame::Map(pairs) => Frame::Array(
+                pairs
+                    .into_iter()
+                    .flat_map(|(key, value)| [key.into_resp2(), value.into_resp2()])
+                    .collect(),
+            ),
+            Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+                Frame::Array(items.into_iter().map(Frame::into_resp2).collect())
+            }
+            frame => frame,
+        }
+    }
+
+    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        match get_u8(src)? {
+            b'+' | b'-' | b':' | b',' | b'#' | b'(' | b'_' => {
+                get_line(src)?;
+                Ok(())
+            }
+            b'$' | b'!' | b'=' => {
+                if b'-' == peek_u8(src)? {
+                    skip(src, 4)
+                } else {
+                    let len: usize = get_decimal(src)?.try_into()?;
+                    skip(src, len + 2)
+                }
+            }
+            b'*' | b'~' | b'>' => {
+                let len = get_decimal(src)?;
+                for _ in 0..len {
+                    Frame::check(src)?;
+                }
+                Ok(())
+            }
+            // map 和 attribute 每一项是一对 key/value
+            b'%' | b'|' => {
+                let len = get_decimal(src)?;
+                for _ in 0..len * 2 {
+                    Frame::check(src)?;
+                }
+                Ok(())
+            }
+            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+        }
+    }
+
+    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, Error> {
+        match get_u8(src)? {
+            b'+' => {
+                let line = get_line(src)?.to_vec();
+                Ok(Frame::Simple(String::from_utf8(line)?))
+            }
+            b'-' => {
+                let line = get_line(src)?.to_vec();
+                Ok(Frame::Error(String::from_utf8(line)?))
+            }
+            b':' => Ok(Frame::Integer(get_signed(src)?)),
+            b'_' => {
+                get_line(src)?;
+                Ok(Frame::Null)
+            }
+            b',' => {
+                let line = std::str::from_utf8(get_line(src)?).map_err(|_| "protocol error; invalid frame format")?;
+                let value = match line {
+                    "inf" => f64::INFINITY,
+                    "-inf" => f64::NEG_INFINITY,
+                    line => line.parse().map_err(|_| "protocol error; invalid frame format")?,
+                };
+                Ok(Frame::Double(value))
+            }
+            b'#' => match get_line(src)? {
+                b"t" => Ok(Frame::Boolean(true)),
+                b"f" => Ok(Frame::Boolean(false)),
+                _ => Err("protocol error; invalid frame format".into()),
+            },
+            b'(' => {
+                let line = get_line(src)?.to_vec();
+                Ok(Frame::BigNumber(String::from_utf8(line)?))
+            }
+            b'!' => {
+                let data = get_blob(src)?;
+                Ok(Frame::Error(String::from_utf8(data.to_vec())?))
+            }
+            b'=' => {
+                let data = get_blob(src)?;
+                if data.len() < 4 || data[3] != b':' {
+                    return Err("protocol error; invalid frame format".into());
+                }
+                let format = String::from_utf8(data[..3].to_vec())?;
+                Ok(Frame::Verbatim(format, data.slice(4..)))
+            }
+            b'$' => {
+                if b'-' == peek_u8(src)? {
+                    let line = get_line(src)?;
+                    if line != b"-1" {
+                        return Err("protocol error; invalid frame format".into());
+                    }
+                    Ok(Frame::Null)
+                } else {
+                    let len: usize = get_decimal(src)?.try_into()?;
+                    let n = len + 2;
+                    if src.remaining() < n {
+                        return Err(Error::Incomplete);
+                    }
+                    let data = Bytes::copy_from_slice(&src.chunk()[..len]);
+                    skip(src, n)?;
+                    Ok(Frame::Bulk(data))
+                }
+            }
+            b'*' => {
+                let len: usize = get_decimal(src)?.try_into()?;
+                let mut out = Vec::with_capacity(len);
+                for _ in 0..len {
+                    out.push(Frame::parse(src)?);
+                }
+                Ok(Frame::Array(out))
+            }
+            b'~' => Ok(Frame::Set(parse_items(src)?)),
+            b'>' => Ok(Frame::Push(parse_items(src)?)),
+            b'%' => {
+                let len: usize = get_decimal(src)?.try_into()?;
+                let mut out = Vec::with_capacity(len);
+                for _ in 0..len {
+                    let key = Frame::parse(src)?;
+                    let value = Frame::parse(src)?;
+                    out.push((key, value));
+                }
+                Ok(Frame::Map(out))
+            }
+            // attribute 只是附加信息，跳过后返回真正的回复
+            b'|' => {
+                let len = get_decimal(src)?;
+                for _ in 0..len * 2 {
+                    Frame::parse(src)?;
+                }
+                Frame::parse(src)
+            }
+            actual => Err(format!("protocol error; invalid frame type byte `{}`", actual).into()),
+        }
+    }
+
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        match self {
+            Frame::Simple(val) => {
+                dst.push(b'+');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Error(val) => {
+                dst.push(b'-');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Integer(val) => {
+                dst.push(b':');
+                dst.extend_from_slice(val.to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Bulk(val) => {
+                dst.push(b'$');
+                dst.extend_from_slice(val.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                dst.extend_from_slice(val);
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Null => dst.extend_from_slice(b"$-1\r\n"),
+            Frame::Array(val) => {
+                dst.push(b'*');
+                dst.extend_from_slice(val.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                for entry in val {
+                    entry.encode(dst);
+                }
+            }
+            Frame::Double(val) => {
+                dst.push(b',');
+                if val.is_infinite() {
+                    dst.extend_from_slice(if *val > 0.0 { b"inf" } else { b"-inf" });
+                } else {
+                    dst.extend_from_slice(val.to_string().as_bytes());
+                }
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Boolean(val) => dst.extend_from_slice(if *val { b"#t\r\n" } else { b"#f\r\n" }),
+            Frame::BigNumber(val) => {
+                dst.push(b'(');
+                dst.extend_from_slice(val.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Verbatim(format, val) => {
+                dst.push(b'=');
+                dst.extend_from_slice((val.len() + 4).to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                dst.extend_from_slice(format.as_bytes());
+                dst.push(b':');
+                dst.extend_from_slice(val);
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Map(val) => {
+                dst.push(b'%');
+                dst.extend_from_slice(val.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                for (key, value) in val {
+                    key.encode(dst);
+                    value.encode(dst);
+                }
+            }
+            Frame::Set(val) | Frame::Push(val) => {
+                dst.push(if matches!(self, Frame::Set(_)) { b'~' } else { b'>' });
+                dst.extend_from_slice(val.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                for entry in val {
+                    entry.encode(dst);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Frame::Simple(response) => response.fmt(fmt),
+            Frame::Error(msg) => write!(fmt, "error: {}", msg),
+            Frame::Integer(num) => num.fmt(fmt),
+            Frame::Bulk(msg) => match std::str::from_utf8(msg) {
+                Ok(string) => string.fmt(fmt),
+                Err(_) => write!(fmt, "{:?}", msg),
+            },
+            Frame::Null => "(nil)".fmt(fmt),
+            Frame::Double(num) => num.fmt(fmt),
+            Frame::Boolean(val) => val.fmt(fmt),
+            Frame::BigNumber(num) => num.fmt(fmt),
+            Frame::Verbatim(_, msg) => String::from_utf8_lossy(msg).fmt(fmt),
+            Frame::Map(pairs) => {
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    write!(fmt, "{} {}", key, value)?;
+                }
+                Ok(())
+            }
+            Frame::Array(parts) | Frame::Set(parts) | Frame::Push(parts) => {
+                for (i, part) in parts.iter().enumerate() {
+                    if i > 0 {
+                        write!(fmt, " ")?;
+                    }
+                    part.fmt(fmt)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn peek_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
+    if !src.has_remaining() {
+        return Err(Error::Incomplete);
+    }
+    Ok(src.chunk()[0])
+}
+
+fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
+    if !src.has_remaining() {
+        return Err(Error::Incomplete);
+    }
+    Ok(src.get_u8())
+}
+
+fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
+    if src.remaining() < n {
+        return Err(Error::Incomplete);
+    }
+    src.advance(n);
+    Ok(())
+}
+
+fn parse_items(src: &mut Cursor<&[u8]>) -> Result<Vec<Frame>, Error> {
+    let len: usize = get_decimal(src)?.try_into()?;
+    let mut out = Vec::with_capacity(len);
+    for _ in 0..len {
+        out.push(Frame::parse(src)?);
+    }
+    Ok(out)
+}
+
+// `$`、`!`、`=` 这类带长度前缀的内容
+fn get_blob(src: &mut Cursor<&[u8]>) -> Result<Bytes, Error> {
+    let len: usize = get_decimal(src)?.try_into()?;
+    let n = len + 2;
+    if src.remaining() < n {
+        return Err(Error::Incomplete);
+    }
+    let data = Bytes::copy_from_slice(&src.chunk()[..len]);
+    skip(src, n)?;
+    Ok(data)
+}
+
+fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
+    let line = get_line(src)?;
+    atoi(line).ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+fn get_signed(src: &mut Cursor<&[u8]>) -> Result<i64, Error> {
+    let line = get_line(src)?;
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| "protocol error; invalid frame format".into())
+}
+
+fn atoi(line: &[u8]) -> Option<u64> {
+    std::str::from_utf8(line).ok()?.parse().ok()
+}
+
+fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], Error> {
+    let start = src.position() as usize;
+    let end = src.get_ref().len();
+    if end < 2 {
+        return Err(Error::Incomplete);
+    }
+    for i in start..end - 1 {
+        if src.get_ref()[i] == b'\r' && src.get_ref()[i + 1] == b'\n' {
+            src.set_position((i + 2) as u64);
+            return Ok(&src.get_ref()[start..i]);
+        }
+    }
+    Err(Error::Incomplete)
+}
+
+impl From<String> for Error {
+    fn from(src: String) -> Error {
+        Error::Other(src.into())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(src: &str) -> Error {
+        src.to_string().into()
+    }
+}
+
+impl From<std::string::FromUtf8Error> for Error {
+    fn from(_src: std::string::FromUtf8Error) -> Error {
+        "protocol error; invalid frame format".into()
+    }
+}
+
+impl From<std::num::TryFromIntError> for Error {
+    fn from(_src: std::num::TryFromIntError) -> Error {
+        "protocol error; invalid frame format".into()
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Incomplete => "stream ended early".fmt(fmt),
+            Error::Other(err) => err.fmt(fmt),
+        }
+    }
+}
@@ -0,0 +1,1219 @@
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::client::{self, unexpected, Client};
+use crate::tls::ClientOptions;
+use crate::Frame;
+
+type Responder<T> = oneshot::Sender<crate::Result<T>>;
+
+struct Request {
+    args: Vec<Bytes>,
+    resp: Responder<Frame>,
+}
+
+macro_rules! args {
+    ($($arg:expr),* $(,)?) => {
+        vec![$(Bytes::copy_from_slice(AsRef::<[u8]>::as_ref(&$arg))),*]
+    };
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub addr: String,
+    pub tls: Option<ClientOptions>,
+    // 每次（重新）建立连接后自动 AUTH
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub timeout: Duration,
+    pub connect_retries: u32,
+    pub reconnect_delay: Duration,
+}
+
+impl Options {
+    pub fn new(addr: impl Into<String>) -> Options {
+        Options {
+            addr: addr.into(),
+            tls: None,
+            username: None,
+            password: None,
+            timeout: Duration::from_secs(5),
+            connect_retries: 3,
+            reconnect_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+/// 可以 clone 到多个任务里使用的客户端句柄，所有请求都交给后台的 manager 任务按顺序发送
+#[derive(Clone)]
+pub struct Handle {
+    tx: mpsc::Sender<Request>,
+    timeout: Duration,
+    reconnects: Arc<AtomicU64>,
+}
+
+pub enum SetSlot<'a> {
+    Importing(&'a str),
+    Migrating(&'a str),
+    Node(&'a str),
+    Stable,
+}
+
+/// CLIENT TRACKING ON 的选项，句柄的连接只能用 REDIRECT 把失效通知转给订阅连接
+#[derive(Default)]
+pub struct TrackingOptions<'a> {
+    pub redirect: Option<i64>,
+    pub prefixes: &'a [&'a str],
+    pub bcast: bool,
+    pub optin: bool,
+    pub optout: bool,
+    pub noloop: bool,
+}
+
+/// 把服务端的回复转换成具体类型
+pub trait FromFrame: Sized {
+    fn from_frame(frame: Frame) -> crate::Result<Self>;
+}
+
+impl FromFrame for Frame {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        Ok(frame)
+    }
+}
+
+impl FromFrame for () {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Simple(_) => Ok(()),
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+impl FromFrame for bool {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Integer(n) => Ok(n != 0),
+            Frame::Simple(_) => Ok(true),
+            Frame::Null => Ok(false),
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+impl FromFrame for i64 {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Integer(n) => Ok(n),
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+impl FromFrame for f64 {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Double(n) => Ok(n),
+            frame => Ok(String::from_frame(frame)?.parse()?),
+        }
+    }
+}
+
+impl FromFrame for Bytes {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Bulk(value) => Ok(value),
+            Frame::Simple(value) => Ok(value.into()),
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+impl FromFrame for String {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        let value = Bytes::from_frame(frame)?;
+        Ok(String::from_utf8(value.to_vec())?)
+    }
+}
+
+impl<T: FromFrame> FromFrame for Option<T> {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Null => Ok(None),
+            frame => Ok(Some(T::from_frame(frame)?)),
+        }
+    }
+}
+
+impl<T: FromFrame> FromFrame for Vec<T> {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Array(items) | Frame::Set(items) => items.into_iter().map(T::from_frame).collect(),
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+// 两个元素的数组，例如 GEOPOS 返回的经纬度
+impl<A: FromFrame, B: FromFrame> FromFrame for (A, B) {
+    fn from_frame(frame: Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Array(items) if items.len() == 2 => {
+                let mut items = items.into_iter();
+                Ok((A::from_frame(items.next().unwrap())?, B::from_frame(items.next().unwrap())?))
+            }
+            frame => Err(unexpected(frame)),
+        }
+    }
+}
+
+impl Handle {
+    pub fn new(options: Options) -> Handle {
+        let (tx, rx) = mpsc::channel(32);
+        let timeout = options.timeout;
+        let session = Session::default();
+        let reconnects = session.reconnects.clone();
+        tokio::spawn(run(options, session, rx));
+        Handle { tx, timeout, reconnects }
+    }
+
+    /// 所有请求共用一条连接，不等上一个回复就继续发送，按顺序把回复交给对应的请求
+    pub fn multiplexed(options: Options) -> Handle {
+        let (tx, rx) = mpsc::channel(1024);
+        let timeout = options.timeout;
+        let session = Session::default();
+        let reconnects = session.reconnects.clone();
+        tokio::spawn(run_multiplexed(options, session, rx));
+        Handle { tx, timeout, reconnects }
+    }
+
+    /// 连接断开后重新建立的次数。重连后会重放 HELLO、CLIENT SETNAME 和 CLIENT TRACKING，
+    /// 但断开期间的失效通知已经丢失，客户端缓存看到这个值变化时应该清空
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// 创建句柄并确认服务端可以连通
+    pub async fn connect(addr: impl Into<String>) -> crate::Result<Handle> {
+        let handle = Handle::new(Options::new(addr));
+        handle.ping(None).await?;
+        Ok(handle)
+    }
+
+    /// 发送任意命令，服务端返回的错误会转换成 Err
+    pub async fn call<T: FromFrame>(&self, args: Vec<Bytes>) -> crate::Result<T> {
+        let (resp, rx) = oneshot::channel();
+        self.tx
+            .send(Request { args, resp })
+            .await
+            .map_err(|_| "client manager has shut down")?;
+        let frame = match tokio::time::timeout(self.timeout, rx).await {
+            Ok(Ok(result)) => result?,
+            Ok(Err(_)) => return Err("client manager dropped the request".into()),
+            Err(_) => return Err("request timed out".into()),
+        };
+        match frame {
+            Frame::Error(msg) => Err(msg.into()),
+            frame => T::from_frame(frame),
+        }
+    }
+
+    pub async fn ping(&self, msg: Option<Bytes>) -> crate::Result<Bytes> {
+        match msg {
+            Some(msg) => self.call(args!(b"PING", msg)).await,
+            None => self.call(args!(b"PING")).await,
+        }
+    }
+
+    pub async fn get(&self, key: &str) -> crate::Result<Option<Bytes>> {
+        self.call(args!(b"GET", key)).await
+    }
+
+    pub async fn set(&self, key: &str, value: Bytes) -> crate::Result<()> {
+        self.call(args!(b"SET", key, value)).await
+    }
+
+    /// key 不存在时才写入，返回是否写入成功
+    pub async fn set_nx(&self, key: &str, value: Bytes) -> crate::Result<bool> {
+        self.call(args!(b"SET", key, value, b"NX")).await
+    }
+
+    /// 把整数值加上 increment（可以是负数），key 不存在时当作 0，返回新的值
+    pub async fn incr_by(&self, key: &str, increment: i64) -> crate::Result<i64> {
+        self.call(args!(b"INCRBY", key, increment.to_string())).await
+    }
+
+    /// key 已存在时才写入，返回是否写入成功
+    pub async fn set_xx(&self, key: &str, value: Bytes) -> crate::Result<bool> {
+        self.call(args!(b"SET", key, value, b"XX")).await
+    }
+
+    /// 写入并设置过期时间（SET key value PX ms）
+    pub async fn set_ex(&self, key: &str, value: Bytes, ttl: Duration) -> crate::Result<()> {
+        self.call(args!(b"SET", key, value, b"PX", ttl.as_millis().to_string())).await
+    }
+
+    pub async fn del(&self, keys: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"DEL");
+        args.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn exists(&self, keys: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"EXISTS");
+        args.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        self.call(args).await
+    }
+
+    /// 返回 key 是否存在并设置成功
+    pub async fn expire(&self, key: &str, ttl: Duration) -> crate::Result<bool> {
+        self.call(args!(b"PEXPIRE", key, ttl.as_millis().to_string())).await
+    }
+
+    pub async fn persist(&self, key: &str) -> crate::Result<bool> {
+        self.call(args!(b"PERSIST", key)).await
+    }
+
+    /// key 不存在或没有过期时间时返回 None
+    pub async fn ttl(&self, key: &str) -> crate::Result<Option<Duration>> {
+        let millis: i64 = self.call(args!(b"PTTL", key)).await?;
+        Ok(u64::try_from(millis).ok().map(Duration::from_millis))
+    }
+
+    pub async fn dbsize(&self) -> crate::Result<i64> {
+        self.call(args!(b"DBSIZE")).await
+    }
+
+    /// 和 del 一样，但大的值由服务端在后台释放
+    pub async fn unlink(&self, keys: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"UNLINK");
+        args.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn rename(&self, key: &str, new_key: &str) -> crate::Result<()> {
+        self.call(args!(b"RENAME", key, new_key)).await
+    }
+
+    /// 返回是否改名成功，new_key 已存在时不改名
+    pub async fn renamenx(&self, key: &str, new_key: &str) -> crate::Result<bool> {
+        self.call(args!(b"RENAMENX", key, new_key)).await
+    }
+
+    /// 返回是否复制成功
+    pub async fn copy(&self, source: &str, dest: &str, replace: bool) -> crate::Result<bool> {
+        let mut args = args!(b"COPY", source, dest);
+        if replace {
+            args.extend(args!(b"REPLACE"));
+        }
+        self.call(args).await
+    }
+
+    /// key 不存在时返回 "none"
+    pub async fn key_type(&self, key: &str) -> crate::Result<String> {
+        self.call(args!(b"TYPE", key)).await
+    }
+
+    pub async fn randomkey(&self) -> crate::Result<Option<Bytes>> {
+        self.call(args!(b"RANDOMKEY")).await
+    }
+
+    pub async fn touch(&self, keys: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"TOUCH");
+        args.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        self.call(args).await
+    }
+
+    /// 带版本和校验和的序列化值，可以交给 restore 在其它实例上恢复
+    pub async fn dump(&self, key: &str) -> crate::Result<Option<Bytes>> {
+        self.call(args!(b"DUMP", key)).await
+    }
+
+    /// ttl 为 None 时恢复出的 key 没有过期时间
+    pub async fn restore(&self, key: &str, ttl: Option<Duration>, payload: Bytes, replace: bool) -> crate::Result<()> {
+        let ttl = ttl.map_or(0, |ttl| ttl.as_millis().max(1));
+        let mut args = args!(b"RESTORE", key, ttl.to_string(), payload);
+        if replace {
+            args.extend(args!(b"REPLACE"));
+        }
+        self.call(args).await
+    }
+
+    pub async fn object_encoding(&self, key: &str) -> crate::Result<Option<String>> {
+        self.call(args!(b"OBJECT", b"ENCODING", key)).await
+    }
+
+    /// 距离上次访问的秒数，LFU 淘汰策略下服务端返回错误
+    pub async fn object_idletime(&self, key: &str) -> crate::Result<Option<i64>> {
+        self.call(args!(b"OBJECT", b"IDLETIME", key)).await
+    }
+
+    /// 对数访问计数，只在 LFU 淘汰策略下可用
+    pub async fn object_freq(&self, key: &str) -> crate::Result<Option<i64>> {
+        self.call(args!(b"OBJECT", b"FREQ", key)).await
+    }
+
+    /// key 和值估算占用的字节数，key 不存在时返回 None
+    pub async fn memory_usage(&self, key: &str) -> crate::Result<Option<i64>> {
+        self.call(args!(b"MEMORY", b"USAGE", key)).await
+    }
+
+    /// 返回匹配的参数名和值
+    pub async fn config_get(&self, pattern: &str) -> crate::Result<Vec<(String, String)>> {
+        let frame: Frame = self.call(args!(b"CONFIG", b"GET", pattern)).await?;
+        let items = match frame {
+            Frame::Map(pairs) => pairs.into_iter().flat_map(|(k, v)| [k, v]).collect(),
+            Frame::Array(items) => items,
+            frame => return Err(unexpected(frame)),
+        };
+        let mut items = items.into_iter().map(String::from_frame);
+        let mut pairs = Vec::new();
+        while let (Some(name), Some(value)) = (items.next(), items.next()) {
+            pairs.push((name?, value?));
+        }
+        Ok(pairs)
+    }
+
+    pub async fn config_set(&self, name: &str, value: &str) -> crate::Result<()> {
+        self.call(args!(b"CONFIG", b"SET", name, value)).await
+    }
+
+    /// 返回 HyperLogLog 是否有变化
+    pub async fn pfadd(&self, key: &str, elements: &[&[u8]]) -> crate::Result<bool> {
+        let mut args = args!(b"PFADD", key);
+        args.extend(elements.iter().map(|element| Bytes::copy_from_slice(element)));
+        self.call(args).await
+    }
+
+    /// 多个 key 时返回并集的基数估计
+    pub async fn pfcount(&self, keys: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"PFCOUNT");
+        args.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn pfmerge(&self, dest: &str, sources: &[&str]) -> crate::Result<()> {
+        let mut args = args!(b"PFMERGE", dest);
+        args.extend(sources.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        self.call(args).await
+    }
+
+    /// 返回这一位原来的值
+    pub async fn setbit(&self, key: &str, offset: u64, bit: bool) -> crate::Result<bool> {
+        let bit = if bit { "1" } else { "0" };
+        self.call(args!(b"SETBIT", key, offset.to_string(), bit)).await
+    }
+
+    pub async fn getbit(&self, key: &str, offset: u64) -> crate::Result<bool> {
+        self.call(args!(b"GETBIT", key, offset.to_string())).await
+    }
+
+    /// range 是按字节计算的闭区间，可以为负数
+    pub async fn bitcount(&self, key: &str, range: Option<(i64, i64)>) -> crate::Result<i64> {
+        let mut args = args!(b"BITCOUNT", key);
+        if let Some((start, end)) = range {
+            args.extend(args!(start.to_string(), end.to_string()));
+        }
+        self.call(args).await
+    }
+
+    /// 找不到时返回 -1
+    pub async fn bitpos(&self, key: &str, bit: bool, range: Option<(i64, i64)>) -> crate::Result<i64> {
+        let mut args = args!(b"BITPOS", key, if bit { "1" } else { "0" });
+        if let Some((start, end)) = range {
+            args.extend(args!(start.to_string(), end.to_string()));
+        }
+        self.call(args).await
+    }
+
+    /// op 是 AND、OR、XOR 或 NOT，返回结果字符串的长度
+    pub async fn bitop(&self, op: &str, dest: &str, keys: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"BITOP", op, dest);
+        args.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        self.call(args).await
+    }
+
+    /// ops 是子命令序列，例如 ["INCRBY", "u8", "#0", "1", "GET", "u8", "#1"]；OVERFLOW FAIL 失败时对应 None
+    pub async fn bitfield(&self, key: &str, ops: &[&str]) -> crate::Result<Vec<Option<i64>>> {
+        let mut args = args!(b"BITFIELD", key);
+        args.extend(ops.iter().map(|op| Bytes::copy_from_slice(op.as_bytes())));
+        self.call(args).await
+    }
+
+    /// items 是（经度，纬度，成员），返回新增的成员数
+    pub async fn geoadd(&self, key: &str, items: &[(f64, f64, &str)]) -> crate::Result<i64> {
+        let mut args = args!(b"GEOADD", key);
+        for (lon, lat, member) in items {
+            args.extend(args!(lon.to_string(), lat.to_string(), member));
+        }
+        self.call(args).await
+    }
+
+    /// 两个成员之间的距离（米），任意一个不存在时返回 None
+    pub async fn geodist(&self, key: &str, member1: &str, member2: &str) -> crate::Result<Option<f64>> {
+        self.call(args!(b"GEODIST", key, member1, member2)).await
+    }
+
+    /// 每个成员的（经度，纬度）
+    pub async fn geopos(&self, key: &str, members: &[&str]) -> crate::Result<Vec<Option<(f64, f64)>>> {
+        let mut args = args!(b"GEOPOS", key);
+        args.extend(members.iter().map(|member| Bytes::copy_from_slice(member.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn geohash(&self, key: &str, members: &[&str]) -> crate::Result<Vec<Option<String>>> {
+        let mut args = args!(b"GEOHASH", key);
+        args.extend(members.iter().map(|member| Bytes::copy_from_slice(member.as_bytes())));
+        self.call(args).await
+    }
+
+    /// 距离（经度，纬度）radius 米以内的成员和距离，从近到远
+    pub async fn geosearch_radius(
+        &self,
+        key: &str,
+        (lon, lat): (f64, f64),
+        radius: f64,
+        count: Option<usize>,
+    ) -> crate::Result<Vec<(String, f64)>> {
+        let mut args = args!(b"GEOSEARCH", key, b"FROMLONLAT", lon.to_string(), lat.to_string());
+        args.extend(args!(b"BYRADIUS", radius.to_string(), b"m", b"ASC", b"WITHDIST"));
+        if let Some(count) = count {
+            args.extend(args!(b"COUNT", count.to_string()));
+        }
+        self.call(args).await
+    }
+
+    /// 以（经度，纬度）为中心、width x height 米的矩形内的成员和距离，从近到远
+    pub async fn geosearch_box(
+        &self,
+        key: &str,
+        (lon, lat): (f64, f64),
+        (width, height): (f64, f64),
+        count: Option<usize>,
+    ) -> crate::Result<Vec<(String, f64)>> {
+        let mut args = args!(b"GEOSEARCH", key, b"FROMLONLAT", lon.to_string(), lat.to_string());
+        args.extend(args!(b"BYBOX", width.to_string(), height.to_string(), b"m", b"ASC", b"WITHDIST"));
+        if let Some(count) = count {
+            args.extend(args!(b"COUNT", count.to_string()));
+        }
+        self.call(args).await
+    }
+
+    /// pairs 是（字段，值），返回新增的字段数
+    pub async fn hset(&self, key: &str, pairs: &[(&str, &[u8])]) -> crate::Result<i64> {
+        let mut args = args!(b"HSET", key);
+        for (field, value) in pairs {
+            args.extend(args!(field, value));
+        }
+        self.call(args).await
+    }
+
+    pub async fn hget(&self, key: &str, field: &str) -> crate::Result<Option<Bytes>> {
+        self.call(args!(b"HGET", key, field)).await
+    }
+
+    /// 所有的（字段，值），顺序由服务端的编码决定
+    pub async fn hgetall(&self, key: &str) -> crate::Result<Vec<(Bytes, Bytes)>> {
+        let frame: Frame = self.call(args!(b"HGETALL", key)).await?;
+        let items = match frame {
+            Frame::Map(pairs) => pairs.into_iter().flat_map(|(k, v)| [k, v]).collect(),
+            Frame::Array(items) => items,
+            frame => return Err(unexpected(frame)),
+        };
+        let mut items = items.into_iter().map(Bytes::from_frame);
+        let mut pairs = Vec::new();
+        while let (Some(field), Some(value)) = (items.next(), items.next()) {
+            pairs.push((field?, value?));
+        }
+        Ok(pairs)
+    }
+
+    pub async fn hdel(&self, key: &str, fields: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"HDEL", key);
+        args.extend(fields.iter().map(|field| Bytes::copy_from_slice(field.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn hlen(&self, key: &str) -> crate::Result<i64> {
+        self.call(args!(b"HLEN", key)).await
+    }
+
+    /// 返回新加入的成员数
+    pub async fn sadd(&self, key: &str, members: &[&[u8]]) -> crate::Result<i64> {
+        let mut args = args!(b"SADD", key);
+        args.extend(members.iter().map(|member| Bytes::copy_from_slice(member)));
+        self.call(args).await
+    }
+
+    pub async fn srem(&self, key: &str, members: &[&[u8]]) -> crate::Result<i64> {
+        let mut args = args!(b"SREM", key);
+        args.extend(members.iter().map(|member| Bytes::copy_from_slice(member)));
+        self.call(args).await
+    }
+
+    pub async fn sismember(&self, key: &str, member: &[u8]) -> crate::Result<bool> {
+        self.call(args!(b"SISMEMBER", key, member)).await
+    }
+
+    pub async fn smembers(&self, key: &str) -> crate::Result<Vec<Bytes>> {
+        self.call(args!(b"SMEMBERS", key)).await
+    }
+
+    pub async fn scard(&self, key: &str) -> crate::Result<i64> {
+        self.call(args!(b"SCARD", key)).await
+    }
+
+    /// 依次插到表头，返回插入后的长度
+    pub async fn lpush(&self, key: &str, values: &[&[u8]]) -> crate::Result<i64> {
+        let mut args = args!(b"LPUSH", key);
+        args.extend(values.iter().map(|value| Bytes::copy_from_slice(value)));
+        self.call(args).await
+    }
+
+    /// 依次追加到表尾，返回插入后的长度
+    pub async fn rpush(&self, key: &str, values: &[&[u8]]) -> crate::Result<i64> {
+        let mut args = args!(b"RPUSH", key);
+        args.extend(values.iter().map(|value| Bytes::copy_from_slice(value)));
+        self.call(args).await
+    }
+
+    pub async fn lpop(&self, key: &str) -> crate::Result<Option<Bytes>> {
+        self.call(args!(b"LPOP", key)).await
+    }
+
+    pub async fn rpop(&self, key: &str) -> crate::Result<Option<Bytes>> {
+        self.call(args!(b"RPOP", key)).await
+    }
+
+    /// start 和 stop 都包含在内，可以为负数
+    pub async fn lrange(&self, key: &str, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        self.call(args!(b"LRANGE", key, start.to_string(), stop.to_string())).await
+    }
+
+    pub async fn llen(&self, key: &str) -> crate::Result<i64> {
+        self.call(args!(b"LLEN", key)).await
+    }
+
+    /// 写入（分数，成员），返回新加入的成员数
+    pub async fn zadd(&self, key: &str, items: &[(f64, &[u8])]) -> crate::Result<i64> {
+        let mut args = args!(b"ZADD", key);
+        for (score, member) in items {
+            args.extend(args!(score.to_string(), member));
+        }
+        self.call(args).await
+    }
+
+    pub async fn zrem(&self, key: &str, members: &[&[u8]]) -> crate::Result<i64> {
+        let mut args = args!(b"ZREM", key);
+        args.extend(members.iter().map(|member| Bytes::copy_from_slice(member)));
+        self.call(args).await
+    }
+
+    pub async fn zscore(&self, key: &str, member: &[u8]) -> crate::Result<Option<f64>> {
+        self.call(args!(b"ZSCORE", key, member)).await
+    }
+
+    pub async fn zcard(&self, key: &str) -> crate::Result<i64> {
+        self.call(args!(b"ZCARD", key)).await
+    }
+
+    /// 按分数从小到大的下标范围，两端都包含
+    pub async fn zrange(&self, key: &str, start: i64, stop: i64) -> crate::Result<Vec<Bytes>> {
+        self.call(args!(b"ZRANGE", key, start.to_string(), stop.to_string())).await
+    }
+
+    pub async fn eval<T: FromFrame>(&self, script: &str, keys: &[&str], args: &[&[u8]]) -> crate::Result<T> {
+        let mut cmd = args!(b"EVAL", script, keys.len().to_string());
+        cmd.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        cmd.extend(args.iter().map(|arg| Bytes::copy_from_slice(arg)));
+        self.call(cmd).await
+    }
+
+    /// 脚本不在服务端缓存里时返回 NOSCRIPT 错误，需要先 script_load 或改用 eval
+    pub async fn evalsha<T: FromFrame>(&self, sha: &str, keys: &[&str], args: &[&[u8]]) -> crate::Result<T> {
+        let mut cmd = args!(b"EVALSHA", sha, keys.len().to_string());
+        cmd.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        cmd.extend(args.iter().map(|arg| Bytes::copy_from_slice(arg)));
+        self.call(cmd).await
+    }
+
+    /// 返回脚本的 SHA1
+    pub async fn script_load(&self, script: &str) -> crate::Result<String> {
+        self.call(args!(b"SCRIPT", b"LOAD", script)).await
+    }
+
+    pub async fn script_exists(&self, shas: &[&str]) -> crate::Result<Vec<bool>> {
+        let mut args = args!(b"SCRIPT", b"EXISTS");
+        args.extend(shas.iter().map(|sha| Bytes::copy_from_slice(sha.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn script_flush(&self) -> crate::Result<()> {
+        self.call(args!(b"SCRIPT", b"FLUSH")).await
+    }
+
+    pub async fn script_kill(&self) -> crate::Result<()> {
+        self.call(args!(b"SCRIPT", b"KILL")).await
+    }
+
+    pub async fn acl_setuser(&self, name: &str, rules: &[&str]) -> crate::Result<()> {
+        let mut args = args!(b"ACL", b"SETUSER", name);
+        args.extend(rules.iter().map(|rule| Bytes::copy_from_slice(rule.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn acl_getuser(&self, name: &str) -> crate::Result<Option<Frame>> {
+        self.call(args!(b"ACL", b"GETUSER", name)).await
+    }
+
+    pub async fn acl_deluser(&self, names: &[&str]) -> crate::Result<i64> {
+        let mut args = args!(b"ACL", b"DELUSER");
+        args.extend(names.iter().map(|name| Bytes::copy_from_slice(name.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn acl_list(&self) -> crate::Result<Vec<String>> {
+        self.call(args!(b"ACL", b"LIST")).await
+    }
+
+    pub async fn acl_users(&self) -> crate::Result<Vec<String>> {
+        self.call(args!(b"ACL", b"USERS")).await
+    }
+
+    pub async fn acl_whoami(&self) -> crate::Result<String> {
+        self.call(args!(b"ACL", b"WHOAMI")).await
+    }
+
+    pub async fn acl_cat(&self, category: Option<&str>) -> crate::Result<Vec<String>> {
+        match category {
+            Some(category) => self.call(args!(b"ACL", b"CAT", category)).await,
+            None => self.call(args!(b"ACL", b"CAT")).await,
+        }
+    }
+
+    pub async fn acl_log(&self, count: usize) -> crate::Result<Frame> {
+        self.call(args!(b"ACL", b"LOG", count.to_string())).await
+    }
+
+    pub async fn acl_log_reset(&self) -> crate::Result<()> {
+        self.call(args!(b"ACL", b"LOG", b"RESET")).await
+    }
+
+    pub async fn acl_save(&self) -> crate::Result<()> {
+        self.call(args!(b"ACL", b"SAVE")).await
+    }
+
+    pub async fn acl_load(&self) -> crate::Result<()> {
+        self.call(args!(b"ACL", b"LOAD")).await
+    }
+
+    pub async fn client_id(&self) -> crate::Result<i64> {
+        self.call(args!(b"CLIENT", b"ID")).await
+    }
+
+    pub async fn client_setname(&self, name: &str) -> crate::Result<()> {
+        self.call(args!(b"CLIENT", b"SETNAME", name)).await
+    }
+
+    pub async fn client_getname(&self) -> crate::Result<Option<String>> {
+        self.call(args!(b"CLIENT", b"GETNAME")).await
+    }
+
+    pub async fn client_list(&self) -> crate::Result<String> {
+        self.call(args!(b"CLIENT", b"LIST")).await
+    }
+
+    pub async fn client_info(&self) -> crate::Result<String> {
+        self.call(args!(b"CLIENT", b"INFO")).await
+    }
+
+    pub async fn client_tracking(&self, options: &TrackingOptions<'_>) -> crate::Result<()> {
+        let mut args = args!(b"CLIENT", b"TRACKING", b"ON");
+        if let Some(id) = options.redirect {
+            args.extend(args!(b"REDIRECT", id.to_string()));
+        }
+        for prefix in options.prefixes {
+            args.extend(args!(b"PREFIX", prefix));
+        }
+        for (set, flag) in [
+            (options.bcast, "BCAST"),
+            (options.optin, "OPTIN"),
+            (options.optout, "OPTOUT"),
+            (options.noloop, "NOLOOP"),
+        ] {
+            if set {
+                args.extend(args!(flag));
+            }
+        }
+        self.call(args).await
+    }
+
+    pub async fn client_tracking_off(&self) -> crate::Result<()> {
+        self.call(args!(b"CLIENT", b"TRACKING", b"OFF")).await
+    }
+
+    pub async fn client_caching(&self, yes: bool) -> crate::Result<()> {
+        let arg: &[u8] = if yes { b"YES" } else { b"NO" };
+        self.call(args!(b"CLIENT", b"CACHING", arg)).await
+    }
+
+    pub async fn client_getredir(&self) -> crate::Result<i64> {
+        self.call(args!(b"CLIENT", b"GETREDIR")).await
+    }
+
+    pub async fn client_trackinginfo(&self) -> crate::Result<Frame> {
+        self.call(args!(b"CLIENT", b"TRACKINGINFO")).await
+    }
+
+    /// 不切换协议版本，只返回服务端信息
+    pub async fn hello(&self) -> crate::Result<Frame> {
+        self.call(args!(b"HELLO")).await
+    }
+
+    pub async fn publish(&self, channel: &str, message: Bytes) -> crate::Result<i64> {
+        self.call(args!(b"PUBLISH", channel, message)).await
+    }
+
+    pub async fn pubsub_channels(&self, pattern: Option<&str>) -> crate::Result<Vec<Bytes>> {
+        match pattern {
+            Some(pattern) => self.call(args!(b"PUBSUB", b"CHANNELS", pattern)).await,
+            None => self.call(args!(b"PUBSUB", b"CHANNELS")).await,
+        }
+    }
+
+    /// 返回 [channel, count, channel, count, ...]
+    pub async fn pubsub_numsub(&self, channels: &[&str]) -> crate::Result<Frame> {
+        let mut args = args!(b"PUBSUB", b"NUMSUB");
+        args.extend(channels.iter().map(|channel| Bytes::copy_from_slice(channel.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn pubsub_numpat(&self) -> crate::Result<i64> {
+        self.call(args!(b"PUBSUB", b"NUMPAT")).await
+    }
+
+    pub async fn command_count(&self) -> crate::Result<i64> {
+        self.call(args!(b"COMMAND", b"COUNT")).await
+    }
+
+    pub async fn command_info(&self, names: &[&str]) -> crate::Result<Frame> {
+        let mut args = args!(b"COMMAND", b"INFO");
+        args.extend(names.iter().map(|name| Bytes::copy_from_slice(name.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn command_docs(&self, names: &[&str]) -> crate::Result<Frame> {
+        let mut args = args!(b"COMMAND", b"DOCS");
+        args.extend(names.iter().map(|name| Bytes::copy_from_slice(name.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn command_getkeys(&self, command: &[&str]) -> crate::Result<Vec<Bytes>> {
+        let mut args = args!(b"COMMAND", b"GETKEYS");
+        args.extend(command.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())));
+        self.call(args).await
+    }
+
+    pub async fn cluster_keyslot(&self, key: &str) -> crate::Result<i64> {
+        self.call(args!(b"CLUSTER", b"KEYSLOT", key)).await
+    }
+
+    pub async fn cluster_myid(&self) -> crate::Result<String> {
+        self.call(args!(b"CLUSTER", b"MYID")).await
+    }
+
+    pub async fn cluster_nodes(&self) -> crate::Result<String> {
+        self.call(args!(b"CLUSTER", b"NODES")).await
+    }
+
+    pub async fn cluster_slots(&self) -> crate::Result<Frame> {
+        self.call(args!(b"CLUSTER", b"SLOTS")).await
+    }
+
+    pub async fn cluster_info(&self) -> crate::Result<String> {
+        self.call(args!(b"CLUSTER", b"INFO")).await
+    }
+
+    pub async fn cluster_meet(&self, ip: &str, port: u16) -> crate::Result<()> {
+        self.call(args!(b"CLUSTER", b"MEET", ip, port.to_string())).await
+    }
+
+    pub async fn cluster_addslots(&self, slots: &[u16]) -> crate::Result<()> {
+        let mut args = args!(b"CLUSTER", b"ADDSLOTS");
+        args.extend(slots.iter().map(|slot| Bytes::from(slot.to_string())));
+        self.call(args).await
+    }
+
+    pub async fn cluster_delslots(&self, slots: &[u16]) -> crate::Result<()> {
+        let mut args = args!(b"CLUSTER", b"DELSLOTS");
+        args.extend(slots.iter().map(|slot| Bytes::from(slot.to_string())));
+        self.call(args).await
+    }
+
+    pub async fn cluster_setslot(&self, slot: u16, state: SetSlot<'_>) -> crate::Result<()> {
+        let slot = slot.to_string();
+        let args = match state {
+            SetSlot::Importing(id) => args!(b"CLUSTER", b"SETSLOT", slot, b"IMPORTING", id),
+            SetSlot::Migrating(id) => args!(b"CLUSTER", b"SETSLOT", slot, b"MIGRATING", id),
+            SetSlot::Node(id) => args!(b"CLUSTER", b"SETSLOT", slot, b"NODE", id),
+            SetSlot::Stable => args!(b"CLUSTER", b"SETSLOT", slot, b"STABLE"),
+        };
+        self.call(args).await
+    }
+
+    pub async fn cluster_countkeysinslot(&self, slot: u16) -> crate::Result<i64> {
+        self.call(args!(b"CLUSTER", b"COUNTKEYSINSLOT", slot.to_string())).await
+    }
+
+    pub async fn cluster_getkeysinslot(&self, slot: u16, count: usize) -> crate::Result<Vec<Bytes>> {
+        self.call(args!(b"CLUSTER", b"GETKEYSINSLOT", slot.to_string(), count.to_string()))
+            .await
+    }
+
+    /// 返回 false 表示本地一个 key 都不存在（NOKEY）
+    pub async fn migrate(
+        &self,
+        host: &str,
+        port: u16,
+        keys: &[&str],
+        timeout: Duration,
+        copy: bool,
+        replace: bool,
+    ) -> crate::Result<bool> {
+        let mut args = args!(b"MIGRATE", host, port.to_string(), b"", b"0", timeout.as_millis().to_string());
+        if copy {
+            args.push(Bytes::from_static(b"COPY"));
+        }
+        if replace {
+            args.push(Bytes::from_static(b"REPLACE"));
+        }
+        args.push(Bytes::from_static(b"KEYS"));
+        args.extend(keys.iter().map(|key| Bytes::copy_from_slice(key.as_bytes())));
+        let reply: String = self.call(args).await?;
+        Ok(reply != "NOKEY")
+    }
+
+    pub async fn replicaof(&self, host: &str, port: u16) -> crate::Result<()> {
+        let _: String = self.call(args!(b"REPLICAOF", host, port.to_string())).await?;
+        Ok(())
+    }
+
+    pub async fn replicaof_no_one(&self) -> crate::Result<()> {
+        self.call(args!(b"REPLICAOF", b"NO", b"ONE")).await
+    }
+
+    /// 主节点返回 ["master", offset, [[ip, port, offset], ...]]，副本返回 ["slave", host, port, state, offset]
+    pub async fn role(&self) -> crate::Result<Frame> {
+        self.call(args!(b"ROLE")).await
+    }
+
+    /// 返回确认收到之前所有写命令的副本数，timeout 需要小于句柄的请求超时
+    pub async fn wait(&self, numreplicas: usize, timeout: Duration) -> crate::Result<i64> {
+        self.call(args!(b"WAIT", numreplicas.to_string(), timeout.as_millis().to_string())).await
+    }
+
+    pub async fn info(&self, section: &str) -> crate::Result<String> {
+        self.call(args!(b"INFO", section)).await
+    }
+}
+
+/// 在连接上设置过的状态，重新连接后按顺序重放
+#[derive(Default)]
+struct Session {
+    hello: Option<Vec<Bytes>>,
+    name: Option<Vec<Bytes>>,
+    tracking: Option<Vec<Bytes>>,
+    connected: bool,
+    reconnects: Arc<AtomicU64>,
+}
+
+impl Session {
+    // 会改变连接状态、需要在重连后重放的命令
+    fn affects(args: &[Bytes]) -> bool {
+        let arg = |i: usize| args.get(i).map(|arg| arg.to_ascii_uppercase());
+        match arg(0).as_deref() {
+            // 不带参数的 HELLO 不切换协议
+            Some(b"HELLO") => args.len() > 1,
+            Some(b"CLIENT") => matches!(arg(1).as_deref(), Some(b"SETNAME" | b"TRACKING")),
+            _ => false,
+        }
+    }
+
+    /// 命令执行成功后记录下来
+    fn record(&mut self, args: &[Bytes]) {
+        if !Session::affects(args) {
+            return;
+        }
+        let command = Some(args.to_vec());
+        if args[0].eq_ignore_ascii_case(b"HELLO") {
+            self.hello = command;
+        } else if args[1].eq_ignore_ascii_case(b"SETNAME") {
+            self.name = command;
+        } else if args.get(2).is_some_and(|arg| arg.eq_ignore_ascii_case(b"ON")) {
+            self.tracking = command;
+        } else {
+            self.tracking = None;
+        }
+    }
+
+    async fn connect(&mut self, options: &Options) -> crate::Result<Client> {
+        let mut client = connect(options).await?;
+        let restore = async {
+            for args in [&self.hello, &self.name, &self.tracking].into_iter().flatten() {
+                if let Frame::Error(msg) = client.call(args).await? {
+                    return Err(format!("failed to restore connection state: {}", msg).into());
+                }
+            }
+            Ok::<(), crate::Error>(())
+        };
+        match tokio::time::timeout(options.timeout, restore).await {
+            Ok(result) => result?,
+            Err(_) => return Err("timed out restoring connection state".into()),
+        }
+        if self.connected {
+            self.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        self.connected = true;
+        Ok(client)
+    }
+}
+
+async fn run(options: Options, mut session: Session, mut rx: mpsc::Receiver<Request>) {
+    let mut client: Option<Client> = None;
+
+    while let Some(Request { args, resp }) = rx.recv().await {
+        if client.is_none() {
+            match session.connect(&options).await {
+                Ok(connected) => client = Some(connected),
+                Err(err) => {
+                    let _ = resp.send(Err(err));
+                    continue;
+                }
+            }
+        }
+        let connection = client.as_mut().unwrap();
+        let result = match tokio::time::timeout(options.timeout, connection.call(&args)).await {
+            Ok(Ok(frame)) => Ok(frame),
+            Ok(Err(err)) => Err(err),
+            Err(_) => Err("request timed out".into()),
+        };
+        // 连接出错或超时后回复已经对不上了，丢掉连接，下一个请求重新连接
+        match &result {
+            Ok(Frame::Error(_)) => {}
+            Ok(_) => session.record(&args),
+            Err(_) => client = None,
+        }
+        let _ = resp.send(result);
+    }
+}
+
+// 一次最多连续写出多少条命令再 flush
+const MAX_BATCH: usize = 128;
+
+// 已经发出、还在等回复的请求，会改变连接状态的请求带上参数，收到回复后记录到 Session
+type Pending = VecDeque<(Responder<Frame>, Option<Vec<Bytes>>)>;
+
+async fn run_multiplexed(options: Options, mut session: Session, mut rx: mpsc::Receiver<Request>) {
+    let mut client: Option<Client> = None;
+    // 服务端按发送顺序回复
+    let mut pending = Pending::new();
+
+    loop {
+        let connection = match client.as_mut() {
+            Some(connection) => connection,
+            None => {
+                let request = match rx.recv().await {
+                    Some(request) => request,
+                    None => return,
+                };
+                match session.connect(&options).await {
+                    Ok(connected) => {
+                        let connection = client.insert(connected);
+                        if let Err(err) = send_batch(connection, request, &mut rx, &mut pending).await {
+                            fail_pending(&mut pending, err);
+                            client = None;
+                        }
+                    }
+                    Err(err) => {
+                        let _ = request.resp.send(Err(err));
+                    }
+                }
+                continue;
+            }
+        };
+
+        let result = tokio::select! {
+            request = rx.recv() => match request {
+                Some(request) => send_batch(connection, request, &mut rx, &mut pending).await,
+                None => {
+                    // 句柄都已经释放，把已发出请求的回复收完再退出
+                    drain(connection, &mut pending, options.timeout).await;
+                    return;
+                }
+            },
+            frame = connection.read_reply(), if !pending.is_empty() => frame.map(|frame| {
+                let (resp, args) = pending.pop_front().unwrap();
+                if let (Some(args), false) = (args, matches!(frame, Frame::Error(_))) {
+                    session.record(&args);
+                }
+                let _ = resp.send(Ok(frame));
+            }),
+        };
+        if let Err(err) = result {
+            fail_pending(&mut pending, err);
+            client = None;
+        }
+    }
+}
+
+/// 写出这个请求以及队列里已经在等待的请求，最后统一 flush
+async fn send_batch(
+    connection: &mut Client,
+    first: Request,
+    rx: &mut mpsc::Receiver<Request>,
+    pending: &mut Pending,
+) -> crate::Result<()> {
+    let mut next = Some(first);
+    let mut sent = 0;
+    while let Some(request) = next.take() {
+        if let Err(err) = connection.send(&request.args).await {
+            let _ = request.resp.send(Err(err.to_string().into()));
+            return Err(err);
+        }
+        let args = Session::affects(&request.args).then_some(request.args);
+        pending.push_back((request.resp, args));
+        sent += 1;
+        if sent < MAX_BATCH {
+            next = rx.try_recv().ok();
+        }
+    }
+    connection.flush().await
+}
+
+// 连接断开时所有还没收到回复的请求都失败，下一个请求会重新连接
+fn fail_pending(pending: &mut Pending, err: crate::Error) {
+    let msg = err.to_string();
+    for (resp, _) in pending.drain(..) {
+        let _ = resp.send(Err(msg.clone().into()));
+    }
+}
+
+async fn drain(connection: &mut Client, pending: &mut Pending, timeout: Duration) {
+    while let Some((resp, _)) = pending.pop_front() {
+        match tokio::time::timeout(timeout, connection.read_reply()).await {
+            Ok(Ok(frame)) => {
+                let _ = resp.send(Ok(frame));
+            }
+            _ => return,
+        }
+    }
+}
+
+pub(crate) async fn connect(options: &Options) -> crate::Result<Client> {
+    let mut delay = options.reconnect_delay;
+    let mut attempt = 0;
+    loop {
+        let result = async {
+            let mut client = client::connect_with(options.addr.as_str(), options.tls.as_ref()).await?;
+            if let Some(password) = &options.password {
+                match &options.username {
+                    Some(username) => client.request(&[b"AUTH", username.as_bytes(), password.as_bytes()]).await?,
+                    None => client.request(&[b"AUTH", password.as_bytes()]).await?,
+                };
+            }
+            Ok::<Client, crate::Error>(client)
+        };
+        match tokio::time::timeout(options.timeout, result).await {
+            Ok(Ok(client)) => return Ok(client),
+            Ok(Err(err)) if attempt >= options.connect_retries => return Err(err),
+            Err(_) if attempt >= options.connect_retries => return Err("connect timed out".into()),
+            _ => {}
+        }
+        attempt += 1;
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Connection;
+    use std::sync::Mutex;
+    use tokio::net::TcpListener;
+
+    // 记录每条连接收到的命令，收到 DROP 时不回复直接断开，其它命令都回复 OK
+    async fn server() -> (Options, Arc<Mutex<Vec<Vec<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let options = Options::new(listener.local_addr().unwrap().to_string());
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let connections = log.clone();
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let index = {
+                    let mut log = connections.lock().unwrap();
+                    log.push(Vec::new());
+                    log.len() - 1
+                };
+                let log = connections.clone();
+                tokio::spawn(async move {
+                    let mut connection = Connection::new(socket);
+                    while let Ok(Some(Frame::Array(parts))) = connection.read_frame().await {
+                        let command = parts
+                            .iter()
+                            .map(|part| match part {
+                                Frame::Bulk(data) => String::from_utf8_lossy(data).into_owned(),
+                                _ => String::new(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        if command == "DROP" {
+                            return;
+                        }
+                        log.lock().unwrap()[index].push(command);
+                        let _ = connection.write_frame(&Frame::ok()).await;
+                    }
+                });
+            }
+        });
+        (options, log)
+    }
+
+    async fn replays_state_after_reconnect(handle: Handle, log: Arc<Mutex<Vec<Vec<String>>>>) {
+        handle.call::<Frame>(args!(b"HELLO", b"3")).await.unwrap();
+        handle.client_setname("worker").await.unwrap();
+        handle.client_tracking(&TrackingOptions { redirect: Some(7), ..Default::default() }).await.unwrap();
+        assert_eq!(handle.reconnects(), 0);
+
+        assert!(handle.call::<Frame>(args!(b"DROP")).await.is_err());
+        handle.ping(None).await.unwrap();
+        assert_eq!(handle.reconnects(), 1);
+        assert_eq!(
+            log.lock().unwrap()[1],
+            ["HELLO 3", "CLIENT SETNAME worker", "CLIENT TRACKING ON REDIRECT 7", "PING"]
+        );
+
+        handle.client_tracking_off().await.unwrap();
+        assert!(handle.call::<Frame>(args!(b"DROP")).await.is_err());
+        handle.ping(None).await.unwrap();
+        assert_eq!(handle.reconnects(), 2);
+        assert_eq!(log.lock().unwrap()[2], ["HELLO 3", "CLIENT SETNAME worker", "PING"]);
+    }
+
+    #[tokio::test]
+    async fn reconnect_restores_session() {
+        let (options, log) = server().await;
+        replays_state_after_reconnect(Handle::new(options), log).await;
+    }
+
+    #[tokio::test]
+    async fn multiplexed_reconnect_restores_session() {
+        let (options, log) = server().await;
+        replays_state_after_reconnect(Handle::multiplexed(options), log).await;
+    }
+}
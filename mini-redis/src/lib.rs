@@ -0,0 +1,19 @@
+pub mod client;
+pub mod connection;
+pub mod frame;
+pub mod handle;
+pub mod pool;
+pub mod tls;
+
+pub use connection::Connection;
+pub use frame::Frame;
+pub use handle::Handle;
+pub use pool::Pool;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// 服务端和客户端都可以用的双向字节流，TCP 和 TLS 连接都满足这个约束
+pub trait Stream: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send {}
+
+impl<T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send> Stream for T {}
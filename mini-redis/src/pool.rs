@@ -0,0 +1,197 @@
+use bytes::Bytes;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::client::Client;
+use crate::handle::{self, Options};
+use crate::Frame;
+
+#[derive(Debug, Clone)]
+pub struct PoolOptions {
+    pub max_size: usize,
+    // 空闲超过这个时间的连接取出时先 PING 一下
+    pub health_check_interval: Duration,
+    // 连接全部被占用时最多等多久
+    pub wait_timeout: Duration,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        PoolOptions {
+            max_size: 16,
+            health_check_interval: Duration::from_secs(30),
+            wait_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// 连接池，每次取出一条独占的连接，用完自动放回
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    options: Options,
+    pool: PoolOptions,
+    idle: Mutex<Vec<(Client, Instant)>>,
+    permits: Arc<Semaphore>,
+}
+
+/// 从池里借出的连接。只能通过 call 使用，这样才知道连接上有没有读了一半的回复
+pub struct Pooled {
+    client: Option<Client>,
+    inner: Arc<Inner>,
+    broken: bool,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Pool {
+    pub fn new(options: Options, pool: PoolOptions) -> Pool {
+        let permits = Arc::new(Semaphore::new(pool.max_size));
+        Pool {
+            inner: Arc::new(Inner {
+                options,
+                pool,
+                idle: Mutex::new(Vec::new()),
+                permits,
+            }),
+        }
+    }
+
+    pub async fn get(&self) -> crate::Result<Pooled> {
+        let permit = tokio::time::timeout(
+            self.inner.pool.wait_timeout,
+            self.inner.permits.clone().acquire_owned(),
+        )
+        .await
+        .map_err(|_| "timed out waiting for a pooled connection")?
+        .map_err(|_| "pool is closed")?;
+
+        loop {
+            let idle = self.inner.idle.lock().unwrap().pop();
+            let (mut client, since) = match idle {
+                Some(idle) => idle,
+                None => break,
+            };
+            if since.elapsed() < self.inner.pool.health_check_interval {
+                return Ok(self.pooled(client, permit));
+            }
+            let ping = [Bytes::from_static(b"PING")];
+            let reply = tokio::time::timeout(self.inner.options.timeout, client.call(&ping)).await;
+            if let Ok(Ok(Frame::Simple(_))) = reply {
+                return Ok(self.pooled(client, permit));
+            }
+            // 健康检查失败的连接直接丢掉，继续看下一条
+        }
+
+        let client = handle::connect(&self.inner.options).await?;
+        Ok(self.pooled(client, permit))
+    }
+
+    /// 当前空闲的连接数
+    pub fn idle(&self) -> usize {
+        self.inner.idle.lock().unwrap().len()
+    }
+
+    fn pooled(&self, client: Client, permit: OwnedSemaphorePermit) -> Pooled {
+        Pooled {
+            client: Some(client),
+            inner: self.inner.clone(),
+            broken: false,
+            _permit: permit,
+        }
+    }
+}
+
+impl Pooled {
+    /// 和 Client::call 一样，但连接出错后不会再放回池里。
+    /// future 在读完回复之前被丢弃（比如外面套了 timeout）时连接也不会放回
+    pub async fn call(&mut self, args: &[Bytes]) -> crate::Result<Frame> {
+        self.broken = true;
+        let result = self.client.as_mut().unwrap().call(args).await;
+        self.broken = result.is_err();
+        result
+    }
+
+    /// 手动标记连接不可用
+    pub fn discard(&mut self) {
+        self.broken = true;
+    }
+
+    /// 取出底层连接自己使用，比如 pipeline 或者订阅，这条连接不会再回到池里
+    pub fn detach(mut self) -> Client {
+        self.broken = true;
+        self.client.take().unwrap()
+    }
+}
+
+impl Drop for Pooled {
+    fn drop(&mut self) {
+        if self.broken {
+            return;
+        }
+        if let Some(client) = self.client.take() {
+            self.inner.idle.lock().unwrap().push((client, Instant::now()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // 只回复 PING 的假服务器，收到 BLOCK 时不回复
+    async fn server() -> Options {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut buf = [0; 1024];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        if !buf[..n].windows(5).any(|w| w == b"BLOCK") {
+                            let _ = socket.write_all(b"+PONG\r\n").await;
+                        }
+                    }
+                });
+            }
+        });
+        Options::new(addr.to_string())
+    }
+
+    #[tokio::test]
+    async fn successful_call_returns_the_connection() {
+        let pool = Pool::new(server().await, PoolOptions::default());
+        let mut conn = pool.get().await.unwrap();
+        conn.call(&[Bytes::from_static(b"PING")]).await.unwrap();
+        drop(conn);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelled_call_drops_the_connection() {
+        let pool = Pool::new(server().await, PoolOptions::default());
+        let mut conn = pool.get().await.unwrap();
+        // 请求已经发出，回复还没读到就超时了
+        let args = [Bytes::from_static(b"BLOCK")];
+        assert!(tokio::time::timeout(Duration::from_millis(50), conn.call(&args)).await.is_err());
+        drop(conn);
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[tokio::test]
+    async fn detached_connection_is_not_returned() {
+        let pool = Pool::new(server().await, PoolOptions::default());
+        let mut client = pool.get().await.unwrap().detach();
+        client.call(&[Bytes::from_static(b"PING")]).await.unwrap();
+        assert_eq!(pool.idle(), 0);
+    }
+}
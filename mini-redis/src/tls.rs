@@ -0,0 +1,88 @@
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, RootCertStore, ServerConfig};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+/// 服务端是否要求客户端出示证书
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientAuth {
+    No,
+    Optional,
+    Required,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    pub ca_cert: PathBuf,
+    pub server_name: String,
+    // 双向认证时客户端使用的证书和私钥
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+}
+
+pub fn load_certs(path: &Path) -> crate::Result<Vec<CertificateDer<'static>>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(format!("no certificate found in {}", path.display()).into());
+    }
+    Ok(certs)
+}
+
+pub fn load_key(path: &Path) -> crate::Result<PrivateKeyDer<'static>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    rustls_pemfile::private_key(&mut reader)?
+        .ok_or_else(|| format!("no private key found in {}", path.display()).into())
+}
+
+fn root_store(path: &Path) -> crate::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(cert)?;
+    }
+    Ok(roots)
+}
+
+pub fn acceptor(
+    cert: &Path,
+    key: &Path,
+    ca_cert: Option<&Path>,
+    auth: ClientAuth,
+) -> crate::Result<TlsAcceptor> {
+    let builder = ServerConfig::builder();
+    let builder = match (ca_cert, auth) {
+        (Some(ca_cert), ClientAuth::Optional | ClientAuth::Required) => {
+            let verifier = WebPkiClientVerifier::builder(Arc::new(root_store(ca_cert)?));
+            let verifier = if auth == ClientAuth::Optional {
+                verifier.allow_unauthenticated().build()?
+            } else {
+                verifier.build()?
+            };
+            builder.with_client_cert_verifier(verifier)
+        }
+        (None, ClientAuth::Optional | ClientAuth::Required) => {
+            return Err("client authentication requires a CA certificate".into())
+        }
+        (_, ClientAuth::No) => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(load_certs(cert)?, load_key(key)?)?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+pub fn connector(options: &ClientOptions) -> crate::Result<TlsConnector> {
+    let builder = ClientConfig::builder().with_root_certificates(root_store(&options.ca_cert)?);
+    let config = match (&options.cert, &options.key) {
+        (Some(cert), Some(key)) => builder.with_client_auth_cert(load_certs(cert)?, load_key(key)?)?,
+        (None, None) => builder.with_no_client_auth(),
+        _ => return Err("client certificate and key must be given together".into()),
+    };
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+pub fn server_name(options: &ClientOptions) -> crate::Result<ServerName<'static>> {
+    Ok(ServerName::try_from(options.server_name.clone())?)
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use bytes::Bytes;
+use common::{call, eventually, free_port, Process};
+use mini_redis::Frame;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn node() -> Process {
+    Process::server(free_port(), &["--cluster-enabled", "yes"])
+}
+
+fn ok() -> Frame {
+    Frame::Simple("OK".into())
+}
+
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+fn error(frame: Frame) -> String {
+    match frame {
+        Frame::Error(message) => message,
+        frame => panic!("expected an error, got {:?}", frame),
+    }
+}
+
+async fn myid(node: &Process) -> String {
+    match node.call(&["CLUSTER", "MYID"]).await {
+        Frame::Bulk(id) => String::from_utf8(id.to_vec()).unwrap(),
+        frame => panic!("unexpected CLUSTER MYID reply {:?}", frame),
+    }
+}
+
+async fn nodes(port: u16) -> String {
+    match call(port, &["CLUSTER", "NODES"]).await {
+        Frame::Bulk(text) => String::from_utf8(text.to_vec()).unwrap(),
+        frame => panic!("unexpected CLUSTER NODES reply {:?}", frame),
+    }
+}
+
+// a 负责所有槽位，b 还没有槽位，两个节点通过 gossip 互相认识
+async fn two_nodes() -> (Process, Process, String, String) {
+    let (a, b) = (node(), node());
+    let slots: Vec<String> = (0..16384).map(|slot| slot.to_string()).collect();
+    let mut args = vec!["CLUSTER", "ADDSLOTS"];
+    args.extend(slots.iter().map(String::as_str));
+    assert_eq!(a.call(&args).await, ok());
+    assert_eq!(a.call(&["CLUSTER", "MEET", "127.0.0.1", &b.port.to_string()]).await, ok());
+
+    let (a_id, b_id) = (myid(&a).await, myid(&b).await);
+    eventually(TIMEOUT, || async { nodes(b.port).await.contains(&a_id) && nodes(a.port).await.contains(&b_id) }).await;
+    // b 从 a 那里学到槽位归属
+    eventually(TIMEOUT, || async { matches!(b.call(&["GET", "foo"]).await, Frame::Error(message) if message.starts_with("MOVED")) })
+        .await;
+    (a, b, a_id, b_id)
+}
+
+#[tokio::test]
+async fn redirects_to_slot_owner() {
+    let (a, b, _, _) = two_nodes().await;
+    assert_eq!(b.call(&["CLUSTER", "KEYSLOT", "foo"]).await, Frame::Integer(12182));
+    assert_eq!(error(b.call(&["GET", "foo"]).await), format!("MOVED 12182 127.0.0.1:{}", a.port));
+    assert_eq!(a.call(&["SET", "foo", "bar"]).await, ok());
+    assert_eq!(a.call(&["GET", "foo"]).await, bulk("bar"));
+    assert!(error(a.call(&["DEL", "foo", "bar"]).await).starts_with("CROSSSLOT"));
+}
+
+#[tokio::test]
+async fn migrates_a_slot_between_processes() {
+    let (a, b, a_id, b_id) = two_nodes().await;
+    let slot = "12182";
+    for key in ["foo", "{foo}1", "{foo}2"] {
+        assert_eq!(a.call(&["SET", key, "value"]).await, ok());
+    }
+    assert_eq!(b.call(&["CLUSTER", "SETSLOT", slot, "IMPORTING", &a_id]).await, ok());
+    assert_eq!(a.call(&["CLUSTER", "SETSLOT", slot, "MIGRATING", &b_id]).await, ok());
+
+    let b_port = b.port.to_string();
+    assert_eq!(a.call(&["MIGRATE", "127.0.0.1", &b_port, "foo", "0", "5000"]).await, ok());
+
+    // 已经迁走的 key 在源节点上返回 ASK，还没迁走的照常读取
+    assert_eq!(error(a.call(&["GET", "foo"]).await), format!("ASK {} 127.0.0.1:{}", slot, b.port));
+    assert_eq!(a.call(&["GET", "{foo}1"]).await, bulk("value"));
+
+    // 目标节点只在 ASKING 之后的下一条命令接受这个槽位
+    assert_eq!(error(b.call(&["GET", "foo"]).await), format!("MOVED {} 127.0.0.1:{}", slot, a.port));
+    let mut client = b.client().await;
+    assert_eq!(client.request(&[b"ASKING"]).await.unwrap(), ok());
+    assert_eq!(client.request(&[b"GET", b"foo"]).await.unwrap(), bulk("value"));
+    assert!(client.request(&[b"GET", b"foo"]).await.unwrap_err().to_string().starts_with("MOVED"));
+
+    let migrated = a.call(&["MIGRATE", "127.0.0.1", &b_port, "", "0", "5000", "KEYS", "{foo}1", "{foo}2"]).await;
+    assert_eq!(migrated, ok());
+    assert_eq!(a.call(&["CLUSTER", "COUNTKEYSINSLOT", slot]).await, Frame::Integer(0));
+    assert_eq!(b.call(&["CLUSTER", "COUNTKEYSINSLOT", slot]).await, Frame::Integer(3));
+
+    for node in [&b, &a] {
+        assert_eq!(node.call(&["CLUSTER", "SETSLOT", slot, "NODE", &b_id]).await, ok());
+    }
+    assert_eq!(error(a.call(&["GET", "foo"]).await), format!("MOVED {} 127.0.0.1:{}", slot, b.port));
+    assert_eq!(b.call(&["GET", "foo"]).await, bulk("value"));
+    // 其它槽位仍然属于 a
+    assert_eq!(error(b.call(&["GET", "bar"]).await), format!("MOVED 5061 127.0.0.1:{}", a.port));
+}
+
+#[tokio::test]
+async fn wildcard_bind_announces_a_routable_address() {
+    let (a_port, b_port) = (free_port(), free_port());
+    let wildcard = ["--cluster-enabled", "yes", "--bind", "0.0.0.0"];
+    let a = Process::server(a_port, &wildcard);
+    let b = Process::server(b_port, &wildcard);
+    let slots: Vec<String> = (0..16384).map(|slot| slot.to_string()).collect();
+    let mut args = vec!["CLUSTER", "ADDSLOTS"];
+    args.extend(slots.iter().map(String::as_str));
+    assert_eq!(a.call(&args).await, ok());
+    assert_eq!(a.call(&["CLUSTER", "MEET", "127.0.0.1", &b_port.to_string()]).await, ok());
+
+    // a 通过 MEET 宣告的是 0.0.0.0，b 应该改用连接的来源地址
+    let a_id = myid(&a).await;
+    let moved = format!("MOVED 12182 127.0.0.1:{}", a_port);
+    eventually(TIMEOUT, || async { matches!(b.call(&["GET", "foo"]).await, Frame::Error(message) if message == moved) })
+        .await;
+    let view = nodes(b_port).await;
+    let line = view.lines().find(|line| line.starts_with(&a_id)).unwrap();
+    assert!(line.contains(&format!(" 127.0.0.1:{}@", a_port)), "{}", view);
+    assert!(!view.contains("0.0.0.0"), "{}", view);
+}
+
+#[tokio::test]
+async fn cluster_announce_ip() {
+    let port = free_port();
+    let args = ["--cluster-enabled", "yes", "--bind", "0.0.0.0", "--cluster-announce-ip", "127.0.0.1"];
+    let node = Process::server(port, &args);
+    let view = nodes(port).await;
+    assert!(view.contains(&format!(" 127.0.0.1:{}@{} myself,master", port, port as u32 + 10000)), "{}", view);
+    assert_eq!(
+        node.call(&["CONFIG", "GET", "cluster-announce-ip"]).await,
+        Frame::Array(vec![bulk("cluster-announce-ip"), bulk("127.0.0.1")])
+    );
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::Frame;
+
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+fn bulks(values: &[&str]) -> Frame {
+    Frame::Array(values.iter().map(|value| bulk(value)).collect())
+}
+
+fn is_error(frame: &Frame, prefix: &str) -> bool {
+    matches!(frame, Frame::Error(message) if message.starts_with(prefix))
+}
+
+async fn encoding(server: &Process, key: &str) -> Frame {
+    server.call(&["OBJECT", "ENCODING", key]).await
+}
+
+#[tokio::test]
+async fn object_encoding_follows_thresholds() {
+    let server = Process::server(
+        free_port(),
+        &["--hash-max-listpack-entries", "4", "--set-max-intset-entries", "3", "--zset-max-listpack-entries", "2"],
+    );
+
+    for i in 0..4 {
+        server.call(&["HSET", "h", &format!("f{}", i), "v"]).await;
+    }
+    assert_eq!(encoding(&server, "h").await, bulk("listpack"));
+    server.call(&["HSET", "h", "f4", "v"]).await;
+    assert_eq!(encoding(&server, "h").await, bulk("hashtable"));
+    assert_eq!(server.call(&["HLEN", "h"]).await, Frame::Integer(5));
+
+    assert_eq!(server.call(&["SADD", "s", "1", "2", "3"]).await, Frame::Integer(3));
+    assert_eq!(encoding(&server, "s").await, bulk("intset"));
+    server.call(&["SADD", "s", "4"]).await;
+    assert_eq!(encoding(&server, "s").await, bulk("listpack"));
+    assert_eq!(server.call(&["SADD", "t", "a"]).await, Frame::Integer(1));
+    assert_eq!(encoding(&server, "t").await, bulk("listpack"));
+
+    assert_eq!(server.call(&["ZADD", "z", "1", "a", "2", "b"]).await, Frame::Integer(2));
+    assert_eq!(encoding(&server, "z").await, bulk("listpack"));
+    assert_eq!(server.call(&["ZADD", "z", "3", "c"]).await, Frame::Integer(1));
+    assert_eq!(encoding(&server, "z").await, bulk("skiplist"));
+    assert_eq!(server.call(&["ZRANGE", "z", "0", "-1"]).await, bulks(&["a", "b", "c"]));
+
+    // 运行时调整只影响之后的插入
+    assert_eq!(server.call(&["CONFIG", "SET", "list-max-listpack-size", "3"]).await, Frame::Simple("OK".into()));
+    assert_eq!(server.call(&["RPUSH", "l", "a", "b", "c"]).await, Frame::Integer(3));
+    assert_eq!(encoding(&server, "l").await, bulk("listpack"));
+    assert_eq!(server.call(&["LPUSH", "l", "z"]).await, Frame::Integer(4));
+    assert_eq!(encoding(&server, "l").await, bulk("quicklist"));
+    assert_eq!(server.call(&["LRANGE", "l", "0", "-1"]).await, bulks(&["z", "a", "b", "c"]));
+    assert!(is_error(&server.call(&["CONFIG", "SET", "list-max-ziplist-size", "0"]).await, "ERR"));
+}
+
+#[tokio::test]
+async fn collections_behave_like_redis() {
+    let server = Process::server(free_port(), &[]);
+
+    assert_eq!(server.call(&["HSET", "h", "a", "1", "b", "2"]).await, Frame::Integer(2));
+    assert_eq!(server.call(&["HINCRBY", "h", "a", "41"]).await, Frame::Integer(42));
+    assert_eq!(server.call(&["HMGET", "h", "a", "x"]).await, Frame::Array(vec![bulk("42"), Frame::Null]));
+    assert_eq!(server.call(&["HGETALL", "h"]).await, bulks(&["a", "42", "b", "2"]));
+    assert_eq!(server.call(&["TYPE", "h"]).await, Frame::Simple("hash".into()));
+
+    assert_eq!(server.call(&["SADD", "s1", "a", "b", "c"]).await, Frame::Integer(3));
+    assert_eq!(server.call(&["SADD", "s2", "b", "c", "d"]).await, Frame::Integer(3));
+    assert_eq!(server.call(&["SINTER", "s1", "s2"]).await, bulks(&["b", "c"]));
+    assert_eq!(server.call(&["SDIFF", "s1", "s2"]).await, bulks(&["a"]));
+    assert_eq!(server.call(&["SMISMEMBER", "s1", "a", "d"]).await, Frame::Array(vec![Frame::Integer(1), Frame::Integer(0)]));
+
+    assert_eq!(server.call(&["RPUSH", "l", "a", "b", "a", "c", "a"]).await, Frame::Integer(5));
+    assert_eq!(server.call(&["LREM", "l", "-2", "a"]).await, Frame::Integer(2));
+    assert_eq!(server.call(&["LRANGE", "l", "0", "-1"]).await, bulks(&["a", "b", "c"]));
+    assert_eq!(server.call(&["LINSERT", "l", "AFTER", "b", "x"]).await, Frame::Integer(4));
+    assert_eq!(server.call(&["LSET", "l", "-1", "z"]).await, Frame::Simple("OK".into()));
+    assert_eq!(server.call(&["LPOP", "l", "2"]).await, bulks(&["a", "b"]));
+    assert_eq!(server.call(&["LINDEX", "l", "1"]).await, bulk("z"));
+
+    // 类型不对时报错，集合删空后 key 也一起删掉
+    assert!(is_error(&server.call(&["LPUSH", "h", "x"]).await, "WRONGTYPE"));
+    assert_eq!(server.call(&["LTRIM", "l", "5", "10"]).await, Frame::Simple("OK".into()));
+    assert_eq!(server.call(&["EXISTS", "l"]).await, Frame::Integer(0));
+    assert_eq!(server.call(&["SREM", "s1", "a", "b", "c"]).await, Frame::Integer(3));
+    assert_eq!(server.call(&["EXISTS", "s1"]).await, Frame::Integer(0));
+    assert_eq!(server.call(&["RPUSHX", "l", "a"]).await, Frame::Integer(0));
+    assert_eq!(server.call(&["EXISTS", "l"]).await, Frame::Integer(0));
+}
+
+#[tokio::test]
+async fn sorted_sets_behave_like_redis() {
+    let server = Process::server(free_port(), &[]);
+
+    assert_eq!(server.call(&["ZADD", "z", "1", "a", "2", "b", "3", "c"]).await, Frame::Integer(3));
+    assert_eq!(server.call(&["ZADD", "z", "CH", "5", "a", "2", "b", "4", "d"]).await, Frame::Integer(2));
+    assert_eq!(server.call(&["ZRANGE", "z", "0", "-1"]).await, bulks(&["b", "c", "d", "a"]));
+    assert_eq!(server.call(&["ZADD", "z", "XX", "9", "e"]).await, Frame::Integer(0));
+    assert_eq!(server.call(&["ZADD", "z", "NX", "9", "a"]).await, Frame::Integer(0));
+    assert_eq!(server.call(&["ZSCORE", "z", "a"]).await, bulk("5"));
+    // GT 和 LT 只限制已有成员的更新
+    assert_eq!(server.call(&["ZADD", "z", "GT", "CH", "1", "a", "6", "c"]).await, Frame::Integer(1));
+    assert_eq!(server.call(&["ZADD", "z", "LT", "CH", "1", "a", "0", "f"]).await, Frame::Integer(2));
+    assert_eq!(server.call(&["ZADD", "z", "INCR", "2.5", "b"]).await, bulk("4.5"));
+    assert_eq!(server.call(&["ZADD", "z", "NX", "INCR", "1", "b"]).await, Frame::Null);
+    assert_eq!(server.call(&["ZINCRBY", "z", "-0.5", "b"]).await, bulk("4"));
+    assert_eq!(server.call(&["ZCARD", "z"]).await, Frame::Integer(5));
+    assert_eq!(server.call(&["ZRANGE", "z", "0", "1", "REV", "WITHSCORES"]).await, bulks(&["c", "6", "d", "4"]));
+    assert_eq!(server.call(&["ZRANGE", "z", "-2", "-1"]).await, bulks(&["d", "c"]));
+
+    assert!(is_error(&server.call(&["ZADD", "z", "NX", "XX", "1", "a"]).await, "ERR"));
+    assert!(is_error(&server.call(&["ZADD", "z", "GT", "LT", "1", "a"]).await, "ERR"));
+    assert!(is_error(&server.call(&["ZADD", "z", "INCR", "1", "a", "2", "b"]).await, "ERR"));
+    assert!(is_error(&server.call(&["ZADD", "z", "nan", "a"]).await, "ERR"));
+    assert!(is_error(&server.call(&["ZADD", "z", "1"]).await, "ERR"));
+
+    assert_eq!(server.call(&["ZREM", "z", "a", "f", "b", "c", "d", "x"]).await, Frame::Integer(5));
+    assert_eq!(server.call(&["EXISTS", "z"]).await, Frame::Integer(0));
+    server.call(&["SET", "s", "v"]).await;
+    assert!(is_error(&server.call(&["ZADD", "s", "1", "a"]).await, "WRONGTYPE"));
+    assert!(is_error(&server.call(&["ZSCORE", "s", "a"]).await, "WRONGTYPE"));
+}
+
+#[tokio::test]
+async fn restore_uses_the_target_thresholds() {
+    let source = Process::server(free_port(), &[]);
+    let target = Process::server(free_port(), &["--hash-max-listpack-entries", "1"]);
+    source.call(&["HSET", "h", "a", "1", "b", "2"]).await;
+    assert_eq!(encoding(&source, "h").await, bulk("listpack"));
+
+    let Frame::Bulk(payload) = source.call(&["DUMP", "h"]).await else { panic!("DUMP failed") };
+    let mut client = target.client().await;
+    let args = [Bytes::from("RESTORE"), Bytes::from("h"), Bytes::from("0"), payload];
+    assert_eq!(client.call(&args).await.unwrap(), Frame::Simple("OK".into()));
+    assert_eq!(encoding(&target, "h").await, bulk("hashtable"));
+}
+
+#[tokio::test]
+async fn dump_and_restore_collections() {
+    let server = Process::server(free_port(), &[]);
+    server.call(&["HSET", "h", "field", "value"]).await;
+    server.call(&["SADD", "s", "1", "two"]).await;
+    server.call(&["RPUSH", "l", "x", "y"]).await;
+
+    for key in ["h", "s", "l"] {
+        let Frame::Bulk(payload) = server.call(&["DUMP", key]).await else { panic!("DUMP {} failed", key) };
+        let mut client = server.client().await;
+        let copy = format!("{}:copy", key);
+        let args = [Bytes::from("RESTORE"), Bytes::from(copy.clone()), Bytes::from("0"), payload];
+        assert_eq!(client.call(&args).await.unwrap(), Frame::Simple("OK".into()));
+        assert_eq!(server.call(&["TYPE", &copy]).await, server.call(&["TYPE", key]).await);
+    }
+    assert_eq!(server.call(&["HGET", "h:copy", "field"]).await, bulk("value"));
+    assert_eq!(server.call(&["SMEMBERS", "s:copy"]).await, bulks(&["1", "two"]));
+    assert_eq!(server.call(&["LRANGE", "l:copy", "0", "-1"]).await, bulks(&["x", "y"]));
+}
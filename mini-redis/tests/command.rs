@@ -0,0 +1,46 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::Frame;
+
+fn is_error(frame: &Frame, prefix: &str) -> bool {
+    matches!(frame, Frame::Error(message) if message.starts_with(prefix))
+}
+
+fn bulks(values: &[&str]) -> Frame {
+    Frame::Array(values.iter().map(|value| Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))).collect())
+}
+
+#[tokio::test]
+async fn arity_is_checked_before_running() {
+    let server = Process::server(free_port(), &[]);
+    let wrong = "ERR wrong number of arguments for 'get' command";
+    assert_eq!(server.call(&["GET"]).await, Frame::Error(wrong.into()));
+    assert_eq!(server.call(&["get", "a", "b"]).await, Frame::Error(wrong.into()));
+    assert!(is_error(&server.call(&["SET", "a"]).await, "ERR wrong number of arguments for 'set'"));
+    assert!(is_error(&server.call(&["DEL"]).await, "ERR wrong number of arguments for 'del'"));
+    // 参数个数不对的写命令什么都不做
+    assert_eq!(server.call(&["EXISTS", "a"]).await, Frame::Integer(0));
+    assert!(is_error(&server.call(&["NOPE"]).await, "ERR unknown command 'NOPE'"));
+}
+
+#[tokio::test]
+async fn command_getkeys() {
+    let server = Process::server(free_port(), &[]);
+    assert_eq!(server.call(&["COMMAND", "GETKEYS", "GET", "a"]).await, bulks(&["a"]));
+    assert_eq!(server.call(&["COMMAND", "GETKEYS", "DEL", "a", "b", "c"]).await, bulks(&["a", "b", "c"]));
+    assert_eq!(server.call(&["COMMAND", "GETKEYS", "BITOP", "OR", "d", "x", "y"]).await, bulks(&["d", "x", "y"]));
+    let eval = ["COMMAND", "GETKEYS", "EVAL", "return 1", "2", "a", "b", "arg"];
+    assert_eq!(server.call(&eval).await, bulks(&["a", "b"]));
+    assert_eq!(
+        server.call(&["COMMAND", "GETKEYS", "MIGRATE", "h", "1", "", "0", "10", "KEYS", "a", "b"]).await,
+        bulks(&["a", "b"])
+    );
+
+    assert!(is_error(&server.call(&["COMMAND", "GETKEYS", "NOPE", "a"]).await, "ERR Invalid command specified"));
+    assert!(is_error(&server.call(&["COMMAND", "GETKEYS", "GET"]).await, "ERR Invalid number of arguments"));
+    assert!(is_error(&server.call(&["COMMAND", "GETKEYS", "PING"]).await, "ERR The command has no key arguments"));
+    let eval = ["COMMAND", "GETKEYS", "EVAL", "return 1", "0"];
+    assert!(is_error(&server.call(&eval).await, "ERR The command has no key arguments"));
+}
@@ -0,0 +1,107 @@
+// 集成测试共用的工具：启动编译好的二进制，测试结束时杀掉
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use mini_redis::client::{self, Client};
+use mini_redis::Frame;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// 一个子进程，工作目录是独立的临时目录，drop 时杀掉并删除目录
+pub struct Process {
+    child: Option<Child>,
+    pub port: u16,
+    pub dir: PathBuf,
+}
+
+impl Process {
+    /// 启动 bin 并等到 port 可以连接
+    pub fn start(bin: &str, port: u16, args: &[String]) -> Process {
+        let dir = std::env::temp_dir().join(format!("mini-redis-test-{}-{}", std::process::id(), port));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let child = Command::new(bin)
+            .args(args)
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let process = Process { child: Some(child), port, dir };
+        wait_until(Duration::from_secs(10), || std::net::TcpStream::connect(("127.0.0.1", port)).is_ok());
+        process
+    }
+
+    /// 启动 server，args 是额外的配置
+    pub fn server(port: u16, args: &[&str]) -> Process {
+        let mut all = vec!["--port".to_string(), port.to_string()];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        Process::start(env!("CARGO_BIN_EXE_server"), port, &all)
+    }
+
+    pub fn kill(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+
+    pub async fn client(&self) -> Client {
+        client::connect(("127.0.0.1", self.port)).await.unwrap()
+    }
+
+    pub async fn call(&self, args: &[&str]) -> Frame {
+        call(self.port, args).await
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.kill();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// 让系统分配一个空闲端口。测试在同一个进程里并发运行，记录已经发出去的端口避免重复
+pub fn free_port() -> u16 {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+    static USED: Mutex<Option<HashSet<u16>>> = Mutex::new(None);
+    loop {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut used = USED.lock().unwrap();
+        if used.get_or_insert_with(HashSet::new).insert(port) {
+            return port;
+        }
+    }
+}
+
+pub async fn call(port: u16, args: &[&str]) -> Frame {
+    let mut client = client::connect(("127.0.0.1", port)).await.unwrap();
+    let args: Vec<Bytes> = args.iter().map(|arg| Bytes::copy_from_slice(arg.as_bytes())).collect();
+    client.call(&args).await.unwrap()
+}
+
+/// 轮询直到条件成立，超时后让测试失败
+pub fn wait_until(timeout: Duration, mut condition: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < timeout, "condition not met within {:?}", timeout);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// wait_until 的异步版本
+pub async fn eventually<F, Fut>(timeout: Duration, mut condition: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let start = Instant::now();
+    while !condition().await {
+        assert!(start.elapsed() < timeout, "condition not met within {:?}", timeout);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::Frame;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn is_error(frame: &Frame, prefix: &str) -> bool {
+    matches!(frame, Frame::Error(message) if message.starts_with(prefix))
+}
+
+async fn dump(server: &Process, key: &str) -> Bytes {
+    match server.call(&["DUMP", key]).await {
+        Frame::Bulk(payload) => payload,
+        other => panic!("DUMP {} returned {:?}", key, other),
+    }
+}
+
+async fn restore(server: &Process, key: &str, ttl: &str, payload: &Bytes, options: &[&str]) -> Frame {
+    let mut client = server.client().await;
+    let mut args = vec![Bytes::from("RESTORE"), Bytes::copy_from_slice(key.as_bytes())];
+    args.push(Bytes::copy_from_slice(ttl.as_bytes()));
+    args.push(payload.clone());
+    args.extend(options.iter().map(|option| Bytes::copy_from_slice(option.as_bytes())));
+    client.call(&args).await.unwrap()
+}
+
+#[tokio::test]
+async fn restore_options() {
+    let server = Process::server(free_port(), &[]);
+    server.call(&["SET", "src", "hello"]).await;
+    let payload = dump(&server, "src").await;
+    assert_eq!(server.call(&["DUMP", "missing"]).await, Frame::Null);
+
+    // 目标存在时需要 REPLACE
+    assert!(is_error(&restore(&server, "src", "0", &payload, &[]).await, "BUSYKEY"));
+    server.call(&["SET", "src", "other"]).await;
+    assert_eq!(restore(&server, "src", "0", &payload, &["REPLACE"]).await, Frame::ok());
+    assert_eq!(server.call(&["GET", "src"]).await, Frame::Bulk(Bytes::from("hello")));
+
+    // ttl 是相对的毫秒数，ABSTTL 时是 unix 毫秒时间戳
+    assert_eq!(restore(&server, "rel", "100000", &payload, &[]).await, Frame::ok());
+    assert!(matches!(server.call(&["TTL", "rel"]).await, Frame::Integer(99..=100)));
+    let unix_now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    let at = (unix_now + 50_000).to_string();
+    assert_eq!(restore(&server, "abs", &at, &payload, &["ABSTTL"]).await, Frame::ok());
+    assert!(matches!(server.call(&["TTL", "abs"]).await, Frame::Integer(49..=50)));
+    // 已经过去的时间点相当于恢复后立即过期，REPLACE 时原来的 key 被删掉
+    let past = (unix_now - 1000).to_string();
+    assert_eq!(restore(&server, "src", &past, &payload, &["ABSTTL", "REPLACE"]).await, Frame::ok());
+    assert_eq!(server.call(&["EXISTS", "src"]).await, Frame::Integer(0));
+    assert!(is_error(&restore(&server, "neg", "-1", &payload, &[]).await, "ERR Invalid TTL value"));
+
+    // IDLETIME 设置空闲时间
+    assert_eq!(restore(&server, "idle", "0", &payload, &["IDLETIME", "1000"]).await, Frame::ok());
+    assert!(matches!(server.call(&["OBJECT", "IDLETIME", "idle"]).await, Frame::Integer(1000..=1001)));
+    assert!(is_error(&restore(&server, "x", "0", &payload, &["IDLETIME", "-1"]).await, "ERR Invalid IDLETIME value"));
+    // IDLETIME 和 FREQ 不能同时使用
+    assert!(is_error(&restore(&server, "x", "0", &payload, &["IDLETIME", "1", "FREQ", "1"]).await, "ERR syntax error"));
+    assert!(is_error(&restore(&server, "x", "0", &payload, &["NOPE"]).await, "ERR syntax error"));
+
+    // 校验和不对的内容不会写入
+    let mut corrupted = payload.to_vec();
+    corrupted[2] ^= 1;
+    let corrupted = Bytes::from(corrupted);
+    assert!(is_error(&restore(&server, "x", "0", &corrupted, &[]).await, "ERR DUMP payload version or checksum"));
+    assert_eq!(server.call(&["EXISTS", "x"]).await, Frame::Integer(0));
+}
+
+#[tokio::test]
+async fn restore_freq_under_lfu() {
+    let server = Process::server(free_port(), &["--maxmemory-policy", "allkeys-lfu"]);
+    server.call(&["RPUSH", "src", "a", "b"]).await;
+    let payload = dump(&server, "src").await;
+    assert_eq!(restore(&server, "hot", "0", &payload, &["FREQ", "100"]).await, Frame::ok());
+    assert_eq!(server.call(&["OBJECT", "FREQ", "hot"]).await, Frame::Integer(100));
+    assert!(is_error(&restore(&server, "x", "0", &payload, &["FREQ", "256"]).await, "ERR Invalid FREQ value"));
+    assert_eq!(server.call(&["LRANGE", "hot", "0", "-1"]).await, server.call(&["LRANGE", "src", "0", "-1"]).await);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::Frame;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// 标准误差约 0.81%，允许 4 倍标准误差；元素是固定的，结果可以复现
+fn assert_close(count: &Frame, actual: i64) {
+    let Frame::Integer(count) = *count else { panic!("unexpected reply {:?}", count) };
+    let error = (count - actual).abs() as f64 / actual as f64;
+    assert!(error <= 4.0 * 1.04 / 128.0, "estimated {} for {} elements", count, actual);
+}
+
+async fn fill(server: &Process, key: &str, prefix: &str, range: std::ops::Range<u64>) {
+    let elements: Vec<String> = range.map(|i| format!("{}:{}", prefix, i)).collect();
+    for chunk in elements.chunks(1000) {
+        let mut args = vec!["PFADD", key];
+        args.extend(chunk.iter().map(String::as_str));
+        server.call(&args).await;
+    }
+}
+
+#[tokio::test]
+async fn pfmerge_counts_the_union() {
+    let server = Process::server(free_port(), &[]);
+
+    // 两个集合有 25_000 个元素重叠，并集是 75_000
+    fill(&server, "a", "merge", 0..50_000).await;
+    fill(&server, "b", "merge", 25_000..75_000).await;
+    assert_eq!(server.call(&["PFMERGE", "ab", "a", "b"]).await, Frame::ok());
+    assert_close(&server.call(&["PFCOUNT", "ab"]).await, 75_000);
+    assert_eq!(server.call(&["PFCOUNT", "a", "b"]).await, server.call(&["PFCOUNT", "ab"]).await);
+
+    // 和自己合并、和不存在的 key 合并都不改变寄存器
+    let registers = server.call(&["PFDEBUG", "GETREG", "a"]).await;
+    server.call(&["PFMERGE", "a", "a", "missing"]).await;
+    assert_eq!(server.call(&["PFDEBUG", "GETREG", "a"]).await, registers);
+    assert_eq!(server.call(&["EXISTS", "missing"]).await, Frame::Integer(0));
+
+    // 目标 key 原有的内容也参与合并
+    fill(&server, "dest", "merge", 75_000..80_000).await;
+    server.call(&["PFMERGE", "dest", "ab"]).await;
+    assert_close(&server.call(&["PFCOUNT", "dest"]).await, 80_000);
+
+    // 小集合合并后仍然是稀疏编码，放不下时转成稠密编码
+    fill(&server, "x", "x", 0..100).await;
+    fill(&server, "y", "y", 0..100).await;
+    server.call(&["PFMERGE", "small", "x", "y"]).await;
+    assert_eq!(server.call(&["PFDEBUG", "ENCODING", "small"]).await, Frame::Simple("sparse".into()));
+    assert_close(&server.call(&["PFCOUNT", "small"]).await, 200);
+
+    let mut parts = Vec::new();
+    for i in 0..10 {
+        let key = format!("part{}", i);
+        fill(&server, &key, &key, 0..300).await;
+        assert_eq!(server.call(&["PFDEBUG", "ENCODING", &key]).await, Frame::Simple("sparse".into()));
+        parts.push(key);
+    }
+    let mut args = vec!["PFMERGE", "parts"];
+    args.extend(parts.iter().map(String::as_str));
+    server.call(&args).await;
+    assert_eq!(server.call(&["PFDEBUG", "ENCODING", "parts"]).await, Frame::Simple("dense".into()));
+    assert_close(&server.call(&["PFCOUNT", "parts"]).await, 3000);
+
+    server.call(&["SET", "s", "abc"]).await;
+    assert!(matches!(server.call(&["PFMERGE", "c", "a", "s"]).await, Frame::Error(e) if e.starts_with("WRONGTYPE")));
+    assert_eq!(server.call(&["EXISTS", "c"]).await, Frame::Integer(0));
+}
+
+#[tokio::test]
+async fn concurrent_pfadd_keeps_every_element() {
+    let server = Process::server(free_port(), &[]);
+    let mut tasks = Vec::new();
+    for i in 0..8u64 {
+        let mut client = server.client().await;
+        tasks.push(tokio::spawn(async move {
+            for j in 0..2000u64 {
+                let element = format!("element:{}", i * 2000 + j);
+                let args = ["PFADD", "hll", &element].map(|arg| Bytes::copy_from_slice(arg.as_bytes()));
+                client.call(&args).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_close(&server.call(&["PFCOUNT", "hll"]).await, 16_000);
+}
+
+// 通过 memcached 协议的 gets 读出 key 的 cas 版本号
+async fn cas(port: u16, key: &str) -> String {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(format!("gets {}\r\n", key).as_bytes()).await.unwrap();
+    let mut reply = Vec::new();
+    while !reply.ends_with(b"END\r\n") {
+        let mut buf = [0; 4096];
+        let n = stream.read(&mut buf).await.unwrap();
+        assert!(n > 0, "connection closed");
+        reply.extend_from_slice(&buf[..n]);
+    }
+    let header = reply.split(|b| *b == b'\n').next().unwrap();
+    String::from_utf8_lossy(header).trim().rsplit(' ').next().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn pfcount_caches_without_modifying_the_key() {
+    let memcache_port = free_port();
+    let server = Process::server(free_port(), &["--memcache-port", &memcache_port.to_string()]);
+
+    server.call(&["PFADD", "h", "a", "b", "c"]).await;
+    server.call(&["EXPIRE", "h", "100"]).await;
+    let before = cas(memcache_port, "h").await;
+    assert_eq!(server.call(&["PFCOUNT", "h"]).await, Frame::Integer(3));
+
+    // 缓存的基数写回了头部，但 cas 版本号和过期时间都不变
+    let Frame::Bulk(raw) = server.call(&["GET", "h"]).await else { panic!("h should be a string") };
+    assert_eq!(raw[8..16], 3u64.to_le_bytes());
+    assert_eq!(cas(memcache_port, "h").await, before);
+    assert!(matches!(server.call(&["TTL", "h"]).await, Frame::Integer(1..=100)));
+
+    // 加入新元素之后缓存失效
+    assert_eq!(server.call(&["PFADD", "h", "d"]).await, Frame::Integer(1));
+    assert_ne!(cas(memcache_port, "h").await, before);
+    assert_eq!(server.call(&["PFCOUNT", "h"]).await, Frame::Integer(4));
+}
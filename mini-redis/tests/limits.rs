@@ -0,0 +1,74 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::client::Client;
+use mini_redis::Frame;
+use std::time::{Duration, Instant};
+
+const EXCEEDED: &str = "ERR max request rate exceeded, try again later";
+
+async fn ping(client: &mut Client) -> Frame {
+    client.call(&[Bytes::from("PING")]).await.unwrap()
+}
+
+#[tokio::test]
+async fn client_limit_rejects_requests_over_the_rate() {
+    let server = Process::server(free_port(), &["--client-rate-limit", "5", "--rate-limit-action", "error"]);
+    let mut client = server.client().await;
+    let mut replies = Vec::new();
+    for _ in 0..8 {
+        replies.push(ping(&mut client).await);
+    }
+    assert!(replies[..5].iter().all(|reply| *reply == Frame::Simple("PONG".into())), "{:?}", replies);
+    assert_eq!(replies[7], Frame::Error(EXCEEDED.into()));
+
+    // 每个连接单独计数
+    let mut other = server.client().await;
+    assert_eq!(ping(&mut other).await, Frame::Simple("PONG".into()));
+    // 令牌按速率补充
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(ping(&mut client).await, Frame::Simple("PONG".into()));
+}
+
+#[tokio::test]
+async fn user_limit_is_shared_between_connections() {
+    let args = ["--user-rate-limit", "4", "--rate-limit-burst", "4", "--rate-limit-action", "error"];
+    let server = Process::server(free_port(), &args);
+    let mut first = server.client().await;
+    let mut second = server.client().await;
+    for _ in 0..2 {
+        assert_eq!(ping(&mut first).await, Frame::Simple("PONG".into()));
+        assert_eq!(ping(&mut second).await, Frame::Simple("PONG".into()));
+    }
+    assert_eq!(ping(&mut first).await, Frame::Error(EXCEEDED.into()));
+    assert_eq!(ping(&mut second).await, Frame::Error(EXCEEDED.into()));
+}
+
+#[tokio::test]
+async fn throttle_delays_instead_of_failing() {
+    let server = Process::server(free_port(), &["--client-rate-limit", "10", "--rate-limit-action", "throttle"]);
+    let mut client = server.client().await;
+    let start = Instant::now();
+    // 前 10 个用掉满的桶，之后每个等 100ms
+    for _ in 0..15 {
+        assert_eq!(ping(&mut client).await, Frame::Simple("PONG".into()));
+    }
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(450), "finished in {:?}", elapsed);
+}
+
+#[tokio::test]
+async fn maxclients_rejects_extra_connections() {
+    let server = Process::server(free_port(), &["--maxclients", "1"]);
+    let mut first = server.client().await;
+    assert_eq!(ping(&mut first).await, Frame::Simple("PONG".into()));
+    let mut second = server.client().await;
+    assert_eq!(ping(&mut second).await, Frame::Error("ERR max number of clients reached".into()));
+    drop(first);
+    common::eventually(Duration::from_secs(5), || async {
+        let mut client = server.client().await;
+        matches!(client.call(&[Bytes::from("PING")]).await, Ok(Frame::Simple(_)))
+    })
+    .await;
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::Frame;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// memcached 文本协议的客户端，按行读取回复
+struct Memcache {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+
+impl Memcache {
+    async fn connect(port: u16) -> Memcache {
+        Memcache { stream: TcpStream::connect(("127.0.0.1", port)).await.unwrap(), buf: Vec::new() }
+    }
+
+    async fn send(&mut self, request: &str) {
+        self.stream.write_all(request.as_bytes()).await.unwrap();
+    }
+
+    async fn line(&mut self) -> String {
+        loop {
+            if let Some(end) = self.buf.windows(2).position(|w| w == b"\r\n") {
+                let line = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                self.buf.drain(..end + 2);
+                return line;
+            }
+            let mut chunk = [0; 4096];
+            let n = self.stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed");
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    async fn call(&mut self, request: &str) -> String {
+        self.send(request).await;
+        self.line().await
+    }
+
+    // 读到 END 为止的所有行
+    async fn get(&mut self, request: &str) -> Vec<String> {
+        self.send(request).await;
+        let mut lines = Vec::new();
+        loop {
+            match self.line().await {
+                end if end == "END" => return lines,
+                line => lines.push(line),
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn protocol_round_trip() {
+    let port = free_port();
+    let server = Process::server(free_port(), &["--memcache-port", &port.to_string()]);
+    let mut mc = Memcache::connect(port).await;
+
+    assert_eq!(mc.call("set a 42 0 5\r\nhello\r\n").await, "STORED");
+    assert_eq!(mc.get("get a missing\r\n").await, ["VALUE a 42 5", "hello"]);
+    // 和 RESP 共用键空间
+    assert_eq!(server.call(&["GET", "a"]).await, Frame::Bulk(Bytes::from("hello")));
+    server.call(&["SET", "b", "from resp"]).await;
+    assert_eq!(mc.get("get b\r\n").await, ["VALUE b 0 9", "from resp"]);
+
+    assert_eq!(mc.call("add a 0 0 1\r\nx\r\n").await, "NOT_STORED");
+    assert_eq!(mc.call("replace missing 0 0 1\r\nx\r\n").await, "NOT_STORED");
+    assert_eq!(mc.call("replace a 7 0 5\r\nworld\r\n").await, "STORED");
+
+    // gets 返回的版本号用于 cas，值被修改后旧的版本号失效
+    let lines = mc.get("gets a\r\n").await;
+    let unique = lines[0].rsplit(' ').next().unwrap().to_string();
+    assert_eq!(lines[0], format!("VALUE a 7 5 {}", unique));
+    assert_eq!(mc.call(&format!("cas a 0 0 3 {}\r\nnew\r\n", unique)).await, "STORED");
+    assert_eq!(mc.call(&format!("cas a 0 0 3 {}\r\nold\r\n", unique)).await, "EXISTS");
+    assert_eq!(mc.call("cas missing 0 0 1 1\r\nx\r\n").await, "NOT_FOUND");
+    assert_eq!(mc.get("get a\r\n").await, ["VALUE a 0 3", "new"]);
+
+    assert_eq!(mc.call("set n 0 0 2\r\n10\r\n").await, "STORED");
+    assert_eq!(mc.call("incr n 5\r\n").await, "15");
+    assert_eq!(mc.call("decr n 20\r\n").await, "0");
+    assert_eq!(mc.call("incr a 1\r\n").await, "CLIENT_ERROR cannot increment or decrement non-numeric value");
+    assert_eq!(mc.call("incr missing 1\r\n").await, "NOT_FOUND");
+
+    assert_eq!(mc.call("touch a 100\r\n").await, "TOUCHED");
+    assert!(matches!(server.call(&["TTL", "a"]).await, Frame::Integer(99..=100)));
+    assert_eq!(mc.call("delete a\r\n").await, "DELETED");
+    assert_eq!(mc.call("delete a\r\n").await, "NOT_FOUND");
+
+    // noreply 不回复，下一条命令的回复紧接着出现
+    mc.send("set quiet 0 0 1 noreply\r\nq\r\n").await;
+    assert_eq!(mc.get("get quiet\r\n").await, ["VALUE quiet 0 1", "q"]);
+    // 负的过期时间相当于删除
+    assert_eq!(mc.call("set gone 0 -1 1\r\nx\r\n").await, "STORED");
+    assert!(mc.get("get gone\r\n").await.is_empty());
+
+    assert_eq!(mc.call("set a x 0 1\r\n").await, "CLIENT_ERROR bad command line format");
+    assert_eq!(mc.call("bogus\r\n").await, "ERROR");
+    assert!(mc.call("version\r\n").await.starts_with("VERSION mini-redis"));
+}
+
+#[tokio::test]
+async fn idle_connections_are_closed() {
+    let port = free_port();
+    let _server = Process::server(free_port(), &["--memcache-port", &port.to_string(), "--timeout", "1"]);
+    let mut mc = Memcache::connect(port).await;
+    assert!(mc.call("version\r\n").await.starts_with("VERSION"));
+
+    // 和 RESP 连接一样，超过 timeout 秒没有命令就断开
+    let mut buf = [0; 16];
+    let read = tokio::time::timeout(Duration::from_secs(5), mc.stream.read(&mut buf)).await;
+    assert_eq!(read.expect("idle connection was not closed").unwrap(), 0);
+}
+
+#[tokio::test]
+async fn rate_limit_exceeded() {
+    let port = free_port();
+    let args = ["--memcache-port", &port.to_string(), "--client-rate-limit", "5", "--rate-limit-action", "error"];
+    let _server = Process::server(free_port(), &args);
+    let mut mc = Memcache::connect(port).await;
+    // 桶的容量默认等于速率，前 5 条用完令牌，之后的直接报错
+    let mut replies = Vec::new();
+    for i in 0..8 {
+        replies.push(mc.call(&format!("set k{} 0 0 1\r\nx\r\n", i)).await);
+    }
+    assert!(replies[..5].iter().all(|reply| reply == "STORED"), "{:?}", replies);
+    assert_eq!(replies[7], "SERVER_ERROR ERR max request rate exceeded, try again later");
+    // 被拒绝的请求不消耗令牌，过一段时间又可以执行
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert_eq!(mc.call("set k 0 0 1\r\nx\r\n").await, "STORED");
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use bytes::Bytes;
+use common::{call, eventually, free_port, Process};
+use mini_redis::Frame;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+fn is_error(frame: &Frame, prefix: &str) -> bool {
+    matches!(frame, Frame::Error(message) if message.starts_with(prefix))
+}
+
+async fn info(port: u16) -> String {
+    match call(port, &["INFO", "replication"]).await {
+        Frame::Bulk(text) => String::from_utf8(text.to_vec()).unwrap(),
+        frame => panic!("unexpected INFO reply {:?}", frame),
+    }
+}
+
+fn replica(primary: &Process, args: &[&str]) -> Process {
+    let replicaof = format!("127.0.0.1 {}", primary.port);
+    let mut all = vec!["--replicaof", &replicaof];
+    all.extend_from_slice(args);
+    Process::server(free_port(), &all)
+}
+
+async fn wait_for_replicas(primary: &Process, count: usize) {
+    let expected = format!("connected_slaves:{}", count);
+    eventually(TIMEOUT, || async { info(primary.port).await.contains(&expected) }).await;
+}
+
+#[tokio::test]
+async fn wait_counts_acknowledging_replicas() {
+    let primary = Process::server(free_port(), &[]);
+    let replicas = [replica(&primary, &[]), replica(&primary, &[])];
+    wait_for_replicas(&primary, 2).await;
+
+    let mut client = primary.client().await;
+    client.set("key", Bytes::from_static(b"value")).await.unwrap();
+    assert_eq!(client.request(&[b"WAIT", b"2", b"5000"]).await.unwrap(), Frame::Integer(2));
+    for replica in &replicas {
+        assert_eq!(replica.call(&["GET", "key"]).await, bulk("value"));
+    }
+
+    // 副本数不够时等到超时，返回实际确认的数量
+    let start = Instant::now();
+    assert_eq!(call(primary.port, &["WAIT", "3", "300"]).await, Frame::Integer(2));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    // 一个副本下线后只剩一个确认
+    let [mut first, _second] = replicas;
+    first.kill();
+    wait_for_replicas(&primary, 1).await;
+    client.set("key", Bytes::from_static(b"again")).await.unwrap();
+    assert_eq!(client.request(&[b"WAIT", b"2", b"300"]).await.unwrap(), Frame::Integer(1));
+}
+
+#[tokio::test]
+async fn replicas_are_read_only() {
+    let primary = Process::server(free_port(), &[]);
+    let replica = replica(&primary, &[]);
+    wait_for_replicas(&primary, 1).await;
+
+    assert!(is_error(&replica.call(&["SET", "key", "value"]).await, "READONLY"));
+    assert_eq!(replica.call(&["GET", "key"]).await, Frame::Null);
+
+    // 关掉只读后副本可以写，但写入不会传回主节点
+    assert_eq!(replica.call(&["CONFIG", "SET", "replica-read-only", "no"]).await, Frame::Simple("OK".into()));
+    assert_eq!(replica.call(&["SET", "local", "value"]).await, Frame::Simple("OK".into()));
+    assert_eq!(primary.call(&["GET", "local"]).await, Frame::Null);
+
+    // REPLICAOF NO ONE 之后变成主节点，写命令不再受限
+    assert_eq!(replica.call(&["CONFIG", "SET", "replica-read-only", "yes"]).await, Frame::Simple("OK".into()));
+    assert!(is_error(&replica.call(&["SET", "key", "value"]).await, "READONLY"));
+    assert_eq!(replica.call(&["REPLICAOF", "NO", "ONE"]).await, Frame::Simple("OK".into()));
+    assert_eq!(replica.call(&["SET", "key", "value"]).await, Frame::Simple("OK".into()));
+}
+
+#[tokio::test]
+async fn stale_replica_refuses_reads() {
+    let mut primary = Process::server(free_port(), &[]);
+    let replica = replica(&primary, &["--replica-serve-stale-data", "no"]);
+    wait_for_replicas(&primary, 1).await;
+    primary.call(&["SET", "key", "value"]).await;
+    eventually(TIMEOUT, || async { replica.call(&["GET", "key"]).await == bulk("value") }).await;
+
+    primary.kill();
+    eventually(TIMEOUT, || async { info(replica.port).await.contains("master_link_status:down") }).await;
+    assert!(is_error(&replica.call(&["GET", "key"]).await, "MASTERDOWN"));
+    assert_eq!(replica.call(&["PING"]).await, Frame::Simple("PONG".into()));
+}
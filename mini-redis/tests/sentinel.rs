@@ -0,0 +1,98 @@
+mod common;
+
+use bytes::Bytes;
+use common::{call, eventually, free_port, Process};
+use mini_redis::Frame;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+fn sentinel(master_port: u16) -> Process {
+    let port = free_port();
+    let args = [
+        "--port".to_string(),
+        port.to_string(),
+        "--sentinel".to_string(),
+        format!("monitor mymaster 127.0.0.1 {} 2", master_port),
+        "--sentinel".to_string(),
+        "down-after-milliseconds mymaster 1000".to_string(),
+        "--sentinel".to_string(),
+        "failover-timeout mymaster 10000".to_string(),
+    ];
+    Process::start(env!("CARGO_BIN_EXE_sentinel"), port, &args)
+}
+
+async fn info(port: u16) -> String {
+    match call(port, &["INFO", "replication"]).await {
+        Frame::Bulk(text) => String::from_utf8(text.to_vec()).unwrap(),
+        frame => panic!("unexpected INFO reply {:?}", frame),
+    }
+}
+
+async fn count(sentinel: &Process, what: &str) -> usize {
+    match sentinel.call(&["SENTINEL", what, "mymaster"]).await {
+        Frame::Array(items) => items.len(),
+        frame => panic!("unexpected SENTINEL {} reply {:?}", what, frame),
+    }
+}
+
+async fn master_port(sentinel: &Process) -> u16 {
+    match sentinel.call(&["SENTINEL", "GET-MASTER-ADDR-BY-NAME", "mymaster"]).await {
+        Frame::Array(addr) => match &addr[..] {
+            [_, Frame::Bulk(port)] => std::str::from_utf8(port).unwrap().parse().unwrap(),
+            _ => panic!("unexpected address {:?}", addr),
+        },
+        frame => panic!("unexpected GET-MASTER-ADDR-BY-NAME reply {:?}", frame),
+    }
+}
+
+#[tokio::test]
+async fn promotes_a_replica_when_the_primary_dies() {
+    let mut primary = Process::server(free_port(), &[]);
+    let replicaof = format!("127.0.0.1 {}", primary.port);
+    let replicas = [
+        Process::server(free_port(), &["--replicaof", &replicaof]),
+        Process::server(free_port(), &["--replicaof", &replicaof]),
+    ];
+    eventually(TIMEOUT, || async { info(primary.port).await.contains("connected_slaves:2") }).await;
+
+    let sentinels = [sentinel(primary.port), sentinel(primary.port), sentinel(primary.port)];
+    // 每个 sentinel 都通过主节点发现了两个副本和另外两个 sentinel
+    for sentinel in &sentinels {
+        eventually(TIMEOUT, || async { count(sentinel, "REPLICAS").await == 2 && count(sentinel, "SENTINELS").await == 2 })
+            .await;
+    }
+    let quorum = sentinels[0].call(&["SENTINEL", "CKQUORUM", "mymaster"]).await;
+    assert!(matches!(quorum, Frame::Simple(message) if message.starts_with("OK 3 usable Sentinels")));
+
+    let old_port = primary.port;
+    primary.kill();
+
+    // 所有 sentinel 最终都指向同一个被提升的副本
+    eventually(TIMEOUT, || async {
+        let mut ports = Vec::new();
+        for sentinel in &sentinels {
+            ports.push(master_port(sentinel).await);
+        }
+        ports[0] != old_port && ports.iter().all(|port| *port == ports[0])
+    })
+    .await;
+    let new_port = master_port(&sentinels[0]).await;
+    let (promoted, other) = match replicas.iter().position(|replica| replica.port == new_port) {
+        Some(0) => (&replicas[0], &replicas[1]),
+        Some(_) => (&replicas[1], &replicas[0]),
+        None => panic!("promoted {} is not one of the replicas", new_port),
+    };
+    assert!(info(promoted.port).await.contains("role:master"));
+
+    // 另一个副本改为复制新的主节点，写入可以传过去
+    let expected = format!("master_port:{}", new_port);
+    eventually(TIMEOUT, || async {
+        let info = info(other.port).await;
+        info.contains(&expected) && info.contains("master_link_status:up")
+    })
+    .await;
+    assert_eq!(promoted.call(&["SET", "after", "failover"]).await, Frame::Simple("OK".into()));
+    eventually(TIMEOUT, || async { other.call(&["GET", "after"]).await == Frame::Bulk(Bytes::from_static(b"failover")) })
+        .await;
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::Frame;
+
+fn is_error(frame: &Frame, prefix: &str) -> bool {
+    matches!(frame, Frame::Error(message) if message.starts_with(prefix))
+}
+
+#[tokio::test]
+async fn incr_and_decr() {
+    let server = Process::server(free_port(), &[]);
+
+    assert_eq!(server.call(&["INCR", "n"]).await, Frame::Integer(1));
+    assert_eq!(server.call(&["INCRBY", "n", "41"]).await, Frame::Integer(42));
+    assert_eq!(server.call(&["DECR", "n"]).await, Frame::Integer(41));
+    assert_eq!(server.call(&["DECRBY", "n", "-9"]).await, Frame::Integer(50));
+    assert_eq!(server.call(&["GET", "n"]).await, Frame::Bulk(Bytes::from("50")));
+
+    // 过期时间保持不变
+    server.call(&["EXPIRE", "n", "100"]).await;
+    server.call(&["INCR", "n"]).await;
+    assert!(matches!(server.call(&["TTL", "n"]).await, Frame::Integer(1..=100)));
+
+    server.call(&["SET", "max", &i64::MAX.to_string()]).await;
+    assert!(is_error(&server.call(&["INCR", "max"]).await, "ERR increment or decrement would overflow"));
+    assert!(is_error(&server.call(&["DECRBY", "n", &i64::MIN.to_string()]).await, "ERR decrement would overflow"));
+    server.call(&["SET", "s", "abc"]).await;
+    assert!(is_error(&server.call(&["INCR", "s"]).await, "ERR value is not an integer"));
+    assert!(is_error(&server.call(&["INCRBY", "n", "1.5"]).await, "ERR value is not an integer"));
+    server.call(&["RPUSH", "l", "x"]).await;
+    assert!(is_error(&server.call(&["INCR", "l"]).await, "WRONGTYPE"));
+}
+
+async fn bitfield(server: &Process, args: &[&str]) -> Vec<Frame> {
+    match server.call(&[&["BITFIELD"], args].concat()).await {
+        Frame::Array(replies) => replies,
+        other => panic!("unexpected reply {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn bitfield_types_offsets_and_overflow() {
+    let server = Process::server(free_port(), &[]);
+    // 同一段位按有符号和无符号读出来不一样
+    assert_eq!(
+        bitfield(&server, &["w", "SET", "u8", "0", "255", "GET", "u8", "0", "GET", "i8", "0", "GET", "u4", "4"]).await,
+        [Frame::Integer(0), Frame::Integer(255), Frame::Integer(-1), Frame::Integer(15)]
+    );
+    assert_eq!(
+        bitfield(&server, &["w64", "SET", "i64", "0", "-1", "GET", "u63", "0", "GET", "i64", "0"]).await,
+        [Frame::Integer(0), Frame::Integer(i64::MAX), Frame::Integer(-1)]
+    );
+    assert!(is_error(&server.call(&["BITFIELD", "w", "GET", "u64", "0"]).await, "ERR Invalid bitfield type"));
+    assert!(is_error(&server.call(&["BITFIELD", "w", "GET", "i65", "0"]).await, "ERR Invalid bitfield type"));
+
+    // #N 按类型宽度换算成位偏移
+    assert_eq!(
+        bitfield(&server, &["n", "SET", "u8", "#1", "200", "GET", "u8", "8", "GET", "u4", "#2", "GET", "u8", "#0"])
+            .await,
+        [Frame::Integer(0), Frame::Integer(200), Frame::Integer(12), Frame::Integer(0)]
+    );
+    assert_eq!(server.call(&["GET", "n"]).await, Frame::Bulk(Bytes::from_static(&[0, 200])));
+
+    // 默认 WRAP
+    assert_eq!(
+        bitfield(&server, &["o", "INCRBY", "u8", "0", "250", "INCRBY", "u8", "0", "10", "INCRBY", "i8", "8", "127"])
+            .await,
+        [Frame::Integer(250), Frame::Integer(4), Frame::Integer(127)]
+    );
+    assert_eq!(bitfield(&server, &["o", "INCRBY", "i8", "8", "1"]).await, [Frame::Integer(-128)]);
+    assert_eq!(
+        bitfield(&server, &["s", "OVERFLOW", "SAT", "INCRBY", "u8", "0", "300", "INCRBY", "i8", "8", "-200"]).await,
+        [Frame::Integer(255), Frame::Integer(-128)]
+    );
+    assert_eq!(
+        bitfield(&server, &["s", "OVERFLOW", "SAT", "SET", "i8", "16", "1000", "GET", "i8", "16"]).await,
+        [Frame::Integer(0), Frame::Integer(127)]
+    );
+    assert_eq!(
+        bitfield(&server, &["f", "SET", "u8", "0", "250", "OVERFLOW", "FAIL", "INCRBY", "u8", "0", "10", "GET", "u8", "0"])
+            .await,
+        [Frame::Integer(0), Frame::Null, Frame::Integer(250)]
+    );
+    // FAIL 之后什么都没写，不会创建 key
+    assert_eq!(bitfield(&server, &["none", "OVERFLOW", "FAIL", "INCRBY", "u8", "0", "300"]).await, [Frame::Null]);
+    assert_eq!(server.call(&["EXISTS", "none"]).await, Frame::Integer(0));
+
+    assert!(is_error(&server.call(&["BITFIELD_RO", "w", "SET", "u8", "0", "1"]).await, "ERR BITFIELD_RO"));
+    assert!(is_error(&server.call(&["BITFIELD", "w", "OVERFLOW", "NOPE"]).await, "ERR Invalid OVERFLOW type"));
+}
+
+#[tokio::test]
+async fn concurrent_setbit_keeps_every_bit() {
+    let server = Process::server(free_port(), &[]);
+    let mut tasks = Vec::new();
+    for i in 0..8u64 {
+        let mut client = server.client().await;
+        tasks.push(tokio::spawn(async move {
+            for j in 0..2000u64 {
+                let offset = (i * 2000 + j).to_string();
+                let args = ["SETBIT", "bits", &offset, "1"].map(|arg| Bytes::copy_from_slice(arg.as_bytes()));
+                client.call(&args).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    assert_eq!(server.call(&["BITCOUNT", "bits"]).await, Frame::Integer(16000));
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use bytes::Bytes;
+use common::{free_port, Process};
+use mini_redis::client;
+use mini_redis::tls::ClientOptions;
+use rcgen::{BasicConstraints, CertificateParams, CertifiedIssuer, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use std::path::{Path, PathBuf};
+
+// 测试时生成的 CA、服务端证书和客户端证书，写到 dir 下
+struct Certs {
+    ca: PathBuf,
+    server_cert: PathBuf,
+    server_key: PathBuf,
+    client_cert: PathBuf,
+    client_key: PathBuf,
+}
+
+fn generate(dir: &Path) -> Certs {
+    let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+
+    let leaf = |name: &str, usage: ExtendedKeyUsagePurpose| {
+        let key = KeyPair::generate().unwrap();
+        let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+        params.extended_key_usages = vec![usage];
+        (params.signed_by(&key, &ca).unwrap().pem(), key.serialize_pem())
+    };
+    let (server_cert, server_key) = leaf("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+    let (client_cert, client_key) = leaf("client", ExtendedKeyUsagePurpose::ClientAuth);
+
+    let write = |name: &str, contents: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    };
+    Certs {
+        ca: write("ca.crt", &ca.pem()),
+        server_cert: write("server.crt", &server_cert),
+        server_key: write("server.key", &server_key),
+        client_cert: write("client.crt", &client_cert),
+        client_key: write("client.key", &client_key),
+    }
+}
+
+fn cert_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-redis-certs-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn start(certs: &Certs, tls_port: u16, auth_clients: &str) -> Process {
+    let tls_port = tls_port.to_string();
+    let args = [
+        "--tls-port",
+        &tls_port,
+        "--tls-cert-file",
+        certs.server_cert.to_str().unwrap(),
+        "--tls-key-file",
+        certs.server_key.to_str().unwrap(),
+        "--tls-ca-cert-file",
+        certs.ca.to_str().unwrap(),
+        "--tls-auth-clients",
+        auth_clients,
+    ];
+    Process::server(free_port(), &args)
+}
+
+fn options(certs: &Certs, with_client_cert: bool) -> ClientOptions {
+    ClientOptions {
+        ca_cert: certs.ca.clone(),
+        server_name: "localhost".to_string(),
+        cert: with_client_cert.then(|| certs.client_cert.clone()),
+        key: with_client_cert.then(|| certs.client_key.clone()),
+    }
+}
+
+#[tokio::test]
+async fn round_trip_over_tls() {
+    let dir = cert_dir("server");
+    let certs = generate(&dir);
+    let tls_port = free_port();
+    let _server = start(&certs, tls_port, "no");
+
+    let mut client = client::connect_tls(("127.0.0.1", tls_port), &options(&certs, false)).await.unwrap();
+    client.set("greeting", Bytes::from_static(b"hello")).await.unwrap();
+    assert_eq!(client.get("greeting").await.unwrap(), Some(Bytes::from_static(b"hello")));
+
+    // 证书里的名字对不上时握手失败
+    let mut wrong_name = options(&certs, false);
+    wrong_name.server_name = "example.com".to_string();
+    assert!(client::connect_tls(("127.0.0.1", tls_port), &wrong_name).await.is_err());
+    let _ = std::fs::remove_dir_all(dir);
+}
+
+#[tokio::test]
+async fn client_certificate_is_required() {
+    let dir = cert_dir("mutual");
+    let certs = generate(&dir);
+    let tls_port = free_port();
+    let _server = start(&certs, tls_port, "yes");
+
+    let mut client = client::connect_tls(("127.0.0.1", tls_port), &options(&certs, true)).await.unwrap();
+    assert_eq!(client.ping(None).await.unwrap(), Bytes::from_static(b"PONG"));
+
+    // TLS 1.3 的客户端证书在握手之后才被服务端检查，第一个请求会失败
+    let refused = async {
+        let mut client = client::connect_tls(("127.0.0.1", tls_port), &options(&certs, false)).await?;
+        client.ping(None).await
+    };
+    assert!(refused.await.is_err());
+    let _ = std::fs::remove_dir_all(dir);
+}